@@ -63,8 +63,13 @@ function softDeleteTask(userId, taskId) {
     .where({ user_id: userId, id: taskId, deleted_at: null });
 }
 
+// the English onboarding templates, the rust server also picks them by locale
 function getDefaultTasks() {
-  return db.select().from("tasks").where({ is_default: true });
+  return db
+    .select()
+    .from("task_templates")
+    .where({ locale: "en" })
+    .orderBy(["position", "id"]);
 }
 
 module.exports = {
@@ -0,0 +1,398 @@
+// `todo_server admin ...`, looking after users and the task templates without
+// writing SQL by hand. Everything goes through the same TodoDB queries the
+// routes use.
+
+use crate::database::admin_queries::SetDisabled;
+use crate::database::template_queries::DEFAULT_LOCALE;
+use crate::database::{TodoDB, TodoDBError, UserId};
+use crate::passwords::new_password_problem;
+use crate::routes::users::{hash_password, register};
+use crate::routes::TodoAppError;
+use std::io::{self, BufRead, Write};
+use thiserror::Error;
+use todo_api::validation::username_problem;
+use todo_api::{CreateTemplateRequest, Role, TemplateId};
+
+pub const USAGE: &str = "\
+usage: todo_server                  run the server
+       todo_server admin <command>
+
+commands:
+  users                                   list every user
+  create-user <username> [--password <password>] [--locale <locale>]
+  disable <username>                      block logins and end the session
+  enable <username>                       undo disable, deleted accounts stay deleted
+  role <username> <user|admin>            admins can use the /admin and /templates routes
+  delete-user <username>                  soft delete the user and their tasks
+  reset-password <username> [--password <password>]
+  disable-2fa <username>                  for users who lost their authenticator and recovery codes
+  sessions                                list logged in users
+  revoke <username> | --all               log users out
+  templates [--locale <locale>]           list the tasks new users start with
+  add-template <title> [--locale <locale>] [--description <text>] [--priority A|B|C]
+  rm-template <id>
+  stats                                   counts of users, tasks and events
+
+without --password the password is read from stdin
+";
+
+#[derive(Debug, PartialEq)]
+pub enum AdminCommand {
+    Users,
+    CreateUser {
+        username: String,
+        password: Option<String>,
+        locale: Option<String>,
+    },
+    Disable {
+        username: String,
+    },
+    Enable {
+        username: String,
+    },
+    Role {
+        username: String,
+        role: Role,
+    },
+    DeleteUser {
+        username: String,
+    },
+    ResetPassword {
+        username: String,
+        password: Option<String>,
+    },
+    DisableTwoFactor {
+        username: String,
+    },
+    Sessions,
+    Revoke {
+        username: Option<String>,
+    },
+    Templates {
+        locale: Option<String>,
+    },
+    AddTemplate {
+        template: CreateTemplateRequest,
+    },
+    RmTemplate {
+        id: TemplateId,
+    },
+    Stats,
+}
+
+#[derive(Error, Debug)]
+pub enum AdminError {
+    #[error("{0}")]
+    Usage(String),
+    #[error("{0}")]
+    Failed(String),
+    #[error(transparent)]
+    DB(#[from] TodoDBError),
+    #[error("{}", .0.name)]
+    App(#[from] TodoAppError),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+fn usage<T>(message: impl Into<String>) -> Result<T, AdminError> {
+    Err(AdminError::Usage(message.into()))
+}
+
+/// Parses what follows `admin` on the command line.
+pub fn parse(args: &[String]) -> Result<AdminCommand, AdminError> {
+    let mut positional = vec![];
+    let mut password = None;
+    let mut description = None;
+    let mut priority = None;
+    let mut locale = None;
+    let mut all = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| match args.next() {
+            Some(value) => Ok(value.clone()),
+            None => usage(format!("{} needs a value", name)),
+        };
+        match arg.as_str() {
+            "--password" => password = Some(value(arg)?),
+            "--description" => description = Some(value(arg)?),
+            "--locale" => locale = Some(value(arg)?),
+            "--priority" => {
+                let value = value(arg)?.to_uppercase();
+                if !["A", "B", "C"].contains(&value.as_str()) {
+                    return usage(format!("priority must be A, B or C, not {}", value));
+                }
+                priority = Some(value);
+            }
+            "--all" => all = true,
+            flag if flag.starts_with("--") => return usage(format!("unknown option {}", flag)),
+            _ => positional.push(arg.clone()),
+        }
+    }
+
+    let mut positional = positional.into_iter();
+    let command = match positional.next() {
+        Some(command) => command,
+        None => return usage("missing command"),
+    };
+    let mut operand = |what: &str| match positional.next() {
+        Some(value) => Ok(value),
+        None => usage(format!("{} needs {}", command, what)),
+    };
+    let parsed = match command.as_str() {
+        "users" => AdminCommand::Users,
+        "create-user" => AdminCommand::CreateUser {
+            username: operand("a username")?,
+            password: password.take(),
+            locale: locale.take(),
+        },
+        "disable" => AdminCommand::Disable {
+            username: operand("a username")?,
+        },
+        "enable" => AdminCommand::Enable {
+            username: operand("a username")?,
+        },
+        "role" => {
+            let username = operand("a username and a role")?;
+            let role = operand("a username and a role")?;
+            match Role::parse(&role) {
+                Some(role) => AdminCommand::Role { username, role },
+                None => return usage(format!("role must be user or admin, not {}", role)),
+            }
+        }
+        "delete-user" => AdminCommand::DeleteUser {
+            username: operand("a username")?,
+        },
+        "reset-password" => AdminCommand::ResetPassword {
+            username: operand("a username")?,
+            password: password.take(),
+        },
+        "disable-2fa" => AdminCommand::DisableTwoFactor {
+            username: operand("a username")?,
+        },
+        "sessions" => AdminCommand::Sessions,
+        "revoke" if std::mem::take(&mut all) => AdminCommand::Revoke { username: None },
+        "revoke" => AdminCommand::Revoke {
+            username: Some(operand("a username or --all")?),
+        },
+        "templates" => AdminCommand::Templates {
+            locale: locale.take(),
+        },
+        "add-template" => AdminCommand::AddTemplate {
+            template: CreateTemplateRequest {
+                title: operand("a title")?,
+                description: description.take(),
+                priority: priority.take(),
+                locale: locale.take(),
+                position: None,
+            },
+        },
+        "rm-template" => {
+            let id = operand("a template id")?;
+            match id.parse() {
+                Ok(id) => AdminCommand::RmTemplate { id },
+                Err(_) => return usage(format!("{} is not a template id", id)),
+            }
+        }
+        "stats" => AdminCommand::Stats,
+        other => return usage(format!("unknown command {}", other)),
+    };
+    if let Some(extra) = positional.next() {
+        return usage(format!("unexpected argument {}", extra));
+    }
+    if password.is_some() || description.is_some() || priority.is_some() || locale.is_some() || all
+    {
+        return usage(format!("{} doesn't take those options", command));
+    }
+    Ok(parsed)
+}
+
+pub async fn run(db: &TodoDB, command: AdminCommand) -> Result<(), AdminError> {
+    match command {
+        AdminCommand::Users => {
+            println!(
+                "{:>6}  {:<24} {:<5}  {:>6}  STATUS",
+                "ID", "USERNAME", "ROLE", "TASKS"
+            );
+            for user in db.list_users(None).await? {
+                // disable sets deleted_at too, enable clears it
+                let status = if user.disabled {
+                    "disabled"
+                } else if user.deleted_at.is_some() {
+                    "deleted"
+                } else if user.logged_in {
+                    "logged in"
+                } else {
+                    "active"
+                };
+                println!(
+                    "{:>6}  {:<24} {:<5}  {:>6}  {}",
+                    user.id,
+                    user.username,
+                    user.role.as_str(),
+                    user.tasks,
+                    status
+                );
+            }
+        }
+        AdminCommand::CreateUser {
+            username,
+            password,
+            locale,
+        } => {
+            let password = password_or_stdin(password)?;
+            let problem = username_problem(&username)
+                .or_else(|| new_password_problem(&username, &password));
+            if let Some(problem) = problem {
+                return Err(AdminError::Failed(problem));
+            }
+            let locale = locale.as_deref().unwrap_or(DEFAULT_LOCALE);
+            let user = register(db, &username, &password, locale).await?;
+            println!("created user {} with id {}", user.username, user.id);
+        }
+        AdminCommand::Disable { username } => {
+            let id = user_id(db, &username).await?;
+            set_disabled(db.set_user_disabled(id, true).await?, &username)?;
+            println!("disabled {}", username);
+        }
+        AdminCommand::Enable { username } => {
+            let id = user_id(db, &username).await?;
+            set_disabled(db.set_user_disabled(id, false).await?, &username)?;
+            println!("enabled {}", username);
+        }
+        AdminCommand::Role { username, role } => {
+            let id = user_id(db, &username).await?;
+            found(db.set_role(id, role).await?, &username)?;
+            println!(
+                "{} is now {} {}",
+                username,
+                if role == Role::Admin { "an" } else { "a" },
+                role.as_str()
+            );
+        }
+        AdminCommand::DeleteUser { username } => {
+            found(db.delete_user(&username).await?, &username)?;
+            println!("deleted {}", username);
+        }
+        AdminCommand::ResetPassword { username, password } => {
+            let password = password_or_stdin(password)?;
+            if let Some(problem) = new_password_problem(&username, &password) {
+                return Err(AdminError::Failed(problem));
+            }
+            let hashed_password = hash_password(&password)?;
+            found(
+                db.set_password(&username, &hashed_password).await?,
+                &username,
+            )?;
+            println!("changed the password of {} and logged them out", username);
+        }
+        AdminCommand::DisableTwoFactor { username } => {
+            let id = user_id(db, &username).await?;
+            if !db.disable_two_factor(id).await? {
+                return Err(AdminError::Failed(format!(
+                    "{} doesn't have two-factor authentication",
+                    username
+                )));
+            }
+            println!("turned off two-factor authentication for {}", username);
+        }
+        AdminCommand::Sessions => {
+            println!("{:>6}  USERNAME", "ID");
+            for (id, username) in db.list_sessions().await? {
+                println!("{:>6}  {}", id, username);
+            }
+        }
+        AdminCommand::Revoke { username: None } => {
+            let count = db.revoke_all_tokens().await?;
+            println!("logged out {} users", count);
+        }
+        AdminCommand::Revoke {
+            username: Some(username),
+        } => {
+            if !db.revoke_token(&username).await? {
+                return Err(AdminError::Failed(format!("{} is not logged in", username)));
+            }
+            println!("logged out {}", username);
+        }
+        AdminCommand::Templates { locale } => {
+            println!("{:>6}  {:<6}  {:>3}  PRI  TITLE", "ID", "LOCALE", "POS");
+            for template in db.get_templates(locale.as_deref()).await? {
+                println!(
+                    "{:>6}  {:<6}  {:>3}  {:<3}  {}",
+                    template.id,
+                    template.locale,
+                    template.position,
+                    template.priority.unwrap_or_default(),
+                    template.title
+                );
+            }
+        }
+        AdminCommand::AddTemplate { template } => {
+            let template = db.insert_template(&template).await?;
+            println!("added template {}", template.id);
+        }
+        AdminCommand::RmTemplate { id } => {
+            if !db.delete_template(id).await? {
+                return Err(AdminError::Failed(format!("no template {}", id)));
+            }
+            println!("removed template {}", id);
+        }
+        AdminCommand::Stats => {
+            let stats = db.usage_stats().await?;
+            println!("users            {}", stats.users);
+            println!("  logged in      {}", stats.logged_in_users);
+            println!("  admins         {}", stats.admins);
+            println!("deleted users    {}", stats.deleted_users);
+            println!("tasks            {}", stats.tasks);
+            println!("  completed      {}", stats.completed_tasks);
+            println!("deleted tasks    {}", stats.deleted_tasks);
+            println!("task templates   {}", stats.templates);
+            println!("task events      {}", stats.task_events);
+        }
+    }
+    Ok(())
+}
+
+// deleted users too, disabled ones are deleted ones and enable needs to find them
+async fn user_id(db: &TodoDB, username: &str) -> Result<UserId, AdminError> {
+    match db.get_by_username(username).await? {
+        Some(user) => Ok(user.id),
+        None => Err(AdminError::Failed(format!("no user {}", username))),
+    }
+}
+
+fn found(found: bool, username: &str) -> Result<(), AdminError> {
+    if found {
+        Ok(())
+    } else {
+        Err(AdminError::Failed(format!("no user {}", username)))
+    }
+}
+
+fn set_disabled(outcome: SetDisabled, username: &str) -> Result<(), AdminError> {
+    match outcome {
+        SetDisabled::Done => Ok(()),
+        SetDisabled::NotFound => found(false, username),
+        SetDisabled::Deleted => Err(AdminError::Failed(format!(
+            "{} was deleted, not disabled, enabling won't bring it back",
+            username
+        ))),
+    }
+}
+
+// so passwords can be piped in instead of showing up in the shell history
+fn password_or_stdin(password: Option<String>) -> Result<String, AdminError> {
+    if let Some(password) = password {
+        return Ok(password);
+    }
+    eprint!("password: ");
+    io::stderr().flush()?;
+    let mut password = String::new();
+    io::stdin().lock().read_line(&mut password)?;
+    let password = password.trim_end_matches(['\r', '\n']).to_string();
+    if password.is_empty() {
+        return Err(AdminError::Failed(
+            "the password can't be empty".to_string(),
+        ));
+    }
+    Ok(password)
+}
@@ -0,0 +1,199 @@
+// Queries behind `todo_server admin`, these report database errors instead of
+// hiding them because an operator is reading the output.
+
+use crate::database::{TodoDB, TodoDBError, UserId};
+use todo_api::{AdminUser, Role, UsageStats};
+use tokio_postgres::Row;
+use tracing::instrument;
+
+/// Outcome of disabling or enabling a user.
+pub enum SetDisabled {
+    Done,
+    NotFound,
+    /// Deleted rather than disabled, by the user or with `admin delete-user`,
+    /// enabling doesn't bring the account back.
+    Deleted,
+}
+
+fn admin_user(row: &Row) -> AdminUser {
+    AdminUser {
+        id: row.get("id"),
+        username: row.get("username"),
+        // anything unknown gets no more than a user
+        role: Role::parse(row.get("role")).unwrap_or_default(),
+        deleted_at: row.get("deleted_at"),
+        disabled: row.get("disabled"),
+        logged_in: row.get("logged_in"),
+        tasks: row.get("tasks"),
+    }
+}
+
+impl TodoDB {
+    // everyone when user_id is None
+    #[instrument(level = "debug", skip_all)]
+    pub async fn list_users(&self, user_id: Option<UserId>) -> Result<Vec<AdminUser>, TodoDBError> {
+        let con = self.connection().await?;
+        let sql = r#"
+            SELECT users.id, username, role, users.deleted_at, disabled,
+                token IS NOT NULL AS logged_in,
+                COUNT(tasks.id) FILTER (WHERE tasks.deleted_at IS NULL) AS tasks
+            FROM users LEFT JOIN tasks ON tasks.user_id = users.id
+            WHERE $1::INTEGER IS NULL OR users.id = $1
+            GROUP BY users.id ORDER BY users.id
+            "#;
+        let rows = con.query(sql, &[&user_id]).await?;
+        Ok(rows.iter().map(admin_user).collect())
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    pub async fn get_role(&self, user_id: UserId) -> Result<Option<Role>, TodoDBError> {
+        let con = self.connection().await?;
+        let sql = "SELECT role FROM users WHERE id = $1";
+        let rows = con.query(sql, &[&user_id]).await?;
+        Ok(rows.first().map(|row| Role::parse(row.get("role")).unwrap_or_default()))
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    pub async fn set_role(&self, user_id: UserId, role: Role) -> Result<bool, TodoDBError> {
+        let con = self.connection().await?;
+        let sql = "UPDATE users SET role = $2 WHERE id = $1";
+        Ok(con.execute(sql, &[&user_id, &role.as_str()]).await? == 1)
+    }
+
+    // a disabled user is a soft deleted one whose tasks are left alone, so
+    // everything that turns deleted users away does the same for them. The
+    // disabled flag tells them apart from users who deleted their account, only
+    // those an admin disabled can be enabled. Disabling logs the user out too,
+    // the token would otherwise keep working, and leaves a deleted user deleted.
+    #[instrument(level = "debug", skip_all)]
+    pub async fn set_user_disabled(
+        &self,
+        user_id: UserId,
+        disabled: bool,
+    ) -> Result<SetDisabled, TodoDBError> {
+        let con = self.connection().await?;
+        let sql = if disabled {
+            r#"
+            WITH target AS (SELECT id, FALSE AS deleted FROM users WHERE id = $1),
+            changed AS (
+                UPDATE users
+                SET disabled = deleted_at IS NULL OR disabled,
+                    deleted_at = COALESCE(deleted_at, NOW()),
+                    token = NULL
+                WHERE id IN (SELECT id FROM target)
+            )
+            SELECT deleted FROM target
+            "#
+        } else {
+            r#"
+            WITH target AS (
+                SELECT id, deleted_at IS NOT NULL AND NOT disabled AS deleted
+                FROM users WHERE id = $1
+            ),
+            changed AS (
+                UPDATE users SET deleted_at = NULL, disabled = FALSE
+                WHERE id IN (SELECT id FROM target WHERE NOT deleted)
+            )
+            SELECT deleted FROM target
+            "#
+        };
+        let rows = con.query(sql, &[&user_id]).await?;
+        Ok(match rows.first().map(|row| row.get("deleted")) {
+            None => SetDisabled::NotFound,
+            Some(true) => SetDisabled::Deleted,
+            Some(false) => SetDisabled::Done,
+        })
+    }
+
+    // soft deletes like tasks, so the username stays taken and the rows stay for
+    // auditing. A disabled user can be deleted too, then enabling won't undo it.
+    #[instrument(level = "debug", skip_all)]
+    pub async fn delete_user(&self, username: &str) -> Result<bool, TodoDBError> {
+        let mut con = self.connection().await?;
+        let transaction = con.transaction().await?;
+        let sql = r#"
+            UPDATE users SET deleted_at = NOW(), disabled = FALSE, token = NULL
+            WHERE username = $1 AND (deleted_at IS NULL OR disabled) RETURNING id
+            "#;
+        let rows = transaction.query(sql, &[&username]).await?;
+        let user_id: UserId = match rows.first() {
+            Some(row) => row.get("id"),
+            None => return Ok(false),
+        };
+        let sql = r#"
+            UPDATE tasks SET deleted_at = NOW(), version = version + 1
+            WHERE user_id = $1 AND deleted_at IS NULL
+            "#;
+        transaction.execute(sql, &[&user_id]).await?;
+        transaction.commit().await?;
+        Ok(true)
+    }
+
+    // takes an already hashed password and ends the user's session
+    #[instrument(level = "debug", skip_all)]
+    pub async fn set_password(
+        &self,
+        username: &str,
+        hashed_password: &str,
+    ) -> Result<bool, TodoDBError> {
+        let con = self.connection().await?;
+        let sql = "UPDATE users SET password = $2, token = NULL WHERE username = $1 AND deleted_at IS NULL";
+        Ok(con.execute(sql, &[&username, &hashed_password]).await? == 1)
+    }
+
+    // a user has at most one session, the token from their last login
+    #[instrument(level = "debug", skip_all)]
+    pub async fn list_sessions(&self) -> Result<Vec<(UserId, String)>, TodoDBError> {
+        let con = self.connection().await?;
+        let sql = "SELECT id, username FROM users WHERE token IS NOT NULL ORDER BY id";
+        let rows = con.query(sql, &[]).await?;
+        Ok(rows
+            .iter()
+            .map(|row| (row.get("id"), row.get("username")))
+            .collect())
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    pub async fn revoke_token(&self, username: &str) -> Result<bool, TodoDBError> {
+        let con = self.connection().await?;
+        let sql = "UPDATE users SET token = NULL WHERE username = $1 AND token IS NOT NULL";
+        Ok(con.execute(sql, &[&username]).await? == 1)
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    pub async fn revoke_all_tokens(&self) -> Result<u64, TodoDBError> {
+        let con = self.connection().await?;
+        let sql = "UPDATE users SET token = NULL WHERE token IS NOT NULL";
+        Ok(con.execute(sql, &[]).await?)
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    pub async fn usage_stats(&self) -> Result<UsageStats, TodoDBError> {
+        let con = self.connection().await?;
+        let sql = r#"
+            SELECT
+                (SELECT COUNT(*) FROM users WHERE deleted_at IS NULL) AS users,
+                (SELECT COUNT(*) FROM users WHERE deleted_at IS NOT NULL) AS deleted_users,
+                (SELECT COUNT(*) FROM users WHERE token IS NOT NULL) AS logged_in_users,
+                (SELECT COUNT(*) FROM users WHERE deleted_at IS NULL AND role = 'admin') AS admins,
+                (SELECT COUNT(*) FROM tasks WHERE user_id IS NOT NULL AND deleted_at IS NULL) AS tasks,
+                (SELECT COUNT(*) FROM tasks WHERE user_id IS NOT NULL AND deleted_at IS NULL
+                    AND completed_at IS NOT NULL) AS completed_tasks,
+                (SELECT COUNT(*) FROM tasks WHERE user_id IS NOT NULL AND deleted_at IS NOT NULL) AS deleted_tasks,
+                (SELECT COUNT(*) FROM task_templates) AS templates,
+                (SELECT COUNT(*) FROM task_events) AS task_events
+            "#;
+        let row = con.query_one(sql, &[]).await?;
+        Ok(UsageStats {
+            users: row.get("users"),
+            deleted_users: row.get("deleted_users"),
+            logged_in_users: row.get("logged_in_users"),
+            admins: row.get("admins"),
+            tasks: row.get("tasks"),
+            completed_tasks: row.get("completed_tasks"),
+            deleted_tasks: row.get("deleted_tasks"),
+            templates: row.get("templates"),
+            task_events: row.get("task_events"),
+        })
+    }
+}
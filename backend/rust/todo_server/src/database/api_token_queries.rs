@@ -0,0 +1,106 @@
+use crate::database::{TodoDB, TodoDBError, UserId};
+use todo_api::{ApiToken, ApiTokenId, TokenScope};
+use tokio_postgres::Row;
+use tracing::instrument;
+
+fn api_token(row: &Row) -> ApiToken {
+    ApiToken {
+        id: row.get("id"),
+        name: row.get("name"),
+        scope: TokenScope::parse(row.get("scope")).unwrap_or_default(),
+        created_at: row.get("created_at"),
+        expires_at: row.get("expires_at"),
+        last_used_at: row.get("last_used_at"),
+    }
+}
+
+impl TodoDB {
+    // expired ones too, so the user can see why a script stopped working
+    #[instrument(level = "debug", skip_all)]
+    pub async fn get_api_tokens(&self, user_id: UserId) -> Result<Vec<ApiToken>, TodoDBError> {
+        let con = self.connection().await?;
+        let sql = "SELECT * FROM api_tokens WHERE user_id = $1 ORDER BY created_at, id";
+        let rows = con.query(sql, &[&user_id]).await?;
+        Ok(rows.iter().map(api_token).collect())
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    pub async fn insert_api_token(
+        &self,
+        user_id: UserId,
+        name: &str,
+        token_hash: &str,
+        scope: TokenScope,
+        expires_in_days: Option<i32>,
+    ) -> Result<ApiToken, TodoDBError> {
+        let con = self.connection().await?;
+        let sql = r#"
+            INSERT INTO api_tokens (user_id, name, token_hash, scope, expires_at)
+            VALUES ($1, $2, $3, $4, NOW() + $5 * INTERVAL '1 day')
+            RETURNING *
+            "#;
+        let expires_in_days = expires_in_days.map(f64::from);
+        let row = con
+            .query_one(
+                sql,
+                &[
+                    &user_id,
+                    &name,
+                    &token_hash,
+                    &scope.as_str(),
+                    &expires_in_days,
+                ],
+            )
+            .await?;
+        Ok(api_token(&row))
+    }
+
+    // false when the user has no such token
+    #[instrument(level = "debug", skip_all)]
+    pub async fn delete_api_token(
+        &self,
+        user_id: UserId,
+        id: ApiTokenId,
+    ) -> Result<bool, TodoDBError> {
+        let con = self.connection().await?;
+        let sql = "DELETE FROM api_tokens WHERE id = $1 AND user_id = $2";
+        Ok(con.execute(sql, &[&id, &user_id]).await? == 1)
+    }
+
+    // the user and scope of a token that hasn't expired, for a user who can
+    // still log in. Notes the time so the settings page can show it, but only
+    // when the one noted is more than a minute old: a script making a request
+    // a second shouldn't write the row every time.
+    #[instrument(level = "debug", skip_all)]
+    pub async fn use_api_token(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<(UserId, String, TokenScope)>, TodoDBError> {
+        let con = self.connection().await?;
+        let sql = r#"
+            WITH found AS (
+                SELECT t.id AS token_id, t.scope, t.last_used_at, u.id, u.username
+                FROM api_tokens t JOIN users u ON t.user_id = u.id
+                WHERE t.token_hash = $1
+                  AND (t.expires_at IS NULL OR t.expires_at > NOW())
+                  AND u.deleted_at IS NULL
+            ), touched AS (
+                UPDATE api_tokens SET last_used_at = NOW()
+                WHERE id IN (
+                    SELECT token_id FROM found
+                    WHERE last_used_at IS NULL
+                       OR last_used_at < NOW() - INTERVAL '1 minute'
+                )
+            )
+            SELECT id, username, scope FROM found
+            "#;
+        let rows = con.query(sql, &[&token_hash]).await?;
+        Ok(rows.first().map(|row| {
+            (
+                row.get("id"),
+                row.get("username"),
+                TokenScope::parse(row.get("scope")).unwrap_or_default(),
+            )
+        }))
+    }
+}
@@ -0,0 +1,69 @@
+use crate::database::{TodoDB, TodoDBError, UserId};
+use crate::events::TaskEvent;
+use tracing::instrument;
+
+pub type EventId = i64;
+
+impl TodoDB {
+    // give the event its place in the user's sequence so clients can resume after it
+    #[instrument(level = "debug", skip_all)]
+    pub async fn insert_task_event(&self, user_id: UserId, event: &TaskEvent) -> Option<EventId> {
+        let con = match self.connection().await {
+            Ok(con) => con,
+            Err(e) => {
+                tracing::error!(error = %e, "inserting a task event failed");
+                return None;
+            }
+        };
+        let sql = "INSERT INTO task_events (user_id, event) VALUES ($1, $2) RETURNING id";
+        let event = serde_json::to_string(event).unwrap();
+        match con.query(sql, &[&user_id, &event]).await {
+            Ok(rows) => rows.first().map(|row| row.get("id")),
+            Err(e) => {
+                tracing::error!(error = %e, "inserting a task event failed");
+                None
+            }
+        }
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    pub async fn get_task_events_after(
+        &self,
+        user_id: UserId,
+        after: EventId,
+    ) -> Option<Vec<(EventId, TaskEvent)>> {
+        let con = match self.connection().await {
+            Ok(con) => con,
+            Err(e) => {
+                tracing::error!(error = %e, "reading task events failed");
+                return None;
+            }
+        };
+        let sql = "SELECT id, event FROM task_events WHERE user_id = $1 AND id > $2 ORDER BY id";
+        let rows = match con.query(sql, &[&user_id, &after]).await {
+            Ok(rows) => rows,
+            Err(e) => {
+                tracing::error!(error = %e, "reading task events failed");
+                return None;
+            }
+        };
+        let events = rows
+            .iter()
+            .filter_map(|row| {
+                let event: String = row.get("event");
+                let event = serde_json::from_str(&event).ok()?;
+                Some((row.get("id"), event))
+            })
+            .collect();
+        Some(events)
+    }
+
+    // events past the retention, how many went
+    #[instrument(level = "debug", skip_all)]
+    pub async fn purge_task_events(&self, retention_secs: i64) -> Result<u64, TodoDBError> {
+        let con = self.connection().await?;
+        let sql =
+            "DELETE FROM task_events WHERE created_at < NOW() - $1::FLOAT8 * INTERVAL '1 second'";
+        Ok(con.execute(sql, &[&(retention_secs as f64)]).await?)
+    }
+}
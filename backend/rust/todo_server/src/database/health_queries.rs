@@ -0,0 +1,57 @@
+use crate::database::{TodoDB, TodoDBError};
+use std::collections::BTreeSet;
+use tracing::instrument;
+
+// the schema docker-compose applies, the server's queries need all of it
+pub const INIT_SQL: &str = include_str!("../../../../../database/init.sql");
+
+/// The (table, column) pairs the `CREATE TABLE`s of `sql` make.
+pub fn schema_columns(sql: &str) -> BTreeSet<(String, String)> {
+    let mut columns = BTreeSet::new();
+    let mut table = None;
+    for line in sql.lines().map(str::trim) {
+        if let Some(rest) = line.strip_prefix("CREATE TABLE IF NOT EXISTS ") {
+            table = rest.split_whitespace().next().map(str::to_string);
+            continue;
+        }
+        if line.starts_with(')') {
+            table = None;
+            continue;
+        }
+        let table = match &table {
+            Some(table) => table,
+            None => continue,
+        };
+        let name = line.split_whitespace().next().unwrap_or_default();
+        // constraints are upper case like the types, columns lower case
+        let is_column = name.chars().next().is_some_and(|c| c.is_ascii_lowercase());
+        if is_column {
+            columns.insert((table.clone(), name.to_string()));
+        }
+    }
+    columns
+}
+
+impl TodoDB {
+    // a connection and a query that needs no table
+    #[instrument(level = "debug", skip_all)]
+    pub async fn ping(&self) -> Result<(), TodoDBError> {
+        let con = self.connection().await?;
+        con.query_one("SELECT 1", &[]).await?;
+        Ok(())
+    }
+
+    /// What init.sql makes that the database doesn't have, as table.column.
+    #[instrument(level = "debug", skip_all)]
+    pub async fn missing_columns(&self) -> Result<Vec<String>, TodoDBError> {
+        let con = self.connection().await?;
+        let sql = "SELECT table_name::TEXT, column_name::TEXT FROM information_schema.columns WHERE table_schema = current_schema()";
+        let rows = con.query(sql, &[]).await?;
+        let present: BTreeSet<(String, String)> =
+            rows.iter().map(|row| (row.get(0), row.get(1))).collect();
+        Ok(schema_columns(INIT_SQL)
+            .difference(&present)
+            .map(|(table, column)| format!("{}.{}", table, column))
+            .collect())
+    }
+}
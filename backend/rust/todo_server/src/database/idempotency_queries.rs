@@ -0,0 +1,169 @@
+use crate::database::{TodoDB, TodoDBError, UserId};
+use tracing::instrument;
+
+/// What to do with a request carrying an `Idempotency-Key`.
+pub enum IdempotencyClaim {
+    /// First time we see this key, run the handler and remember its response.
+    New,
+    /// The original request is still being handled.
+    InProgress,
+    /// The key was already used for a different method, path or body.
+    Mismatch,
+    Replay(StoredResponse),
+}
+
+pub struct StoredResponse {
+    pub status_code: i32,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl TodoDB {
+    // try to take the key, if somebody already has it tell the caller what
+    // happened to it. A claim older than the lease that never got a response is
+    // taken over, and so is any key past the retention that housekeeping hasn't
+    // purged yet.
+    #[allow(clippy::too_many_arguments)]
+    #[instrument(level = "debug", skip_all)]
+    pub async fn claim_idempotency_key(
+        &self,
+        user_id: UserId,
+        key: &str,
+        method: &str,
+        path: &str,
+        fingerprint: &str,
+        retention_secs: i64,
+        lease_secs: i64,
+    ) -> Option<IdempotencyClaim> {
+        let con = match self.connection().await {
+            Ok(con) => con,
+            Err(e) => {
+                tracing::error!(error = %e, "claiming an idempotency key failed");
+                return None;
+            }
+        };
+        let sql = r#"
+            INSERT INTO idempotency_keys
+                (idempotency_key, user_id, request_method, request_path, request_hash)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (COALESCE(user_id, 0), idempotency_key) DO UPDATE
+            SET request_method = EXCLUDED.request_method,
+                request_path = EXCLUDED.request_path,
+                request_hash = EXCLUDED.request_hash,
+                status_code = NULL,
+                response_headers = NULL,
+                response_body = NULL,
+                created_at = NOW()
+            WHERE (idempotency_keys.status_code IS NULL
+                    AND idempotency_keys.created_at < NOW() - $6::FLOAT8 * INTERVAL '1 second')
+                OR idempotency_keys.created_at < NOW() - $7::FLOAT8 * INTERVAL '1 second'
+            RETURNING id
+            "#;
+        let params: [&(dyn tokio_postgres::types::ToSql + Sync); 7] = [
+            &key,
+            &user_id,
+            &method,
+            &path,
+            &fingerprint,
+            &(lease_secs as f64),
+            &(retention_secs as f64),
+        ];
+        let inserted = match con.query(sql, &params).await {
+            Ok(rows) => !rows.is_empty(),
+            Err(e) => {
+                tracing::error!(error = %e, "claiming an idempotency key failed");
+                return None;
+            }
+        };
+        if inserted {
+            return Some(IdempotencyClaim::New);
+        }
+
+        let sql = r#"
+            SELECT request_method, request_path, request_hash, status_code, response_headers,
+                response_body
+            FROM idempotency_keys
+            WHERE COALESCE(user_id, 0) = $1 AND idempotency_key = $2
+            "#;
+        let rows = con.query(sql, &[&user_id, &key]).await.ok()?;
+        let row = rows.first()?;
+        let stored_method: String = row.get("request_method");
+        let stored_path: String = row.get("request_path");
+        let stored_hash: Option<String> = row.get("request_hash");
+        if stored_method != method
+            || stored_path != path
+            || stored_hash.as_deref() != Some(fingerprint)
+        {
+            return Some(IdempotencyClaim::Mismatch);
+        }
+        let status_code: Option<i32> = row.get("status_code");
+        match status_code {
+            None => Some(IdempotencyClaim::InProgress),
+            Some(status_code) => {
+                let headers: Option<String> = row.get("response_headers");
+                let body: Option<String> = row.get("response_body");
+                Some(IdempotencyClaim::Replay(StoredResponse {
+                    status_code,
+                    headers: headers
+                        .and_then(|h| serde_json::from_str(&h).ok())
+                        .unwrap_or_default(),
+                    body: body.unwrap_or_default(),
+                }))
+            }
+        }
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    pub async fn save_idempotent_response(
+        &self,
+        user_id: UserId,
+        key: &str,
+        response: &StoredResponse,
+    ) -> bool {
+        let con = match self.connection().await {
+            Ok(con) => con,
+            Err(e) => {
+                tracing::error!(error = %e, "saving an idempotent response failed");
+                return false;
+            }
+        };
+        let sql = r#"
+            UPDATE idempotency_keys
+            SET (status_code, response_headers, response_body) = ($1, $2, $3)
+            WHERE COALESCE(user_id, 0) = $4 AND idempotency_key = $5
+            "#;
+        let headers = serde_json::to_string(&response.headers).unwrap();
+        let result = con
+            .execute(
+                sql,
+                &[&response.status_code, &headers, &response.body, &user_id, &key],
+            )
+            .await;
+        matches!(result, Ok(1))
+    }
+
+    // let the key be used again, e.g. when the handler failed on our side
+    #[instrument(level = "debug", skip_all)]
+    pub async fn release_idempotency_key(&self, user_id: UserId, key: &str) -> bool {
+        let con = match self.connection().await {
+            Ok(con) => con,
+            Err(e) => {
+                tracing::error!(error = %e, "releasing an idempotency key failed");
+                return false;
+            }
+        };
+        let sql = r#"
+            DELETE FROM idempotency_keys
+            WHERE COALESCE(user_id, 0) = $1 AND idempotency_key = $2
+            "#;
+        matches!(con.execute(sql, &[&user_id, &key]).await, Ok(1))
+    }
+
+    // keys past the retention, how many went
+    #[instrument(level = "debug", skip_all)]
+    pub async fn purge_idempotency_keys(&self, retention_secs: i64) -> Result<u64, TodoDBError> {
+        let con = self.connection().await?;
+        let sql = "DELETE FROM idempotency_keys WHERE created_at < NOW() - $1::FLOAT8 * INTERVAL '1 second'";
+        Ok(con.execute(sql, &[&(retention_secs as f64)]).await?)
+    }
+}
@@ -1,20 +1,37 @@
+pub mod admin_queries;
+pub mod api_token_queries;
+pub mod event_queries;
+pub mod health_queries;
+pub mod idempotency_queries;
+pub mod oidc_queries;
+pub mod password_reset_queries;
 pub mod task_queries;
+pub mod template_queries;
+pub mod two_factor_queries;
 pub mod user_queries;
 
+use crate::routes::api_tokens::TOKEN_PREFIX;
+use crate::routes::auth::{hash_token, Credentials};
 use crate::routes::users::UserInfo;
+use actix_web::http::header::AUTHORIZATION;
+use actix_web::http::StatusCode;
 use actix_web::HttpRequest;
-use deadpool_postgres::Pool;
+use deadpool::managed::TimeoutType;
+use deadpool_postgres::{Client, Pool, PoolConfig, PoolError, Timeouts};
 use deadpool_postgres::{Config, ManagerConfig, RecyclingMethod, Runtime};
+use std::time::Duration;
 use thiserror::Error;
+use todo_api::TokenScope;
+use tokio_postgres::error::SqlState;
 use tokio_postgres::NoTls;
+use tracing::instrument;
 
-pub type UserId = i32;
-pub type TaskId = i32;
+pub use todo_api::{TaskId, UserId};
 
 #[derive(Error, Debug)]
 pub enum TodoDBError {
     #[error("error getting connection from DB pool: {0}")]
-    DBPoolError(tokio_postgres::Error),
+    DBPoolError(#[from] deadpool_postgres::PoolError),
     #[error("error executing DB query: {0}")]
     DBQueryError(#[from] tokio_postgres::Error),
     #[error("error creating table: {0}")]
@@ -23,48 +40,225 @@ pub enum TodoDBError {
     ReadFileError(#[from] std::io::Error),
 }
 
-impl actix_web::error::ResponseError for TodoDBError {}
+impl TodoDBError {
+    /// Postgres can't be reached or went away, worth trying again later.
+    pub fn is_unavailable(&self) -> bool {
+        match self {
+            TodoDBError::DBPoolError(_) => true,
+            TodoDBError::DBQueryError(e) => e.is_closed(),
+            _ => false,
+        }
+    }
+}
+
+impl actix_web::error::ResponseError for TodoDBError {
+    fn status_code(&self) -> StatusCode {
+        if self.is_unavailable() {
+            StatusCode::SERVICE_UNAVAILABLE
+        } else {
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
 
 pub struct TodoDB {
     pool: Pool,
+    settings: PoolSettings,
+}
+
+/// How the connection pool waits for, opens and retries connections.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PoolSettings {
+    pub max_size: usize,
+    /// How long a request waits for a connection when all of them are in use.
+    pub wait: Duration,
+    /// How long opening a new connection may take.
+    pub connect: Duration,
+    /// How many more times opening a connection is tried after it failed.
+    pub retries: u32,
+    /// The wait before the first retry, doubled for each one after it.
+    pub backoff: Duration,
+}
+
+impl Default for PoolSettings {
+    fn default() -> Self {
+        PoolSettings {
+            max_size: PoolConfig::default().max_size,
+            wait: Duration::from_secs(5),
+            connect: Duration::from_secs(5),
+            retries: 2,
+            backoff: Duration::from_millis(100),
+        }
+    }
+}
+
+impl PoolSettings {
+    pub fn from_env() -> Self {
+        let defaults = PoolSettings::default();
+        PoolSettings {
+            max_size: env_number("DB_POOL_MAX_SIZE")
+                .filter(|size| *size > 0)
+                .unwrap_or(defaults.max_size),
+            wait: env_number("DB_POOL_WAIT_SECS")
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.wait),
+            connect: env_number("DB_CONNECT_TIMEOUT_SECS")
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.connect),
+            retries: env_number("DB_CONNECT_RETRIES").unwrap_or(defaults.retries),
+            backoff: env_number("DB_RETRY_BACKOFF_MS")
+                .map(Duration::from_millis)
+                .unwrap_or(defaults.backoff),
+        }
+    }
+}
+
+fn env_number<N: std::str::FromStr>(name: &str) -> Option<N> {
+    dotenv::var(name)
+        .ok()
+        .and_then(|number| number.parse().ok())
+}
+
+// worth another try: Postgres not listening (yet), starting up or full, not
+// e.g. a wrong password or a pool that's busy, the request waited for that
+fn is_transient(e: &PoolError) -> bool {
+    match e {
+        PoolError::Timeout(TimeoutType::Create) => true,
+        PoolError::Backend(e) => match e.code() {
+            None => true,
+            Some(code) => {
+                *code == SqlState::CANNOT_CONNECT_NOW || *code == SqlState::TOO_MANY_CONNECTIONS
+            }
+        },
+        _ => false,
+    }
+}
+
+/// How many connections the pool has and how many requests wait for one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PoolStatus {
+    pub max_size: usize,
+    pub size: usize,
+    pub available: usize,
+    pub waiting: usize,
+}
+
+impl Default for TodoDB {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl TodoDB {
     pub fn new() -> Self {
+        Self::with_settings(PoolSettings::from_env())
+    }
+
+    pub fn with_settings(settings: PoolSettings) -> Self {
         // postgresql://matt@localhost/brooks
         let mut config = Config::new();
         config.dbname = Some("brooks".to_string());
         config.user = Some("matt".to_string());
+        // a connection Postgres closed is found out before a request gets it
         config.manager = Some(ManagerConfig {
-            recycling_method: RecyclingMethod::Fast,
+            recycling_method: RecyclingMethod::Verified,
         });
+        let mut pool = PoolConfig::new(settings.max_size);
+        pool.timeouts = Timeouts {
+            wait: Some(settings.wait),
+            create: Some(settings.connect),
+            recycle: Some(settings.connect),
+        };
+        config.pool = Some(pool);
         let pool = config.create_pool(Some(Runtime::Tokio1), NoTls).unwrap();
-        TodoDB { pool }
+        TodoDB { pool, settings }
     }
 
-    pub async fn get_by_token(&self, token: &str) -> Option<UserInfo> {
-        let con = self.pool.get().await.unwrap();
-        let sql = "SELECT id, username, token FROM users WHERE token = $1 LIMIT 1";
-        let result = con.query(sql, &[&token.to_string()]).await;
-        if let Ok(r) = result {
-            if let Some(user_row) = r.first() {
-                return Some(UserInfo {
-                    id: user_row.get("id"),
-                    username: user_row.get("username"),
-                    token: user_row.get("token"),
-                });
+    // a connection from the pool, or an error when Postgres can't be reached.
+    // Only getting the connection is retried, a query may not be safe to repeat.
+    pub(crate) async fn connection(&self) -> Result<Client, TodoDBError> {
+        let mut backoff = self.settings.backoff;
+        let mut retries = self.settings.retries;
+        loop {
+            match self.pool.get().await {
+                Ok(con) => return Ok(con),
+                Err(e) if retries > 0 && is_transient(&e) => {
+                    tracing::warn!(
+                        error = %e,
+                        retry_in_ms = backoff.as_millis() as u64,
+                        "getting a database connection failed"
+                    );
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                    retries -= 1;
+                }
+                Err(e) => return Err(e.into()),
             }
         }
-        None
     }
 
-    pub async fn authenticate(&self, req: &HttpRequest) -> Option<UserInfo> {
-        let token = req.headers().get("x-auth-token");
-        if let Some(t) = token {
-            if let Some(token_string) = t.to_str().ok() {
-                return self.get_by_token(token_string).await;
-            }
+    pub fn pool_status(&self) -> PoolStatus {
+        let status = self.pool.status();
+        // deadpool counts the requests waiting as negative available ones
+        PoolStatus {
+            max_size: status.max_size,
+            size: status.size,
+            available: status.available.max(0) as usize,
+            waiting: (-status.available).max(0) as usize,
         }
-        None
     }
+
+    #[instrument(level = "debug", skip_all)]
+    pub async fn get_by_token(&self, token: &str) -> Result<Option<UserInfo>, TodoDBError> {
+        let con = self.connection().await?;
+        let sql = "SELECT id, username, token FROM users WHERE token = $1 AND deleted_at IS NULL LIMIT 1";
+        let rows = con.query(sql, &[&token.to_string()]).await?;
+        Ok(rows.first().map(|user_row| UserInfo {
+            id: user_row.get("id"),
+            username: user_row.get("username"),
+            token: user_row.get("token"),
+        }))
+    }
+
+    // the user behind a session token or a personal API token, and what the
+    // token may do. Sessions may do anything.
+    #[instrument(level = "debug", skip_all)]
+    pub async fn credentials(&self, token: &str) -> Result<Option<Credentials>, TodoDBError> {
+        if !token.starts_with(TOKEN_PREFIX) {
+            let user = self.get_by_token(token).await?;
+            return Ok(user.map(|user| Credentials {
+                user,
+                scope: TokenScope::ReadWrite,
+                api_token: false,
+            }));
+        }
+        let (id, username, scope) = match self.use_api_token(&hash_token(token)).await? {
+            Some(found) => found,
+            None => return Ok(None),
+        };
+        Ok(Some(Credentials {
+            user: UserInfo {
+                id,
+                username,
+                token: token.to_string(),
+            },
+            scope,
+            api_token: true,
+        }))
+    }
+}
+
+/// The token of a request, from `Authorization: Bearer` or else `x-auth-token`.
+pub fn request_token(req: &HttpRequest) -> Option<&str> {
+    bearer_token(req).or_else(|| session_header(req))
+}
+
+fn session_header(req: &HttpRequest) -> Option<&str> {
+    req.headers().get("x-auth-token")?.to_str().ok()
+}
+
+fn bearer_token(req: &HttpRequest) -> Option<&str> {
+    let value = req.headers().get(AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, token) = value.split_once(' ')?;
+    scheme.eq_ignore_ascii_case("bearer").then(|| token.trim())
 }
@@ -0,0 +1,162 @@
+use crate::database::user_queries::{create_user_error, insert_user};
+use crate::database::{TodoDB, TodoDBError, UserId};
+use crate::routes::users::UserInfo;
+use crate::routes::TodoAppError;
+use tokio_postgres::error::SqlState;
+use tracing::instrument;
+
+/// What an SSO login remembers while the browser is at the identity provider.
+pub struct OidcLogin {
+    pub code_verifier: String,
+    pub nonce: String,
+    // the user linking their account, None for a login
+    pub user_id: Option<UserId>,
+}
+
+/// The user an identity logs in as.
+pub struct IdentityUser {
+    pub id: UserId,
+    pub username: String,
+    // false once the user is disabled or deleted
+    pub active: bool,
+}
+
+/// The identity of somebody's first SSO login, for the user made for it.
+pub struct NewIdentity<'a> {
+    pub issuer: &'a str,
+    pub subject: &'a str,
+    // only one the identity provider vouched for
+    pub email: Option<&'a str>,
+}
+
+impl TodoDB {
+    #[instrument(level = "debug", skip_all)]
+    pub async fn insert_oidc_login(
+        &self,
+        state_hash: &str,
+        login: &OidcLogin,
+        ttl_minutes: i32,
+    ) -> Result<(), TodoDBError> {
+        let con = self.connection().await?;
+        let sql = r#"
+            INSERT INTO oidc_logins (state_hash, code_verifier, nonce, user_id, expires_at)
+            VALUES ($1, $2, $3, $4, NOW() + $5 * INTERVAL '1 minute')
+            "#;
+        let ttl_minutes = f64::from(ttl_minutes);
+        con.execute(
+            sql,
+            &[
+                &state_hash,
+                &login.code_verifier,
+                &login.nonce,
+                &login.user_id,
+                &ttl_minutes,
+            ],
+        )
+        .await?;
+        Ok(())
+    }
+
+    // uses up the login, None when the state is unknown, used or expired
+    #[instrument(level = "debug", skip_all)]
+    pub async fn take_oidc_login(
+        &self,
+        state_hash: &str,
+    ) -> Result<Option<OidcLogin>, TodoDBError> {
+        let con = self.connection().await?;
+        let sql = r#"
+            UPDATE oidc_logins SET used_at = NOW()
+            WHERE state_hash = $1 AND used_at IS NULL AND expires_at > NOW()
+            RETURNING code_verifier, nonce, user_id
+            "#;
+        let rows = con.query(sql, &[&state_hash]).await?;
+        Ok(rows.first().map(|row| OidcLogin {
+            code_verifier: row.get("code_verifier"),
+            nonce: row.get("nonce"),
+            user_id: row.get("user_id"),
+        }))
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    pub async fn get_identity_user(
+        &self,
+        issuer: &str,
+        subject: &str,
+    ) -> Result<Option<IdentityUser>, TodoDBError> {
+        let con = self.connection().await?;
+        let sql = r#"
+            SELECT u.id, u.username, u.deleted_at IS NULL AS active
+            FROM user_identities i JOIN users u ON u.id = i.user_id
+            WHERE i.issuer = $1 AND i.subject = $2
+            "#;
+        let rows = con.query(sql, &[&issuer, &subject]).await?;
+        Ok(rows.first().map(|row| IdentityUser {
+            id: row.get("id"),
+            username: row.get("username"),
+            active: row.get("active"),
+        }))
+    }
+
+    // false when the identity belongs to a user already
+    #[instrument(level = "debug", skip_all)]
+    pub async fn link_identity(
+        &self,
+        user_id: UserId,
+        issuer: &str,
+        subject: &str,
+    ) -> Result<bool, TodoDBError> {
+        let con = self.connection().await?;
+        let sql = "INSERT INTO user_identities (user_id, issuer, subject) VALUES ($1, $2, $3)";
+        match con.execute(sql, &[&user_id, &issuer, &subject]).await {
+            Ok(row_count) => Ok(row_count == 1),
+            Err(e) if e.code() == Some(&SqlState::UNIQUE_VIOLATION) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // create_user for a first SSO login, linked to the identity in the same
+    // transaction so no account is left without one. None, with nothing
+    // created, when a login racing this one linked the identity first. An
+    // email another account has is left out, like update_user does.
+    #[instrument(level = "debug", skip_all)]
+    pub async fn create_sso_user(
+        &self,
+        username: &str,
+        password: &str,
+        token: &str,
+        locale: &str,
+        identity: &NewIdentity<'_>,
+    ) -> Result<Option<UserInfo>, TodoAppError> {
+        let mut con = self.connection().await?;
+        let mut transaction = con.transaction().await.map_err(create_user_error)?;
+        let id = insert_user(&transaction, username, password, token, locale)
+            .await
+            .map_err(create_user_error)?;
+        if let Some(email) = identity.email {
+            let savepoint = transaction.transaction().await.map_err(TodoDBError::from)?;
+            let sql = "UPDATE users SET email = $2 WHERE id = $1";
+            match savepoint.execute(sql, &[&id, &email]).await {
+                Ok(_) => savepoint.commit().await.map_err(TodoDBError::from)?,
+                Err(e) if e.code() == Some(&SqlState::UNIQUE_VIOLATION) => {
+                    savepoint.rollback().await.map_err(TodoDBError::from)?
+                }
+                Err(e) => return Err(TodoDBError::from(e).into()),
+            }
+        }
+        let sql = "INSERT INTO user_identities (user_id, issuer, subject) VALUES ($1, $2, $3)";
+        match transaction
+            .execute(sql, &[&id, &identity.issuer, &identity.subject])
+            .await
+        {
+            Ok(_) => {}
+            Err(e) if e.code() == Some(&SqlState::UNIQUE_VIOLATION) => return Ok(None),
+            Err(e) => return Err(TodoDBError::from(e).into()),
+        }
+        transaction.commit().await.map_err(TodoDBError::from)?;
+        Ok(Some(UserInfo {
+            id,
+            username: username.to_string(),
+            token: token.to_string(),
+        }))
+    }
+}
@@ -0,0 +1,63 @@
+use crate::database::{TodoDB, TodoDBError, UserId};
+use tracing::instrument;
+
+impl TodoDB {
+    // false when no user who can log in has that email
+    #[instrument(level = "debug", skip_all)]
+    pub async fn insert_password_reset(
+        &self,
+        email: &str,
+        token_hash: &str,
+        ttl_minutes: i32,
+    ) -> Result<bool, TodoDBError> {
+        let con = self.connection().await?;
+        let sql = r#"
+            INSERT INTO password_resets (user_id, token_hash, expires_at)
+            SELECT id, $2, NOW() + $3 * INTERVAL '1 minute' FROM users
+            WHERE email = $1 AND deleted_at IS NULL
+            "#;
+        let ttl_minutes = f64::from(ttl_minutes);
+        Ok(con
+            .execute(sql, &[&email, &token_hash, &ttl_minutes])
+            .await?
+            == 1)
+    }
+
+    // uses up the reset and every other one the user still had, and logs them
+    // out, all or nothing. false when the token is unknown, used or expired.
+    #[instrument(level = "debug", skip_all)]
+    pub async fn reset_password(
+        &self,
+        token_hash: &str,
+        hashed_password: &str,
+    ) -> Result<bool, TodoDBError> {
+        let mut con = self.connection().await?;
+        let transaction = con.transaction().await?;
+        let sql = r#"
+            UPDATE password_resets SET used_at = NOW()
+            WHERE token_hash = $1 AND used_at IS NULL AND expires_at > NOW()
+            RETURNING user_id
+            "#;
+        let rows = transaction.query(sql, &[&token_hash]).await?;
+        let user_id: UserId = match rows.first() {
+            Some(row) => row.get("user_id"),
+            None => return Ok(false),
+        };
+        let sql = r#"
+            UPDATE users SET password = $2, token = NULL
+            WHERE id = $1 AND deleted_at IS NULL
+            "#;
+        if transaction
+            .execute(sql, &[&user_id, &hashed_password])
+            .await?
+            != 1
+        {
+            return Ok(false);
+        }
+        let sql =
+            "UPDATE password_resets SET used_at = NOW() WHERE user_id = $1 AND used_at IS NULL";
+        transaction.execute(sql, &[&user_id]).await?;
+        transaction.commit().await?;
+        Ok(true)
+    }
+}
@@ -1,40 +1,50 @@
-use crate::database::{TaskId, TodoDB, UserId};
-use crate::routes::tasks::{CreateTaskRequest, Task, TaskInfo};
+use crate::database::{TaskId, TodoDB, TodoDBError, UserId};
+use crate::routes::tasks::{CreateTaskRequest, Task, TaskInfo, UpdateTaskRequest};
 use chrono::NaiveDateTime;
+use tracing::instrument;
+
+/// Outcome of a write that may be guarded by the version a client sent in `If-Match`.
+pub enum VersionedWrite<T> {
+    Written(T),
+    /// The task exists but has moved on; carries the current copy so the client can resolve it.
+    Conflict(TaskInfo),
+    NotFound,
+}
 
 impl TodoDB {
-    pub async fn insert_task(&self, task: &CreateTaskRequest, user_id: UserId) -> Option<TaskInfo> {
-        let con = self.pool.get().await.unwrap();
-        let sql = "INSERT INTO tasks (title, description, user_id) VALUES ($1, $2, $3) RETURNING id, priority, title, completed_at, description";
-        let err = con
-            .query(sql, &[&task.title, &task.description.clone(), &user_id])
-            .await;
-        if err.is_err() {
-            return None;
-        }
-        let query_result = err.ok().unwrap();
-        if let Some(row) = query_result.first() {
-            return Some(TaskInfo {
-                id: row.get("id"),
-                priority: row.get("priority"),
-                title: row.get("title"),
-                completed_at: row.get("completed_at"),
-                description: row.get("description"),
-            });
-        }
-        None
+    #[instrument(level = "debug", skip_all)]
+    pub async fn insert_task(
+        &self,
+        task: &CreateTaskRequest,
+        user_id: UserId,
+    ) -> Result<TaskInfo, TodoDBError> {
+        let con = self.connection().await?;
+        let sql = "INSERT INTO tasks (title, description, priority, user_id) VALUES ($1, $2, $3, $4) RETURNING id, priority, title, completed_at, description, version";
+        let row = con
+            .query_one(
+                sql,
+                &[&task.title, &task.description, &task.priority, &user_id],
+            )
+            .await?;
+        Ok(TaskInfo {
+            id: row.get("id"),
+            priority: row.get("priority"),
+            title: row.get("title"),
+            completed_at: row.get("completed_at"),
+            description: row.get("description"),
+            version: row.get("version"),
+        })
     }
 
-    pub async fn get_all_tasks(&self, user_id: UserId) -> Option<Vec<TaskInfo>> {
-        let con = self.pool.get().await.unwrap();
+    #[instrument(level = "debug", skip_all)]
+    pub async fn get_all_tasks(
+        &self,
+        user_id: UserId,
+    ) -> Result<Vec<TaskInfo>, TodoDBError> {
+        let con = self.connection().await?;
         let sql =
-            "SELECT completed_at, description, id, priority, title FROM tasks WHERE user_id = $1";
-        let err = con.query(sql, &[&user_id]).await;
-        if err.is_err() {
-            return None;
-        }
-
-        let query_result = err.ok().unwrap();
+            "SELECT completed_at, description, id, priority, title, version FROM tasks WHERE user_id = $1 AND deleted_at IS NULL";
+        let query_result = con.query(sql, &[&user_id]).await?;
         let mut results = vec![];
         for row in query_result {
             results.push(TaskInfo {
@@ -43,27 +53,23 @@ impl TodoDB {
                 title: row.get("title"),
                 completed_at: row.get("completed_at"),
                 description: row.get("description"),
+                version: row.get("version"),
             });
         }
-        Some(results)
+        Ok(results)
     }
 
-    pub async fn get_task(&self, user_id: UserId, task_id: TaskId) -> Option<Task> {
-        let con = self.pool.get().await.unwrap();
+    #[instrument(level = "debug", skip_all)]
+    pub async fn get_task(
+        &self,
+        user_id: UserId,
+        task_id: TaskId,
+    ) -> Result<Option<Task>, TodoDBError> {
+        let con = self.connection().await?;
         let sql = "SELECT * FROM tasks WHERE user_id = $1 AND id = $2";
-        let err = con.query(sql, &[&user_id, &task_id]).await;
-        if err.is_err() {
-            let e = err.err().unwrap();
-            if let Some(db_err) = e.as_db_error() {
-                println!("db_get_task error {}", db_err.message().to_string());
-            } else {
-                println!("where is the error");
-            }
-            return None;
-        }
-        let query_result = err.ok().unwrap();
+        let query_result = con.query(sql, &[&user_id, &task_id]).await?;
         if let Some(row) = query_result.first() {
-            return Some(Task {
+            return Ok(Some(Task {
                 id: row.get("id"),
                 priority: row.get("priority"),
                 title: row.get("title"),
@@ -72,18 +78,29 @@ impl TodoDB {
                 deleted_at: row.get("deleted_at"),
                 user_id: row.get("user_id"),
                 is_default: row.get("is_default"),
-            });
+                version: row.get("version"),
+            }));
         }
-        None
+        Ok(None)
     }
 
-    pub async fn mark_completed(&self, user_id: UserId, task_id: TaskId) -> bool {
+    #[instrument(level = "debug", skip_all)]
+    pub async fn mark_completed(
+        &self,
+        user_id: UserId,
+        task_id: TaskId,
+    ) -> Result<Option<TaskInfo>, TodoDBError> {
         let completed = Some(chrono::Local::now().naive_local());
         self.update_completed_status(user_id, task_id, completed)
             .await
     }
 
-    pub async fn mark_uncompleted(&self, user_id: UserId, task_id: TaskId) -> bool {
+    #[instrument(level = "debug", skip_all)]
+    pub async fn mark_uncompleted(
+        &self,
+        user_id: UserId,
+        task_id: TaskId,
+    ) -> Result<Option<TaskInfo>, TodoDBError> {
         self.update_completed_status(user_id, task_id, None).await
     }
 
@@ -92,117 +109,113 @@ impl TodoDB {
         user_id: UserId,
         task_id: TaskId,
         completed_at: Option<NaiveDateTime>,
-    ) -> bool {
-        let con = self.pool.get().await.unwrap();
-        let sql = "UPDATE tasks SET completed_at = $1 WHERE user_id = $2 AND id = $3 AND deleted_at = NULL";
-        let err = con.query(sql, &[&completed_at, &user_id, &task_id]).await;
-        if err.is_err() {
-            let e = err.err().unwrap();
-            if let Some(db_err) = e.as_db_error() {
-                println!("db_get_task error {}", db_err.message().to_string());
-            } else {
-                println!("where is the error");
-            }
-            return false;
-        }
-        true
+    ) -> Result<Option<TaskInfo>, TodoDBError> {
+        let con = self.connection().await?;
+        let sql = r#"
+            UPDATE tasks
+            SET (completed_at, version) = ($1, version + 1)
+            WHERE user_id = $2 AND id = $3 AND deleted_at IS NULL
+            RETURNING id, priority, title, completed_at, description, version
+            "#;
+        let rows = con.query(sql, &[&completed_at, &user_id, &task_id]).await?;
+        Ok(rows.first().map(|row| TaskInfo {
+            id: row.get("id"),
+            priority: row.get("priority"),
+            title: row.get("title"),
+            completed_at: row.get("completed_at"),
+            description: row.get("description"),
+            version: row.get("version"),
+        }))
     }
 
-    pub async fn update_task(&self, task: &TaskInfo, user_id: UserId) -> Option<TaskInfo> {
-        let con = self.pool.get().await.unwrap();
+    // only the fields present in the request are written, a null priority or
+    // description empties the column
+    #[instrument(level = "debug", skip_all)]
+    pub async fn update_task(
+        &self,
+        task_id: TaskId,
+        task: &UpdateTaskRequest,
+        user_id: UserId,
+        expected_version: Option<i32>,
+    ) -> Result<VersionedWrite<TaskInfo>, TodoDBError> {
+        let con = self.connection().await?;
         let sql = r#"
             UPDATE tasks 
-            SET (completed_at, priority, title, description) = ($1, $2, $3, $4) 
-            WHERE id = $5 AND deleted_at is NULL AND user_id = $6
-            RETURNING id, priority, title, completed_at, description
+            SET (completed_at, priority, title, description, version) = (
+                CASE WHEN $1 THEN $2::TIMESTAMP ELSE completed_at END,
+                CASE WHEN $3 THEN $4::VARCHAR ELSE priority END,
+                COALESCE($5, title),
+                CASE WHEN $6 THEN $7::TEXT ELSE description END,
+                version + 1)
+            WHERE id = $8 AND deleted_at is NULL AND user_id = $9
+                AND ($10::INTEGER IS NULL OR version = $10)
+            RETURNING id, priority, title, completed_at, description, version
             "#;
-        let err = con
+        let query_result = con
             .query(
                 sql,
                 &[
-                    &task.completed_at,
-                    &task.priority,
+                    &task.completed_at.is_some(),
+                    &task.completed_at.flatten(),
+                    &task.priority.is_some(),
+                    &task.priority.clone().flatten(),
                     &task.title,
-                    &task.description,
-                    &task.id,
+                    &task.description.is_some(),
+                    &task.description.clone().flatten(),
+                    &task_id,
                     &user_id,
+                    &expected_version,
                 ],
             )
-            .await;
-        if err.is_err() {
-            let e = err.err().unwrap();
-            if let Some(db_err) = e.as_db_error() {
-                println!("db_get_task error {}", db_err.message().to_string());
-            } else {
-                println!("where is the error");
-            }
-            return None;
-        }
-
-        let query_result = err.ok().unwrap();
-        if query_result.len() == 0 {
-            println!("so returning is not returning anything!!!!");
-        }
-
+            .await?;
         if let Some(row) = query_result.first() {
-            Some(TaskInfo {
+            return Ok(VersionedWrite::Written(TaskInfo {
                 id: row.get("id"),
                 priority: row.get("priority"),
                 title: row.get("title"),
                 completed_at: row.get("completed_at"),
                 description: row.get("description"),
-            })
-        } else {
-            None
+                version: row.get("version"),
+            }));
         }
+        self.missed_write(user_id, task_id).await
     }
 
-    pub async fn soft_delete_task(&self, user_id: UserId, task_id: TaskId) -> bool {
-        let con = self.pool.get().await.unwrap();
+    #[instrument(level = "debug", skip_all)]
+    pub async fn soft_delete_task(
+        &self,
+        user_id: UserId,
+        task_id: TaskId,
+        expected_version: Option<i32>,
+    ) -> Result<VersionedWrite<()>, TodoDBError> {
+        let con = self.connection().await?;
         let sql = r#"
             UPDATE tasks 
-            SET (deleted_at) = ($1) 
+            SET (deleted_at, version) = ($1, version + 1) 
             WHERE id = $2 AND user_id = $3 AND deleted_at is NULL
+                AND ($4::INTEGER IS NULL OR version = $4)
             "#;
         let time = chrono::Utc::now().naive_local();
-        let err = con.query(sql, &[&time, &task_id, &user_id]).await;
-        if err.is_err() {
-            let e = err.err().unwrap();
-            if let Some(db_err) = e.as_db_error() {
-                println!("db_get_task error {}", db_err.message().to_string());
-            } else {
-                println!("where is the error");
-            }
-            return false;
+        let deleted = con
+            .execute(sql, &[&time, &task_id, &user_id, &expected_version])
+            .await?;
+        if deleted == 1 {
+            return Ok(VersionedWrite::Written(()));
         }
-        true
+        self.missed_write(user_id, task_id).await
     }
 
-    pub async fn get_default_tasks(&self) -> Option<Vec<TaskInfo>> {
-        // return db.select().from("tasks").where({ is_default: true });
-        let con = self.pool.get().await.unwrap();
-        let sql = r#" SELECT * FROM tasks WHERE is_default is true"#;
-        let err = con.query(sql, &[]).await;
-        if err.is_err() {
-            let e = err.err().unwrap();
-            if let Some(db_err) = e.as_db_error() {
-                println!("db_get_task error {}", db_err.message().to_string());
-            } else {
-                println!("where is the error");
+    // a guarded write touched no rows, work out whether the task is gone or just newer
+    async fn missed_write<T>(
+        &self,
+        user_id: UserId,
+        task_id: TaskId,
+    ) -> Result<VersionedWrite<T>, TodoDBError> {
+        Ok(match self.get_task(user_id, task_id).await? {
+            Some(current) if current.deleted_at.is_none() => {
+                VersionedWrite::Conflict(TaskInfo::from(current))
             }
-            return None;
-        }
-        let query_result = err.ok().unwrap();
-        let mut tasks = vec![];
-        for row in query_result {
-            tasks.push(TaskInfo {
-                id: row.get("id"),
-                priority: row.get("priority"),
-                title: row.get("title"),
-                completed_at: row.get("completed_at"),
-                description: row.get("description"),
-            });
-        }
-        Some(tasks)
+            _ => VersionedWrite::NotFound,
+        })
     }
 }
@@ -0,0 +1,119 @@
+use crate::database::{TodoDB, TodoDBError};
+use todo_api::{CreateTemplateRequest, TaskTemplate, TemplateId, UpdateTemplateRequest};
+use tokio_postgres::Row;
+use tracing::instrument;
+
+// the locale of the templates that used to be the is_default tasks
+pub const DEFAULT_LOCALE: &str = "en";
+
+fn template(row: &Row) -> TaskTemplate {
+    TaskTemplate {
+        id: row.get("id"),
+        locale: row.get("locale"),
+        position: row.get("position"),
+        title: row.get("title"),
+        description: row.get("description"),
+        priority: row.get("priority"),
+    }
+}
+
+impl TodoDB {
+    // all of them when locale is None
+    #[instrument(level = "debug", skip_all)]
+    pub async fn get_templates(
+        &self,
+        locale: Option<&str>,
+    ) -> Result<Vec<TaskTemplate>, TodoDBError> {
+        let con = self.connection().await?;
+        let sql = r#"
+            SELECT * FROM task_templates WHERE $1::TEXT IS NULL OR locale = $1
+            ORDER BY locale, position, id
+            "#;
+        let rows = con.query(sql, &[&locale]).await?;
+        Ok(rows.iter().map(template).collect())
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    pub async fn get_template(&self, id: TemplateId) -> Result<Option<TaskTemplate>, TodoDBError> {
+        let con = self.connection().await?;
+        let sql = "SELECT * FROM task_templates WHERE id = $1";
+        let rows = con.query(sql, &[&id]).await?;
+        Ok(rows.first().map(template))
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    pub async fn template_locales(&self) -> Result<Vec<String>, TodoDBError> {
+        let con = self.connection().await?;
+        let sql = "SELECT DISTINCT locale FROM task_templates ORDER BY locale";
+        let rows = con.query(sql, &[]).await?;
+        Ok(rows.iter().map(|row| row.get("locale")).collect())
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    pub async fn insert_template(
+        &self,
+        request: &CreateTemplateRequest,
+    ) -> Result<TaskTemplate, TodoDBError> {
+        let con = self.connection().await?;
+        let sql = r#"
+            INSERT INTO task_templates (locale, position, title, description, priority)
+            VALUES ($1::VARCHAR, COALESCE($2, (SELECT COALESCE(MAX(position) + 1, 0) FROM task_templates WHERE locale = $1::VARCHAR)),
+                $3, $4, $5)
+            RETURNING *
+            "#;
+        let locale = request.locale.as_deref().unwrap_or(DEFAULT_LOCALE);
+        let row = con
+            .query_one(
+                sql,
+                &[
+                    &locale,
+                    &request.position,
+                    &request.title,
+                    &request.description,
+                    &request.priority,
+                ],
+            )
+            .await?;
+        Ok(template(&row))
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    pub async fn update_template(
+        &self,
+        id: TemplateId,
+        request: &UpdateTemplateRequest,
+    ) -> Result<Option<TaskTemplate>, TodoDBError> {
+        let con = self.connection().await?;
+        let sql = r#"
+            UPDATE task_templates SET
+                title = COALESCE($2, title),
+                description = COALESCE($3, description),
+                priority = COALESCE($4, priority),
+                locale = COALESCE($5, locale),
+                position = COALESCE($6, position)
+            WHERE id = $1
+            RETURNING *
+            "#;
+        let rows = con
+            .query(
+                sql,
+                &[
+                    &id,
+                    &request.title,
+                    &request.description,
+                    &request.priority,
+                    &request.locale,
+                    &request.position,
+                ],
+            )
+            .await?;
+        Ok(rows.first().map(template))
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    pub async fn delete_template(&self, id: TemplateId) -> Result<bool, TodoDBError> {
+        let con = self.connection().await?;
+        let sql = "DELETE FROM task_templates WHERE id = $1";
+        Ok(con.execute(sql, &[&id]).await? == 1)
+    }
+}
@@ -0,0 +1,186 @@
+use crate::database::{TodoDB, TodoDBError, UserId};
+use chrono::NaiveDateTime;
+use tracing::instrument;
+
+/// Where a user is with two-factor authentication.
+pub struct TwoFactor {
+    // set once enrolment has started, even if it was never confirmed
+    pub secret: Option<String>,
+    pub enabled_at: Option<NaiveDateTime>,
+}
+
+impl TwoFactor {
+    pub fn enabled(&self) -> bool {
+        self.enabled_at.is_some()
+    }
+}
+
+impl TodoDB {
+    #[instrument(level = "debug", skip_all)]
+    pub async fn get_two_factor(&self, user_id: UserId) -> Result<Option<TwoFactor>, TodoDBError> {
+        let con = self.connection().await?;
+        let sql = "SELECT totp_secret, totp_enabled_at FROM users WHERE id = $1";
+        let rows = con.query(sql, &[&user_id]).await?;
+        Ok(rows.first().map(|row| TwoFactor {
+            secret: row.get("totp_secret"),
+            enabled_at: row.get("totp_enabled_at"),
+        }))
+    }
+
+    // a new secret replaces one that was never confirmed, false once 2FA is on
+    #[instrument(level = "debug", skip_all)]
+    pub async fn start_two_factor(
+        &self,
+        user_id: UserId,
+        secret: &str,
+    ) -> Result<bool, TodoDBError> {
+        let con = self.connection().await?;
+        let sql = r#"
+            UPDATE users SET totp_secret = $2, totp_last_step = NULL
+            WHERE id = $1 AND totp_enabled_at IS NULL AND deleted_at IS NULL
+            "#;
+        Ok(con.execute(sql, &[&user_id, &secret]).await? == 1)
+    }
+
+    // turns 2FA on with the step of the code that confirmed it and replaces any
+    // recovery codes, all or nothing. false when enrolment wasn't started or is done.
+    #[instrument(level = "debug", skip_all)]
+    pub async fn enable_two_factor(
+        &self,
+        user_id: UserId,
+        step: i64,
+        recovery_code_hashes: &[String],
+    ) -> Result<bool, TodoDBError> {
+        let mut con = self.connection().await?;
+        let transaction = con.transaction().await?;
+        let sql = r#"
+            UPDATE users SET totp_enabled_at = NOW(), totp_last_step = $2
+            WHERE id = $1 AND totp_secret IS NOT NULL AND totp_enabled_at IS NULL
+            "#;
+        if transaction.execute(sql, &[&user_id, &step]).await? != 1 {
+            return Ok(false);
+        }
+        transaction
+            .execute("DELETE FROM recovery_codes WHERE user_id = $1", &[&user_id])
+            .await?;
+        let sql = "INSERT INTO recovery_codes (user_id, code_hash) VALUES ($1, $2)";
+        for code_hash in recovery_code_hashes {
+            transaction.execute(sql, &[&user_id, code_hash]).await?;
+        }
+        transaction.commit().await?;
+        Ok(true)
+    }
+
+    // false when 2FA was off already
+    #[instrument(level = "debug", skip_all)]
+    pub async fn disable_two_factor(&self, user_id: UserId) -> Result<bool, TodoDBError> {
+        let mut con = self.connection().await?;
+        let transaction = con.transaction().await?;
+        let sql = r#"
+            UPDATE users SET totp_secret = NULL, totp_enabled_at = NULL, totp_last_step = NULL
+            WHERE id = $1 AND totp_secret IS NOT NULL
+            "#;
+        let changed = transaction.execute(sql, &[&user_id]).await? == 1;
+        transaction
+            .execute("DELETE FROM recovery_codes WHERE user_id = $1", &[&user_id])
+            .await?;
+        transaction
+            .execute(
+                "UPDATE two_factor_challenges SET used_at = NOW() WHERE user_id = $1 AND used_at IS NULL",
+                &[&user_id],
+            )
+            .await?;
+        transaction.commit().await?;
+        Ok(changed)
+    }
+
+    // false when a code for this step or a later one was used already
+    #[instrument(level = "debug", skip_all)]
+    pub async fn use_totp_step(&self, user_id: UserId, step: i64) -> Result<bool, TodoDBError> {
+        let con = self.connection().await?;
+        let sql = r#"
+            UPDATE users SET totp_last_step = $2
+            WHERE id = $1 AND (totp_last_step IS NULL OR totp_last_step < $2)
+            "#;
+        Ok(con.execute(sql, &[&user_id, &step]).await? == 1)
+    }
+
+    // false when the code is wrong or was used already
+    #[instrument(level = "debug", skip_all)]
+    pub async fn use_recovery_code(
+        &self,
+        user_id: UserId,
+        code_hash: &str,
+    ) -> Result<bool, TodoDBError> {
+        let con = self.connection().await?;
+        let sql = r#"
+            UPDATE recovery_codes SET used_at = NOW()
+            WHERE id = (
+                SELECT id FROM recovery_codes
+                WHERE user_id = $1 AND code_hash = $2 AND used_at IS NULL
+                LIMIT 1
+            )
+            "#;
+        Ok(con.execute(sql, &[&user_id, &code_hash]).await? == 1)
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    pub async fn unused_recovery_codes(&self, user_id: UserId) -> Result<i64, TodoDBError> {
+        let con = self.connection().await?;
+        let sql =
+            "SELECT COUNT(*) AS count FROM recovery_codes WHERE user_id = $1 AND used_at IS NULL";
+        let row = con.query_one(sql, &[&user_id]).await?;
+        Ok(row.get("count"))
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    pub async fn insert_two_factor_challenge(
+        &self,
+        user_id: UserId,
+        token_hash: &str,
+        ttl_minutes: i32,
+    ) -> Result<(), TodoDBError> {
+        let con = self.connection().await?;
+        let sql = r#"
+            INSERT INTO two_factor_challenges (user_id, token_hash, expires_at)
+            VALUES ($1, $2, NOW() + $3 * INTERVAL '1 minute')
+            "#;
+        let ttl_minutes = f64::from(ttl_minutes);
+        con.execute(sql, &[&user_id, &token_hash, &ttl_minutes])
+            .await?;
+        Ok(())
+    }
+
+    // the id and username of the user a challenge that is still open belongs
+    // to. Each lookup counts as an attempt, after `max_attempts` the password
+    // has to be given again.
+    #[instrument(level = "debug", skip_all)]
+    pub async fn attempt_two_factor_challenge(
+        &self,
+        token_hash: &str,
+        max_attempts: i32,
+    ) -> Result<Option<(UserId, String)>, TodoDBError> {
+        let con = self.connection().await?;
+        let sql = r#"
+            UPDATE two_factor_challenges c SET attempts = c.attempts + 1
+            FROM users u
+            WHERE c.user_id = u.id AND c.token_hash = $1 AND c.used_at IS NULL
+              AND c.expires_at > NOW() AND c.attempts < $2
+              AND u.deleted_at IS NULL
+            RETURNING u.id, u.username
+            "#;
+        let rows = con.query(sql, &[&token_hash, &max_attempts]).await?;
+        Ok(rows.first().map(|row| (row.get("id"), row.get("username"))))
+    }
+
+    // false when it was used in the meantime
+    #[instrument(level = "debug", skip_all)]
+    pub async fn finish_two_factor_challenge(&self, token_hash: &str) -> Result<bool, TodoDBError> {
+        let con = self.connection().await?;
+        let sql = r#"
+            UPDATE two_factor_challenges SET used_at = NOW()
+            WHERE token_hash = $1 AND used_at IS NULL
+            "#;
+        Ok(con.execute(sql, &[&token_hash]).await? == 1)
+    }
+}
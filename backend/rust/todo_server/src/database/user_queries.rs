@@ -6,120 +6,197 @@ addTokenToUser(token, userId) {
 // /Users/matt/external_code/BrooksYew/brooks-full-stack/backend/nodejs/express/database/userQueries.js
 // /Users/matt/Documents/Programming/rust/postgres-test/src/main.rs
 
-use crate::database::{TodoDB, UserId};
+use crate::database::{TodoDB, TodoDBError, UserId};
 use crate::routes::users::{User, UserInfo};
 use crate::routes::TodoAppError;
+use actix_web::http::StatusCode;
+use actix_web::ResponseError;
+use tokio_postgres::error::SqlState;
+use tokio_postgres::Transaction;
+use tracing::instrument;
 
 impl TodoDB {
-    // hash password and store username, token, and hashed password in db
-    // get back unique user id from db and return UserCreatedInfo
+    // store username, token, and the already hashed password, and give the new
+    // user a task for every template of their locale. It all happens in one
+    // transaction so a failed insert can't leave a half seeded account behind.
+    #[instrument(level = "debug", skip_all)]
     pub async fn create_user(
         &self,
         username: &str,
         password: &str,
         token: &str,
+        locale: &str,
     ) -> Result<UserInfo, TodoAppError> {
-        let con = self.pool.get().await.unwrap();
-        let sql = "INSERT INTO users (username, password, token) VALUES ($1, $2, $3)";
-        let err = con
-            .execute(
-                sql,
-                &[
-                    &username.to_string(),
-                    &password.to_string(),
-                    &token.to_string(),
-                ],
-            )
-            .await;
+        let mut con = self.connection().await?;
+        let transaction = con.transaction().await.map_err(create_user_error)?;
+        let id = insert_user(&transaction, username, password, token, locale)
+            .await
+            .map_err(create_user_error)?;
+        transaction.commit().await.map_err(create_user_error)?;
 
-        if err.is_err() {
-            let e = err.err().unwrap();
-            if let Some(db_err) = e.as_db_error() {
-                return Err(TodoAppError {
-                    name: db_err.message().to_string(),
-                });
-            } else {
-                return Err(TodoAppError {
-                    name: "error from db_create_user".to_string(),
-                });
-            }
-        }
-
-        // database needs to return the id key after insertion
-        let sql = "SELECT id FROM users WHERE username = $1";
-        let err = con.query(sql, &[&username.to_string()]).await;
-
-        if err.is_err() {
-            let e = err.err().unwrap();
-            if let Some(db_err) = e.as_db_error() {
-                return Err(TodoAppError {
-                    name: db_err.message().to_string(),
-                });
-            } else {
-                return Err(TodoAppError {
-                    name: "error from db_create_user trying to get id".to_string(),
-                });
-            }
-        }
-
-        let query_result = err.unwrap();
-        if let Some(row) = query_result.first() {
-            let result = UserInfo {
-                id: row.get("id"),
-                username: username.to_string(),
-                token: token.to_string(),
-            };
-            Ok(result)
-        } else {
-            Err(TodoAppError {
-                name: "error from db_create_user trying to get id no first row".to_string(),
-            })
-        }
+        Ok(UserInfo {
+            id,
+            username: username.to_string(),
+            token: token.to_string(),
+        })
     }
 
-    pub async fn get_by_username(&self, username: &str) -> Option<User> {
-        let con = self.pool.get().await.unwrap();
+    #[instrument(level = "debug", skip_all)]
+    pub async fn get_by_username(&self, username: &str) -> Result<Option<User>, TodoDBError> {
+        let con = self.connection().await?;
         let sql = "SELECT * FROM users WHERE username = $1 LIMIT 1";
-        let result = con.query(sql, &[&username.to_string()]).await;
-        if let Ok(r) = result {
-            if let Some(user_row) = r.first() {
-                return Some(User {
-                    id: user_row.get("id"),
-                    username: user_row.get("username"), 
-                    password: user_row.get("password"),
-                    deleted_at: user_row.get("deleted_at"), 
-                    token: user_row.get("token"),
-                });
-            }
-        }
-        None
+        let rows = con.query(sql, &[&username.to_string()]).await?;
+        Ok(rows.first().map(|user_row| User {
+            id: user_row.get("id"),
+            username: user_row.get("username"),
+            password: user_row.get("password"),
+            deleted_at: user_row.get("deleted_at"),
+            // NULL once the user has logged out
+            token: user_row
+                .get::<_, Option<String>>("token")
+                .unwrap_or_default(),
+        }))
     }
 
+    #[instrument(level = "debug", skip_all)]
     pub async fn find_and_remove_token(&self, token: &str) -> Result<u64, TodoAppError> {
-        let con = self.pool.get().await.unwrap();
+        let con = self.connection().await?;
         let sql = "UPDATE users SET token = NULL WHERE token = $1";
         let result = con.execute(sql, &[&token.to_string()]).await;
         match result {
             Ok(row_count) => Ok(row_count),
             Err(_) => Err(TodoAppError {
                 name: "problems setting token to null".to_string(),
+                status: StatusCode::INTERNAL_SERVER_ERROR,
             }),
         }
     }
 
+    #[instrument(level = "debug", skip_all)]
     pub async fn add_token_to_user(
         &self,
         token: &str,
         user_id: UserId,
     ) -> Result<(), TodoAppError> {
-        let con = self.pool.get().await.unwrap();
+        let con = self.connection().await?;
         let sql = "UPDATE users SET token = $1 WHERE id = $2";
         let result = con.execute(sql, &[&token.to_string(), &user_id]).await;
         if result.is_err() {
             return Err(TodoAppError {
                 name: "problems setting token".to_string(),
+                status: StatusCode::INTERNAL_SERVER_ERROR,
             });
         }
         Ok(())
     }
+
+    // the new username and email together or neither, false when the name is
+    // taken (deleted users keep theirs). Some(None) removes the email, and an
+    // email another account has is left as it was without saying so, or this
+    // would tell whose it is.
+    #[instrument(level = "debug", skip_all)]
+    pub async fn update_user(
+        &self,
+        user_id: UserId,
+        username: Option<&str>,
+        email: Option<Option<&str>>,
+    ) -> Result<bool, TodoDBError> {
+        let mut con = self.connection().await?;
+        let mut transaction = con.transaction().await?;
+        if let Some(username) = username {
+            let sql = "UPDATE users SET username = $2 WHERE id = $1 AND deleted_at IS NULL";
+            match transaction.execute(sql, &[&user_id, &username]).await {
+                Ok(1) => {}
+                Ok(_) => return Ok(false),
+                Err(e) if e.code() == Some(&SqlState::UNIQUE_VIOLATION) => return Ok(false),
+                Err(e) => return Err(e.into()),
+            }
+        }
+        if let Some(email) = email {
+            // a savepoint, so a taken email doesn't undo the username
+            let savepoint = transaction.transaction().await?;
+            let sql = "UPDATE users SET email = $2 WHERE id = $1 AND deleted_at IS NULL";
+            match savepoint.execute(sql, &[&user_id, &email]).await {
+                Ok(_) => savepoint.commit().await?,
+                Err(e) if e.code() == Some(&SqlState::UNIQUE_VIOLATION) => {
+                    savepoint.rollback().await?
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+        transaction.commit().await?;
+        Ok(true)
+    }
+
+    // None removes the email, false when somebody else has it
+    #[instrument(level = "debug", skip_all)]
+    pub async fn change_email(
+        &self,
+        user_id: UserId,
+        email: Option<&str>,
+    ) -> Result<bool, TodoDBError> {
+        let con = self.connection().await?;
+        let sql = "UPDATE users SET email = $2 WHERE id = $1 AND deleted_at IS NULL";
+        match con.execute(sql, &[&user_id, &email]).await {
+            Ok(row_count) => Ok(row_count == 1),
+            Err(e) if e.code() == Some(&SqlState::UNIQUE_VIOLATION) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // takes an already hashed password, the new token replaces the user's only
+    // session so whoever had the old one is logged out
+    #[instrument(level = "debug", skip_all)]
+    pub async fn change_password(
+        &self,
+        user_id: UserId,
+        hashed_password: &str,
+        token: &str,
+    ) -> Result<bool, TodoDBError> {
+        let con = self.connection().await?;
+        let sql = "UPDATE users SET password = $2, token = $3 WHERE id = $1 AND deleted_at IS NULL";
+        Ok(con.execute(sql, &[&user_id, &hashed_password, &token]).await? == 1)
+    }
+}
+
+// a 409 when the username is taken
+// the user and their seeded tasks, for create_user and the others that make
+// accounts in a transaction of their own
+pub(super) async fn insert_user(
+    transaction: &Transaction<'_>,
+    username: &str,
+    password: &str,
+    token: &str,
+    locale: &str,
+) -> Result<UserId, tokio_postgres::Error> {
+    let sql = "INSERT INTO users (username, password, token) VALUES ($1, $2, $3) RETURNING id";
+    let rows = transaction
+        .query(sql, &[&username, &password, &token])
+        .await?;
+    let id: UserId = rows[0].get("id");
+
+    let sql = r#"
+        INSERT INTO tasks (title, description, priority, user_id)
+        SELECT title, description, priority, $1 FROM task_templates
+        WHERE locale = $2 ORDER BY position, id
+        "#;
+    transaction.execute(sql, &[&id, &locale]).await?;
+    Ok(id)
+}
+
+pub(super) fn create_user_error(e: tokio_postgres::Error) -> TodoAppError {
+    if e.code() == Some(&SqlState::UNIQUE_VIOLATION) {
+        return TodoAppError {
+            name: "the username is taken".to_string(),
+            status: StatusCode::CONFLICT,
+        };
+    }
+    let name = match e.as_db_error() {
+        Some(db_err) => db_err.message().to_string(),
+        None => "error from db_create_user".to_string(),
+    };
+    TodoAppError {
+        name,
+        status: TodoDBError::from(e).status_code(),
+    }
 }
@@ -0,0 +1,70 @@
+use crate::database::event_queries::EventId;
+use crate::database::{TodoDB, UserId};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+// one week, override with TASK_EVENT_RETENTION_SECS
+const DEFAULT_RETENTION_SECS: i64 = 7 * 24 * 60 * 60;
+
+pub use todo_api::TaskEvent;
+
+/// An event with its position in the user's persisted sequence, if storing it worked.
+pub type SequencedEvent = (Option<EventId>, TaskEvent);
+
+/// Fans task events out to the connections subscribed for a user.
+#[derive(Default)]
+pub struct TaskEvents {
+    subscribers: Mutex<HashMap<UserId, Vec<UnboundedSender<SequencedEvent>>>>,
+    closed: AtomicBool,
+}
+
+impl TaskEvents {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn subscribe(&self, user_id: UserId) -> UnboundedReceiver<SequencedEvent> {
+        let (sender, receiver) = unbounded_channel();
+        let mut subscribers = self.subscribers.lock().unwrap();
+        // after close the sender is dropped here and the subscription ends at once
+        if !self.closed.load(Ordering::SeqCst) {
+            subscribers.entry(user_id).or_default().push(sender);
+        }
+        receiver
+    }
+
+    /// Ends every subscription, and any made later, so the WebSockets and event
+    /// streams finish and the server can stop. Clients reconnect to the next one.
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        self.subscribers.lock().unwrap().clear();
+    }
+
+    pub async fn publish(&self, db: &TodoDB, user_id: UserId, event: TaskEvent) {
+        let id = db.insert_task_event(user_id, &event).await;
+        self.send(user_id, (id, event));
+    }
+
+    /// Hands an event that has been stored already to the user's subscribers.
+    /// Two publishes for a user can get here in the other order than their ids.
+    pub fn send(&self, user_id: UserId, (id, event): SequencedEvent) {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        if let Some(senders) = subscribers.get_mut(&user_id) {
+            // a failed send means that connection has gone away
+            senders.retain(|sender| sender.send((id, event.clone())).is_ok());
+            if senders.is_empty() {
+                subscribers.remove(&user_id);
+            }
+        }
+    }
+}
+
+/// How long events are kept for replays, housekeeping purges older ones.
+pub fn retention_secs() -> i64 {
+    dotenv::var("TASK_EVENT_RETENTION_SECS")
+        .ok()
+        .and_then(|secs| secs.parse().ok())
+        .unwrap_or(DEFAULT_RETENTION_SECS)
+}
@@ -0,0 +1,150 @@
+// Probes for whatever runs the server, outside /api/v1 like /metrics:
+//
+// GET /healthz answers 200 as long as the process can answer at all.
+// GET /readyz answers 200 when a pooled connection to Postgres runs a query and
+// the database has every table and column database/init.sql makes, 503 with
+// the failing checks otherwise. Anybody can ask, so why a check fails only goes
+// to the log.
+//
+// curl localhost:3010/readyz
+// {"status":"ok","checks":{"database":{"status":"ok","latency_ms":2},"schema":{"status":"ok"}}}
+//
+// main.rs also waits for the database before it starts listening, see
+// wait_for_database.
+
+use crate::database::TodoDB;
+use actix_web::{web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+// a probe that hangs is no better than one that fails
+const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+const DEFAULT_STARTUP_WAIT: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Ok,
+    Failing,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Check {
+    pub status: Status,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Check {
+    fn failing(error: String) -> Self {
+        Check {
+            status: Status::Failing,
+            latency_ms: None,
+            error: Some(error),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Health {
+    pub status: Status,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub checks: BTreeMap<String, Check>,
+}
+
+pub async fn healthz() -> HttpResponse {
+    HttpResponse::Ok().json(Health {
+        status: Status::Ok,
+        checks: BTreeMap::new(),
+    })
+}
+
+pub async fn readyz(db: web::Data<TodoDB>) -> HttpResponse {
+    let mut health = readiness(&db).await;
+    // Postgres' errors name hosts, users and tables, not for the whole internet
+    for (name, check) in health.checks.iter_mut() {
+        if let Some(error) = check.error.take() {
+            tracing::warn!(check = %name, %error, "readiness check failing");
+            check.error = Some("see the server log".to_string());
+        }
+    }
+    match health.status {
+        Status::Ok => HttpResponse::Ok().json(health),
+        Status::Failing => HttpResponse::ServiceUnavailable().json(health),
+    }
+}
+
+/// Runs the checks /readyz answers with.
+pub async fn readiness(db: &TodoDB) -> Health {
+    let mut checks = BTreeMap::new();
+    let started = Instant::now();
+    let database = match tokio::time::timeout(CHECK_TIMEOUT, db.ping()).await {
+        Ok(Ok(())) => Check {
+            status: Status::Ok,
+            latency_ms: Some(started.elapsed().as_millis() as u64),
+            error: None,
+        },
+        Ok(Err(e)) => Check::failing(e.to_string()),
+        Err(_) => Check::failing(format!("no answer in {:?}", CHECK_TIMEOUT)),
+    };
+    // without a connection the schema can't be looked at either
+    let schema = if database.status == Status::Ok {
+        match tokio::time::timeout(CHECK_TIMEOUT, db.missing_columns()).await {
+            Ok(Ok(missing)) if missing.is_empty() => Check {
+                status: Status::Ok,
+                latency_ms: None,
+                error: None,
+            },
+            Ok(Ok(missing)) => Check::failing(format!(
+                "missing {}, run database/init.sql again, it only adds what's missing",
+                missing.join(", ")
+            )),
+            Ok(Err(e)) => Check::failing(e.to_string()),
+            Err(_) => Check::failing(format!("no answer in {:?}", CHECK_TIMEOUT)),
+        }
+    } else {
+        Check::failing("no database connection".to_string())
+    };
+    checks.insert("database".to_string(), database);
+    checks.insert("schema".to_string(), schema);
+    let status = if checks.values().all(|check| check.status == Status::Ok) {
+        Status::Ok
+    } else {
+        Status::Failing
+    };
+    Health { status, checks }
+}
+
+/// Checks the database until it's ready, for DB_STARTUP_WAIT_SECS (30 by
+/// default) while it can't be reached, so the server and Postgres can start
+/// together. A database without the schema won't get one by waiting.
+pub async fn wait_for_database(db: &TodoDB) -> Result<(), String> {
+    let wait = dotenv::var("DB_STARTUP_WAIT_SECS")
+        .ok()
+        .and_then(|secs| secs.parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_STARTUP_WAIT);
+    let started = Instant::now();
+    loop {
+        let health = readiness(db).await;
+        let failing = |name: &str| {
+            let check = &health.checks[name];
+            (check.status == Status::Failing).then(|| check.error.clone().unwrap_or_default())
+        };
+        if let Some(error) = failing("database") {
+            if started.elapsed() >= wait {
+                return Err(format!("can't reach the database: {}", error));
+            }
+            tracing::warn!(%error, "waiting for the database");
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            continue;
+        }
+        return match failing("schema") {
+            Some(error) => Err(format!("the database isn't set up: {}", error)),
+            None => Ok(()),
+        };
+    }
+}
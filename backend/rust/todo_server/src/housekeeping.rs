@@ -0,0 +1,35 @@
+// Deletes what the database only keeps for a while, once an hour in the
+// background instead of on every request that adds to it: idempotency keys past
+// IDEMPOTENCY_KEY_RETENTION_SECS and task events past TASK_EVENT_RETENTION_SECS.
+
+use crate::database::TodoDB;
+use crate::events;
+use crate::middleware::idempotency;
+use actix_web::rt;
+use std::time::Duration;
+
+const PURGE_EVERY: Duration = Duration::from_secs(60 * 60);
+
+/// Purges now and then every hour, for as long as the server runs.
+pub async fn purge_regularly(db: actix_web::web::Data<TodoDB>) {
+    let mut interval = rt::time::interval(PURGE_EVERY);
+    loop {
+        interval.tick().await;
+        purge(&db).await;
+    }
+}
+
+/// One round of purging, a failure is left for the next one.
+pub async fn purge(db: &TodoDB) {
+    match db
+        .purge_idempotency_keys(idempotency::retention_secs())
+        .await
+    {
+        Ok(purged) => tracing::debug!(purged, "purged idempotency keys"),
+        Err(e) => tracing::warn!(error = %e, "purging idempotency keys failed"),
+    }
+    match db.purge_task_events(events::retention_secs()).await {
+        Ok(purged) => tracing::debug!(purged, "purged task events"),
+        Err(e) => tracing::warn!(error = %e, "purging task events failed"),
+    }
+}
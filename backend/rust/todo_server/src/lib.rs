@@ -1,4 +1,16 @@
+pub mod admin;
 pub mod routes;
 pub mod database;
+pub mod events;
+pub mod health;
+pub mod housekeeping;
+pub mod mailer;
+pub mod metrics;
+pub mod middleware;
+pub mod oidc;
+pub mod passwords;
+pub mod shutdown;
+pub mod telemetry;
+pub mod totp;
 
 
@@ -0,0 +1,248 @@
+// Sending email, so far only password reset links. main.rs picks the Mailer
+// from the environment:
+//
+//     SMTP_HOST=mail.example.com SMTP_PORT=587 SMTP_USERNAME=todo SMTP_PASSWORD=... MAIL_FROM=todo@example.com
+//         speaks SMTP to a relay, upgraded with STARTTLS before anything else
+//         is sent, or not at all. SMTP_STARTTLS=off is for a relay on the same
+//         host only and can't be used with a username and password.
+//         SMTP_TIMEOUT_SECS (10 by default) is how long sending one email gets.
+//     MAIL_DIR=/tmp/todo-mail
+//         writes every email to a file in that directory instead
+//     MAIL_STDOUT=on
+//         prints every email to stdout, reset links and all, for development
+//     none of them
+//         the server doesn't start, rather than lose the emails
+
+use async_trait::async_trait;
+use lettre::message::header::ContentType;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::transport::smtp::client::{Tls, TlsParameters};
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+use todo_api::validation::email_problem;
+
+const DEFAULT_FROM: &str = "todo@localhost";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+#[derive(Error, Debug)]
+pub enum MailError {
+    #[error("could not send email: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("not sending to {0}")]
+    BadAddress(String),
+    #[error("could not send email: {0}")]
+    Smtp(#[from] lettre::transport::smtp::Error),
+    #[error("could not write the email: {0}")]
+    Message(#[from] lettre::error::Error),
+    #[error("the mail server took longer than {0:?}")]
+    TimedOut(Duration),
+    #[error("{0}")]
+    Settings(String),
+}
+
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, email: &Email) -> Result<(), MailError>;
+}
+
+// the address goes into the headers and the SMTP commands as it is, so a line
+// break in it would let whoever typed it add their own
+fn check_address(address: &str) -> Result<(), MailError> {
+    match email_problem(address) {
+        Some(problem) => Err(MailError::BadAddress(problem)),
+        None => Ok(()),
+    }
+}
+
+/// The Mailer the environment asks for, see the top of this file.
+pub fn from_env() -> Result<Box<dyn Mailer>, MailError> {
+    let from = dotenv::var("MAIL_FROM").unwrap_or_else(|_| DEFAULT_FROM.to_string());
+    if let Ok(host) = dotenv::var("SMTP_HOST") {
+        let settings = SmtpSettings::from_env(host, from);
+        return Ok(Box::new(SmtpMailer::new(settings)?));
+    }
+    if let Ok(dir) = dotenv::var("MAIL_DIR") {
+        return Ok(Box::new(FileMailer {
+            dir: Some(PathBuf::from(dir)),
+            from,
+        }));
+    }
+    if dotenv::var("MAIL_STDOUT").is_ok_and(|stdout| stdout == "on") {
+        tracing::warn!(
+            "MAIL_STDOUT=on, emails go to stdout and anyone who reads the logs can reset passwords"
+        );
+        return Ok(Box::new(FileMailer { dir: None, from }));
+    }
+    Err(MailError::Settings(
+        "nowhere to send emails, set SMTP_HOST, MAIL_DIR or MAIL_STDOUT=on".to_string(),
+    ))
+}
+
+/// Writes each email to its own file in `dir`, or to stdout without one. For
+/// development and tests, which read the reset links back out of the files.
+pub struct FileMailer {
+    pub dir: Option<PathBuf>,
+    pub from: String,
+}
+
+impl FileMailer {
+    pub fn new(dir: Option<PathBuf>) -> Self {
+        FileMailer {
+            dir,
+            from: DEFAULT_FROM.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl Mailer for FileMailer {
+    async fn send(&self, email: &Email) -> Result<(), MailError> {
+        check_address(&email.to)?;
+        let message = format!(
+            "From: {}\nTo: {}\nSubject: {}\n\n{}\n",
+            self.from, email.to, email.subject, email.body
+        );
+        match &self.dir {
+            Some(dir) => {
+                std::fs::create_dir_all(dir)?;
+                let nanos = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_nanos();
+                let to: String = email
+                    .to
+                    .chars()
+                    .map(|c| {
+                        if c.is_alphanumeric() || "@.-".contains(c) {
+                            c
+                        } else {
+                            '_'
+                        }
+                    })
+                    .collect();
+                std::fs::write(dir.join(format!("{}-{}.eml", nanos, to)), message)?;
+            }
+            None => println!("{}", message),
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SmtpSettings {
+    pub host: String,
+    pub port: u16,
+    pub credentials: Option<(String, String)>,
+    pub from: String,
+    /// Off only for a relay on the same host, see the top of this file.
+    pub starttls: bool,
+    pub timeout: Duration,
+}
+
+impl Default for SmtpSettings {
+    fn default() -> Self {
+        SmtpSettings {
+            host: "localhost".to_string(),
+            port: 587,
+            credentials: None,
+            from: DEFAULT_FROM.to_string(),
+            starttls: true,
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+impl SmtpSettings {
+    pub fn from_env(host: String, from: String) -> Self {
+        let default = SmtpSettings::default();
+        let credentials = match (dotenv::var("SMTP_USERNAME"), dotenv::var("SMTP_PASSWORD")) {
+            (Ok(username), Ok(password)) => Some((username, password)),
+            _ => None,
+        };
+        SmtpSettings {
+            host,
+            port: dotenv::var("SMTP_PORT")
+                .ok()
+                .and_then(|port| port.parse().ok())
+                .unwrap_or(default.port),
+            credentials,
+            from,
+            starttls: dotenv::var("SMTP_STARTTLS").map_or(true, |starttls| starttls != "off"),
+            timeout: dotenv::var("SMTP_TIMEOUT_SECS")
+                .ok()
+                .and_then(|secs| secs.parse().ok())
+                .map(Duration::from_secs)
+                .unwrap_or(default.timeout),
+        }
+    }
+}
+
+/// SMTP through lettre, STARTTLS required unless the settings turn it off.
+pub struct SmtpMailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+    timeout: Duration,
+}
+
+impl SmtpMailer {
+    /// Refuses to send a password in the clear, and a From that isn't an address.
+    pub fn new(settings: SmtpSettings) -> Result<Self, MailError> {
+        check_address(&settings.from)?;
+        let tls = if settings.starttls {
+            Tls::Required(TlsParameters::new(settings.host.clone())?)
+        } else if settings.credentials.is_some() {
+            return Err(MailError::Settings(
+                "SMTP_USERNAME and SMTP_PASSWORD need STARTTLS, they'd go over the network in \
+                 the clear"
+                    .to_string(),
+            ));
+        } else {
+            Tls::None
+        };
+        let mut builder = AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&settings.host)
+            .port(settings.port)
+            .tls(tls)
+            .timeout(Some(settings.timeout));
+        if let Some((username, password)) = settings.credentials {
+            builder = builder.credentials(Credentials::new(username, password));
+        }
+        Ok(SmtpMailer {
+            transport: builder.build(),
+            from: settings.from.parse().map_err(|_| {
+                MailError::BadAddress(format!("{} is not an email address", settings.from))
+            })?,
+            timeout: settings.timeout,
+        })
+    }
+}
+
+#[async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(&self, email: &Email) -> Result<(), MailError> {
+        check_address(&email.to)?;
+        let to: Mailbox = email
+            .to
+            .parse()
+            .map_err(|_| MailError::BadAddress(format!("{} is not an email address", email.to)))?;
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(to)
+            .subject(email.subject.as_str())
+            .header(ContentType::TEXT_PLAIN)
+            .body(email.body.clone())?;
+        // the transport's timeout is for each command, this is for the whole email
+        match tokio::time::timeout(self.timeout, self.transport.send(message)).await {
+            Ok(sent) => sent.map(|_| ()).map_err(MailError::from),
+            Err(_) => Err(MailError::TimedOut(self.timeout)),
+        }
+    }
+}
@@ -1,32 +1,106 @@
 use actix_web::{App, HttpServer, web};
-use todo_server::routes::users as user;
-use todo_server::routes::tasks as task;
+use todo_server::admin::{self, AdminError};
+use todo_server::routes;
+use todo_server::events::TaskEvents;
+use todo_server::health::{healthz, readyz, wait_for_database};
+use todo_server::housekeeping;
+use todo_server::mailer::{self, Mailer};
+use todo_server::metrics::{serve_metrics, Metrics};
+use todo_server::middleware::rate_limit::RateLimiter;
+use todo_server::oidc::Oidc;
+use todo_server::{shutdown, telemetry};
+use std::sync::Arc;
 
 use todo_server::database::TodoDB;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     let db = TodoDB::new();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.split_first() {
+        None => {}
+        Some((command, rest)) if command == "admin" => {
+            let result = match admin::parse(rest) {
+                Ok(command) => admin::run(&db, command).await,
+                Err(error) => Err(error),
+            };
+            match result {
+                Ok(()) => return Ok(()),
+                Err(AdminError::Usage(message)) => {
+                    eprintln!("todo_server: {}\n\n{}", message, admin::USAGE);
+                    std::process::exit(2);
+                }
+                Err(error) => {
+                    eprintln!("todo_server: {}", error);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Some((command, _)) if command == "--help" || command == "-h" => {
+            print!("{}", admin::USAGE);
+            return Ok(());
+        }
+        Some(_) => {
+            eprint!("{}", admin::USAGE);
+            std::process::exit(2);
+        }
+    }
+    telemetry::init();
+    // fail here rather than on the first request
+    if let Err(error) = wait_for_database(&db).await {
+        tracing::error!(%error, "not starting");
+        std::process::exit(1);
+    }
     let data = web::Data::new(db);
-    HttpServer::new(move || {
-        App::new()
+    actix_web::rt::spawn(housekeeping::purge_regularly(data.clone()));
+    let task_events = web::Data::new(TaskEvents::new());
+    // shared by the workers, or each would count on its own
+    let rate_limiter = web::Data::new(RateLimiter::from_env());
+    let metrics = web::Data::new(Metrics::from_env());
+    if !metrics.enabled() {
+        tracing::info!("METRICS_TOKEN isn't set, /metrics is off");
+    }
+    let mailer: web::Data<dyn Mailer> = match mailer::from_env() {
+        Ok(mailer) => web::Data::from(Arc::from(mailer)),
+        Err(error) => {
+            tracing::error!(%error, "not starting");
+            std::process::exit(1);
+        }
+    };
+    // SSO logins only when OIDC_ISSUER and OIDC_CLIENT_ID say where to
+    let oidc = Oidc::from_env().map(web::Data::new);
+    let open_events = task_events.clone();
+    let server = HttpServer::new(move || {
+        let mut app = App::new()
             .app_data(data.clone())
-            .service( web::scope("/api/v1")
-            .route("/users", web::post().to(user::create_user))
-            .route("/users/login", web::post().to(user::login))
-            .route("/users/logout", web::post().to(user::logout))
-            .route("/tasks", web::post().to(task::create_task))
-            .route("/tasks", web::get().to(task::get_all_tasks))
-            .route("/tasks/{id}", web::get().to(task::get_task_id))
-            .route("/tasks/{id}", web::patch().to(task::update_task))
-            .route("/tasks/{id}", web::delete().to(task::delete_task))
-            .route("/tasks/{id}/completed", web::put().to(task::set_task_completed))
-            .route("/tasks/{id}/uncompleted", web::put().to(task::set_task_uncompleted))
-        )
+            .app_data(task_events.clone())
+            .app_data(rate_limiter.clone())
+            .app_data(metrics.clone())
+            .app_data(mailer.clone())
+            .route("/metrics", web::get().to(serve_metrics))
+            .route("/healthz", web::get().to(healthz))
+            .route("/readyz", web::get().to(readyz));
+        if let Some(oidc) = &oidc {
+            app = app.app_data(oidc.clone());
+        }
+        app.configure(routes::configure)
     })
     .bind(("127.0.0.1", 3010))?
-    .run()
-    .await
+    // the signals are handled below, the event streams have to end first
+    .disable_signals()
+    .shutdown_timeout(shutdown::timeout().as_secs());
+    tracing::info!(addresses = ?server.addrs(), "listening");
+    let server = server.run();
+    let handle = server.handle();
+    actix_web::rt::spawn(async move {
+        shutdown::requested().await;
+        tracing::info!("shutting down, finishing the requests in flight");
+        open_events.close();
+        handle.stop(true).await;
+    });
+    server.await?;
+    tracing::info!("stopped");
+    Ok(())
 }
 
 // /Users/matt/Documents/Programming/rust/postgres-test/src/main.rs
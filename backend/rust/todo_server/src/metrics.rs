@@ -0,0 +1,289 @@
+// Prometheus metrics, scraped from GET /metrics (outside /api/v1, it's for the
+// monitoring, not the clients):
+//
+// todo_http_requests_total{method, route, status}
+// todo_http_request_duration_seconds{method, route, status}, a histogram
+// todo_logins_total{method, outcome}, method password, two_factor or sso and
+//     outcome success, failure, locked_out or two_factor (a code is needed)
+// todo_db_pool_max_size, todo_db_pool_size, todo_db_pool_available and
+//     todo_db_pool_waiting, the Postgres connection pool
+// todo_users{state}, active or deleted (disabled ones included)
+// todo_tasks{state}, open, completed or deleted
+// todo_logged_in_users
+//
+// The requests are counted by middleware/request_metrics.rs, the pool and the
+// users and tasks are looked at when Prometheus scrapes, the users and tasks at
+// most once a minute. Scrapes need `Authorization: Bearer` with METRICS_TOKEN,
+// without one set /metrics answers 404.
+
+use crate::database::TodoDB;
+use crate::routes::auth::hash_token;
+use actix_web::http::header::{AUTHORIZATION, CONTENT_TYPE, WWW_AUTHENTICATE};
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest, HttpResponse, HttpResponseBuilder};
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry,
+    TextEncoder,
+};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// counting users and tasks reads every row, scrapes in between get the last counts
+const COUNT_EVERY: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LoginMethod {
+    Password,
+    TwoFactor,
+    Sso,
+}
+
+impl LoginMethod {
+    fn as_str(&self) -> &'static str {
+        match self {
+            LoginMethod::Password => "password",
+            LoginMethod::TwoFactor => "two_factor",
+            LoginMethod::Sso => "sso",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LoginOutcome {
+    Success,
+    Failure,
+    LockedOut,
+    /// The password was right, the login goes on with a two-factor code.
+    TwoFactor,
+}
+
+impl LoginOutcome {
+    fn as_str(&self) -> &'static str {
+        match self {
+            LoginOutcome::Success => "success",
+            LoginOutcome::Failure => "failure",
+            LoginOutcome::LockedOut => "locked_out",
+            LoginOutcome::TwoFactor => "two_factor",
+        }
+    }
+}
+
+pub struct Metrics {
+    registry: Registry,
+    requests: IntCounterVec,
+    request_duration: HistogramVec,
+    logins: IntCounterVec,
+    pool_max_size: IntGauge,
+    pool_size: IntGauge,
+    pool_available: IntGauge,
+    pool_waiting: IntGauge,
+    users: IntGaugeVec,
+    tasks: IntGaugeVec,
+    logged_in_users: IntGauge,
+    // hashed, so comparing it doesn't take longer the more of it a guess gets right
+    token_hash: Option<String>,
+    counted_at: Mutex<Option<Instant>>,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let registry = Registry::new();
+        let request_labels = ["method", "route", "status"];
+        let requests = IntCounterVec::new(
+            Opts::new("todo_http_requests_total", "HTTP requests answered"),
+            &request_labels,
+        )
+        .unwrap();
+        let request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "todo_http_request_duration_seconds",
+                "How long answering HTTP requests took",
+            ),
+            &request_labels,
+        )
+        .unwrap();
+        let logins = IntCounterVec::new(
+            Opts::new("todo_logins_total", "Login attempts"),
+            &["method", "outcome"],
+        )
+        .unwrap();
+        let gauge = |name: &str, help: &str| IntGauge::new(name, help).unwrap();
+        let pool_max_size = gauge("todo_db_pool_max_size", "Most connections the pool opens");
+        let pool_size = gauge("todo_db_pool_size", "Connections the pool has open");
+        let pool_available = gauge("todo_db_pool_available", "Idle connections in the pool");
+        let pool_waiting = gauge(
+            "todo_db_pool_waiting",
+            "Requests waiting for a connection from the pool",
+        );
+        let users = IntGaugeVec::new(Opts::new("todo_users", "Users"), &["state"]).unwrap();
+        let tasks = IntGaugeVec::new(Opts::new("todo_tasks", "Tasks"), &["state"]).unwrap();
+        let logged_in_users = gauge("todo_logged_in_users", "Users with a session token");
+
+        registry.register(Box::new(requests.clone())).unwrap();
+        registry
+            .register(Box::new(request_duration.clone()))
+            .unwrap();
+        registry.register(Box::new(logins.clone())).unwrap();
+        for gauge in [
+            &pool_max_size,
+            &pool_size,
+            &pool_available,
+            &pool_waiting,
+            &logged_in_users,
+        ] {
+            registry.register(Box::new(gauge.clone())).unwrap();
+        }
+        registry.register(Box::new(users.clone())).unwrap();
+        registry.register(Box::new(tasks.clone())).unwrap();
+        Metrics {
+            registry,
+            requests,
+            request_duration,
+            logins,
+            pool_max_size,
+            pool_size,
+            pool_available,
+            pool_waiting,
+            users,
+            tasks,
+            logged_in_users,
+            token_hash: None,
+            counted_at: Mutex::new(None),
+        }
+    }
+
+    /// With METRICS_TOKEN as the token scrapes have to send.
+    pub fn from_env() -> Self {
+        Self::new().with_token(dotenv::var("METRICS_TOKEN").ok())
+    }
+
+    /// Scrapes have to send `token` as a bearer token, with None or an empty
+    /// one /metrics is off.
+    pub fn with_token(mut self, token: Option<String>) -> Self {
+        self.token_hash = token
+            .filter(|token| !token.is_empty())
+            .map(|token| hash_token(&token));
+        self
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.token_hash.is_some()
+    }
+
+    fn allows(&self, req: &HttpRequest) -> bool {
+        let bearer = req
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        match (&self.token_hash, bearer) {
+            (Some(token_hash), Some(bearer)) => hash_token(bearer.trim()) == *token_hash,
+            _ => false,
+        }
+    }
+
+    // true at most once every COUNT_EVERY
+    fn count_due(&self) -> bool {
+        let mut counted_at = self.counted_at.lock().unwrap();
+        if counted_at.is_some_and(|at| at.elapsed() < COUNT_EVERY) {
+            return false;
+        }
+        *counted_at = Some(Instant::now());
+        true
+    }
+
+    pub fn observe_request(&self, method: &str, route: &str, status: u16, elapsed: Duration) {
+        let status = status.to_string();
+        let labels = [method, route, status.as_str()];
+        self.requests.with_label_values(&labels).inc();
+        self.request_duration
+            .with_label_values(&labels)
+            .observe(elapsed.as_secs_f64());
+    }
+
+    pub fn login(&self, method: LoginMethod, outcome: LoginOutcome) {
+        self.logins
+            .with_label_values(&[method.as_str(), outcome.as_str()])
+            .inc();
+    }
+
+    /// Looks at the pool and, unless they were counted less than a minute ago,
+    /// counts the users and tasks, then writes out everything in Prometheus'
+    /// text format.
+    pub async fn render(&self, db: &TodoDB) -> String {
+        let pool = db.pool_status();
+        self.pool_max_size.set(pool.max_size as i64);
+        self.pool_size.set(pool.size as i64);
+        self.pool_available.set(pool.available as i64);
+        self.pool_waiting.set(pool.waiting as i64);
+        if !self.count_due() {
+            return self.text();
+        }
+        // the last counts stay when the database can't be asked
+        match db.usage_stats().await {
+            Ok(stats) => {
+                self.users.with_label_values(&["active"]).set(stats.users);
+                self.users
+                    .with_label_values(&["deleted"])
+                    .set(stats.deleted_users);
+                let open_tasks = stats.tasks - stats.completed_tasks;
+                self.tasks.with_label_values(&["open"]).set(open_tasks);
+                self.tasks
+                    .with_label_values(&["completed"])
+                    .set(stats.completed_tasks);
+                self.tasks
+                    .with_label_values(&["deleted"])
+                    .set(stats.deleted_tasks);
+                self.logged_in_users.set(stats.logged_in_users);
+            }
+            Err(e) => tracing::warn!(error = %e, "counting users and tasks for metrics failed"),
+        }
+        self.text()
+    }
+
+    /// Everything as it is, in Prometheus' text format.
+    pub fn text(&self) -> String {
+        let mut text = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut text)
+            .unwrap();
+        String::from_utf8(text).unwrap()
+    }
+}
+
+/*
+# metrics
+## route: "/metrics" GET
+
+curl -H "Authorization: Bearer $METRICS_TOKEN" localhost:3010/metrics
+
+### response:
+# HELP todo_http_requests_total HTTP requests answered
+# TYPE todo_http_requests_total counter
+todo_http_requests_total{method="GET",route="/api/v1/tasks",status="200"} 12
+...
+*/
+
+pub async fn serve_metrics(
+    req: HttpRequest,
+    metrics: web::Data<Metrics>,
+    db: web::Data<TodoDB>,
+) -> HttpResponse {
+    if !metrics.enabled() {
+        return HttpResponse::NotFound().finish();
+    }
+    if !metrics.allows(&req) {
+        return HttpResponseBuilder::new(StatusCode::UNAUTHORIZED)
+            .insert_header((WWW_AUTHENTICATE, r#"Bearer realm="metrics""#))
+            .body("send METRICS_TOKEN as a bearer token");
+    }
+    HttpResponse::Ok()
+        .insert_header((CONTENT_TYPE, TextEncoder::new().format_type()))
+        .body(metrics.render(&db).await)
+}
@@ -0,0 +1,185 @@
+use crate::database::idempotency_queries::{IdempotencyClaim, StoredResponse};
+use crate::database::TodoDB;
+use crate::routes::auth::credentials;
+use actix_web::body::{to_bytes, BoxBody, MessageBody};
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue, CONTENT_TYPE, ETAG, LOCATION};
+use actix_web::http::{Method, StatusCode};
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpResponse, HttpResponseBuilder};
+use sha2::{Digest, Sha256};
+
+pub const IDEMPOTENCY_KEY: &str = "idempotency-key";
+const REPLAYED: &str = "idempotent-replayed";
+const MAX_KEY_LENGTH: usize = 255;
+// one day, override with IDEMPOTENCY_KEY_RETENTION_SECS
+const DEFAULT_RETENTION_SECS: i64 = 24 * 60 * 60;
+// a claim whose request never finished (the server died, say) is given up after
+// a minute, override with IDEMPOTENCY_KEY_LEASE_SECS
+const DEFAULT_LEASE_SECS: i64 = 60;
+
+/*
+Retried POST/PUT/PATCH/DELETE requests that carry the same `Idempotency-Key`
+header get the response of the first attempt back instead of running twice.
+Keys are scoped to the user sending them and forgotten after the retention window.
+Reusing a key for another method, path or body is a 422. Requests without a
+valid token (creating an account, logging in) aren't remembered at all, there's
+nobody to keep them apart by.
+*/
+pub async fn idempotency_keys(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let key = match req.headers().get(IDEMPOTENCY_KEY) {
+        Some(key) if is_mutating(req.method()) => key.to_str().ok().map(str::to_string),
+        _ => return Ok(next.call(req).await?.map_into_boxed_body()),
+    };
+    let key = match key {
+        Some(key) if !key.is_empty() && key.len() <= MAX_KEY_LENGTH => key,
+        _ => {
+            let response = HttpResponseBuilder::new(StatusCode::BAD_REQUEST)
+                .body("Idempotency-Key must be 1 to 255 visible characters");
+            return Ok(req.into_response(response));
+        }
+    };
+    let db = match req.app_data::<web::Data<TodoDB>>() {
+        Some(db) => db.clone(),
+        None => return Ok(next.call(req).await?.map_into_boxed_body()),
+    };
+
+    let user_id = match credentials(req.request()).await? {
+        Some(found) => found.user.id,
+        None => return Ok(next.call(req).await?.map_into_boxed_body()),
+    };
+    let method = req.method().to_string();
+    let path = req.path().to_string();
+    // the handler reads the body after this, so it's put back
+    let body = req.extract::<web::Bytes>().await?;
+    let fingerprint: String = Sha256::new()
+        .chain_update(&method)
+        .chain_update([0])
+        .chain_update(&path)
+        .chain_update([0])
+        .chain_update(&body)
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    req.set_payload(Payload::from(body));
+    let claim = db
+        .claim_idempotency_key(
+            user_id,
+            &key,
+            &method,
+            &path,
+            &fingerprint,
+            retention_secs(),
+            env_secs("IDEMPOTENCY_KEY_LEASE_SECS", DEFAULT_LEASE_SECS),
+        )
+        .await;
+
+    match claim {
+        Some(IdempotencyClaim::New) => {}
+        Some(IdempotencyClaim::Replay(stored)) => {
+            return Ok(req.into_response(replay(stored)));
+        }
+        Some(IdempotencyClaim::InProgress) => {
+            let response = HttpResponseBuilder::new(StatusCode::CONFLICT)
+                .body("a request with this Idempotency-Key is still being processed");
+            return Ok(req.into_response(response));
+        }
+        Some(IdempotencyClaim::Mismatch) => {
+            let response = HttpResponseBuilder::new(StatusCode::UNPROCESSABLE_ENTITY)
+                .body("this Idempotency-Key was already used for a different request");
+            return Ok(req.into_response(response));
+        }
+        None => {
+            let response = HttpResponseBuilder::new(StatusCode::SERVICE_UNAVAILABLE)
+                .body("could not check Idempotency-Key");
+            return Ok(req.into_response(response));
+        }
+    }
+
+    let response = match next.call(req).await {
+        Ok(response) => response,
+        Err(e) => {
+            db.release_idempotency_key(user_id, &key).await;
+            return Err(e);
+        }
+    };
+    let status = response.status();
+    let headers = remembered_headers(response.headers());
+    let (req, response) = response.into_parts();
+    let (head, body) = response.into_parts();
+    let body = match to_bytes(body).await {
+        Ok(body) => body,
+        Err(_) => {
+            db.release_idempotency_key(user_id, &key).await;
+            let response = HttpResponseBuilder::new(StatusCode::INTERNAL_SERVER_ERROR)
+                .body("could not read response body");
+            return Ok(ServiceResponse::new(req, response));
+        }
+    };
+
+    // a failure on our side is worth retrying for real
+    if status.is_server_error() {
+        db.release_idempotency_key(user_id, &key).await;
+    } else {
+        let stored = StoredResponse {
+            status_code: status.as_u16() as i32,
+            headers,
+            body: String::from_utf8_lossy(&body).to_string(),
+        };
+        db.save_idempotent_response(user_id, &key, &stored).await;
+    }
+
+    Ok(ServiceResponse::new(req, head.set_body(body).map_into_boxed_body()))
+}
+
+fn is_mutating(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::POST | Method::PUT | Method::PATCH | Method::DELETE
+    )
+}
+
+/// How long keys are remembered, housekeeping purges older ones.
+pub fn retention_secs() -> i64 {
+    env_secs("IDEMPOTENCY_KEY_RETENTION_SECS", DEFAULT_RETENTION_SECS)
+}
+
+fn env_secs(name: &str, default: i64) -> i64 {
+    dotenv::var(name)
+        .ok()
+        .and_then(|secs| secs.parse().ok())
+        .unwrap_or(default)
+}
+
+fn remembered_headers(headers: &actix_web::http::header::HeaderMap) -> Vec<(String, String)> {
+    [CONTENT_TYPE, ETAG, LOCATION]
+        .iter()
+        .filter_map(|name| {
+            let value = headers.get(name)?.to_str().ok()?;
+            Some((name.to_string(), value.to_string()))
+        })
+        .collect()
+}
+
+fn replay(stored: StoredResponse) -> HttpResponse {
+    let status = u16::try_from(stored.status_code)
+        .ok()
+        .and_then(|code| StatusCode::from_u16(code).ok())
+        .unwrap_or(StatusCode::OK);
+    let mut response = HttpResponseBuilder::new(status);
+    for (name, value) in stored.headers {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::try_from(name.as_str()),
+            HeaderValue::from_str(&value),
+        ) {
+            response.insert_header((name, value));
+        }
+    }
+    response
+        .insert_header((REPLAYED, "true"))
+        .body(stored.body)
+}
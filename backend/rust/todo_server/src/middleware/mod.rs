@@ -0,0 +1,5 @@
+pub mod idempotency;
+pub mod rate_limit;
+pub mod request_metrics;
+pub mod request_spans;
+pub mod scopes;
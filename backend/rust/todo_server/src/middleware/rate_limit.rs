@@ -0,0 +1,308 @@
+use crate::database::{request_token, UserId};
+use crate::routes::auth::{hash_token, looked_up_user};
+use crate::routes::openapi::API_PREFIX;
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::RETRY_AFTER;
+use actix_web::http::{Method, StatusCode};
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpResponse, HttpResponseBuilder};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// past this many buckets the full ones get forgotten, they'd start out full anyway
+const MAX_TRACKED: usize = 10_000;
+const MAX_LOCKOUT: Duration = Duration::from_secs(60 * 60);
+
+/*
+Token buckets per client IP for the routes that check a password or send mail,
+per username for logins, and per user for the rest of the API. Only a token
+that has worked before counts as its user's, any other goes by the client IP,
+or making up a new token for every request would get a new bucket every time. An empty bucket answers 429 with a Retry-After. Logins that keep failing
+also lock the username for a while, twice as long with every failure after
+LOGIN_LOCKOUT_AFTER, until one succeeds.
+
+The limits are requests per minute, 0 turns one off:
+RATE_LIMIT_LOGIN_PER_IP, RATE_LIMIT_LOGIN_PER_USERNAME, RATE_LIMIT_SIGNUP_PER_IP,
+RATE_LIMIT_API, LOGIN_LOCKOUT_AFTER (failures) and LOGIN_LOCKOUT_SECS. Behind a
+proxy set RATE_LIMIT_TRUST_FORWARDED=true, or everybody shares the proxy's IP.
+*/
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RateLimits {
+    pub login_per_ip: u32,
+    pub login_per_username: u32,
+    pub signup_per_ip: u32,
+    pub api: u32,
+    pub lockout_after: u32,
+    pub lockout: Duration,
+    pub trust_forwarded: bool,
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        RateLimits {
+            login_per_ip: 20,
+            login_per_username: 10,
+            signup_per_ip: 5,
+            api: 600,
+            lockout_after: 5,
+            lockout: Duration::from_secs(30),
+            trust_forwarded: false,
+        }
+    }
+}
+
+impl RateLimits {
+    pub fn from_env() -> Self {
+        let defaults = RateLimits::default();
+        RateLimits {
+            login_per_ip: env_number("RATE_LIMIT_LOGIN_PER_IP", defaults.login_per_ip),
+            login_per_username: env_number(
+                "RATE_LIMIT_LOGIN_PER_USERNAME",
+                defaults.login_per_username,
+            ),
+            signup_per_ip: env_number("RATE_LIMIT_SIGNUP_PER_IP", defaults.signup_per_ip),
+            api: env_number("RATE_LIMIT_API", defaults.api),
+            lockout_after: env_number("LOGIN_LOCKOUT_AFTER", defaults.lockout_after),
+            lockout: Duration::from_secs(
+                env_number("LOGIN_LOCKOUT_SECS", defaults.lockout.as_secs() as u32).into(),
+            ),
+            trust_forwarded: dotenv::var("RATE_LIMIT_TRUST_FORWARDED")
+                .map(|trust| trust == "true")
+                .unwrap_or(defaults.trust_forwarded),
+        }
+    }
+}
+
+fn env_number(name: &str, default: u32) -> u32 {
+    dotenv::var(name)
+        .ok()
+        .and_then(|number| number.parse().ok())
+        .unwrap_or(default)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Limit {
+    LoginPerIp,
+    LoginPerUsername,
+    SignupPerIp,
+    Api,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct TokenBucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    fn refill(&mut self, per_minute: u32, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        let capacity = f64::from(per_minute);
+        self.tokens = (self.tokens + elapsed * capacity / 60.0).min(capacity);
+        self.updated = now;
+    }
+
+    // a token, or how long until there is one
+    fn take(&mut self, per_minute: u32, now: Instant) -> Result<(), Duration> {
+        self.refill(per_minute, now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            return Ok(());
+        }
+        let wait = (1.0 - self.tokens) * 60.0 / f64::from(per_minute);
+        Err(Duration::from_secs_f64(wait))
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct FailedLogins {
+    count: u32,
+    last: Instant,
+    locked_until: Option<Instant>,
+}
+
+pub struct RateLimiter {
+    limits: RateLimits,
+    buckets: Mutex<HashMap<(Limit, String), TokenBucket>>,
+    failed_logins: Mutex<HashMap<String, FailedLogins>>,
+    // the SHA-256 of tokens that worked, and whose they are
+    known_tokens: Mutex<HashMap<String, UserId>>,
+}
+
+impl RateLimiter {
+    pub fn new(limits: RateLimits) -> Self {
+        RateLimiter {
+            limits,
+            buckets: Mutex::new(HashMap::new()),
+            failed_logins: Mutex::new(HashMap::new()),
+            known_tokens: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        RateLimiter::new(RateLimits::from_env())
+    }
+
+    fn per_minute(&self, limit: Limit) -> u32 {
+        match limit {
+            Limit::LoginPerIp => self.limits.login_per_ip,
+            Limit::LoginPerUsername => self.limits.login_per_username,
+            Limit::SignupPerIp => self.limits.signup_per_ip,
+            Limit::Api => self.limits.api,
+        }
+    }
+
+    /// Takes a token from the bucket of `key`, the error is how long to wait.
+    pub fn check(&self, limit: Limit, key: &str) -> Result<(), Duration> {
+        let per_minute = self.per_minute(limit);
+        if per_minute == 0 {
+            return Ok(());
+        }
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= MAX_TRACKED {
+            buckets.retain(|(limit, _), bucket| {
+                let per_minute = self.per_minute(*limit);
+                bucket.refill(per_minute, now);
+                bucket.tokens < f64::from(per_minute)
+            });
+        }
+        buckets
+            .entry((limit, key.to_string()))
+            .or_insert(TokenBucket {
+                tokens: f64::from(per_minute),
+                updated: now,
+            })
+            .take(per_minute, now)
+    }
+
+    /// Whether `username` may try a password now, the error is how long until
+    /// it may.
+    pub fn login_allowed(&self, username: &str) -> Result<(), Duration> {
+        let now = Instant::now();
+        let locked_until = self
+            .failed_logins
+            .lock()
+            .unwrap()
+            .get(username)
+            .and_then(|failed| failed.locked_until);
+        if let Some(locked_until) = locked_until.filter(|until| *until > now) {
+            return Err(locked_until - now);
+        }
+        self.check(Limit::LoginPerUsername, username)
+    }
+
+    pub fn login_failed(&self, username: &str) {
+        if self.limits.lockout_after == 0 {
+            return;
+        }
+        let now = Instant::now();
+        let mut failed_logins = self.failed_logins.lock().unwrap();
+        if failed_logins.len() >= MAX_TRACKED {
+            failed_logins.retain(|_, failed| now.duration_since(failed.last) < MAX_LOCKOUT);
+        }
+        let failed = failed_logins
+            .entry(username.to_string())
+            .or_insert(FailedLogins {
+                count: 0,
+                last: now,
+                locked_until: None,
+            });
+        // an hour without failures starts over
+        if now.duration_since(failed.last) >= MAX_LOCKOUT {
+            failed.count = 0;
+        }
+        failed.count += 1;
+        failed.last = now;
+        if failed.count >= self.limits.lockout_after {
+            let doublings = (failed.count - self.limits.lockout_after).min(16);
+            let lockout = (self.limits.lockout * 2u32.pow(doublings)).min(MAX_LOCKOUT);
+            failed.locked_until = Some(now + lockout);
+        }
+    }
+
+    pub fn login_succeeded(&self, username: &str) {
+        self.failed_logins.lock().unwrap().remove(username);
+    }
+
+    fn client_ip(&self, req: &ServiceRequest) -> String {
+        let info = req.connection_info();
+        let ip = if self.limits.trust_forwarded {
+            info.realip_remote_addr()
+        } else {
+            info.peer_addr()
+        };
+        ip.unwrap_or("unknown").to_string()
+    }
+
+    // which bucket a request takes its token from
+    fn bucket(&self, req: &ServiceRequest) -> (Limit, String) {
+        let path = req.path().strip_prefix(API_PREFIX).unwrap_or(req.path());
+        if req.method() == Method::POST {
+            match path {
+                "/users" => return (Limit::SignupPerIp, self.client_ip(req)),
+                "/users/login"
+                | "/users/login/two-factor"
+                | "/users/forgot-password"
+                | "/users/reset-password" => return (Limit::LoginPerIp, self.client_ip(req)),
+                _ => {}
+            }
+        }
+        let known_user = request_token(req.request()).and_then(|token| {
+            self.known_tokens
+                .lock()
+                .unwrap()
+                .get(&hash_token(token))
+                .copied()
+        });
+        match known_user {
+            Some(user_id) => (Limit::Api, format!("user {}", user_id)),
+            None => (Limit::Api, self.client_ip(req)),
+        }
+    }
+
+    // the token worked, its requests count as the user's from now on
+    fn token_worked(&self, token_hash: String, user_id: UserId) {
+        let mut known_tokens = self.known_tokens.lock().unwrap();
+        // start over rather than track them all, each is learned again on its next request
+        if known_tokens.len() >= MAX_TRACKED && !known_tokens.contains_key(&token_hash) {
+            known_tokens.clear();
+        }
+        known_tokens.insert(token_hash, user_id);
+    }
+}
+
+pub fn too_many_requests(retry_after: Duration, message: &str) -> HttpResponse {
+    // whole seconds, rounded up so that trying again then works
+    let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+    HttpResponseBuilder::new(StatusCode::TOO_MANY_REQUESTS)
+        .insert_header((RETRY_AFTER, seconds.max(1).to_string()))
+        .body(format!(
+            "{}, try again in {} seconds",
+            message,
+            seconds.max(1)
+        ))
+}
+
+pub async fn rate_limits(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let limiter = match req.app_data::<web::Data<RateLimiter>>() {
+        Some(limiter) => limiter.clone(),
+        None => return Ok(next.call(req).await?.map_into_boxed_body()),
+    };
+    let (limit, key) = limiter.bucket(&req);
+    if let Err(retry_after) = limiter.check(limit, &key) {
+        return Ok(req.into_response(too_many_requests(retry_after, "too many requests")));
+    }
+    let token_hash = request_token(req.request()).map(hash_token);
+    let response = next.call(req).await?;
+    if let (Some(token_hash), Some(user_id)) = (token_hash, looked_up_user(response.request())) {
+        limiter.token_worked(token_hash, user_id);
+    }
+    Ok(response.map_into_boxed_body())
+}
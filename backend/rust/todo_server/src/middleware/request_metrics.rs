@@ -0,0 +1,34 @@
+use crate::metrics::Metrics;
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{web, Error};
+use std::time::Instant;
+
+/*
+Counts every request and how long it took by method, route pattern and status
+for the /metrics endpoint. Without a `web::Data<Metrics>` in the app, like in
+most tests, nothing is counted.
+*/
+pub async fn request_metrics(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let metrics = match req.app_data::<web::Data<Metrics>>() {
+        Some(metrics) => metrics.clone(),
+        None => return Ok(next.call(req).await?.map_into_boxed_body()),
+    };
+    let method = req.method().to_string();
+    // the pattern, not the path, or every task id would be a time series
+    let route = req
+        .match_pattern()
+        .unwrap_or_else(|| "unmatched".to_string());
+    let started = Instant::now();
+    let result = next.call(req).await;
+    let status = match &result {
+        Ok(response) => response.status(),
+        Err(e) => e.as_response_error().status_code(),
+    };
+    metrics.observe_request(&method, &route, status.as_u16(), started.elapsed());
+    Ok(result?.map_into_boxed_body())
+}
@@ -0,0 +1,88 @@
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpMessage};
+use std::time::Instant;
+use tracing::field::Empty;
+use tracing::Instrument;
+
+/*
+Every request runs in a `request` span with its id, method, route pattern, the
+id of the user its token belongs to (routes/auth.rs fills that in), and once it
+is answered the status and the latency in milliseconds. Whatever is logged
+while handling it carries those fields.
+
+The id comes from the client's x-request-id header when it sent a usable one,
+otherwise it's made up here, and the response sends it back in x-request-id so
+a bug report can point at the lines in the log.
+*/
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+/// The id of the request, in the request's extensions.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RequestId(pub String);
+
+fn request_id(req: &ServiceRequest) -> String {
+    req.headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| {
+            !id.is_empty()
+                && id.len() <= MAX_REQUEST_ID_LENGTH
+                && id.chars().all(|c| c.is_ascii_graphic())
+        })
+        .map(str::to_string)
+        .unwrap_or_else(|| {
+            (0..16)
+                .map(|_| format!("{:02x}", rand::random::<u8>()))
+                .collect()
+        })
+}
+
+pub async fn request_spans(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let id = request_id(&req);
+    let route = req
+        .match_pattern()
+        .unwrap_or_else(|| "unmatched".to_string());
+    let span = tracing::info_span!(
+        "request",
+        request_id = %id,
+        method = %req.method(),
+        route = %route,
+        user_id = Empty,
+        status = Empty,
+        latency_ms = Empty,
+    );
+    req.extensions_mut().insert(RequestId(id.clone()));
+    let started = Instant::now();
+    let result = next.call(req).instrument(span.clone()).await;
+    span.record("latency_ms", started.elapsed().as_millis() as u64);
+    let mut response = match result {
+        Ok(response) => response.map_into_boxed_body(),
+        Err(e) => {
+            let status = e.as_response_error().status_code().as_u16();
+            span.record("status", status);
+            tracing::error!(parent: &span, error = %e, "request failed");
+            return Err(e);
+        }
+    };
+    span.record("status", response.status().as_u16());
+    if response.status().is_server_error() {
+        match response.response().error() {
+            Some(e) => tracing::error!(parent: &span, error = %e, "request failed"),
+            None => tracing::error!(parent: &span, "request failed"),
+        }
+    }
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response
+            .headers_mut()
+            .insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+    }
+    Ok(response)
+}
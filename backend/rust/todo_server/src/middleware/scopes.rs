@@ -0,0 +1,22 @@
+use crate::routes::auth::{credentials, insufficient_scope};
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::Error;
+
+/*
+A read-only API token gets a 403 for anything but GET and HEAD, before the
+idempotency keys or a handler see the request. Looking the token up here also
+saves the extractors in routes/auth.rs from doing it again.
+*/
+pub async fn require_scope(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    if let Some(found) = credentials(req.request()).await? {
+        if !found.allows(req.method()) {
+            return Ok(req.into_response(insufficient_scope()));
+        }
+    }
+    Ok(next.call(req).await?.map_into_boxed_body())
+}
@@ -0,0 +1,271 @@
+// Logging in through an OpenID Connect provider (the company SSO) with the
+// authorization code flow and PKCE. main.rs turns it on when the environment
+// names a provider:
+//
+//     OIDC_ISSUER=https://sso.example.com OIDC_CLIENT_ID=todo OIDC_CLIENT_SECRET=...
+//         the issuer's /.well-known/openid-configuration says where everything
+//         else is. Register OIDC_REDIRECT_URI, which defaults to the yew
+//         solution's /sso-callback page under APP_URL, with the provider.
+//
+// The browser goes to the provider and comes back to the yew solution with a
+// code, which it hands to /auth/oidc/callback. The server swaps the code for an
+// ID token and checks the token's signature against the provider's JWKS.
+
+use crate::routes::password_resets::app_url;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::sync::Mutex;
+use thiserror::Error;
+
+// what ID tokens may be signed with, never a shared secret
+const ALGORITHMS: [Algorithm; 8] = [
+    Algorithm::RS256,
+    Algorithm::RS384,
+    Algorithm::RS512,
+    Algorithm::PS256,
+    Algorithm::PS384,
+    Algorithm::PS512,
+    Algorithm::ES256,
+    Algorithm::ES384,
+];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OidcConfig {
+    pub issuer: String,
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    pub redirect_uri: String,
+}
+
+impl OidcConfig {
+    /// None when OIDC_ISSUER or OIDC_CLIENT_ID isn't set.
+    pub fn from_env() -> Option<Self> {
+        let issuer = dotenv::var("OIDC_ISSUER").ok()?;
+        let client_id = dotenv::var("OIDC_CLIENT_ID").ok()?;
+        let redirect_uri = dotenv::var("OIDC_REDIRECT_URI")
+            .unwrap_or_else(|_| format!("{}/sso-callback", app_url().trim_end_matches('/')));
+        Some(OidcConfig {
+            issuer: issuer.trim_end_matches('/').to_string(),
+            client_id,
+            client_secret: dotenv::var("OIDC_CLIENT_SECRET").ok(),
+            redirect_uri,
+        })
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum OidcError {
+    #[error("could not reach the identity provider: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("the identity provider answered {0}")]
+    BadResponse(String),
+    #[error("invalid ID token: {0}")]
+    InvalidToken(String),
+}
+
+/// The parts of the discovery document the login needs.
+#[derive(Deserialize, Clone, Debug)]
+pub struct Discovery {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub jwks_uri: String,
+}
+
+/// Who the provider says logged in.
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct IdClaims {
+    pub iss: String,
+    pub sub: String,
+    pub nonce: Option<String>,
+    pub email: Option<String>,
+    pub email_verified: Option<bool>,
+    pub preferred_username: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: Option<String>,
+}
+
+pub struct Oidc {
+    pub config: OidcConfig,
+    http: reqwest::Client,
+    discovery: Mutex<Option<Discovery>>,
+    jwks: Mutex<Option<JwkSet>>,
+}
+
+impl Oidc {
+    pub fn new(config: OidcConfig) -> Self {
+        Oidc {
+            config,
+            http: reqwest::Client::new(),
+            discovery: Mutex::new(None),
+            jwks: Mutex::new(None),
+        }
+    }
+
+    pub fn from_env() -> Option<Self> {
+        OidcConfig::from_env().map(Oidc::new)
+    }
+
+    // fetched once, providers don't move their endpoints around
+    pub async fn discovery(&self) -> Result<Discovery, OidcError> {
+        if let Some(discovery) = self.discovery.lock().unwrap().clone() {
+            return Ok(discovery);
+        }
+        let url = format!("{}/.well-known/openid-configuration", self.config.issuer);
+        let discovery: Discovery = self.get_json(&url).await?;
+        if discovery.issuer.trim_end_matches('/') != self.config.issuer {
+            return Err(OidcError::BadResponse(format!(
+                "a discovery document for {}",
+                discovery.issuer
+            )));
+        }
+        *self.discovery.lock().unwrap() = Some(discovery.clone());
+        Ok(discovery)
+    }
+
+    /// Where to send the browser.
+    pub async fn authorization_url(
+        &self,
+        state: &str,
+        nonce: &str,
+        code_verifier: &str,
+    ) -> Result<String, OidcError> {
+        let discovery = self.discovery().await?;
+        let mut url = reqwest::Url::parse(&discovery.authorization_endpoint)
+            .map_err(|e| OidcError::BadResponse(format!("a bad authorization_endpoint: {}", e)))?;
+        url.query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &self.config.client_id)
+            .append_pair("redirect_uri", &self.config.redirect_uri)
+            .append_pair("scope", "openid profile email")
+            .append_pair("state", state)
+            .append_pair("nonce", nonce)
+            .append_pair("code_challenge", &code_challenge(code_verifier))
+            .append_pair("code_challenge_method", "S256");
+        Ok(url.to_string())
+    }
+
+    /// Swaps the code from the redirect for an ID token and checks it.
+    pub async fn exchange_code(
+        &self,
+        code: &str,
+        code_verifier: &str,
+        nonce: &str,
+    ) -> Result<IdClaims, OidcError> {
+        let discovery = self.discovery().await?;
+        let form = [
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", &self.config.redirect_uri),
+            ("client_id", &self.config.client_id),
+            ("code_verifier", code_verifier),
+        ];
+        let mut request = self.http.post(&discovery.token_endpoint).form(&form);
+        if let Some(secret) = &self.config.client_secret {
+            request = request.basic_auth(&self.config.client_id, Some(secret));
+        }
+        let response = request.send().await?;
+        let status = response.status();
+        let body = response.text().await?;
+        if !status.is_success() {
+            return Err(OidcError::BadResponse(format!("{} {}", status, body)));
+        }
+        let id_token = serde_json::from_str::<TokenResponse>(&body)
+            .ok()
+            .and_then(|response| response.id_token)
+            .ok_or_else(|| OidcError::BadResponse("a token response without an id_token".into()))?;
+        self.verify_id_token(&id_token, nonce).await
+    }
+
+    pub async fn verify_id_token(
+        &self,
+        id_token: &str,
+        nonce: &str,
+    ) -> Result<IdClaims, OidcError> {
+        let jwks = self.jwks(false).await?;
+        match validate_id_token(id_token, &jwks, &self.config, nonce) {
+            // the provider rotated its keys since we last looked
+            Err(OidcError::InvalidToken(reason)) if reason == UNKNOWN_KEY => {
+                let jwks = self.jwks(true).await?;
+                validate_id_token(id_token, &jwks, &self.config, nonce)
+            }
+            result => result,
+        }
+    }
+
+    async fn jwks(&self, refresh: bool) -> Result<JwkSet, OidcError> {
+        if !refresh {
+            if let Some(jwks) = self.jwks.lock().unwrap().clone() {
+                return Ok(jwks);
+            }
+        }
+        let discovery = self.discovery().await?;
+        let jwks: JwkSet = self.get_json(&discovery.jwks_uri).await?;
+        *self.jwks.lock().unwrap() = Some(jwks.clone());
+        Ok(jwks)
+    }
+
+    async fn get_json<T: DeserializeOwned>(&self, url: &str) -> Result<T, OidcError> {
+        let response = self.http.get(url).send().await?;
+        let status = response.status();
+        let body = response.text().await?;
+        if !status.is_success() {
+            return Err(OidcError::BadResponse(format!("{} for {}", status, url)));
+        }
+        serde_json::from_str(&body)
+            .map_err(|e| OidcError::BadResponse(format!("something unreadable for {}: {}", url, e)))
+    }
+}
+
+const UNKNOWN_KEY: &str = "signed with a key that isn't in the JWKS";
+
+/// Checks the signature, issuer, audience, expiry and nonce of an ID token.
+pub fn validate_id_token(
+    id_token: &str,
+    jwks: &JwkSet,
+    config: &OidcConfig,
+    nonce: &str,
+) -> Result<IdClaims, OidcError> {
+    let invalid = |reason: String| OidcError::InvalidToken(reason);
+    let header = decode_header(id_token).map_err(|e| invalid(e.to_string()))?;
+    if !ALGORITHMS.contains(&header.alg) {
+        return Err(invalid(format!("signed with {:?}", header.alg)));
+    }
+    let jwk = match &header.kid {
+        Some(kid) => jwks.find(kid),
+        // a provider with a single key doesn't have to name it
+        None if jwks.keys.len() == 1 => jwks.keys.first(),
+        None => None,
+    }
+    .ok_or_else(|| invalid(UNKNOWN_KEY.to_string()))?;
+    let key = DecodingKey::from_jwk(jwk).map_err(|e| invalid(e.to_string()))?;
+    let mut validation = Validation::new(header.alg);
+    validation.set_audience(&[&config.client_id]);
+    validation.set_issuer(&[&config.issuer]);
+    validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+    let claims = decode::<IdClaims>(id_token, &key, &validation)
+        .map_err(|e| invalid(e.to_string()))?
+        .claims;
+    if claims.nonce.as_deref() != Some(nonce) {
+        return Err(invalid("the nonce doesn't match the login".to_string()));
+    }
+    Ok(claims)
+}
+
+/// A PKCE code verifier, 256 random bits as base64url (RFC 7636).
+pub fn new_code_verifier() -> String {
+    URL_SAFE_NO_PAD.encode(rand::random::<[u8; 32]>())
+}
+
+/// The S256 code challenge for a verifier.
+pub fn code_challenge(code_verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()))
+}
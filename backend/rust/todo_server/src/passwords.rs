@@ -0,0 +1,92 @@
+// The password policy for new passwords: todo_api's rules with the limits from
+// PASSWORD_MIN_LENGTH and PASSWORD_MIN_STRENGTH, and when BREACHED_PASSWORDS_FILE
+// names one, not in a list of breached passwords. That is a file of uppercase
+// SHA-1 hashes, one per line and sorted, like the "ordered by hash" download of
+// Have I Been Pwned. Anything after a ':' on a line (the count) is ignored. It's
+// binary searched where it is, so even the full list doesn't need loading.
+
+use sha1::{Digest, Sha1};
+use std::cmp::Ordering;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Seek, SeekFrom};
+use std::path::Path;
+use todo_api::validation::PasswordPolicy;
+
+pub fn policy_from_env() -> PasswordPolicy {
+    let defaults = PasswordPolicy::default();
+    PasswordPolicy {
+        min_length: env_number("PASSWORD_MIN_LENGTH").unwrap_or(defaults.min_length),
+        min_strength: env_number("PASSWORD_MIN_STRENGTH").unwrap_or(defaults.min_strength),
+    }
+}
+
+fn env_number<N: std::str::FromStr>(name: &str) -> Option<N> {
+    dotenv::var(name)
+        .ok()
+        .and_then(|number| number.parse().ok())
+}
+
+/// What's wrong with `password` as the new password of `username`, if anything.
+pub fn new_password_problem(username: &str, password: &str) -> Option<String> {
+    if let Some(problem) = policy_from_env().problem(username, password) {
+        return Some(problem);
+    }
+    let path = dotenv::var("BREACHED_PASSWORDS_FILE").ok()?;
+    match is_breached(Path::new(&path), password) {
+        Ok(true) => Some(
+            "the password is in a list of breached passwords, pick one nobody has used".to_string(),
+        ),
+        Ok(false) => None,
+        // a missing list shouldn't stop everybody from signing up
+        Err(e) => {
+            tracing::warn!(path = %path, error = %e, "checking for breached passwords failed");
+            None
+        }
+    }
+}
+
+pub fn sha1_hex(password: &str) -> String {
+    Sha1::digest(password.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect()
+}
+
+pub fn is_breached(path: &Path, password: &str) -> io::Result<bool> {
+    let hash = sha1_hex(password);
+    let mut file = File::open(path)?;
+    // lines starting before `low` sort before the hash, ones starting at or after
+    // `high` after it
+    let mut low = 0;
+    let mut high = file.metadata()?.len();
+    while low < high {
+        let middle = low + (high - low) / 2;
+        let (start, end, line) = line_from(&mut file, middle)?;
+        if start >= high {
+            high = middle;
+            continue;
+        }
+        let key = line.split(':').next().unwrap_or_default().trim();
+        match key.to_ascii_uppercase().as_str().cmp(&hash) {
+            Ordering::Equal => return Ok(true),
+            Ordering::Less => low = end,
+            Ordering::Greater => high = middle,
+        }
+    }
+    Ok(false)
+}
+
+// the first line starting at or after `offset`, with where it starts and ends
+fn line_from(file: &mut File, offset: u64) -> io::Result<(u64, u64, String)> {
+    let seek_to = offset.saturating_sub(1);
+    file.seek(SeekFrom::Start(seek_to))?;
+    let mut reader = BufReader::new(file);
+    let mut start = seek_to;
+    if offset > 0 {
+        let mut rest = Vec::new();
+        start += reader.read_until(b'\n', &mut rest)? as u64;
+    }
+    let mut line = String::new();
+    let length = reader.read_line(&mut line)? as u64;
+    Ok((start, start + length, line))
+}
@@ -0,0 +1,164 @@
+use crate::database::admin_queries::SetDisabled;
+use crate::database::{TodoDB, UserId};
+use crate::routes::auth::RequireAdmin;
+use crate::routes::openapi::{IdempotencyKey, InvalidToken};
+use crate::routes::TodoAppError;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, HttpResponseBuilder};
+use todo_api::{
+    AdminUser, AdminUserListResponse, AdminUserResponse, DataResponse, StatsResponse, UsageStats,
+};
+
+/*
+Only for users with the admin role, send an admin's token as x-auth-token.
+
+# list users
+## route: "/admin/users" GET
+
+curl localhost:3010/api/v1/admin/users -H "x-auth-token: $TOKEN"
+
+### response:
+{
+    "data": [
+        {
+            "id": 3,
+            "username": "woodroww",
+            "role": "admin",
+            "deleted_at": null,
+            "disabled": false,
+            "logged_in": true,
+            "tasks": 2
+        }
+    ]
+}
+
+# disable a user, they are logged out and can't log in again
+## route: "/admin/users/:userId/disabled" PUT
+sets their deleted_at, like deleting the account but their tasks are kept
+
+# let a disabled user log in again
+## route: "/admin/users/:userId/enabled" PUT
+a user who deleted their account gets a 409, enabling only undoes disabling
+
+both answer with the user like the list does
+
+# aggregate stats
+## route: "/admin/stats" GET
+
+### response:
+{"data":{"users":14,"deleted_users":1,"logged_in_users":4,"admins":1,"tasks":60,"completed_tasks":3,"deleted_tasks":8,"templates":4,"task_events":67}}
+*/
+
+#[utoipa::path(
+    get,
+    path = "/admin/users",
+    tag = "admin",
+    summary = "Every user, deleted ones included",
+    security(("token" = []), ("bearer" = [])),
+    responses(
+        (status = 200, description = "The users", body = DataResponse<Vec<AdminUser>>),
+        (status = 401, response = InvalidToken),
+        (status = 403, description = "Not an admin", body = String, content_type = "text/plain"),
+    ),
+)]
+pub async fn list_users(
+    _admin: RequireAdmin,
+    db: web::Data<TodoDB>,
+) -> Result<HttpResponse, TodoAppError> {
+    let users = db.list_users(None).await?;
+    Ok(HttpResponse::Ok().json(AdminUserListResponse { data: users }))
+}
+
+#[utoipa::path(
+    put,
+    path = "/admin/users/{id}/disabled",
+    tag = "admin",
+    summary = "Disable a user by setting their deleted_at, which logs them out and stops them logging in",
+    security(("token" = []), ("bearer" = [])),
+    params(IdempotencyKey, ("id" = i32, Path, description = "The user's id")),
+    responses(
+        (status = 200, description = "The disabled user", body = DataResponse<AdminUser>),
+        (status = 400, description = "Admins can't disable themselves", body = String, content_type = "text/plain"),
+        (status = 401, response = InvalidToken),
+        (status = 403, description = "Not an admin, or a read_only API token", body = String, content_type = "text/plain"),
+        (status = 404, description = "No such user", body = String, content_type = "text/plain"),
+    ),
+)]
+pub async fn disable_user(
+    admin: RequireAdmin,
+    id: web::Path<UserId>,
+    db: web::Data<TodoDB>,
+) -> Result<HttpResponse, TodoAppError> {
+    // locking yourself out could leave nobody to undo it
+    if admin.0.id == *id {
+        return Ok(HttpResponseBuilder::new(StatusCode::BAD_REQUEST)
+            .body("admins can't disable themselves"));
+    }
+    set_disabled(&db, *id, true).await
+}
+
+#[utoipa::path(
+    put,
+    path = "/admin/users/{id}/enabled",
+    tag = "admin",
+    summary = "Let a disabled user log in again, a deleted one stays deleted",
+    security(("token" = []), ("bearer" = [])),
+    params(IdempotencyKey, ("id" = i32, Path, description = "The user's id")),
+    responses(
+        (status = 200, description = "The enabled user", body = DataResponse<AdminUser>),
+        (status = 401, response = InvalidToken),
+        (status = 403, description = "Not an admin, or a read_only API token", body = String, content_type = "text/plain"),
+        (status = 404, description = "No such user", body = String, content_type = "text/plain"),
+        (status = 409, description = "The user deleted their account, it wasn't disabled", body = String, content_type = "text/plain"),
+    ),
+)]
+pub async fn enable_user(
+    _admin: RequireAdmin,
+    id: web::Path<UserId>,
+    db: web::Data<TodoDB>,
+) -> Result<HttpResponse, TodoAppError> {
+    set_disabled(&db, *id, false).await
+}
+
+async fn set_disabled(
+    db: &TodoDB,
+    id: UserId,
+    disabled: bool,
+) -> Result<HttpResponse, TodoAppError> {
+    match db.set_user_disabled(id, disabled).await? {
+        SetDisabled::Done => {}
+        SetDisabled::NotFound => {
+            return Ok(
+                HttpResponseBuilder::new(StatusCode::NOT_FOUND).body(format!("no user {}", id))
+            )
+        }
+        SetDisabled::Deleted => {
+            return Ok(HttpResponseBuilder::new(StatusCode::CONFLICT)
+                .body(format!("user {} was deleted, not disabled", id)))
+        }
+    }
+    match db.list_users(Some(id)).await?.pop() {
+        Some(user) => Ok(HttpResponse::Ok().json(AdminUserResponse { data: user })),
+        None => Ok(HttpResponseBuilder::new(StatusCode::NOT_FOUND).body(format!("no user {}", id))),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/stats",
+    tag = "admin",
+    summary = "Counts of users, tasks and events",
+    security(("token" = []), ("bearer" = [])),
+    responses(
+        (status = 200, description = "The counts", body = DataResponse<UsageStats>),
+        (status = 401, response = InvalidToken),
+        (status = 403, description = "Not an admin", body = String, content_type = "text/plain"),
+    ),
+)]
+pub async fn stats(
+    _admin: RequireAdmin,
+    db: web::Data<TodoDB>,
+) -> Result<HttpResponse, TodoAppError> {
+    let stats = db.usage_stats().await?;
+    Ok(HttpResponse::Ok().json(StatsResponse { data: stats }))
+}
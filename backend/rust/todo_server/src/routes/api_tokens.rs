@@ -0,0 +1,156 @@
+use crate::database::TodoDB;
+use crate::routes::auth::{hash_token, random_token, SessionUser};
+use crate::routes::openapi::{IdempotencyKey, InvalidToken, SessionOnly};
+use crate::routes::TodoAppError;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, HttpResponseBuilder};
+use todo_api::{
+    ApiToken, ApiTokenId, ApiTokenListResponse, CreateApiTokenRequest, DataResponse,
+    MessageResponse, NewApiToken, NewApiTokenResponse,
+};
+
+/*
+Personal API tokens, for scripts that shouldn't use the browser's session token.
+They go in `Authorization: Bearer`, or x-auth-token like a session token. A
+read_only token is turned away like a bad token for anything but GET. Making,
+listing and revoking tokens takes the session token, an API token can't do it.
+
+# list tokens
+## route: "/users/me/tokens" GET
+
+curl localhost:3010/api/v1/users/me/tokens -H "x-auth-token: $TOKEN"
+
+### response:
+{
+    "data": [
+        {
+            "id": 1,
+            "name": "backup script",
+            "scope": "read_only",
+            "created_at": "2022-05-11T18:45:16.214145",
+            "expires_at": null,
+            "last_used_at": "2022-05-12T07:00:02.513921"
+        }
+    ]
+}
+
+# create a token
+## route: "/users/me/tokens" POST
+scope is read_only (the default) or read_write, without expires_in_days it works
+until it is revoked. The answer is the only time the token is shown.
+
+curl -X POST localhost:3010/api/v1/users/me/tokens \
+-H "x-auth-token: $TOKEN" \
+-H "Content-Type: application/json" \
+--data '{ "name": "backup script", "scope": "read_only", "expires_in_days": 90 }'
+
+### response:
+{"data":{"token":"todo_pat_5f1c...","api_token":{"id":1,"name":"backup script",...}}}
+
+curl localhost:3010/api/v1/tasks -H "Authorization: Bearer todo_pat_5f1c..."
+
+# revoke a token
+## route: "/users/me/tokens/:id" DELETE
+
+curl -X DELETE localhost:3010/api/v1/users/me/tokens/1 -H "x-auth-token: $TOKEN"
+
+### response:
+{"message":"token revoked"}
+*/
+
+// tells API tokens from session tokens, and makes leaked ones easy to search for
+pub const TOKEN_PREFIX: &str = "todo_pat_";
+const MAX_NAME_LENGTH: usize = 64;
+
+fn bad_request(message: &str) -> HttpResponse {
+    HttpResponseBuilder::new(StatusCode::BAD_REQUEST).body(message.to_string())
+}
+
+#[utoipa::path(
+    get,
+    path = "/users/me/tokens",
+    tag = "users",
+    summary = "The user's personal API tokens, without the tokens themselves",
+    security(("token" = [])),
+    responses(
+        (status = 200, description = "The tokens", body = DataResponse<Vec<ApiToken>>),
+        (status = 401, response = InvalidToken),
+        (status = 403, response = SessionOnly),
+    ),
+)]
+pub async fn get_api_tokens(
+    SessionUser(user): SessionUser,
+    db: web::Data<TodoDB>,
+) -> Result<HttpResponse, TodoAppError> {
+    let data = db.get_api_tokens(user.id).await?;
+    Ok(HttpResponse::Ok().json(ApiTokenListResponse { data }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/users/me/tokens",
+    tag = "users",
+    summary = "Create a personal API token, the answer is the only time it's shown",
+    security(("token" = [])),
+    params(IdempotencyKey),
+    request_body = CreateApiTokenRequest,
+    responses(
+        (status = 200, description = "The token and what it's called", body = DataResponse<NewApiToken>),
+        (status = 400, description = "An empty or too long name, or expires_in_days under 1", body = String, content_type = "text/plain"),
+        (status = 401, response = InvalidToken),
+        (status = 403, response = SessionOnly),
+    ),
+)]
+pub async fn create_api_token(
+    SessionUser(user): SessionUser,
+    body: web::Json<CreateApiTokenRequest>,
+    db: web::Data<TodoDB>,
+) -> Result<HttpResponse, TodoAppError> {
+    let name = body.name.trim();
+    if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+        return Ok(bad_request("the name has to be 1 to 64 characters"));
+    }
+    if body.expires_in_days.is_some_and(|days| days < 1) {
+        return Ok(bad_request("expires_in_days has to be at least 1"));
+    }
+    let token = format!("{}{}", TOKEN_PREFIX, random_token());
+    let api_token = db
+        .insert_api_token(
+            user.id,
+            name,
+            &hash_token(&token),
+            body.scope,
+            body.expires_in_days,
+        )
+        .await?;
+    Ok(HttpResponse::Ok().json(NewApiTokenResponse {
+        data: NewApiToken { token, api_token },
+    }))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/users/me/tokens/{id}",
+    tag = "users",
+    summary = "Revoke a personal API token",
+    security(("token" = [])),
+    params(IdempotencyKey, ("id" = i32, Path, description = "The API token's id")),
+    responses(
+        (status = 200, description = "Revoked", body = MessageResponse),
+        (status = 401, response = InvalidToken),
+        (status = 403, response = SessionOnly),
+        (status = 404, description = "The user has no such token", body = String, content_type = "text/plain"),
+    ),
+)]
+pub async fn delete_api_token(
+    SessionUser(user): SessionUser,
+    id: web::Path<ApiTokenId>,
+    db: web::Data<TodoDB>,
+) -> Result<HttpResponse, TodoAppError> {
+    if !db.delete_api_token(user.id, *id).await? {
+        return Ok(HttpResponseBuilder::new(StatusCode::NOT_FOUND).body(format!("no token {}", id)));
+    }
+    Ok(HttpResponse::Ok().json(MessageResponse {
+        message: "token revoked".to_string(),
+    }))
+}
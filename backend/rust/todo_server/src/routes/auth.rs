@@ -0,0 +1,205 @@
+use crate::database::{request_token, TodoDB, TodoDBError, UserId};
+use crate::routes::users::UserInfo;
+use actix_web::dev::Payload;
+use actix_web::error::{ErrorForbidden, ErrorInternalServerError, InternalError};
+use actix_web::http::header::WWW_AUTHENTICATE;
+use actix_web::http::{Method, StatusCode};
+use actix_web::{
+    web, Error, FromRequest, HttpMessage, HttpRequest, HttpResponse, HttpResponseBuilder,
+};
+use futures_util::future::LocalBoxFuture;
+use sha2::{Digest, Sha256};
+use todo_api::{Role, TokenScope};
+
+/*
+Handlers that take an `AuthenticatedUser` argument only run for a logged in
+user, with a session token or a personal API token in x-auth-token or
+`Authorization: Bearer`. Without one the request gets a 401 with a
+`WWW-Authenticate` header. `SessionUser` turns personal API tokens away with a
+403, for the account itself: its username, email, password, two-factor
+authentication and tokens. A leaked API token can't take the account over.
+
+Handlers that take a `RequireAdmin` argument only run for a logged in admin:
+without a valid token the request gets a 401, with the token of a user who
+isn't an admin a 403. Make a user an admin with
+`todo_server admin role <username> admin`.
+
+The scopes of API tokens are the `require_scope` middleware's job, a read-only
+one gets a 403 for anything but GET and HEAD before a handler runs.
+
+When the database can't be asked about the token the request gets a 503, not a
+401 that would have the client throw a good token away.
+*/
+
+const REALM: &str = "todo_server";
+
+/// Who a request's token belongs to and what it may do.
+#[derive(Clone, Debug)]
+pub struct Credentials {
+    pub user: UserInfo,
+    pub scope: TokenScope,
+    pub api_token: bool,
+}
+
+impl Credentials {
+    pub fn allows(&self, method: &Method) -> bool {
+        self.scope == TokenScope::ReadWrite || method.is_safe()
+    }
+}
+
+// the lookup, kept in the request's extensions so that the middleware and the
+// extractors share one, even when it found nothing. A failed one isn't kept.
+#[derive(Clone)]
+struct LookedUp(Option<Credentials>);
+
+/// The credentials of the request's token, from the database the first time.
+pub async fn credentials(req: &HttpRequest) -> Result<Option<Credentials>, TodoDBError> {
+    if let Some(LookedUp(found)) = req.extensions().get::<LookedUp>() {
+        return Ok(found.clone());
+    }
+    let found = match (req.app_data::<web::Data<TodoDB>>(), request_token(req)) {
+        (Some(db), Some(token)) => db.credentials(token).await?,
+        _ => None,
+    };
+    if let Some(found) = &found {
+        tracing::Span::current().record("user_id", found.user.id);
+    }
+    req.extensions_mut().insert(LookedUp(found.clone()));
+    Ok(found)
+}
+
+/// Who the request's token belongs to, if it has been looked up already and
+/// worked. Never asks the database.
+pub fn looked_up_user(req: &HttpRequest) -> Option<UserId> {
+    match req.extensions().get::<LookedUp>() {
+        Some(LookedUp(Some(found))) => Some(found.user.id),
+        _ => None,
+    }
+}
+
+/// Settles who the request is from without asking the database, for tests and
+/// anything else that has already checked the token.
+pub fn remember_credentials(req: &HttpRequest, found: Option<Credentials>) {
+    req.extensions_mut().insert(LookedUp(found));
+}
+
+/// A 401 for a request with a token that doesn't work.
+pub fn invalid_token() -> HttpResponse {
+    let challenge = format!(r#"Bearer realm="{}", error="invalid_token""#, REALM);
+    HttpResponseBuilder::new(StatusCode::UNAUTHORIZED)
+        .insert_header((WWW_AUTHENTICATE, challenge))
+        .body("invalid token")
+}
+
+// a 401 that tells a request without a token how to send one
+fn unauthorized(req: &HttpRequest) -> Error {
+    let response = match request_token(req) {
+        Some(_) => invalid_token(),
+        None => HttpResponseBuilder::new(StatusCode::UNAUTHORIZED)
+            .insert_header((WWW_AUTHENTICATE, format!(r#"Bearer realm="{}""#, REALM)))
+            .body("invalid token"),
+    };
+    InternalError::from_response("invalid token", response).into()
+}
+
+/// A 403 for a read-only API token that tried to change something.
+pub fn insufficient_scope() -> HttpResponse {
+    let challenge = format!(
+        r#"Bearer realm="{}", error="insufficient_scope", scope="{}""#,
+        REALM,
+        TokenScope::ReadWrite.as_str()
+    );
+    HttpResponseBuilder::new(StatusCode::FORBIDDEN)
+        .insert_header((WWW_AUTHENTICATE, challenge))
+        .body("this API token is read-only")
+}
+
+/// A 403 for a personal API token on a route only a session may use.
+pub fn session_required() -> HttpResponse {
+    let challenge = format!(r#"Bearer realm="{}", error="insufficient_scope""#, REALM);
+    HttpResponseBuilder::new(StatusCode::FORBIDDEN)
+        .insert_header((WWW_AUTHENTICATE, challenge))
+        .body("this needs a session token, API tokens can't do it")
+}
+
+/// The user making the request.
+pub struct AuthenticatedUser(pub UserInfo);
+
+impl FromRequest for AuthenticatedUser {
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let req = req.clone();
+        Box::pin(async move {
+            match credentials(&req).await? {
+                Some(found) if found.allows(req.method()) => Ok(AuthenticatedUser(found.user)),
+                Some(_) => {
+                    Err(InternalError::from_response("read-only", insufficient_scope()).into())
+                }
+                None => Err(unauthorized(&req)),
+            }
+        })
+    }
+}
+
+/// The user of a session token, an API token gets a 403.
+pub struct SessionUser(pub UserInfo);
+
+impl FromRequest for SessionUser {
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let req = req.clone();
+        Box::pin(async move {
+            match credentials(&req).await? {
+                Some(found) if !found.api_token => Ok(SessionUser(found.user)),
+                Some(_) => {
+                    Err(InternalError::from_response("API token", session_required()).into())
+                }
+                None => Err(unauthorized(&req)),
+            }
+        })
+    }
+}
+
+/// The admin making the request.
+pub struct RequireAdmin(pub UserInfo);
+
+impl FromRequest for RequireAdmin {
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let req = req.clone();
+        let user = AuthenticatedUser::from_request(&req, payload);
+        Box::pin(async move {
+            let AuthenticatedUser(user) = user.await?;
+            let db = req
+                .app_data::<web::Data<TodoDB>>()
+                .ok_or_else(|| ErrorInternalServerError("no database"))?;
+            match db.get_role(user.id).await {
+                Ok(Some(Role::Admin)) => Ok(RequireAdmin(user)),
+                Ok(_) => Err(ErrorForbidden("admin role required")),
+                Err(e) => Err(e.into()),
+            }
+        })
+    }
+}
+
+// reset links, recovery codes and login challenges only go in the database as
+// this hash, so reading it doesn't give away working ones
+pub fn hash_token(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+// 256 random bits as hex
+pub fn random_token() -> String {
+    (0..32)
+        .map(|_| format!("{:02x}", rand::random::<u8>()))
+        .collect()
+}
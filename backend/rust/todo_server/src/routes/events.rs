@@ -0,0 +1,257 @@
+use crate::database::event_queries::EventId;
+use crate::database::{TodoDB, TodoDBError};
+use crate::events::{SequencedEvent, TaskEvent, TaskEvents};
+use crate::routes::auth::{credentials, invalid_token};
+use crate::routes::openapi::InvalidToken;
+use crate::routes::users::UserInfo;
+use actix_web::http::header::{CACHE_CONTROL, CONTENT_TYPE};
+use actix_web::http::StatusCode;
+use actix_web::web::Bytes;
+use actix_web::{rt, web, Error, HttpRequest, HttpResponse, HttpResponseBuilder};
+use actix_ws::Message;
+use futures_util::stream;
+use serde::Deserialize;
+use std::collections::{HashSet, VecDeque};
+use std::time::Duration;
+use tokio::sync::mpsc::UnboundedReceiver;
+use utoipa::IntoParams;
+
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(15);
+const RETRY_MS: u32 = 5000;
+
+// browsers can't set headers on a WebSocket or an EventSource,
+// so the token may come in the query string
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct EventsQuery {
+    /// Browsers can't set headers on a WebSocket or an EventSource, so the token may come here instead
+    token: Option<String>,
+    /// Same as the Last-Event-ID header
+    #[param(value_type = Option<i64>)]
+    last_event_id: Option<EventId>,
+}
+
+/*
+# task events over a WebSocket
+## route: "/ws" GET
+
+websocat "ws://localhost:3010/api/v1/ws?token=eyJ0eXAiOiJKV1QiLCJhbGciOiJIUzI1NiJ9.InUxIg.2zMFwZKIQP3m5ZCT8Dv2e59TF7VObGMgGBB3yq8CPDM"
+
+### messages:
+{"type":"task_updated","data":{"id":8,"priority":"A","title":"Curl is fun","completed_at":null,"description":"typing and stuff in the terminal","version":3}}
+{"type":"task_deleted","data":{"id":8}}
+*/
+
+#[utoipa::path(
+    get,
+    path = "/ws",
+    tag = "events",
+    summary = "A WebSocket that pushes a TaskEvent message for every change to the user's tasks",
+    security(("token" = []), ("bearer" = []), ("query_token" = [])),
+    params(EventsQuery),
+    responses(
+        (status = 101, description = "Switching to the WebSocket, every text message is a TaskEvent", body = TaskEvent),
+        (status = 401, response = InvalidToken),
+    ),
+)]
+pub async fn task_socket(
+    req: HttpRequest,
+    body: web::Payload,
+    query: web::Query<EventsQuery>,
+    db: web::Data<TodoDB>,
+    events: web::Data<TaskEvents>,
+) -> Result<HttpResponse, Error> {
+    let user = match authenticate(&req, &query, &db).await? {
+        Some(user) => user,
+        None => {
+            return Ok(invalid_token());
+        }
+    };
+
+    let (response, mut session, mut messages) = actix_ws::handle(&req, body)?;
+    let mut task_events = events.subscribe(user.id);
+
+    rt::spawn(async move {
+        let mut heartbeat = rt::time::interval(HEARTBEAT_INTERVAL);
+        loop {
+            tokio::select! {
+                event = task_events.recv() => {
+                    let event = match event {
+                        Some((_, event)) => event,
+                        None => break,
+                    };
+                    let text = serde_json::to_string(&event).unwrap();
+                    if session.text(text).await.is_err() {
+                        break;
+                    }
+                }
+                message = messages.recv() => {
+                    match message {
+                        Some(Ok(Message::Ping(bytes))) => {
+                            if session.pong(&bytes).await.is_err() {
+                                break;
+                            }
+                        }
+                        Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                        // the socket only pushes, anything the client says is ignored
+                        Some(Ok(_)) => {}
+                    }
+                }
+                _ = heartbeat.tick() => {
+                    if session.ping(b"").await.is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+        let _ = session.close(None).await;
+    });
+
+    Ok(response)
+}
+
+/*
+# task events as Server-Sent Events, for when WebSockets get blocked
+## route: "/events" GET
+reconnecting with a Last-Event-ID header (or ?last_event_id=) replays what was missed
+
+curl -N -H "Last-Event-ID: 41" "localhost:3010/api/v1/events?token=eyJ0eXAiOiJKV1QiLCJhbGciOiJIUzI1NiJ9.InUxIg.2zMFwZKIQP3m5ZCT8Dv2e59TF7VObGMgGBB3yq8CPDM"
+
+### stream:
+retry: 5000
+
+id: 42
+data: {"type":"task_deleted","data":{"id":8}}
+*/
+
+#[utoipa::path(
+    get,
+    path = "/events",
+    tag = "events",
+    summary = "The same events as Server-Sent Events, resumable with Last-Event-ID",
+    security(("token" = []), ("bearer" = []), ("query_token" = [])),
+    params(EventsQuery, ("Last-Event-ID" = Option<i64>, Header, description = "Replay the events after this id")),
+    responses(
+        (status = 200, description = "An event stream, the data of every event is a TaskEvent", body = String, content_type = "text/event-stream"),
+        (status = 400, description = "Last-Event-ID isn't an event id", body = String, content_type = "text/plain"),
+        (status = 401, response = InvalidToken),
+        (status = 503, description = "The missed events could not be read", body = String, content_type = "text/plain"),
+    ),
+)]
+pub async fn task_event_stream(
+    req: HttpRequest,
+    query: web::Query<EventsQuery>,
+    db: web::Data<TodoDB>,
+    events: web::Data<TaskEvents>,
+) -> Result<HttpResponse, Error> {
+    let user = match authenticate(&req, &query, &db).await? {
+        Some(user) => user,
+        None => {
+            return Ok(invalid_token());
+        }
+    };
+    let last_event_id = match last_event_id(&req, &query) {
+        Ok(last_event_id) => last_event_id,
+        Err(_) => {
+            return Ok(HttpResponseBuilder::new(StatusCode::BAD_REQUEST)
+                .body("Last-Event-ID must be an event id"));
+        }
+    };
+
+    // subscribe before reading the backlog so nothing slips in between the two
+    let live = events.subscribe(user.id);
+    let mut backlog = VecDeque::new();
+    if let Some(last_event_id) = last_event_id {
+        match db.get_task_events_after(user.id, last_event_id).await {
+            Some(missed) => backlog.extend(missed),
+            None => {
+                return Ok(HttpResponseBuilder::new(StatusCode::SERVICE_UNAVAILABLE)
+                    .body("could not read missed events"));
+            }
+        }
+    }
+
+    let stream = EventStream {
+        retry_sent: false,
+        replayed: backlog.iter().map(|(id, _)| *id).collect(),
+        backlog,
+        live,
+        keepalive: rt::time::interval(KEEPALIVE_INTERVAL),
+    };
+    Ok(HttpResponse::Ok()
+        .insert_header((CONTENT_TYPE, "text/event-stream"))
+        .insert_header((CACHE_CONTROL, "no-cache"))
+        .streaming(stream::unfold(stream, EventStream::next_frame)))
+}
+
+struct EventStream {
+    retry_sent: bool,
+    backlog: VecDeque<(EventId, TaskEvent)>,
+    // what the backlog has, an event published while it was read comes live too.
+    // Not the highest id sent: ids are given out before the events are committed,
+    // so a live event can come after one with a higher id.
+    replayed: HashSet<EventId>,
+    live: UnboundedReceiver<SequencedEvent>,
+    keepalive: rt::time::Interval,
+}
+
+impl EventStream {
+    async fn next_frame(mut self) -> Option<(Result<Bytes, Error>, Self)> {
+        if !self.retry_sent {
+            self.retry_sent = true;
+            return Some((Ok(Bytes::from(format!("retry: {}\n\n", RETRY_MS))), self));
+        }
+        if let Some((id, event)) = self.backlog.pop_front() {
+            return Some((Ok(event_frame(Some(id), &event)), self));
+        }
+        loop {
+            tokio::select! {
+                event = self.live.recv() => {
+                    let (id, event) = event?;
+                    // already sent from the backlog, and it won't come again
+                    if id.is_some_and(|id| self.replayed.remove(&id)) {
+                        continue;
+                    }
+                    return Some((Ok(event_frame(id, &event)), self));
+                }
+                _ = self.keepalive.tick() => {
+                    // a comment line, keeps proxies from timing the connection out
+                    return Some((Ok(Bytes::from_static(b": keepalive\n\n")), self));
+                }
+            }
+        }
+    }
+}
+
+fn event_frame(id: Option<EventId>, event: &TaskEvent) -> Bytes {
+    let data = serde_json::to_string(event).unwrap();
+    match id {
+        Some(id) => Bytes::from(format!("id: {}\ndata: {}\n\n", id, data)),
+        None => Bytes::from(format!("data: {}\n\n", data)),
+    }
+}
+
+fn last_event_id(req: &HttpRequest, query: &EventsQuery) -> Result<Option<EventId>, ()> {
+    match req.headers().get("last-event-id") {
+        Some(value) => {
+            let value = value.to_str().map_err(|_| ())?;
+            value.trim().parse().map(Some).map_err(|_| ())
+        }
+        None => Ok(query.last_event_id),
+    }
+}
+
+pub(crate) async fn authenticate(
+    req: &HttpRequest,
+    query: &EventsQuery,
+    db: &TodoDB,
+) -> Result<Option<UserInfo>, TodoDBError> {
+    if let Some(found) = credentials(req).await? {
+        return Ok(found.allows(req.method()).then_some(found.user));
+    }
+    match &query.token {
+        Some(token) => db.get_by_token(token).await,
+        None => Ok(None),
+    }
+}
@@ -1,13 +1,33 @@
+pub mod admin;
+pub mod api_tokens;
+pub mod auth;
 pub mod tasks;
 pub mod users;
 pub mod errors;
+pub mod events;
+pub mod oidc;
+pub mod openapi;
+pub mod password_resets;
+pub mod templates;
+pub mod two_factor;
+
+use crate::database::TodoDBError;
+use crate::middleware::idempotency::idempotency_keys;
+use crate::middleware::rate_limit::rate_limits;
+use crate::middleware::request_metrics::request_metrics;
+use crate::middleware::request_spans::request_spans;
+use crate::middleware::scopes::require_scope;
+use actix_web::middleware::from_fn;
+use actix_web::web;
 
 use thiserror::Error;
 use actix_web::error::ResponseError;
+use actix_web::http::StatusCode;
 
 #[derive(Debug, Error)]
 pub struct TodoAppError {
     pub name: String,
+    pub status: StatusCode,
     //source: actix_web::error::Error,
 }
 
@@ -18,7 +38,70 @@ impl std::fmt::Display for TodoAppError {
 }
 
 // actix_web Use default implementation for `error_response()` method
-impl ResponseError for TodoAppError {}
-
+impl ResponseError for TodoAppError {
+    fn status_code(&self) -> StatusCode {
+        self.status
+    }
+}
 
+// a 503 when Postgres can't be reached, so clients know to try again
+impl From<TodoDBError> for TodoAppError {
+    fn from(e: TodoDBError) -> Self {
+        TodoAppError {
+            name: e.to_string(),
+            status: e.status_code(),
+        }
+    }
+}
 
+// every route of the API, main.rs and the client tests mount the same table
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope(openapi::API_PREFIX)
+            .wrap(from_fn(idempotency_keys))
+            .wrap(from_fn(require_scope))
+            // a limited request never reaches the database
+            .wrap(from_fn(rate_limits))
+            .wrap(from_fn(request_metrics))
+            // outermost, so even a 429 has a request id
+            .wrap(from_fn(request_spans))
+            .route("/users", web::post().to(users::create_user))
+            .route("/users/login", web::post().to(users::login))
+            .route("/users/login/two-factor", web::post().to(two_factor::login_two_factor))
+            .route("/users/logout", web::post().to(users::logout))
+            .route("/users/me", web::patch().to(users::update_me))
+            .route("/users/me", web::delete().to(users::delete_me))
+            .route("/users/me/password", web::post().to(users::change_password))
+            .route("/users/me/two-factor", web::get().to(two_factor::two_factor_status))
+            .route("/users/me/two-factor", web::post().to(two_factor::start_two_factor))
+            .route("/users/me/two-factor", web::delete().to(two_factor::disable_two_factor))
+            .route("/users/me/two-factor/confirm", web::post().to(two_factor::confirm_two_factor))
+            .route("/users/me/tokens", web::get().to(api_tokens::get_api_tokens))
+            .route("/users/me/tokens", web::post().to(api_tokens::create_api_token))
+            .route("/users/me/tokens/{id}", web::delete().to(api_tokens::delete_api_token))
+            .route("/users/forgot-password", web::post().to(password_resets::forgot_password))
+            .route("/users/reset-password", web::post().to(password_resets::reset_password))
+            .route("/auth/oidc/authorize", web::post().to(oidc::oidc_authorize))
+            .route("/auth/oidc/callback", web::post().to(oidc::oidc_callback))
+            .route("/tasks", web::post().to(tasks::create_task))
+            .route("/tasks", web::get().to(tasks::get_all_tasks))
+            .route("/tasks/{id}", web::get().to(tasks::get_task_id))
+            .route("/tasks/{id}", web::patch().to(tasks::update_task))
+            .route("/tasks/{id}", web::delete().to(tasks::delete_task))
+            .route("/tasks/{id}/completed", web::put().to(tasks::set_task_completed))
+            .route("/tasks/{id}/uncompleted", web::put().to(tasks::set_task_uncompleted))
+            .route("/admin/users", web::get().to(admin::list_users))
+            .route("/admin/users/{id}/disabled", web::put().to(admin::disable_user))
+            .route("/admin/users/{id}/enabled", web::put().to(admin::enable_user))
+            .route("/admin/stats", web::get().to(admin::stats))
+            .route("/templates", web::get().to(templates::get_templates))
+            .route("/templates", web::post().to(templates::create_template))
+            .route("/templates/{id}", web::get().to(templates::get_template))
+            .route("/templates/{id}", web::patch().to(templates::update_template))
+            .route("/templates/{id}", web::delete().to(templates::delete_template))
+            .route("/ws", web::get().to(events::task_socket))
+            .route("/events", web::get().to(events::task_event_stream))
+            .route("/openapi.json", web::get().to(openapi::openapi_json))
+            .route("/docs", web::get().to(openapi::api_docs)),
+    );
+}
@@ -0,0 +1,305 @@
+use crate::database::oidc_queries::{IdentityUser, NewIdentity, OidcLogin};
+use crate::database::TodoDB;
+use crate::metrics::{LoginMethod, LoginOutcome, Metrics};
+use crate::oidc::{self, IdClaims, Oidc, OidcError};
+use crate::routes::auth::{credentials, hash_token, invalid_token, random_token};
+use crate::routes::openapi::{IdempotencyKey, InvalidToken, API_PREFIX};
+use crate::routes::templates::onboarding_locale;
+use crate::routes::users::{create_token, hash_password, log_in, normalize_email};
+use crate::routes::TodoAppError;
+use actix_web::cookie::{time::Duration as CookieDuration, Cookie, SameSite};
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest, HttpResponse, HttpResponseBuilder};
+use todo_api::validation::{is_username_char, username_problem};
+use todo_api::{
+    AuthResponse, DataResponse, OidcAuthorization, OidcAuthorizationResponse, OidcCallbackRequest,
+    UserInfo,
+};
+
+/*
+Logging in with the company SSO, see oidc.rs for turning it on. Both routes
+answer 404 when it isn't configured.
+
+# start an SSO login
+## route: "/auth/oidc/authorize" POST
+answers where to send the browser, and sets an HttpOnly todo_sso cookie the
+callback needs back, so only the browser that started the login can finish it.
+With an x-auth-token (a session token, not a
+personal API token) the identity the user logs in as at the provider gets linked
+to that user instead, so they can log in either way from then on.
+
+curl -X POST -c cookies.txt localhost:3010/api/v1/auth/oidc/authorize
+
+### response:
+{"data":{"authorization_url":"https://sso.example.com/authorize?response_type=code&client_id=todo&..."}}
+
+# finish an SSO login
+## route: "/auth/oidc/callback" POST
+with the code and state the provider sent the browser back with, within 10
+minutes, and the todo_sso cookie from the start (400 without it). Somebody the server hasn't seen before gets a new account, named after
+their preferred_username or email. The provider takes care of any second factor,
+so two-factor authentication isn't asked for here.
+
+curl -X POST -b cookies.txt \
+localhost:3010/api/v1/auth/oidc/callback \
+-H "Content-Type: application/json" \
+--data '{ "code": "SplxlOBeZQQYbYS6WxSbIA", "state": "af0ifjsldkj..." }'
+
+### response:
+the user like login answers
+*/
+
+const LOGIN_TTL_MINUTES: i32 = 10;
+// holds the hash of the state, ties the login to the browser that started it
+const STATE_COOKIE: &str = "todo_sso";
+
+fn state_cookie(req: &HttpRequest, value: String, max_age: CookieDuration) -> Cookie<'static> {
+    Cookie::build(STATE_COOKIE, value)
+        .path(format!("{}/auth/oidc", API_PREFIX))
+        .http_only(true)
+        .same_site(SameSite::Lax)
+        .secure(req.connection_info().scheme() == "https")
+        .max_age(max_age)
+        .finish()
+}
+
+fn not_configured() -> HttpResponse {
+    HttpResponseBuilder::new(StatusCode::NOT_FOUND).body("single sign-on isn't configured")
+}
+
+fn bad_request(message: &str) -> HttpResponse {
+    HttpResponseBuilder::new(StatusCode::BAD_REQUEST).body(message.to_string())
+}
+
+fn provider_error(error: OidcError) -> HttpResponse {
+    tracing::warn!(%error, "SSO login failed");
+    match error {
+        OidcError::InvalidToken(_) => bad_request(&error.to_string()),
+        _ => HttpResponseBuilder::new(StatusCode::BAD_GATEWAY).body(error.to_string()),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/oidc/authorize",
+    tag = "users",
+    summary = "Start an SSO login, or with a token link the SSO account to the user",
+    security((), ("token" = [])),
+    params(IdempotencyKey),
+    responses(
+        (status = 200, description = "Where to send the browser", body = DataResponse<OidcAuthorization>),
+        (status = 401, response = InvalidToken),
+        (status = 404, description = "Single sign-on isn't configured", body = String, content_type = "text/plain"),
+        (status = 502, description = "The identity provider couldn't be reached", body = String, content_type = "text/plain"),
+    ),
+)]
+pub async fn oidc_authorize(
+    req: HttpRequest,
+    db: web::Data<TodoDB>,
+    oidc: Option<web::Data<Oidc>>,
+) -> Result<HttpResponse, TodoAppError> {
+    let oidc = match oidc {
+        Some(oidc) => oidc,
+        None => return Ok(not_configured()),
+    };
+    let user_id = if req.headers().contains_key("x-auth-token") {
+        match credentials(&req).await? {
+            Some(found) if !found.api_token => Some(found.user.id),
+            _ => return Ok(invalid_token()),
+        }
+    } else {
+        None
+    };
+    let state = random_token();
+    let login = OidcLogin {
+        code_verifier: oidc::new_code_verifier(),
+        nonce: random_token(),
+        user_id,
+    };
+    let authorization_url = match oidc
+        .authorization_url(&state, &login.nonce, &login.code_verifier)
+        .await
+    {
+        Ok(url) => url,
+        Err(e) => return Ok(provider_error(e)),
+    };
+    let state_hash = hash_token(&state);
+    db.insert_oidc_login(&state_hash, &login, LOGIN_TTL_MINUTES)
+        .await?;
+    let max_age = CookieDuration::minutes(LOGIN_TTL_MINUTES.into());
+    Ok(HttpResponse::Ok()
+        .cookie(state_cookie(&req, state_hash, max_age))
+        .json(OidcAuthorizationResponse {
+            data: OidcAuthorization { authorization_url },
+        }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/oidc/callback",
+    tag = "users",
+    summary = "Finish an SSO login with the code and state from the redirect, a new identity gets a new user",
+    request_body = OidcCallbackRequest,
+    responses(
+        (status = 200, description = "The user and their token", body = DataResponse<UserInfo>),
+        (status = 400, description = "The state expired, the todo_sso cookie from the start is missing, or the ID token didn't check out", body = String, content_type = "text/plain"),
+        (status = 403, description = "The user is disabled or deleted", body = String, content_type = "text/plain"),
+        (status = 404, description = "Single sign-on isn't configured", body = String, content_type = "text/plain"),
+        (status = 409, description = "The SSO account is linked to another user", body = String, content_type = "text/plain"),
+        (status = 502, description = "The identity provider couldn't be reached or turned down the code", body = String, content_type = "text/plain"),
+    ),
+)]
+pub async fn oidc_callback(
+    req: HttpRequest,
+    body: web::Json<OidcCallbackRequest>,
+    db: web::Data<TodoDB>,
+    oidc: Option<web::Data<Oidc>>,
+    metrics: Option<web::Data<Metrics>>,
+) -> Result<HttpResponse, TodoAppError> {
+    let counted = |outcome| {
+        if let Some(metrics) = &metrics {
+            metrics.login(LoginMethod::Sso, outcome);
+        }
+    };
+    let oidc = match oidc {
+        Some(oidc) => oidc,
+        None => return Ok(not_configured()),
+    };
+    // a state the browser didn't get from us is somebody else's login, finishing
+    // it would log this browser in as them
+    let state_hash = hash_token(&body.state);
+    if req
+        .cookie(STATE_COOKIE)
+        .is_none_or(|cookie| cookie.value() != state_hash)
+    {
+        return Ok(bad_request(
+            "the SSO login was started in another browser, try again",
+        ));
+    }
+    let login = match db.take_oidc_login(&state_hash).await? {
+        Some(login) => login,
+        None => return Ok(bad_request("the SSO login expired, try again")),
+    };
+    let claims = match oidc
+        .exchange_code(&body.code, &login.code_verifier, &login.nonce)
+        .await
+    {
+        Ok(claims) => claims,
+        Err(e) => {
+            counted(LoginOutcome::Failure);
+            return Ok(provider_error(e));
+        }
+    };
+    let issuer = &oidc.config.issuer;
+    let linked = db.get_identity_user(issuer, &claims.sub).await?;
+    let user = match (login.user_id, linked) {
+        (Some(user_id), Some(user)) if user.id != user_id => {
+            return Ok(HttpResponseBuilder::new(StatusCode::CONFLICT)
+                .body("that SSO account is linked to another user"))
+        }
+        (_, Some(user)) => user,
+        (Some(user_id), None) => {
+            if !db.link_identity(user_id, issuer, &claims.sub).await? {
+                return Ok(HttpResponseBuilder::new(StatusCode::CONFLICT)
+                    .body("that SSO account is linked to another user"));
+            }
+            match db.get_identity_user(issuer, &claims.sub).await? {
+                Some(user) => user,
+                None => return Ok(bad_request("the SSO login expired, try again")),
+            }
+        }
+        (None, None) => {
+            let locale = onboarding_locale(&db, &req).await;
+            match register_sso_user(&db, issuer, &claims, &locale).await? {
+                Some(new_user) => IdentityUser {
+                    id: new_user.id,
+                    username: new_user.username,
+                    active: true,
+                },
+                // a first login racing this one made the user, this one logs
+                // in as them too
+                None => match db.get_identity_user(issuer, &claims.sub).await? {
+                    Some(user) => user,
+                    None => return Ok(bad_request("the SSO login expired, try again")),
+                },
+            }
+        }
+    };
+    if !user.active {
+        counted(LoginOutcome::Failure);
+        return Ok(
+            HttpResponseBuilder::new(StatusCode::FORBIDDEN).body("this account can't log in")
+        );
+    }
+    let user_info = log_in(&db, user.id, user.username).await?;
+    counted(LoginOutcome::Success);
+    Ok(HttpResponse::Ok()
+        .cookie(state_cookie(&req, String::new(), CookieDuration::ZERO))
+        .json(AuthResponse { data: user_info }))
+}
+
+// an account for somebody logging in with SSO for the first time, None when
+// another login linked the identity first. They never learn the random
+// password, but can set one through Forgot Password when the provider vouched
+// for their email.
+async fn register_sso_user(
+    db: &TodoDB,
+    issuer: &str,
+    claims: &IdClaims,
+    locale: &str,
+) -> Result<Option<todo_api::UserInfo>, TodoAppError> {
+    let email = match (&claims.email, claims.email_verified) {
+        (Some(email), Some(true)) => Some(normalize_email(email)),
+        _ => None,
+    };
+    let identity = NewIdentity {
+        issuer,
+        subject: &claims.sub,
+        email: email.as_deref(),
+    };
+    let hashed_password = hash_password(&random_token())?;
+    let name = username_base(claims);
+    for n in 1..=100 {
+        let username = match n {
+            1 => name.clone(),
+            n => format!("{}-{}", name, n),
+        };
+        let token = create_token(&username)?;
+        // the unique username is what decides, a check first could race
+        match db
+            .create_sso_user(&username, &hashed_password, &token, locale, &identity)
+            .await
+        {
+            Err(e) if e.status == StatusCode::CONFLICT => continue,
+            result => return result,
+        }
+    }
+    Err(TodoAppError {
+        name: format!("no free username like {}", name),
+        status: StatusCode::INTERNAL_SERVER_ERROR,
+    })
+}
+
+fn username_base(claims: &IdClaims) -> String {
+    let name = claims
+        .preferred_username
+        .as_deref()
+        .or_else(|| {
+            claims
+                .email
+                .as_deref()
+                .and_then(|email| email.split('@').next())
+        })
+        .unwrap_or_default();
+    // room for the number that makes it unique in the 64 characters users allow
+    let name: String = name
+        .chars()
+        .filter(|c| is_username_char(*c))
+        .take(56)
+        .collect();
+    if username_problem(&name).is_some() {
+        "sso-user".to_string()
+    } else {
+        name
+    }
+}
@@ -0,0 +1,261 @@
+use crate::routes::{
+    admin, api_tokens, events, oidc, password_resets, tasks, templates, two_factor, users,
+};
+use actix_web::http::header::ContentType;
+use actix_web::HttpResponse;
+use serde_json::Value;
+use utoipa::openapi::header::HeaderBuilder;
+use utoipa::openapi::path::{Parameter, ParameterBuilder, ParameterIn};
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::openapi::{ContentBuilder, ObjectBuilder, RefOr, Required, Response, ResponseBuilder};
+use utoipa::{IntoParams, Modify, OpenApi, ToResponse};
+
+pub const API_PREFIX: &str = "/api/v1";
+
+/*
+# OpenAPI document
+## route: "/openapi.json" GET
+
+curl localhost:3010/api/v1/openapi.json
+
+## route: "/docs" GET
+the same document rendered by Redoc, open it in a browser
+http://localhost:3010/api/v1/docs
+*/
+
+#[utoipa::path(
+    get,
+    path = "/openapi.json",
+    tag = "docs",
+    summary = "This document",
+    responses((status = 200, description = "The OpenAPI document", body = Object)),
+)]
+pub async fn openapi_json() -> HttpResponse {
+    HttpResponse::Ok().json(spec())
+}
+
+#[utoipa::path(
+    get,
+    path = "/docs",
+    tag = "docs",
+    summary = "This document rendered by Redoc",
+    responses((status = 200, description = "An HTML page", body = String, content_type = "text/html")),
+)]
+pub async fn api_docs() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(DOCS_PAGE)
+}
+
+// a fixed release of Redoc, `latest` would change the page under us
+const DOCS_PAGE: &str = r#"<!DOCTYPE html>
+<html>
+  <head>
+    <title>todo_server API</title>
+    <meta charset="utf-8"/>
+    <meta name="viewport" content="width=device-width, initial-scale=1">
+  </head>
+  <body>
+    <redoc spec-url="openapi.json"></redoc>
+    <script src="https://cdn.redoc.ly/redoc/v2.1.5/bundles/redoc.standalone.js" crossorigin="anonymous"></script>
+  </body>
+</html>
+"#;
+
+/// Every route registered in `routes::configure`, from the `#[utoipa::path]`
+/// of its handler, with the schemas of the todo_api types it takes and sends.
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "todo_server",
+        description = "Users and their tasks. Send the token from login or create user as `x-auth-token`, or a personal API token as `Authorization: Bearer`. A missing or invalid token gets a 401 with a WWW-Authenticate header, a read_only API token gets a 403 for anything but GET and HEAD. Any route answers 429 with a Retry-After header when a client sends too many requests, and 503 while the database can't be reached. Every response has an x-request-id header, the request's own when it sent one.",
+    ),
+    servers((url = "/api/v1")),
+    paths(
+        users::create_user,
+        users::login,
+        two_factor::login_two_factor,
+        users::logout,
+        users::update_me,
+        users::delete_me,
+        users::change_password,
+        two_factor::two_factor_status,
+        two_factor::start_two_factor,
+        two_factor::disable_two_factor,
+        two_factor::confirm_two_factor,
+        api_tokens::get_api_tokens,
+        api_tokens::create_api_token,
+        api_tokens::delete_api_token,
+        password_resets::forgot_password,
+        password_resets::reset_password,
+        oidc::oidc_authorize,
+        oidc::oidc_callback,
+        tasks::create_task,
+        tasks::get_all_tasks,
+        tasks::get_task_id,
+        tasks::update_task,
+        tasks::delete_task,
+        tasks::set_task_completed,
+        tasks::set_task_uncompleted,
+        admin::list_users,
+        admin::disable_user,
+        admin::enable_user,
+        admin::stats,
+        templates::get_templates,
+        templates::create_template,
+        templates::get_template,
+        templates::update_template,
+        templates::delete_template,
+        events::task_socket,
+        events::task_event_stream,
+        openapi_json,
+        api_docs,
+    ),
+    components(responses(InvalidToken, ReadOnly, SessionOnly, RateLimited, NoSuchTask)),
+    modifiers(&SecuritySchemes),
+)]
+pub struct ApiDoc;
+
+/// The OpenAPI document as JSON.
+pub fn spec() -> Value {
+    serde_json::to_value(ApiDoc::openapi()).unwrap()
+}
+
+// the ways to send a token, named in each route's `security(...)`
+struct SecuritySchemes;
+
+impl Modify for SecuritySchemes {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        // filled in from Cargo.toml, which doesn't have a license
+        openapi.info.license = None;
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "token",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new("x-auth-token"))),
+        );
+        components.add_security_scheme(
+            "bearer",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .description(Some(
+                        "A session token or a personal API token, read_only ones only work for GET",
+                    ))
+                    .build(),
+            ),
+        );
+        components.add_security_scheme(
+            "query_token",
+            SecurityScheme::ApiKey(ApiKey::Query(ApiKeyValue::new("token"))),
+        );
+    }
+}
+
+fn text_response(description: &str) -> ResponseBuilder {
+    let text = ContentBuilder::new()
+        .schema(Some(
+            ObjectBuilder::new().schema_type(utoipa::openapi::Type::String),
+        ))
+        .build();
+    ResponseBuilder::new()
+        .description(description)
+        .content("text/plain", text)
+}
+
+/// A 401, the token is missing or doesn't work.
+pub struct InvalidToken;
+
+impl<'r> ToResponse<'r> for InvalidToken {
+    fn response() -> (&'r str, RefOr<Response>) {
+        (
+            "InvalidToken",
+            text_response("Invalid token").build().into(),
+        )
+    }
+}
+
+/// The 403 for a read_only API token on a route that changes something.
+pub struct ReadOnly;
+
+impl<'r> ToResponse<'r> for ReadOnly {
+    fn response() -> (&'r str, RefOr<Response>) {
+        let response = text_response("A read_only API token, this needs read_write");
+        ("ReadOnly", response.build().into())
+    }
+}
+
+/// The 403 for a personal API token on a route for the account itself.
+pub struct SessionOnly;
+
+impl<'r> ToResponse<'r> for SessionOnly {
+    fn response() -> (&'r str, RefOr<Response>) {
+        let response = text_response("A personal API token, this needs a session token");
+        ("SessionOnly", response.build().into())
+    }
+}
+
+/// A 429 from the rate limiter.
+pub struct RateLimited;
+
+impl<'r> ToResponse<'r> for RateLimited {
+    fn response() -> (&'r str, RefOr<Response>) {
+        let retry_after = HeaderBuilder::new()
+            .schema(ObjectBuilder::new().schema_type(utoipa::openapi::Type::Integer))
+            .description(Some("Seconds until trying again"))
+            .build();
+        let response = text_response(
+            "Too many attempts from this address, or failed logins for this username",
+        )
+        .header("Retry-After", retry_after);
+        ("RateLimited", response.build().into())
+    }
+}
+
+/// The 404 for a task that doesn't exist, is deleted or is someone else's.
+pub struct NoSuchTask;
+
+impl<'r> ToResponse<'r> for NoSuchTask {
+    fn response() -> (&'r str, RefOr<Response>) {
+        ("NoSuchTask", text_response("No such task").build().into())
+    }
+}
+
+fn header(name: &str, description: &str, schema: ObjectBuilder) -> Parameter {
+    ParameterBuilder::new()
+        .name(name)
+        .parameter_in(ParameterIn::Header)
+        .required(Required::False)
+        .description(Some(description))
+        .schema(Some(schema))
+        .build()
+}
+
+/// The Idempotency-Key header the idempotency middleware looks for.
+pub struct IdempotencyKey;
+
+impl IntoParams for IdempotencyKey {
+    fn into_params(_: impl Fn() -> Option<ParameterIn>) -> Vec<Parameter> {
+        let key = ObjectBuilder::new()
+            .schema_type(utoipa::openapi::Type::String)
+            .min_length(Some(1))
+            .max_length(Some(255));
+        vec![header(
+            "Idempotency-Key",
+            "Retries with the same key get the first response back instead of running again, the same key with another body gets a 422. Only for requests with a token",
+            key,
+        )]
+    }
+}
+
+/// The If-Match header of the task routes that check the version.
+pub struct IfMatch;
+
+impl IntoParams for IfMatch {
+    fn into_params(_: impl Fn() -> Option<ParameterIn>) -> Vec<Parameter> {
+        vec![header(
+            "If-Match",
+            "Only change the task if it is still at this version, the ETag it was read with",
+            ObjectBuilder::new().schema_type(utoipa::openapi::Type::String),
+        )]
+    }
+}
@@ -0,0 +1,126 @@
+use crate::database::TodoDB;
+use crate::mailer::{Email, Mailer};
+use crate::passwords::new_password_problem;
+use crate::routes::auth::{hash_token, random_token};
+use crate::routes::openapi::RateLimited;
+use crate::routes::users::{hash_password, normalize_email};
+use crate::routes::TodoAppError;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, HttpResponseBuilder};
+use todo_api::{ForgotPasswordRequest, MessageResponse, ResetPasswordRequest};
+
+/*
+# forgot password
+## route: "/users/forgot-password" POST
+emails a reset link to the account with that email, if there is one. The answer
+is the same either way so it can't be used to find out who has an account.
+
+curl -X POST \
+localhost:3010/api/v1/users/forgot-password \
+-H "Content-Type: application/json" \
+--data '{ "email": "matt@example.com" }'
+
+### response:
+{"message":"if that email belongs to an account, a reset link is on its way"}
+
+# reset password
+## route: "/users/reset-password" POST
+with the token from the link, which works once and for an hour. Logs the user out.
+
+curl -X POST \
+localhost:3010/api/v1/users/reset-password \
+-H "Content-Type: application/json" \
+--data '{ "token": "1f0c...", "new_password": "myfancierpass" }'
+
+### response:
+{"message":"password changed, log in with the new one"}
+*/
+
+const RESET_TOKEN_TTL_MINUTES: i32 = 60;
+
+// where the yew solution is served, the link in the email points at its reset
+// page and SSO logins come back to its /sso-callback page
+pub fn app_url() -> String {
+    dotenv::var("APP_URL").unwrap_or_else(|_| "http://localhost:8080".to_string())
+}
+
+#[utoipa::path(
+    post,
+    path = "/users/forgot-password",
+    tag = "users",
+    summary = "Email a password reset link, the answer is the same for an unknown email",
+    request_body = ForgotPasswordRequest,
+    responses(
+        (status = 200, description = "Sent if the email belongs to an account", body = MessageResponse),
+        (status = 429, response = RateLimited),
+    ),
+)]
+pub async fn forgot_password(
+    body: web::Json<ForgotPasswordRequest>,
+    db: web::Data<TodoDB>,
+    mailer: web::Data<dyn Mailer>,
+) -> Result<HttpResponse, TodoAppError> {
+    let email = normalize_email(&body.email);
+    let token = random_token();
+    if db
+        .insert_password_reset(&email, &hash_token(&token), RESET_TOKEN_TTL_MINUTES)
+        .await?
+    {
+        let email = Email {
+            to: email,
+            subject: "Reset your todo password".to_string(),
+            body: format!(
+                "Somebody asked to reset the password of your todo account. If it was you, \
+                 pick a new one here within {} minutes:\n\n{}/reset-password?token={}\n\n\
+                 If it wasn't, you can ignore this email.",
+                RESET_TOKEN_TTL_MINUTES,
+                app_url(),
+                token
+            ),
+        };
+        // the answer can't differ from the one for an unknown email, not even in
+        // how long it takes, so the sending happens after it
+        let mailer = mailer.into_inner();
+        actix_web::rt::spawn(async move {
+            if let Err(e) = mailer.send(&email).await {
+                tracing::error!(to = %email.to, error = %e, "sending a password reset failed");
+            }
+        });
+    }
+    Ok(HttpResponse::Ok().json(MessageResponse {
+        message: "if that email belongs to an account, a reset link is on its way".to_string(),
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/users/reset-password",
+    tag = "users",
+    summary = "Set a new password with the token from a reset link, which logs the user out",
+    request_body = ResetPasswordRequest,
+    responses(
+        (status = 200, description = "Changed", body = MessageResponse),
+        (status = 400, description = "The token is wrong, used or expired, or the password policy turns the password down", body = String, content_type = "text/plain"),
+        (status = 429, response = RateLimited),
+    ),
+)]
+pub async fn reset_password(
+    body: web::Json<ResetPasswordRequest>,
+    db: web::Data<TodoDB>,
+) -> Result<HttpResponse, TodoAppError> {
+    // the link doesn't say whose password it is, so that rule can't be checked
+    if let Some(problem) = new_password_problem("", &body.new_password) {
+        return Ok(HttpResponseBuilder::new(StatusCode::BAD_REQUEST).body(problem));
+    }
+    let hashed_password = hash_password(&body.new_password)?;
+    if !db
+        .reset_password(&hash_token(&body.token), &hashed_password)
+        .await?
+    {
+        return Ok(HttpResponseBuilder::new(StatusCode::BAD_REQUEST)
+            .body("the reset link is wrong, used or expired"));
+    }
+    Ok(HttpResponse::Ok().json(MessageResponse {
+        message: "password changed, log in with the new one".to_string(),
+    }))
+}
@@ -1,170 +1,316 @@
+use crate::database::task_queries::VersionedWrite;
 use crate::database::{TaskId, TodoDB, UserId};
+use crate::events::{TaskEvent, TaskEvents};
+use crate::routes::auth::AuthenticatedUser;
 use crate::routes::TodoAppError;
+use crate::routes::openapi::{IdempotencyKey, IfMatch, InvalidToken, NoSuchTask, ReadOnly};
+use actix_web::http::header::{ETAG, IF_MATCH};
 use actix_web::http::StatusCode;
 use actix_web::{web, HttpRequest, HttpResponse, HttpResponseBuilder};
 use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
+use todo_api::{DataResponse, TaskListResponse, TaskResponse};
 
-#[derive(Serialize, Deserialize)]
-pub struct CreateTaskRequest {
-    pub title: String,
-    pub description: String,
-}
+// the API's view of a task, shared with the clients
+pub use todo_api::{CreateTaskRequest, Task as TaskInfo, UpdateTaskRequest};
 
+// a row of the tasks table
 #[derive(Serialize, Deserialize)]
-pub struct TaskInfo {
+pub struct Task {
     pub id: TaskId,
     pub priority: Option<String>,
     pub title: String,
     pub completed_at: Option<NaiveDateTime>,
-    pub description: String,
+    pub description: Option<String>,
+    pub deleted_at: Option<NaiveDateTime>,
+    pub user_id: UserId,
+    pub is_default: bool,
+    pub version: i32,
 }
 
-#[derive(Serialize, Deserialize)]
-struct TaskResponse {
-    data: TaskInfo,
+impl From<Task> for TaskInfo {
+    fn from(task: Task) -> Self {
+        TaskInfo {
+            id: task.id,
+            priority: task.priority,
+            title: task.title,
+            completed_at: task.completed_at,
+            description: task.description,
+            version: task.version,
+        }
+    }
 }
 
-#[derive(Serialize, Deserialize)]
-struct TaskListResponse {
-    data: Vec<TaskInfo>,
+fn etag(version: i32) -> String {
+    format!("\"{}\"", version)
 }
 
-#[derive(Serialize, Deserialize)]
-pub struct Task {
-    pub id: TaskId,
-    pub priority: Option<String>,
-    pub title: String,
-    pub completed_at: Option<NaiveDateTime>,
-    pub description: String,
-    pub deleted_at: Option<NaiveDateTime>,
-    pub user_id: UserId,
-    pub is_default: bool,
+// Ok(None) when the client sent no precondition (or `*`), Err(()) when If-Match
+// can't be one of our ETags, which can never match. If-Match compares strongly,
+// so a weak W/ tag never matches either.
+fn if_match_version(req: &HttpRequest) -> Result<Option<i32>, ()> {
+    let header = match req.headers().get(IF_MATCH) {
+        Some(h) => h.to_str().map_err(|_| ())?.trim(),
+        None => return Ok(None),
+    };
+    if header == "*" {
+        return Ok(None);
+    }
+    header
+        .strip_prefix('"')
+        .and_then(|header| header.strip_suffix('"'))
+        .ok_or(())?
+        .parse()
+        .map(Some)
+        .map_err(|_| ())
 }
 
+fn no_such_task() -> HttpResponse {
+    HttpResponseBuilder::new(StatusCode::NOT_FOUND).body("no such task")
+}
+
+fn precondition_failed(current: Option<TaskInfo>) -> HttpResponse {
+    match current {
+        Some(task) => HttpResponseBuilder::new(StatusCode::PRECONDITION_FAILED)
+            .insert_header((ETAG, etag(task.version)))
+            .json(TaskResponse { data: task }),
+        None => HttpResponseBuilder::new(StatusCode::PRECONDITION_FAILED)
+            .body("If-Match does not name a task version"),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/tasks",
+    tag = "tasks",
+    summary = "Create a task",
+    security(("token" = []), ("bearer" = [])),
+    params(IdempotencyKey),
+    request_body = CreateTaskRequest,
+    responses(
+        (status = 200, description = "The new task", body = DataResponse<TaskInfo>,
+            headers(("ETag" = String, description = "The task's version"))),
+        (status = 401, response = InvalidToken),
+        (status = 403, response = ReadOnly),
+    ),
+)]
 pub async fn create_task(
-    req: HttpRequest,
+    AuthenticatedUser(user): AuthenticatedUser,
     body: web::Json<CreateTaskRequest>,
     db: web::Data<TodoDB>,
+    events: web::Data<TaskEvents>,
 ) -> Result<HttpResponse, TodoAppError> {
-    if let Some(user) = db.authenticate(&req).await {
-        let create_request = CreateTaskRequest {
-            title: body.title.clone(),
-            description: body.description.clone(),
-        };
-        let create_response = db.insert_task(&create_request, user.id).await;
-        if let Some(info) = create_response {
-            return Ok(HttpResponse::Ok().json(TaskResponse { data: info }));
-        } else {
-            return Ok(HttpResponseBuilder::new(StatusCode::BAD_REQUEST)
-                .body("something wrong with db returning inserted task"));
-        }
-    }
-    Ok(HttpResponseBuilder::new(StatusCode::BAD_REQUEST).body("invalid token"))
+    let info = db.insert_task(&body, user.id).await?;
+    events
+        .publish(&db, user.id, TaskEvent::TaskCreated(info.clone()))
+        .await;
+    Ok(HttpResponse::Ok()
+        .insert_header((ETAG, etag(info.version)))
+        .json(TaskResponse { data: info }))
 }
 
+#[utoipa::path(
+    get,
+    path = "/tasks",
+    tag = "tasks",
+    summary = "All of the user's tasks",
+    security(("token" = []), ("bearer" = [])),
+    responses(
+        (status = 200, description = "The tasks", body = DataResponse<Vec<TaskInfo>>),
+        (status = 401, response = InvalidToken),
+    ),
+)]
 pub async fn get_all_tasks(
-    req: HttpRequest,
+    AuthenticatedUser(user): AuthenticatedUser,
     db: web::Data<TodoDB>,
 ) -> Result<HttpResponse, TodoAppError> {
-    if let Some(user) = db.authenticate(&req).await {
-        let result = db.get_all_tasks(user.id).await;
-        if let Some(tasks) = result {
-            return Ok(HttpResponse::Ok().json(TaskListResponse { data: tasks }));
-        }
-    }
-    return Ok(HttpResponseBuilder::new(StatusCode::BAD_REQUEST).body("invalid token"));
+    let tasks = db.get_all_tasks(user.id).await?;
+    Ok(HttpResponse::Ok().json(TaskListResponse { data: tasks }))
 }
 
+#[utoipa::path(
+    put,
+    path = "/tasks/{id}/completed",
+    tag = "tasks",
+    summary = "Mark a task as completed now",
+    security(("token" = []), ("bearer" = [])),
+    params(IdempotencyKey, ("id" = i32, Path, description = "The task's id")),
+    responses(
+        (status = 200, description = "Completed", body = String, content_type = "text/plain",
+            headers(("ETag" = String, description = "The task's new version"))),
+        (status = 401, response = InvalidToken),
+        (status = 403, response = ReadOnly),
+        (status = 404, response = NoSuchTask),
+    ),
+)]
 pub async fn set_task_completed(
-    req: HttpRequest,
+    AuthenticatedUser(user): AuthenticatedUser,
     db: web::Data<TodoDB>,
+    events: web::Data<TaskEvents>,
     id: web::Path<TaskId>,
 ) -> Result<HttpResponse, TodoAppError> {
-    if let Some(user) = db.authenticate(&req).await {
-        if db.mark_completed(user.id, *id).await {
-            return Ok(
-                HttpResponse::Ok().body(format!("OK you completed task {}", id.into_inner()))
-            );
-        }
+    if let Some(task) = db.mark_completed(user.id, *id).await? {
+        let version = task.version;
+        events
+            .publish(&db, user.id, TaskEvent::TaskUpdated(task))
+            .await;
+        return Ok(HttpResponse::Ok()
+            .insert_header((ETAG, etag(version)))
+            .body(format!("OK you completed task {}", id.into_inner())));
     }
-    Ok(HttpResponseBuilder::new(StatusCode::BAD_REQUEST).body("error"))
+    Ok(no_such_task())
 }
 
+#[utoipa::path(
+    put,
+    path = "/tasks/{id}/uncompleted",
+    tag = "tasks",
+    summary = "Mark a task as not completed",
+    security(("token" = []), ("bearer" = [])),
+    params(IdempotencyKey, ("id" = i32, Path, description = "The task's id")),
+    responses(
+        (status = 200, description = "Not completed", body = String, content_type = "text/plain",
+            headers(("ETag" = String, description = "The task's new version"))),
+        (status = 401, response = InvalidToken),
+        (status = 403, response = ReadOnly),
+        (status = 404, response = NoSuchTask),
+    ),
+)]
 pub async fn set_task_uncompleted(
-    req: HttpRequest,
+    AuthenticatedUser(user): AuthenticatedUser,
     db: web::Data<TodoDB>,
+    events: web::Data<TaskEvents>,
     id: web::Path<TaskId>,
 ) -> Result<HttpResponse, TodoAppError> {
-    if let Some(user) = db.authenticate(&req).await {
-        if db.mark_uncompleted(user.id, *id).await {
-            return Ok(
-                HttpResponse::Ok().body(format!("OK you un-completed task {}", id.into_inner()))
-            );
-        }
+    if let Some(task) = db.mark_uncompleted(user.id, *id).await? {
+        let version = task.version;
+        events
+            .publish(&db, user.id, TaskEvent::TaskUpdated(task))
+            .await;
+        return Ok(HttpResponse::Ok()
+            .insert_header((ETAG, etag(version)))
+            .body(format!("OK you un-completed task {}", id.into_inner())));
     }
-    Ok(HttpResponseBuilder::new(StatusCode::BAD_REQUEST).body("error"))
+    Ok(no_such_task())
 }
 
+#[utoipa::path(
+    get,
+    path = "/tasks/{id}",
+    tag = "tasks",
+    summary = "One task",
+    security(("token" = []), ("bearer" = [])),
+    params(("id" = i32, Path, description = "The task's id")),
+    responses(
+        (status = 200, description = "The task", body = DataResponse<TaskInfo>,
+            headers(("ETag" = String, description = "The task's version"))),
+        (status = 401, response = InvalidToken),
+        (status = 404, response = NoSuchTask),
+    ),
+)]
 pub async fn get_task_id(
-    req: HttpRequest,
+    AuthenticatedUser(user): AuthenticatedUser,
     db: web::Data<TodoDB>,
     id: web::Path<i32>,
 ) -> Result<HttpResponse, TodoAppError> {
-    if let Some(user) = db.authenticate(&req).await {
-        let task_id = id.into_inner();
-        let task = db.get_task(user.id, task_id).await;
-        if let Some(t) = task {
-            let info = TaskInfo {
-                id: t.id,
-                priority: t.priority,
-                title: t.title,
-                completed_at: t.completed_at,
-                description: t.description,
-            };
-            return Ok(HttpResponse::Ok().json(TaskResponse { data: info }));
-        } else {
-            return Ok(HttpResponseBuilder::new(StatusCode::BAD_REQUEST).body("no such task"));
-        }
+    let task_id = id.into_inner();
+    let task = db.get_task(user.id, task_id).await?;
+    if let Some(t) = task.filter(|t| t.deleted_at.is_none()) {
+        let info = TaskInfo::from(t);
+        return Ok(HttpResponse::Ok()
+            .insert_header((ETAG, etag(info.version)))
+            .json(TaskResponse { data: info }));
     }
-    return Ok(HttpResponseBuilder::new(StatusCode::BAD_REQUEST).body("invalid token"));
+    Ok(no_such_task())
 }
 
+#[utoipa::path(
+    patch,
+    path = "/tasks/{id}",
+    tag = "tasks",
+    summary = "Change some of a task's fields",
+    security(("token" = []), ("bearer" = [])),
+    params(IdempotencyKey, IfMatch, ("id" = i32, Path, description = "The task's id")),
+    request_body = UpdateTaskRequest,
+    responses(
+        (status = 200, description = "The updated task", body = DataResponse<TaskInfo>,
+            headers(("ETag" = String, description = "The task's version"))),
+        (status = 401, response = InvalidToken),
+        (status = 403, response = ReadOnly),
+        (status = 404, response = NoSuchTask),
+        (status = 412, description = "If-Match doesn't match the task's version, the body is the task as it is now", body = DataResponse<TaskInfo>,
+            headers(("ETag" = String, description = "The task's version"))),
+    ),
+)]
 pub async fn update_task(
     req: HttpRequest,
+    AuthenticatedUser(user): AuthenticatedUser,
     db: web::Data<TodoDB>,
-    _id: web::Path<TaskId>,
-    body: web::Json<TaskInfo>,
+    events: web::Data<TaskEvents>,
+    id: web::Path<TaskId>,
+    body: web::Json<UpdateTaskRequest>,
 ) -> Result<HttpResponse, TodoAppError> {
-    println!("we are here in the right function");
-
-    if let Some(user) = db.authenticate(&req).await {
-        let update = TaskInfo {
-            id: body.id,
-            priority: body.priority.clone(),
-            title: body.title.clone(),
-            completed_at: body.completed_at,
-            description: body.description.clone(),
-        };
-        if let Some(update_result) = db.update_task(&update, user.id).await {
-            return Ok(HttpResponse::Ok().json(TaskResponse {
-                data: update_result,
-            }));
+    let expected_version = match if_match_version(&req) {
+        Ok(version) => version,
+        Err(_) => return Ok(precondition_failed(None)),
+    };
+    match db.update_task(*id, &body, user.id, expected_version).await? {
+        VersionedWrite::Written(update_result) => {
+            events
+                .publish(&db, user.id, TaskEvent::TaskUpdated(update_result.clone()))
+                .await;
+            return Ok(HttpResponse::Ok()
+                .insert_header((ETAG, etag(update_result.version)))
+                .json(TaskResponse {
+                    data: update_result,
+                }));
         }
+        VersionedWrite::Conflict(current) => {
+            return Ok(precondition_failed(Some(current)));
+        }
+        VersionedWrite::NotFound => {}
     }
-    Ok(HttpResponseBuilder::new(StatusCode::BAD_REQUEST).body("error"))
+    Ok(no_such_task())
 }
 
+#[utoipa::path(
+    delete,
+    path = "/tasks/{id}",
+    tag = "tasks",
+    summary = "Delete a task",
+    security(("token" = []), ("bearer" = [])),
+    params(IdempotencyKey, IfMatch, ("id" = i32, Path, description = "The task's id")),
+    responses(
+        (status = 200, description = "Deleted", body = String, content_type = "text/plain"),
+        (status = 401, response = InvalidToken),
+        (status = 403, response = ReadOnly),
+        (status = 404, response = NoSuchTask),
+        (status = 412, description = "If-Match doesn't match the task's version, the body is the task as it is now", body = DataResponse<TaskInfo>,
+            headers(("ETag" = String, description = "The task's version"))),
+    ),
+)]
 pub async fn delete_task(
     req: HttpRequest,
+    AuthenticatedUser(user): AuthenticatedUser,
     db: web::Data<TodoDB>,
+    events: web::Data<TaskEvents>,
     id: web::Path<TaskId>,
 ) -> Result<HttpResponse, TodoAppError> {
-    if let Some(user) = db.authenticate(&req).await {
-        let success = db.soft_delete_task(user.id, *id).await;
-        if success {
+    let expected_version = match if_match_version(&req) {
+        Ok(version) => version,
+        Err(_) => return Ok(precondition_failed(None)),
+    };
+    match db.soft_delete_task(user.id, *id, expected_version).await? {
+        VersionedWrite::Written(()) => {
+            events
+                .publish(&db, user.id, TaskEvent::TaskDeleted { id: *id })
+                .await;
             return Ok(HttpResponseBuilder::new(StatusCode::OK).body("deleted task"));
         }
+        VersionedWrite::Conflict(current) => {
+            return Ok(precondition_failed(Some(current)));
+        }
+        VersionedWrite::NotFound => {}
     }
-    Ok(HttpResponseBuilder::new(StatusCode::BAD_REQUEST).body("error"))
+    Ok(no_such_task())
 }
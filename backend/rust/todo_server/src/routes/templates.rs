@@ -0,0 +1,226 @@
+use crate::database::template_queries::DEFAULT_LOCALE;
+use crate::database::TodoDB;
+use crate::routes::auth::RequireAdmin;
+use crate::routes::TodoAppError;
+use crate::routes::openapi::{IdempotencyKey, InvalidToken};
+use actix_web::http::header::ACCEPT_LANGUAGE;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest, HttpResponse, HttpResponseBuilder};
+use serde::Deserialize;
+use utoipa::IntoParams;
+use todo_api::{
+    CreateTemplateRequest, DataResponse, TaskTemplate, TemplateId, TemplateListResponse,
+    TemplateResponse, UpdateTemplateRequest,
+};
+
+/*
+The tasks a new account starts with. Only admins can see or change them.
+
+# list templates
+## route: "/templates" GET, "/templates?locale=es" for one locale
+
+curl localhost:3010/api/v1/templates -H "x-auth-token: $TOKEN"
+
+### response:
+{
+    "data": [
+        {
+            "id": 1,
+            "locale": "en",
+            "position": 0,
+            "title": "I am a task, you can complete me by checking the box",
+            "description": "This is my description",
+            "priority": "A"
+        }
+    ]
+}
+
+# create a template
+## route: "/templates" POST
+
+curl -X POST localhost:3010/api/v1/templates \
+-H "x-auth-token: $TOKEN" \
+-H "Content-Type: application/json" \
+--data '{ "title": "Riega las plantas", "priority": "B", "locale": "es" }'
+
+# get, change or delete a template
+## route: "/templates/:id" GET, PATCH, DELETE
+PATCH takes the fields of the create body, anything left out stays as it is
+*/
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct TemplateQuery {
+    /// Only the templates of this locale
+    locale: Option<String>,
+}
+
+fn not_found(id: TemplateId) -> HttpResponse {
+    HttpResponseBuilder::new(StatusCode::NOT_FOUND).body(format!("no template {}", id))
+}
+
+#[utoipa::path(
+    get,
+    path = "/templates",
+    tag = "templates",
+    summary = "The templates new users get their first tasks from",
+    security(("token" = []), ("bearer" = [])),
+    params(TemplateQuery),
+    responses(
+        (status = 200, description = "The templates", body = DataResponse<Vec<TaskTemplate>>),
+        (status = 401, response = InvalidToken),
+        (status = 403, description = "Not an admin", body = String, content_type = "text/plain"),
+    ),
+)]
+pub async fn get_templates(
+    _admin: RequireAdmin,
+    query: web::Query<TemplateQuery>,
+    db: web::Data<TodoDB>,
+) -> Result<HttpResponse, TodoAppError> {
+    let templates = db.get_templates(query.locale.as_deref()).await?;
+    Ok(HttpResponse::Ok().json(TemplateListResponse { data: templates }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/templates",
+    tag = "templates",
+    summary = "Create a template",
+    security(("token" = []), ("bearer" = [])),
+    params(IdempotencyKey),
+    request_body = CreateTemplateRequest,
+    responses(
+        (status = 200, description = "The new template", body = DataResponse<TaskTemplate>),
+        (status = 401, response = InvalidToken),
+        (status = 403, description = "Not an admin, or a read_only API token", body = String, content_type = "text/plain"),
+    ),
+)]
+pub async fn create_template(
+    _admin: RequireAdmin,
+    body: web::Json<CreateTemplateRequest>,
+    db: web::Data<TodoDB>,
+) -> Result<HttpResponse, TodoAppError> {
+    let template = db.insert_template(&body).await?;
+    Ok(HttpResponse::Ok().json(TemplateResponse { data: template }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/templates/{id}",
+    tag = "templates",
+    summary = "One template",
+    security(("token" = []), ("bearer" = [])),
+    params(("id" = i32, Path, description = "The template's id")),
+    responses(
+        (status = 200, description = "The template", body = DataResponse<TaskTemplate>),
+        (status = 401, response = InvalidToken),
+        (status = 403, description = "Not an admin", body = String, content_type = "text/plain"),
+        (status = 404, description = "No such template", body = String, content_type = "text/plain"),
+    ),
+)]
+pub async fn get_template(
+    _admin: RequireAdmin,
+    id: web::Path<TemplateId>,
+    db: web::Data<TodoDB>,
+) -> Result<HttpResponse, TodoAppError> {
+    match db.get_template(*id).await? {
+        Some(template) => Ok(HttpResponse::Ok().json(TemplateResponse { data: template })),
+        None => Ok(not_found(*id)),
+    }
+}
+
+#[utoipa::path(
+    patch,
+    path = "/templates/{id}",
+    tag = "templates",
+    summary = "Change some of a template's fields",
+    security(("token" = []), ("bearer" = [])),
+    params(IdempotencyKey, ("id" = i32, Path, description = "The template's id")),
+    request_body = UpdateTemplateRequest,
+    responses(
+        (status = 200, description = "The updated template", body = DataResponse<TaskTemplate>),
+        (status = 401, response = InvalidToken),
+        (status = 403, description = "Not an admin, or a read_only API token", body = String, content_type = "text/plain"),
+        (status = 404, description = "No such template", body = String, content_type = "text/plain"),
+    ),
+)]
+pub async fn update_template(
+    _admin: RequireAdmin,
+    id: web::Path<TemplateId>,
+    body: web::Json<UpdateTemplateRequest>,
+    db: web::Data<TodoDB>,
+) -> Result<HttpResponse, TodoAppError> {
+    match db.update_template(*id, &body).await? {
+        Some(template) => Ok(HttpResponse::Ok().json(TemplateResponse { data: template })),
+        None => Ok(not_found(*id)),
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/templates/{id}",
+    tag = "templates",
+    summary = "Delete a template, accounts made from it keep their tasks",
+    security(("token" = []), ("bearer" = [])),
+    params(IdempotencyKey, ("id" = i32, Path, description = "The template's id")),
+    responses(
+        (status = 200, description = "Deleted", body = String, content_type = "text/plain"),
+        (status = 401, response = InvalidToken),
+        (status = 403, description = "Not an admin, or a read_only API token", body = String, content_type = "text/plain"),
+        (status = 404, description = "No such template", body = String, content_type = "text/plain"),
+    ),
+)]
+pub async fn delete_template(
+    _admin: RequireAdmin,
+    id: web::Path<TemplateId>,
+    db: web::Data<TodoDB>,
+) -> Result<HttpResponse, TodoAppError> {
+    if db.delete_template(*id).await? {
+        Ok(HttpResponse::Ok().body(format!("deleted template {}", id)))
+    } else {
+        Ok(not_found(*id))
+    }
+}
+
+/// The locale a new account gets its templates in, the best match for the
+/// request's Accept-Language among the locales there are templates for.
+pub async fn onboarding_locale(db: &TodoDB, req: &HttpRequest) -> String {
+    let accept_language = req
+        .headers()
+        .get(ACCEPT_LANGUAGE)
+        .and_then(|header| header.to_str().ok());
+    match (accept_language, db.template_locales().await) {
+        (Some(accept_language), Ok(locales)) => choose_locale(accept_language, &locales),
+        _ => None,
+    }
+    .unwrap_or_else(|| DEFAULT_LOCALE.to_string())
+}
+
+/// Picks from `available` for an Accept-Language like "es-MX,es;q=0.9,en;q=0.8",
+/// trying each language in order of preference, first exactly and then by its
+/// primary subtag, so "es-MX" gets "es" templates when there are no "es-MX" ones.
+pub fn choose_locale(accept_language: &str, available: &[String]) -> Option<String> {
+    let mut wanted: Vec<(String, f32)> = accept_language
+        .split(',')
+        .filter_map(|part| {
+            let mut pieces = part.split(';');
+            let tag = pieces.next()?.trim().to_lowercase();
+            let quality = pieces
+                .find_map(|piece| piece.trim().strip_prefix("q="))
+                .map_or(Some(1.0), |q| q.trim().parse().ok())?;
+            (!tag.is_empty() && tag != "*" && quality > 0.0).then_some((tag, quality))
+        })
+        .collect();
+    // stable, so equally preferred languages keep the order they were sent in
+    wanted.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    let find = |tag: &str| {
+        available
+            .iter()
+            .find(|locale| locale.to_lowercase() == tag)
+            .cloned()
+    };
+    wanted.iter().find_map(|(tag, _)| {
+        find(tag).or_else(|| tag.split('-').next().and_then(&find))
+    })
+}
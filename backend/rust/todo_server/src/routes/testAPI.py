@@ -20,6 +20,7 @@ def create_user(user, password):
     json_response["id"]
     json_response["username"]
     jwt = json_response["token"]
+    assert("password" not in json_response)
     print("create_user() passed")
     return jwt
 
@@ -39,6 +40,7 @@ def login(user, password):
     json_response["id"]
     json_response["username"]
     jwt = json_response["token"]
+    assert("password" not in json_response)
     print("login() passed")
     return jwt, json_response["id"]
 
@@ -72,6 +74,25 @@ def create_task(jwt, title, description):
     return json_response["id"]
 
 
+def create_task_idempotent(jwt, title, description):
+    key = "".join(random.choices(string.ascii_letters, k=20))
+    header = { "Content-Type": "application/json", "x-auth-token": jwt, "Idempotency-Key": key }
+    payload = { "title": title, "description": description }
+    first = requests.post(
+        "http://localhost:3010/api/v1/tasks",
+        data=json.dumps(payload),
+        headers=header)
+    assert(first.status_code == requests.codes.ok)
+    retry = requests.post(
+        "http://localhost:3010/api/v1/tasks",
+        data=json.dumps(payload),
+        headers=header)
+    assert(retry.status_code == requests.codes.ok)
+    assert(retry.headers["Idempotent-Replayed"] == "true")
+    assert(retry.json()["data"]["id"] == first.json()["data"]["id"])
+    print("create_task_idempotent() passed")
+
+
 def get_task(jwt, task_id):
     header = { "x-auth-token": jwt }
     r = requests.get(
@@ -139,6 +160,51 @@ def update_task(jwt, task_id):
     print("update_task() passed")
 
 
+def update_task_conflict(jwt, task_id):
+    r = requests.get(
+        f"http://localhost:3010/api/v1/tasks/{task_id}",
+        headers={ "x-auth-token": jwt })
+    assert(r.status_code == requests.codes.ok)
+    etag = r.headers["ETag"]
+    payload = r.json()["data"]
+    payload["title"] = "first writer"
+    header = { "Content-Type": "application/json", "x-auth-token": jwt, "If-Match": etag }
+    r = requests.patch(
+        f"http://localhost:3010/api/v1/tasks/{task_id}",
+        data=json.dumps(payload),
+        headers=header)
+    assert(r.status_code == requests.codes.ok)
+    assert(r.headers["ETag"] != etag)
+    payload["title"] = "second writer"
+    r = requests.patch(
+        f"http://localhost:3010/api/v1/tasks/{task_id}",
+        data=json.dumps(payload),
+        headers=header)
+    print(f"update_task_conflict() {r.status_code}")
+    assert(r.status_code == requests.codes.precondition_failed)
+    assert(r.json()["data"]["title"] == "first writer")
+    print("update_task_conflict() passed")
+
+
+def update_task_partial(jwt, task_id):
+    header = { "Content-Type": "application/json", "x-auth-token": jwt }
+    before = requests.get(
+        f"http://localhost:3010/api/v1/tasks/{task_id}",
+        headers=header).json()["data"]
+    r = requests.patch(
+        f"http://localhost:3010/api/v1/tasks/{task_id}",
+        data=json.dumps({ "priority": "C", "completed_at": None }),
+        headers=header)
+    print(f"update_task_partial() {r.status_code}")
+    assert(r.status_code == requests.codes.ok)
+    after = r.json()["data"]
+    assert(after["priority"] == "C")
+    assert(after["completed_at"] is None)
+    assert(after["title"] == before["title"])
+    assert(after["description"] == before["description"])
+    print("update_task_partial() passed")
+
+
 def mark_task_complete(jwt, task_id):
     header = { "x-auth-token": jwt }
     r = requests.put(
@@ -157,10 +223,43 @@ def mark_task_uncompleted(jwt, task_id):
     print("mark_task_uncompleted() passed")
 
 
+def read_task_events(jwt, last_event_id, count):
+    header = { "x-auth-token": jwt, "Last-Event-ID": str(last_event_id) }
+    r = requests.get(
+        "http://localhost:3010/api/v1/events",
+        headers=header,
+        stream=True,
+        timeout=5)
+    assert(r.status_code == requests.codes.ok)
+    assert(r.headers["Content-Type"] == "text/event-stream")
+    events = []
+    event_id = None
+    for line in r.iter_lines(decode_unicode=True):
+        if line.startswith("id: "):
+            event_id = int(line[4:])
+        elif line.startswith("data: "):
+            events.append((event_id, json.loads(line[6:])))
+            if len(events) == count:
+                break
+    r.close()
+    return events
+
+
+def task_events_resume(jwt, task_id):
+    mark_task_complete(jwt, task_id)
+    mark_task_uncompleted(jwt, task_id)
+    events = read_task_events(jwt, 0, 1)
+    first_id = events[0][0]
+    events = read_task_events(jwt, first_id, 1)
+    assert(events[0][0] > first_id)
+    assert(events[0][1]["type"] in ["task_created", "task_updated", "task_deleted"])
+    print("task_events_resume() passed")
+
+
 def test():
     letters = string.ascii_letters
     user = "".join(random.choices(letters, k=10))
-    password = "".join(random.choices(letters, k=10))
+    password = "".join(random.choices(letters, k=16))
     create_user(user, password)
     jwt, user_id = login(user, password)
     check_default_tasks(jwt)
@@ -169,11 +268,15 @@ def test():
     task_id = create_task(jwt, title, description)
     for i in range(3):
         create_task(jwt, f"{title}_{i}", f"{description}_{i}")
+    create_task_idempotent(jwt, title, description)
     get_all_user_tasks(jwt, user_id)
     get_task(jwt, task_id)
     mark_task_complete(jwt, task_id)
     mark_task_uncompleted(jwt, task_id)
     update_task(jwt, task_id)
+    update_task_conflict(jwt, task_id)
+    update_task_partial(jwt, task_id)
+    task_events_resume(jwt, task_id)
     return jwt, user, password
 
 # jwt, user, password = test()
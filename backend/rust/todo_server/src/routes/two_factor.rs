@@ -0,0 +1,348 @@
+use crate::database::{TodoDB, UserId};
+use crate::metrics::{LoginMethod, LoginOutcome, Metrics};
+use crate::middleware::rate_limit::{too_many_requests, RateLimiter};
+use crate::routes::auth::{hash_token, random_token, SessionUser};
+use crate::routes::openapi::{IdempotencyKey, InvalidToken, RateLimited, SessionOnly};
+use crate::routes::users::log_in;
+use crate::routes::TodoAppError;
+use crate::totp;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, HttpResponseBuilder};
+use bcrypt::verify;
+use chrono::Utc;
+use todo_api::{
+    AuthResponse, DataResponse, DisableTwoFactorRequest, MessageResponse, RecoveryCodes,
+    RecoveryCodesResponse, TwoFactorChallenge, TwoFactorCodeRequest, TwoFactorLoginRequest,
+    TwoFactorSetup, TwoFactorSetupResponse, TwoFactorStatus, TwoFactorStatusResponse, UserInfo,
+};
+
+/*
+Two-factor authentication with the codes of an authenticator app (TOTP), see
+totp.rs. Turning it on takes two steps, so a typo while scanning the secret
+can't lock anybody out.
+
+# two-factor status
+## route: "/users/me/two-factor" GET
+
+curl localhost:3010/api/v1/users/me/two-factor -H "x-auth-token: $TOKEN"
+
+### response:
+{"data":{"enabled":false,"recovery_codes_left":0}}
+
+# start enrolment
+## route: "/users/me/two-factor" POST
+a new secret for the app, asking again replaces it until it is confirmed. 409
+when two-factor authentication is on already.
+
+curl -X POST localhost:3010/api/v1/users/me/two-factor -H "x-auth-token: $TOKEN"
+
+### response:
+{
+    "data": {
+        "secret": "JBSWY3DPEHPK3PXPJBSWY3DPEHPK3PXP",
+        "otpauth_uri": "otpauth://totp/Todo:woodroww?secret=JBSWY3DPEHPK3PXPJBSWY3DPEHPK3PXP&issuer=Todo&algorithm=SHA1&digits=6&period=30"
+    }
+}
+
+# confirm enrolment
+## route: "/users/me/two-factor/confirm" POST
+with a code the app shows for the new secret. Turns two-factor authentication
+on and answers the recovery codes, which are never shown again.
+
+curl -X POST \
+localhost:3010/api/v1/users/me/two-factor/confirm \
+-H "x-auth-token: $TOKEN" \
+-H "Content-Type: application/json" \
+--data '{ "code": "492039" }'
+
+### response:
+{"data":{"recovery_codes":["k3mza-q7xpd", ...]}}
+
+# turn off
+## route: "/users/me/two-factor" DELETE
+
+curl -X DELETE \
+localhost:3010/api/v1/users/me/two-factor \
+-H "x-auth-token: $TOKEN" \
+-H "Content-Type: application/json" \
+--data '{ "password": "myfancypass" }'
+
+### response:
+{"message":"two-factor authentication turned off"}
+
+# log in with a code
+## route: "/users/login/two-factor" POST
+after /users/login answered 202 with a challenge. The code is one from the app
+or a recovery code. A challenge works for 5 minutes and 5 codes, and a wrong
+code counts towards the username's lockout like a wrong password.
+
+curl -X POST \
+localhost:3010/api/v1/users/login/two-factor \
+-H "Content-Type: application/json" \
+--data '{ "challenge": "9b1e...", "code": "492039" }'
+
+### response:
+the user like login answers
+*/
+
+const CHALLENGE_TTL_MINUTES: i32 = 5;
+const CHALLENGE_ATTEMPTS: i32 = 5;
+const RECOVERY_CODES: usize = 10;
+
+// a fresh challenge when the user has two-factor authentication on
+pub(crate) async fn challenge(
+    db: &TodoDB,
+    user_id: UserId,
+) -> Result<Option<TwoFactorChallenge>, TodoAppError> {
+    if !db
+        .get_two_factor(user_id)
+        .await?
+        .is_some_and(|two_factor| two_factor.enabled())
+    {
+        return Ok(None);
+    }
+    let challenge = random_token();
+    db.insert_two_factor_challenge(user_id, &hash_token(&challenge), CHALLENGE_TTL_MINUTES)
+        .await?;
+    Ok(Some(TwoFactorChallenge { challenge }))
+}
+
+// ten random base32 characters, 50 bits, split in two to read them out easily
+fn new_recovery_code() -> String {
+    let code = totp::base32_encode(&rand::random::<[u8; 7]>()).to_lowercase();
+    format!("{}-{}", &code[..5], &code[5..10])
+}
+
+// the dash, spaces and case don't matter when a recovery code is typed in
+fn hash_recovery_code(code: &str) -> String {
+    let code: String = code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    hash_token(&code)
+}
+
+// a code from the app that wasn't used before, or an unused recovery code
+async fn check_code(db: &TodoDB, user_id: UserId, code: &str) -> Result<bool, TodoAppError> {
+    let secret = match db.get_two_factor(user_id).await? {
+        Some(two_factor) if two_factor.enabled() => two_factor.secret.unwrap_or_default(),
+        _ => return Ok(false),
+    };
+    if let Some(step) = totp::verify(&secret, code, Utc::now().timestamp()) {
+        return Ok(db.use_totp_step(user_id, step).await?);
+    }
+    Ok(db
+        .use_recovery_code(user_id, &hash_recovery_code(code))
+        .await?)
+}
+
+fn bad_request(message: &str) -> HttpResponse {
+    HttpResponseBuilder::new(StatusCode::BAD_REQUEST).body(message.to_string())
+}
+
+#[utoipa::path(
+    get,
+    path = "/users/me/two-factor",
+    tag = "users",
+    summary = "Whether the user has two-factor authentication on",
+    security(("token" = [])),
+    responses(
+        (status = 200, description = "The status", body = DataResponse<TwoFactorStatus>),
+        (status = 401, response = InvalidToken),
+        (status = 403, response = SessionOnly),
+    ),
+)]
+pub async fn two_factor_status(
+    SessionUser(user): SessionUser,
+    db: web::Data<TodoDB>,
+) -> Result<HttpResponse, TodoAppError> {
+    let enabled = db
+        .get_two_factor(user.id)
+        .await?
+        .is_some_and(|two_factor| two_factor.enabled());
+    let recovery_codes_left = db.unused_recovery_codes(user.id).await?;
+    Ok(HttpResponse::Ok().json(TwoFactorStatusResponse {
+        data: TwoFactorStatus {
+            enabled,
+            recovery_codes_left,
+        },
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/users/me/two-factor",
+    tag = "users",
+    summary = "Start turning on two-factor authentication with a new secret for the authenticator app",
+    security(("token" = [])),
+    params(IdempotencyKey),
+    responses(
+        (status = 200, description = "The secret and its otpauth URI for a QR code", body = DataResponse<TwoFactorSetup>),
+        (status = 401, response = InvalidToken),
+        (status = 403, response = SessionOnly),
+        (status = 409, description = "Two-factor authentication is on already", body = String, content_type = "text/plain"),
+    ),
+)]
+pub async fn start_two_factor(
+    SessionUser(user): SessionUser,
+    db: web::Data<TodoDB>,
+) -> Result<HttpResponse, TodoAppError> {
+    let secret = totp::generate_secret();
+    if !db.start_two_factor(user.id, &secret).await? {
+        return Ok(HttpResponseBuilder::new(StatusCode::CONFLICT)
+            .body("two-factor authentication is on already"));
+    }
+    let otpauth_uri = totp::otpauth_uri(&user.username, &secret);
+    Ok(HttpResponse::Ok().json(TwoFactorSetupResponse {
+        data: TwoFactorSetup {
+            secret,
+            otpauth_uri,
+        },
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/users/me/two-factor/confirm",
+    tag = "users",
+    summary = "Turn on two-factor authentication with a code for the new secret",
+    security(("token" = [])),
+    params(IdempotencyKey),
+    request_body = TwoFactorCodeRequest,
+    responses(
+        (status = 200, description = "The recovery codes, shown this once", body = DataResponse<RecoveryCodes>),
+        (status = 400, description = "Wrong code", body = String, content_type = "text/plain"),
+        (status = 401, response = InvalidToken),
+        (status = 403, response = SessionOnly),
+        (status = 409, description = "Enrolment wasn't started, or two-factor authentication is on already", body = String, content_type = "text/plain"),
+    ),
+)]
+pub async fn confirm_two_factor(
+    SessionUser(user): SessionUser,
+    body: web::Json<TwoFactorCodeRequest>,
+    db: web::Data<TodoDB>,
+) -> Result<HttpResponse, TodoAppError> {
+    let secret = match db.get_two_factor(user.id).await? {
+        Some(two_factor) if !two_factor.enabled() => two_factor.secret,
+        _ => None,
+    };
+    let secret = match secret {
+        Some(secret) => secret,
+        None => {
+            return Ok(HttpResponseBuilder::new(StatusCode::CONFLICT)
+                .body("start two-factor enrolment first"))
+        }
+    };
+    let step = match totp::verify(&secret, &body.code, Utc::now().timestamp()) {
+        Some(step) => step,
+        None => return Ok(bad_request("incorrect code")),
+    };
+    let recovery_codes: Vec<String> = (0..RECOVERY_CODES).map(|_| new_recovery_code()).collect();
+    let hashes: Vec<String> = recovery_codes
+        .iter()
+        .map(|code| hash_recovery_code(code))
+        .collect();
+    if !db.enable_two_factor(user.id, step, &hashes).await? {
+        return Ok(
+            HttpResponseBuilder::new(StatusCode::CONFLICT).body("start two-factor enrolment first")
+        );
+    }
+    Ok(HttpResponse::Ok().json(RecoveryCodesResponse {
+        data: RecoveryCodes { recovery_codes },
+    }))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/users/me/two-factor",
+    tag = "users",
+    summary = "Turn off two-factor authentication, the recovery codes stop working",
+    security(("token" = [])),
+    params(IdempotencyKey),
+    request_body = DisableTwoFactorRequest,
+    responses(
+        (status = 200, description = "Turned off", body = MessageResponse),
+        (status = 400, description = "Wrong password", body = String, content_type = "text/plain"),
+        (status = 401, response = InvalidToken),
+        (status = 403, response = SessionOnly),
+    ),
+)]
+pub async fn disable_two_factor(
+    SessionUser(user): SessionUser,
+    body: web::Json<DisableTwoFactorRequest>,
+    db: web::Data<TodoDB>,
+) -> Result<HttpResponse, TodoAppError> {
+    let password_matches = db
+        .get_by_username(&user.username)
+        .await?
+        .is_some_and(|stored| verify(&body.password, &stored.password).unwrap_or(false));
+    if !password_matches {
+        return Ok(bad_request("incorrect password"));
+    }
+    db.disable_two_factor(user.id).await?;
+    Ok(HttpResponse::Ok().json(MessageResponse {
+        message: "two-factor authentication turned off".to_string(),
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/users/login/two-factor",
+    tag = "users",
+    summary = "Finish a login with a code from the authenticator app or a recovery code",
+    request_body = TwoFactorLoginRequest,
+    responses(
+        (status = 200, description = "The user and their token", body = DataResponse<UserInfo>),
+        (status = 400, description = "Wrong code, or the challenge expired or was tried too often", body = String, content_type = "text/plain"),
+        (status = 429, response = RateLimited),
+    ),
+)]
+pub async fn login_two_factor(
+    body: web::Json<TwoFactorLoginRequest>,
+    db: web::Data<TodoDB>,
+    limiter: Option<web::Data<RateLimiter>>,
+    metrics: Option<web::Data<Metrics>>,
+) -> Result<HttpResponse, TodoAppError> {
+    let counted = |outcome| {
+        if let Some(metrics) = &metrics {
+            metrics.login(LoginMethod::TwoFactor, outcome);
+        }
+    };
+    let challenge_hash = hash_token(&body.challenge);
+    let (user_id, username) = match db
+        .attempt_two_factor_challenge(&challenge_hash, CHALLENGE_ATTEMPTS)
+        .await?
+    {
+        Some(user) => user,
+        None => {
+            counted(LoginOutcome::Failure);
+            return Ok(bad_request("the login expired, log in again"));
+        }
+    };
+    // a wrong code counts towards the username's lockout like a wrong password
+    if let Some(limiter) = &limiter {
+        if let Err(retry_after) = limiter.login_allowed(&username) {
+            counted(LoginOutcome::LockedOut);
+            return Ok(too_many_requests(retry_after, "too many login attempts"));
+        }
+    }
+    if !check_code(&db, user_id, &body.code).await? {
+        if let Some(limiter) = &limiter {
+            limiter.login_failed(&username);
+        }
+        counted(LoginOutcome::Failure);
+        return Ok(bad_request("incorrect code"));
+    }
+    // two requests with the same challenge and different codes can't both log in
+    if !db.finish_two_factor_challenge(&challenge_hash).await? {
+        counted(LoginOutcome::Failure);
+        return Ok(bad_request("the login expired, log in again"));
+    }
+    if let Some(limiter) = &limiter {
+        limiter.login_succeeded(&username);
+    }
+    let user_info = log_in(&db, user_id, username).await?;
+    counted(LoginOutcome::Success);
+    Ok(HttpResponse::Ok().json(AuthResponse { data: user_info }))
+}
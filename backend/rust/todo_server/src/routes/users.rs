@@ -1,9 +1,20 @@
 use crate::database::{TodoDB, UserId};
-use crate::routes::tasks::CreateTaskRequest;
+use crate::metrics::{LoginMethod, LoginOutcome, Metrics};
+use crate::middleware::rate_limit::{too_many_requests, RateLimiter};
+use crate::passwords::new_password_problem;
+use crate::routes::auth::{invalid_token, SessionUser};
+use crate::routes::templates::onboarding_locale;
+use crate::routes::two_factor;
+use todo_api::validation::{email_problem, username_problem};
+use todo_api::{
+    AuthResponse, ChangePasswordRequest, DataResponse, LoginRequest, MessageResponse,
+    TwoFactorChallenge, TwoFactorChallengeResponse, UpdateUserRequest,
+};
 use crate::routes::TodoAppError;
+use crate::routes::openapi::{IdempotencyKey, InvalidToken, RateLimited, SessionOnly};
 use actix_web::http::StatusCode;
 use actix_web::{web, Error, HttpRequest, HttpResponse, HttpResponseBuilder};
-use chrono::NaiveDateTime;
+use chrono::{NaiveDateTime, Utc};
 use dotenv;
 use serde::{Deserialize, Serialize};
 use bcrypt::{hash, verify, DEFAULT_COST};
@@ -18,33 +29,7 @@ pub struct User {
     pub token: String,
 }
 
-#[derive(Serialize, Deserialize)]
-pub struct UserInfo {
-    pub id: UserId,
-    pub username: String,
-    pub token: String,
-}
-
-#[derive(Serialize, Deserialize)]
-struct CreateUserResponse {
-    data: UserInfo,
-}
-
-#[derive(Serialize, Deserialize)]
-pub struct LoginInfo {
-    username: String,
-    password: String,
-}
-
-#[derive(Serialize, Deserialize)]
-struct LoginResponse {
-    data: User,
-}
-
-#[derive(Serialize, Deserialize)]
-struct MessageResponse {
-    message: String,
-}
+pub use todo_api::UserInfo;
 
 /*
 # create user
@@ -65,33 +50,60 @@ localhost:3010/api/v1/users \
 }
 */
 
-// Create user, create token from username, insert default tasks
+// Create user, create token from username, insert the tasks from the templates
+// of the locale the request's Accept-Language asks for
 // return new user
 
+#[utoipa::path(
+    post,
+    path = "/users",
+    tag = "users",
+    summary = "Create a user, who starts out with a task for every template of their locale",
+    params(("Accept-Language" = Option<String>, Header, description = "Picks the locale of the templates, English when none match")),
+    request_body = LoginRequest,
+    responses(
+        (status = 200, description = "The new user and their token", body = DataResponse<UserInfo>),
+        (status = 400, description = "The username isn't 3 to 64 letters, digits, '.', '_' or '-', or the password is too short, too easy to guess or breached", body = String, content_type = "text/plain"),
+        (status = 429, response = RateLimited),
+    ),
+)]
 pub async fn create_user(
-    body: web::Json<LoginInfo>,
+    req: HttpRequest,
+    body: web::Json<LoginRequest>,
     db: web::Data<TodoDB>,
 ) -> Result<HttpResponse, Error> {
 
-    let new_token = create_token(&body.username).unwrap();
-    let hashed_password = hash(&body.password, DEFAULT_COST).unwrap();
-    let new_user = db
-        .create_user(&body.username, &hashed_password, &new_token)
-        .await?;
-    let default_tasks = db.get_default_tasks().await;
-    if let Some(tasks) = default_tasks {
-        for t in tasks {
-            let create_task = CreateTaskRequest {
-                title: t.title,
-                description: t.description,
-            };
-            db.insert_task(&create_task, new_user.id).await;
-        }
+    let problem = username_problem(&body.username)
+        .or_else(|| new_password_problem(&body.username, &body.password));
+    if let Some(problem) = problem {
+        return Ok(HttpResponseBuilder::new(StatusCode::BAD_REQUEST).body(problem));
     }
-    let response = CreateUserResponse { data: new_user };
+    let locale = onboarding_locale(&db, &req).await;
+    let new_user = register(&db, &body.username, &body.password, &locale).await?;
+    let response = AuthResponse { data: new_user };
     Ok(HttpResponse::Ok().json(response))
 }
 
+// shared with `todo_server admin create-user`
+pub async fn register(
+    db: &TodoDB,
+    username: &str,
+    password: &str,
+    locale: &str,
+) -> Result<UserInfo, TodoAppError> {
+    let new_token = create_token(username)?;
+    let hashed_password = hash_password(password)?;
+    db.create_user(username, &hashed_password, &new_token, locale)
+        .await
+}
+
+pub fn hash_password(password: &str) -> Result<String, TodoAppError> {
+    hash(password, DEFAULT_COST).map_err(|e| TodoAppError {
+        name: format!("could not hash password: {}", e),
+        status: StatusCode::INTERNAL_SERVER_ERROR,
+    })
+}
+
 /*
 # login
 ## route: "/login"
@@ -102,7 +114,8 @@ localhost:3010/api/v1/users/login \
 --data '{ "username": "woodroww", "password": "myfancypass" }'
 
 ### response:
-(with different token than from the creation request)
+(with different token than from the creation request, a user with two-factor
+authentication on gets a 202 with a challenge instead, see two_factor.rs)
 {
     "data": {
         "id": 3,
@@ -112,27 +125,88 @@ localhost:3010/api/v1/users/login \
 }
 */
 
+// the bcrypt of a password nobody has, verified when there is no user to log in
+const NOBODYS_PASSWORD: &str = "$2b$12$oTsaZIW8lwFo2xx39r.jlOBgFk6aUSoMgUSnhonHc0o2oDHaUo96a";
+
+#[utoipa::path(
+    post,
+    path = "/users/login",
+    tag = "users",
+    summary = "Log in, which hands out a new token",
+    request_body = LoginRequest,
+    responses(
+        (status = 200, description = "The user and their token", body = DataResponse<UserInfo>),
+        (status = 202, description = "The password was right but the user has two-factor authentication on, finish with /users/login/two-factor", body = DataResponse<TwoFactorChallenge>),
+        (status = 400, description = "Wrong username or password", body = String, content_type = "text/plain"),
+        (status = 429, response = RateLimited),
+    ),
+)]
 pub async fn login(
-    body: web::Json<LoginInfo>,
+    body: web::Json<LoginRequest>,
     db: web::Data<TodoDB>,
+    limiter: Option<web::Data<RateLimiter>>,
+    metrics: Option<web::Data<Metrics>>,
 ) -> Result<HttpResponse, TodoAppError> {
-
-    let result = db.get_by_username(&body.username).await;
-    if let Some(user) = result {
-        let result = verify(&body.password, &user.password);
-        if let Ok(valid) = result {
-            if valid {
-                // addTokenToUser
-                let login_token = create_token(&body.username).unwrap();
-                db.add_token_to_user(&login_token, user.id).await?;
-                return Ok(HttpResponse::Ok().json(LoginResponse { data: user }));
-            }
+    let counted = |outcome| {
+        if let Some(metrics) = &metrics {
+            metrics.login(LoginMethod::Password, outcome);
+        }
+    };
+    if let Some(limiter) = &limiter {
+        if let Err(retry_after) = limiter.login_allowed(&body.username) {
+            counted(LoginOutcome::LockedOut);
+            return Ok(too_many_requests(retry_after, "too many login attempts"));
+        }
+    }
+    let user = db
+        .get_by_username(&body.username)
+        .await?
+        // disabled and deleted users get the same answer as a wrong password
+        .filter(|user| user.deleted_at.is_none());
+    // nobody to check against still takes as long as a wrong password, or the
+    // time the answer takes would tell which usernames exist
+    let stored_password = user.as_ref().map_or(NOBODYS_PASSWORD, |user| &user.password);
+    let valid = verify(&body.password, stored_password).unwrap_or(false);
+    if let Some(user) = user.filter(|_| valid) {
+        // with 2FA on the token only comes from /users/login/two-factor, and
+        // the lockout lasts until the code is right too
+        if let Some(challenge) = two_factor::challenge(&db, user.id).await? {
+            counted(LoginOutcome::TwoFactor);
+            return Ok(HttpResponse::Accepted().json(TwoFactorChallengeResponse {
+                data: challenge,
+            }));
+        }
+        if let Some(limiter) = &limiter {
+            limiter.login_succeeded(&body.username);
         }
+        let user_info = log_in(&db, user.id, user.username).await?;
+        counted(LoginOutcome::Success);
+        return Ok(HttpResponse::Ok().json(AuthResponse { data: user_info }));
     }
+    // unknown usernames too, so the lockout doesn't tell which ones exist
+    if let Some(limiter) = &limiter {
+        limiter.login_failed(&body.username);
+    }
+    counted(LoginOutcome::Failure);
     Ok(HttpResponseBuilder::new(StatusCode::BAD_REQUEST)
         .body("incorrect username or password"))
 }
 
+// a new token for the user, which ends the session they had
+pub(crate) async fn log_in(
+    db: &TodoDB,
+    id: UserId,
+    username: String,
+) -> Result<UserInfo, TodoAppError> {
+    let login_token = create_token(&username)?;
+    db.add_token_to_user(&login_token, id).await?;
+    Ok(UserInfo {
+        id,
+        username,
+        token: login_token,
+    })
+}
+
 // get user from db, compare passwords, create login token
 // return user or error 500
 /*
@@ -150,36 +224,220 @@ localhost:3010/api/v1/users/logout \
 // find and remove token from db
 // return message or error 500
 
+#[utoipa::path(
+    post,
+    path = "/users/logout",
+    tag = "users",
+    summary = "Log out, the token stops working",
+    security(("token" = [])),
+    params(IdempotencyKey),
+    responses(
+        (status = 200, description = "Logged out", body = MessageResponse),
+        (status = 400, description = "Not logged in with a session token", body = String, content_type = "text/plain"),
+        (status = 401, response = InvalidToken),
+        (status = 403, response = SessionOnly),
+    ),
+)]
 pub async fn logout(
-    req: HttpRequest,
+    SessionUser(user): SessionUser,
     db: web::Data<TodoDB>,
 ) -> Result<HttpResponse, TodoAppError> {
-    if let Some(user) = db.authenticate(&req).await {
-        let row_count = db.find_and_remove_token(&user.token).await?;
-        if row_count == 1 {
-            let response = MessageResponse {
-                message: "user logged out".to_string(),
-            };
-            return Ok(HttpResponse::Ok().json(response));
-        }
+    let row_count = db.find_and_remove_token(&user.token).await?;
+    if row_count == 1 {
+        let response = MessageResponse {
+            message: "user logged out".to_string(),
+        };
+        return Ok(HttpResponse::Ok().json(response));
     }
     Ok(HttpResponseBuilder::new(StatusCode::BAD_REQUEST)
         .body("user not logged in or some other error"))
 }
 
-fn create_token(username: &str) -> Result<String, TodoAppError> {
+/*
+# change username
+## route: "/users/me" PATCH
+
+curl -X PATCH \
+localhost:3010/api/v1/users/me \
+-H "x-auth-token: $TOKEN" \
+-H "Content-Type: application/json" \
+--data '{ "username": "woodroww2", "email": "matt@example.com" }'
+
+### response:
+the user like login answers, with the same token. 409 if somebody has the name
+already, and nothing changes. The email is where password reset links go, "" removes
+it. An email another account has isn't set, and the answer doesn't say so, that
+would tell who has an account.
+
+# change password
+## route: "/users/me/password" POST
+
+curl -X POST \
+localhost:3010/api/v1/users/me/password \
+-H "x-auth-token: $TOKEN" \
+-H "Content-Type: application/json" \
+--data '{ "current_password": "myfancypass", "new_password": "myfancierpass" }'
+
+### response:
+the user with a new token, the old one stops working
+
+# delete account
+## route: "/users/me" DELETE
+
+curl -X DELETE localhost:3010/api/v1/users/me -H "x-auth-token: $TOKEN"
+
+### response:
+{"message":"user deleted"}
+*/
+
+// stored lowercase so Forgot Password finds it however it's typed
+pub fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+#[utoipa::path(
+    patch,
+    path = "/users/me",
+    tag = "users",
+    summary = "Change the user's username or email, the token keeps working",
+    security(("token" = [])),
+    params(IdempotencyKey),
+    request_body = UpdateUserRequest,
+    responses(
+        (status = 200, description = "The user and their token", body = DataResponse<UserInfo>),
+        (status = 400, description = "A bad username or email", body = String, content_type = "text/plain"),
+        (status = 401, response = InvalidToken),
+        (status = 403, response = SessionOnly),
+        (status = 409, description = "The username is taken, nothing changed", body = String, content_type = "text/plain"),
+    ),
+)]
+pub async fn update_me(
+    SessionUser(mut user): SessionUser,
+    body: web::Json<UpdateUserRequest>,
+    db: web::Data<TodoDB>,
+) -> Result<HttpResponse, TodoAppError> {
+    // everything is checked before anything changes
+    let username = body
+        .username
+        .as_deref()
+        .filter(|name| *name != user.username);
+    if let Some(problem) = username.and_then(username_problem) {
+        return Ok(HttpResponseBuilder::new(StatusCode::BAD_REQUEST).body(problem));
+    }
+    let email = body.email.as_deref().map(normalize_email);
+    if let Some(problem) = email
+        .as_deref()
+        .filter(|email| !email.is_empty())
+        .and_then(email_problem)
+    {
+        return Ok(HttpResponseBuilder::new(StatusCode::BAD_REQUEST).body(problem));
+    }
+    // "" removes it
+    let email = email
+        .as_deref()
+        .map(|email| Some(email).filter(|email| !email.is_empty()));
+    if !db.update_user(user.id, username, email).await? {
+        return Ok(HttpResponseBuilder::new(StatusCode::CONFLICT)
+            .body(format!("{} is taken", username.unwrap_or_default())));
+    }
+    if let Some(username) = username {
+        user.username = username.to_string();
+    }
+    Ok(HttpResponse::Ok().json(AuthResponse { data: user }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/users/me/password",
+    tag = "users",
+    summary = "Change the user's password, which hands out a new token and ends the old session",
+    security(("token" = [])),
+    params(IdempotencyKey),
+    request_body = ChangePasswordRequest,
+    responses(
+        (status = 200, description = "The user and their new token", body = DataResponse<UserInfo>),
+        (status = 400, description = "Wrong current password, or a new one the password policy turns down", body = String, content_type = "text/plain"),
+        (status = 401, response = InvalidToken),
+        (status = 403, response = SessionOnly),
+    ),
+)]
+pub async fn change_password(
+    SessionUser(mut user): SessionUser,
+    body: web::Json<ChangePasswordRequest>,
+    db: web::Data<TodoDB>,
+) -> Result<HttpResponse, TodoAppError> {
+    let current_password_matches = db
+        .get_by_username(&user.username)
+        .await?
+        .is_some_and(|stored| {
+            verify(&body.current_password, &stored.password).unwrap_or(false)
+        });
+    if !current_password_matches {
+        return Ok(HttpResponseBuilder::new(StatusCode::BAD_REQUEST).body("incorrect password"));
+    }
+    if let Some(problem) = new_password_problem(&user.username, &body.new_password) {
+        return Ok(HttpResponseBuilder::new(StatusCode::BAD_REQUEST).body(problem));
+    }
+    let hashed_password = hash_password(&body.new_password)?;
+    let token = create_token(&user.username)?;
+    if !db.change_password(user.id, &hashed_password, &token).await? {
+        return Ok(invalid_token());
+    }
+    user.token = token;
+    Ok(HttpResponse::Ok().json(AuthResponse { data: user }))
+}
+
+// soft deletes the user and their tasks, login turns deleted users away
+#[utoipa::path(
+    delete,
+    path = "/users/me",
+    tag = "users",
+    summary = "Delete the user and their tasks, they can't log in again",
+    security(("token" = [])),
+    params(IdempotencyKey),
+    responses(
+        (status = 200, description = "Deleted", body = MessageResponse),
+        (status = 401, response = InvalidToken),
+        (status = 403, response = SessionOnly),
+    ),
+)]
+pub async fn delete_me(
+    SessionUser(user): SessionUser,
+    db: web::Data<TodoDB>,
+) -> Result<HttpResponse, TodoAppError> {
+    if !db.delete_user(&user.username).await? {
+        return Ok(invalid_token());
+    }
+    Ok(HttpResponse::Ok().json(MessageResponse {
+        message: "user deleted".to_string(),
+    }))
+}
+
+// iat and jti make every token different, so a new one really replaces the last
+#[derive(Serialize)]
+struct Claims<'a> {
+    username: &'a str,
+    iat: i64,
+    jti: String,
+}
+
+pub fn create_token(username: &str) -> Result<String, TodoAppError> {
     let secret = dotenv::var("JWT_SECRET");
     match secret {
         Ok(s) => {
             // this needs to be done once somewhere idk where
             let encoding_key = &EncodingKey::from_secret(s.as_bytes());
-            let token = encode(&Header::default(), &username, encoding_key).unwrap();
+            let claims = Claims {
+                username,
+                iat: Utc::now().timestamp(),
+                jti: format!("{:016x}", rand::random::<u64>()),
+            };
+            let token = encode(&Header::default(), &claims, encoding_key).unwrap();
             Ok(token)
         }
-        Err(_e) => {
-            return Err(TodoAppError {
-                name: "could not get secrect from env".to_string(),
-            });
-        }
+        Err(_e) => Err(TodoAppError {
+            name: "could not get secrect from env".to_string(),
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+        }),
     }
 }
@@ -0,0 +1,32 @@
+// Stopping without dropping requests: on SIGTERM (what docker stop and
+// Kubernetes send) or ctrl-c the server stops taking connections, ends the task
+// event streams so their clients reconnect elsewhere, and gives the requests in
+// flight SHUTDOWN_TIMEOUT_SECS (30 by default) to finish before it exits.
+
+use actix_web::rt::signal;
+use std::time::Duration;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub fn timeout() -> Duration {
+    dotenv::var("SHUTDOWN_TIMEOUT_SECS")
+        .ok()
+        .and_then(|secs| secs.parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_TIMEOUT)
+}
+
+/// Waits for SIGTERM or ctrl-c.
+pub async fn requested() {
+    #[cfg(unix)]
+    {
+        use signal::unix::{signal, SignalKind};
+        let mut terminate = signal(SignalKind::terminate()).expect("listening for SIGTERM");
+        tokio::select! {
+            _ = terminate.recv() => {}
+            _ = signal::ctrl_c() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = signal::ctrl_c().await;
+}
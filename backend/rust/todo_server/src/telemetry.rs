@@ -0,0 +1,64 @@
+// Logging with tracing. Every request gets a span with its request id, method,
+// route, user id, status and latency (middleware/request_spans.rs), and every
+// TodoDB query a debug span, both logged with how long they took when they
+// close.
+//
+// LOG_FORMAT is "pretty" (the default, one readable line per event) or "json"
+// (one object per line, for a log collector). RUST_LOG picks what's logged, by
+// default "info". Add "todo_server::database=debug" to see the queries:
+//
+// RUST_LOG=info,todo_server::database=debug LOG_FORMAT=json cargo run
+
+use std::io::IsTerminal;
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::EnvFilter;
+
+const DEFAULT_FILTER: &str = "info";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LogFormat {
+    Pretty,
+    Json,
+}
+
+impl LogFormat {
+    pub fn parse(format: &str) -> Option<LogFormat> {
+        match format.trim().to_ascii_lowercase().as_str() {
+            "pretty" => Some(LogFormat::Pretty),
+            "json" => Some(LogFormat::Json),
+            _ => None,
+        }
+    }
+
+    pub fn from_env() -> LogFormat {
+        match dotenv::var("LOG_FORMAT") {
+            Ok(format) => LogFormat::parse(&format).unwrap_or_else(|| {
+                eprintln!("todo_server: LOG_FORMAT {} isn't pretty or json", format);
+                LogFormat::Pretty
+            }),
+            Err(_) => LogFormat::Pretty,
+        }
+    }
+}
+
+fn filter_from_env() -> EnvFilter {
+    let directives = dotenv::var("RUST_LOG").unwrap_or_else(|_| DEFAULT_FILTER.to_string());
+    EnvFilter::try_new(&directives).unwrap_or_else(|e| {
+        eprintln!("todo_server: RUST_LOG {} doesn't parse {}", directives, e);
+        EnvFilter::new(DEFAULT_FILTER)
+    })
+}
+
+/// Sends the server's spans and events to stdout, call it once before the
+/// server starts.
+pub fn init() {
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter_from_env())
+        .with_span_events(FmtSpan::CLOSE)
+        // no color codes in a file or a pipe
+        .with_ansi(std::io::stdout().is_terminal());
+    match LogFormat::from_env() {
+        LogFormat::Pretty => builder.init(),
+        LogFormat::Json => builder.json().flatten_event(true).init(),
+    }
+}
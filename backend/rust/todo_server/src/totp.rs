@@ -0,0 +1,128 @@
+// Time-based one-time passwords (RFC 6238) as authenticator apps make them:
+// HMAC-SHA1, 6 digits, a new code every 30 seconds. The secret is shared with
+// the app as base32 (RFC 4648, without padding) in an otpauth:// URI, which the
+// app can read from a QR code or the user can type in.
+
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+
+pub const DIGITS: u32 = 6;
+pub const STEP_SECONDS: i64 = 30;
+// codes from one step either side still count, for clocks that are a little off
+pub const WINDOW: i64 = 1;
+pub const ISSUER: &str = "Todo";
+
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// 160 random bits, the size of an SHA-1 key, as base32.
+pub fn generate_secret() -> String {
+    let secret: [u8; 20] = rand::random();
+    base32_encode(&secret)
+}
+
+pub fn base32_encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity((bytes.len() * 8).div_ceil(5));
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for byte in bytes {
+        buffer = (buffer << 8) | u32::from(*byte);
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            encoded.push(BASE32_ALPHABET[((buffer >> bits) & 31) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        encoded.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 31) as usize] as char);
+    }
+    encoded
+}
+
+/// Case, spaces and padding don't matter, None for anything else that isn't base32.
+pub fn base32_decode(encoded: &str) -> Option<Vec<u8>> {
+    let mut decoded = Vec::with_capacity(encoded.len() * 5 / 8);
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for c in encoded.chars().filter(|c| !c.is_whitespace() && *c != '=') {
+        let c = c.to_ascii_uppercase() as u8;
+        let value = BASE32_ALPHABET.iter().position(|a| *a == c)? as u32;
+        buffer = (buffer << 5) | value;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            decoded.push((buffer >> bits) as u8);
+        }
+    }
+    Some(decoded)
+}
+
+/// The 30 second step a unix time falls in.
+pub fn step_at(unix_time: i64) -> i64 {
+    unix_time.div_euclid(STEP_SECONDS)
+}
+
+/// The HOTP (RFC 4226) of `counter`, `digits` long with leading zeros.
+pub fn hotp(key: &[u8], counter: u64, digits: u32) -> String {
+    let mut mac = Hmac::<Sha1>::new_from_slice(key).expect("HMAC takes keys of any length");
+    mac.update(&counter.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+    format!(
+        "{:0width$}",
+        binary % 10u32.pow(digits),
+        width = digits as usize
+    )
+}
+
+/// The code an authenticator app shows for `secret` during `step`.
+pub fn code_at(secret: &str, step: i64) -> Option<String> {
+    let key = base32_decode(secret)?;
+    Some(hotp(&key, step as u64, DIGITS))
+}
+
+/// The step `code` belongs to when it is right for a step near `unix_time`.
+/// Callers remember the step and turn away codes for it or earlier ones, so a
+/// code can't be used twice.
+pub fn verify(secret: &str, code: &str, unix_time: i64) -> Option<i64> {
+    let code: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+    if code.len() != DIGITS as usize || !code.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let key = base32_decode(secret)?;
+    let now = step_at(unix_time);
+    (now - WINDOW..=now + WINDOW)
+        .filter(|step| *step >= 0)
+        .find(|step| hotp(&key, *step as u64, DIGITS) == code)
+}
+
+/// What goes in the QR code, see
+/// https://github.com/google/google-authenticator/wiki/Key-Uri-Format
+pub fn otpauth_uri(account: &str, secret: &str) -> String {
+    format!(
+        "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+        percent_encode(ISSUER),
+        percent_encode(account),
+        secret,
+        percent_encode(ISSUER),
+        DIGITS,
+        STEP_SECONDS
+    )
+}
+
+fn percent_encode(text: &str) -> String {
+    text.bytes()
+        .map(|byte| {
+            if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) {
+                (byte as char).to_string()
+            } else {
+                format!("%{:02X}", byte)
+            }
+        })
+        .collect()
+}
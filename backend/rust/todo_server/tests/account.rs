@@ -0,0 +1,187 @@
+// The /users/me routes against the database (ignored, run it with
+// `cargo test -p todo_server -- --ignored`).
+
+use actix_web::{test as actix_test, web, App};
+use std::time::{SystemTime, UNIX_EPOCH};
+use todo_api::{AuthResponse, ChangePasswordRequest, LoginRequest, UpdateUserRequest};
+use todo_server::database::TodoDB;
+use todo_server::events::TaskEvents;
+use todo_server::routes::{self, auth::hash_token};
+
+#[actix_rt::test]
+#[ignore = "needs todo_server's Postgres database"]
+async fn users_manage_their_own_account() {
+    if std::env::var("JWT_SECRET").is_err() {
+        std::env::set_var("JWT_SECRET", "keyboardcat");
+    }
+    let db = web::Data::new(TodoDB::new());
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(db.clone())
+            .app_data(web::Data::new(TaskEvents::new()))
+            .configure(routes::configure),
+    )
+    .await;
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let login = |username: &str, password: &str| {
+        actix_test::TestRequest::post()
+            .uri("/api/v1/users/login")
+            .set_json(LoginRequest {
+                username: username.to_string(),
+                password: password.to_string(),
+            })
+            .to_request()
+    };
+
+    let mut users = vec![];
+    for name in ["first", "second"] {
+        let request = actix_test::TestRequest::post()
+            .uri("/api/v1/users")
+            .set_json(LoginRequest {
+                username: format!("account-{}-{}", name, nanos),
+                password: "myfancypass".to_string(),
+            })
+            .to_request();
+        let response: AuthResponse = actix_test::call_and_read_body_json(&app, request).await;
+        users.push(response.data);
+    }
+    let (user, other) = (users[0].clone(), users[1].clone());
+
+    let request = actix_test::TestRequest::patch()
+        .uri("/api/v1/users/me")
+        .set_json(UpdateUserRequest {
+            username: Some(format!("account-renamed-{}", nanos)),
+            ..UpdateUserRequest::default()
+        })
+        .to_request();
+    assert_eq!(actix_test::call_service(&app, request).await.status(), 401);
+
+    let request = actix_test::TestRequest::patch()
+        .uri("/api/v1/users/me")
+        .insert_header(("x-auth-token", user.token.clone()))
+        .set_json(UpdateUserRequest {
+            username: Some(other.username.clone()),
+            ..UpdateUserRequest::default()
+        })
+        .to_request();
+    assert_eq!(actix_test::call_service(&app, request).await.status(), 409);
+
+    // whether an account has an email, by asking for a reset link
+    let has_email = |email: String| {
+        let db = db.clone();
+        async move {
+            db.insert_password_reset(&email, &hash_token(&email), 60)
+                .await
+                .unwrap()
+        }
+    };
+    let update = |token: &str, username: Option<String>, email: &str| {
+        actix_test::TestRequest::patch()
+            .uri("/api/v1/users/me")
+            .insert_header(("x-auth-token", token.to_string()))
+            .set_json(UpdateUserRequest {
+                username,
+                email: Some(email.to_string()),
+            })
+            .to_request()
+    };
+    let (email, others) = (
+        format!("account-{}@example.com", nanos),
+        format!("account-other-{}@example.com", nanos),
+    );
+    // a taken username and the email is left too
+    let request = update(&user.token, Some(other.username.clone()), &email);
+    assert_eq!(actix_test::call_service(&app, request).await.status(), 409);
+    assert!(!has_email(email.clone()).await);
+    for bad in [
+        "not an email",
+        "matt@example.com\r\nBcc: everyone@example.com",
+    ] {
+        let request = update(&user.token, None, bad);
+        assert_eq!(actix_test::call_service(&app, request).await.status(), 400);
+    }
+    let request = update(&other.token, None, &others);
+    assert!(actix_test::call_service(&app, request)
+        .await
+        .status()
+        .is_success());
+    let request = update(&user.token, None, &email);
+    assert!(actix_test::call_service(&app, request)
+        .await
+        .status()
+        .is_success());
+    // another account's email looks like it worked, and changes nothing
+    let request = update(&user.token, None, &others.to_uppercase());
+    assert!(actix_test::call_service(&app, request)
+        .await
+        .status()
+        .is_success());
+    assert!(has_email(email.clone()).await);
+
+    let renamed = format!("account-renamed-{}", nanos);
+    let request = actix_test::TestRequest::patch()
+        .uri("/api/v1/users/me")
+        .insert_header(("x-auth-token", user.token.clone()))
+        .set_json(UpdateUserRequest {
+            username: Some(renamed.clone()),
+            ..UpdateUserRequest::default()
+        })
+        .to_request();
+    let response: AuthResponse = actix_test::call_and_read_body_json(&app, request).await;
+    assert_eq!(response.data.username, renamed);
+    assert_eq!(response.data.token, user.token);
+
+    let request = actix_test::TestRequest::post()
+        .uri("/api/v1/users/me/password")
+        .insert_header(("x-auth-token", user.token.clone()))
+        .set_json(ChangePasswordRequest {
+            current_password: "wrong".to_string(),
+            new_password: "myfancierpass".to_string(),
+        })
+        .to_request();
+    assert_eq!(actix_test::call_service(&app, request).await.status(), 400);
+
+    let request = actix_test::TestRequest::post()
+        .uri("/api/v1/users/me/password")
+        .insert_header(("x-auth-token", user.token.clone()))
+        .set_json(ChangePasswordRequest {
+            current_password: "myfancypass".to_string(),
+            new_password: "myfancierpass".to_string(),
+        })
+        .to_request();
+    let changed: AuthResponse = actix_test::call_and_read_body_json(&app, request).await;
+    assert_ne!(changed.data.token, user.token);
+    assert!(db.get_by_token(&user.token).await.unwrap().is_none());
+    assert!(db
+        .get_by_token(&changed.data.token)
+        .await
+        .unwrap()
+        .is_some());
+    let request = login(&renamed, "myfancypass");
+    assert_eq!(actix_test::call_service(&app, request).await.status(), 400);
+    let request = login(&renamed, "myfancierpass");
+    assert!(actix_test::call_service(&app, request)
+        .await
+        .status()
+        .is_success());
+
+    let token = db.get_by_username(&renamed).await.unwrap().unwrap().token;
+    let request = actix_test::TestRequest::delete()
+        .uri("/api/v1/users/me")
+        .insert_header(("x-auth-token", token))
+        .to_request();
+    assert!(actix_test::call_service(&app, request)
+        .await
+        .status()
+        .is_success());
+    let deleted = db.get_by_username(&renamed).await.unwrap().unwrap();
+    assert!(deleted.deleted_at.is_some());
+    assert!(db.get_all_tasks(deleted.id).await.unwrap().is_empty());
+    let request = login(&renamed, "myfancierpass");
+    assert_eq!(actix_test::call_service(&app, request).await.status(), 400);
+
+    db.delete_user(&other.username).await.unwrap();
+}
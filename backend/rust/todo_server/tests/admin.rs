@@ -0,0 +1,190 @@
+// `todo_server admin` argument parsing, and a run through the commands against
+// the database (ignored, run it with `cargo test -p todo_server -- --ignored`).
+
+use std::time::{SystemTime, UNIX_EPOCH};
+use todo_api::{CreateTemplateRequest, Role};
+use todo_server::admin::{parse, run, AdminCommand, AdminError};
+use todo_server::database::TodoDB;
+
+fn args(line: &str) -> Vec<String> {
+    line.split_whitespace().map(str::to_string).collect()
+}
+
+#[test]
+fn parses_commands() {
+    assert_eq!(parse(&args("users")).unwrap(), AdminCommand::Users);
+    assert_eq!(
+        parse(&args("create-user woodroww --password myfancypass")).unwrap(),
+        AdminCommand::CreateUser {
+            username: "woodroww".to_string(),
+            password: Some("myfancypass".to_string()),
+            locale: None,
+        }
+    );
+    assert_eq!(
+        parse(&args("role woodroww admin")).unwrap(),
+        AdminCommand::Role {
+            username: "woodroww".to_string(),
+            role: Role::Admin,
+        }
+    );
+    assert_eq!(
+        parse(&args("disable-2fa woodroww")).unwrap(),
+        AdminCommand::DisableTwoFactor {
+            username: "woodroww".to_string(),
+        }
+    );
+    assert_eq!(
+        parse(&args("revoke --all")).unwrap(),
+        AdminCommand::Revoke { username: None }
+    );
+    assert_eq!(
+        parse(&args("add-template chores --priority b --locale es")).unwrap(),
+        AdminCommand::AddTemplate {
+            template: CreateTemplateRequest {
+                title: "chores".to_string(),
+                priority: Some("B".to_string()),
+                locale: Some("es".to_string()),
+                ..CreateTemplateRequest::default()
+            }
+        }
+    );
+    assert_eq!(
+        parse(&args("rm-template 12")).unwrap(),
+        AdminCommand::RmTemplate { id: 12 }
+    );
+}
+
+#[test]
+fn rejects_bad_arguments() {
+    for line in [
+        "",
+        "frobnicate",
+        "disable",
+        "disable-2fa",
+        "revoke",
+        "role woodroww",
+        "role woodroww superuser",
+        "rm-template twelve",
+        "stats --locale es",
+        "users --password secret",
+        "add-template chores --priority Z",
+        "stats extra",
+        "sessions --verbose",
+    ] {
+        assert!(
+            matches!(parse(&args(line)), Err(AdminError::Usage(_))),
+            "{:?}",
+            line
+        );
+    }
+}
+
+#[actix_rt::test]
+#[ignore = "needs todo_server's Postgres database"]
+async fn manages_users() {
+    if std::env::var("JWT_SECRET").is_err() {
+        std::env::set_var("JWT_SECRET", "keyboardcat");
+    }
+    let db = TodoDB::new();
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let username = format!("admin-{}", nanos);
+    let command = |line: String| parse(&args(&line)).unwrap();
+
+    run(
+        &db,
+        command(format!("create-user {} --password myfancypass", username)),
+    )
+    .await
+    .unwrap();
+    let user = db.get_by_username(&username).await.unwrap().unwrap();
+    assert!(db.get_by_token(&user.token).await.unwrap().is_some());
+
+    run(&db, command(format!("disable {}", username)))
+        .await
+        .unwrap();
+    let disabled = db.get_by_username(&username).await.unwrap().unwrap();
+    assert!(disabled.deleted_at.is_some());
+    assert!(db.get_by_token(&user.token).await.unwrap().is_none());
+    assert!(db.list_users(Some(user.id)).await.unwrap()[0].disabled);
+
+    run(&db, command(format!("role {} admin", username)))
+        .await
+        .unwrap();
+    assert_eq!(db.get_role(user.id).await.unwrap(), Some(Role::Admin));
+
+    run(&db, command(format!("enable {}", username)))
+        .await
+        .unwrap();
+    run(
+        &db,
+        command(format!("reset-password {} --password myfancierpass", username)),
+    )
+    .await
+    .unwrap();
+    let reset = db.get_by_username(&username).await.unwrap().unwrap();
+    assert!(reset.deleted_at.is_none());
+    assert!(bcrypt::verify("myfancierpass", &reset.password).unwrap());
+
+    run(&db, command(format!("delete-user {}", username)))
+        .await
+        .unwrap();
+    let deleted = db.get_by_username(&username).await.unwrap().unwrap();
+    assert!(deleted.deleted_at.is_some());
+    assert!(db.get_all_tasks(deleted.id).await.unwrap().is_empty());
+    // disabled users are deleted ones, only a name nobody has is an error
+    run(&db, command(format!("disable {}", username)))
+        .await
+        .unwrap();
+    assert!(matches!(
+        run(&db, command(format!("disable {}-nobody", username))).await,
+        Err(AdminError::Failed(_))
+    ));
+    // enabling undoes disable, not delete-user
+    assert!(matches!(
+        run(&db, command(format!("enable {}", username))).await,
+        Err(AdminError::Failed(_))
+    ));
+    let still_deleted = db.get_by_username(&username).await.unwrap().unwrap();
+    assert!(still_deleted.deleted_at.is_some());
+
+    run(&db, command("stats".to_string())).await.unwrap();
+}
+
+#[actix_rt::test]
+#[ignore = "needs todo_server's Postgres database"]
+async fn manages_templates() {
+    let db = TodoDB::new();
+    let title = format!(
+        "template-{}",
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos()
+    );
+    // a locale of its own so no account signing up meanwhile gets the task
+    run(
+        &db,
+        parse(&args(&format!(
+            "add-template {} --priority C --locale zz-admin",
+            title
+        )))
+        .unwrap(),
+    )
+    .await
+    .unwrap();
+    let templates = db.get_templates(Some("zz-admin")).await.unwrap();
+    let added = templates
+        .iter()
+        .find(|template| template.title == title)
+        .unwrap();
+    assert_eq!(added.priority.as_deref(), Some("C"));
+
+    run(&db, AdminCommand::RmTemplate { id: added.id })
+        .await
+        .unwrap();
+    assert!(db.get_template(added.id).await.unwrap().is_none());
+}
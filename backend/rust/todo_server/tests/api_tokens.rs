@@ -0,0 +1,174 @@
+// Personal API tokens against the database (ignored, run it with
+// `cargo test -p todo_server -- --ignored`).
+
+use actix_web::{test as actix_test, web, App};
+use std::time::{SystemTime, UNIX_EPOCH};
+use todo_api::{
+    ApiTokenListResponse, AuthResponse, CreateApiTokenRequest, CreateTaskRequest, LoginRequest,
+    NewApiTokenResponse, TokenScope,
+};
+use todo_server::database::TodoDB;
+use todo_server::events::TaskEvents;
+use todo_server::routes;
+
+#[actix_rt::test]
+#[ignore = "needs todo_server's Postgres database"]
+async fn api_tokens_work_within_their_scope() {
+    if std::env::var("JWT_SECRET").is_err() {
+        std::env::set_var("JWT_SECRET", "keyboardcat");
+    }
+    let db = web::Data::new(TodoDB::new());
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(db.clone())
+            .app_data(web::Data::new(TaskEvents::new()))
+            .configure(routes::configure),
+    )
+    .await;
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let request = actix_test::TestRequest::post()
+        .uri("/api/v1/users")
+        .set_json(LoginRequest {
+            username: format!("api-tokens-{}", nanos),
+            password: "myfancypass".to_string(),
+        })
+        .to_request();
+    let user: AuthResponse = actix_test::call_and_read_body_json(&app, request).await;
+    let session = user.data.token;
+
+    let create = |token: &str, scope: TokenScope, expires_in_days: Option<i32>| {
+        actix_test::TestRequest::post()
+            .uri("/api/v1/users/me/tokens")
+            .insert_header(("x-auth-token", token.to_string()))
+            .set_json(CreateApiTokenRequest {
+                name: format!("{:?} script", scope),
+                scope,
+                expires_in_days,
+            })
+            .to_request()
+    };
+    let bearer = |token: &str| ("Authorization", format!("Bearer {}", token));
+    let get_tasks = |token: &str| {
+        actix_test::TestRequest::get()
+            .uri("/api/v1/tasks")
+            .insert_header(bearer(token))
+            .to_request()
+    };
+    let create_task = |token: &str| {
+        actix_test::TestRequest::post()
+            .uri("/api/v1/tasks")
+            .insert_header(bearer(token))
+            .set_json(CreateTaskRequest {
+                title: "from a script".to_string(),
+                ..CreateTaskRequest::default()
+            })
+            .to_request()
+    };
+
+    let read_only: NewApiTokenResponse =
+        actix_test::call_and_read_body_json(&app, create(&session, TokenScope::ReadOnly, None))
+            .await;
+    let read_only = read_only.data;
+    assert!(read_only.token.starts_with("todo_pat_"));
+    assert_eq!(read_only.api_token.expires_at, None);
+    assert_eq!(
+        actix_test::call_service(&app, get_tasks(&read_only.token))
+            .await
+            .status(),
+        200
+    );
+    assert_eq!(
+        actix_test::call_service(&app, create_task(&read_only.token))
+            .await
+            .status(),
+        403
+    );
+
+    let read_write: NewApiTokenResponse = actix_test::call_and_read_body_json(
+        &app,
+        create(&session, TokenScope::ReadWrite, Some(30)),
+    )
+    .await;
+    let read_write = read_write.data;
+    assert!(read_write.api_token.expires_at.is_some());
+    assert_eq!(
+        actix_test::call_service(&app, create_task(&read_write.token))
+            .await
+            .status(),
+        200
+    );
+    // the session token works as a bearer token too
+    assert_eq!(
+        actix_test::call_service(&app, get_tasks(&session))
+            .await
+            .status(),
+        200
+    );
+
+    // API tokens can't make more of themselves, a 403 like the other /users/me
+    // routes
+    let response =
+        actix_test::call_service(&app, create(&read_write.token, TokenScope::ReadWrite, None))
+            .await;
+    assert_eq!(response.status(), 403);
+    let response =
+        actix_test::call_service(&app, create(&session, TokenScope::ReadOnly, Some(0))).await;
+    assert_eq!(response.status(), 400);
+
+    // the list never has the tokens themselves
+    let request = actix_test::TestRequest::get()
+        .uri("/api/v1/users/me/tokens")
+        .insert_header(("x-auth-token", session.clone()))
+        .to_request();
+    let body = actix_test::call_and_read_body(&app, request).await;
+    assert!(!String::from_utf8_lossy(&body).contains(&read_only.token));
+    let tokens: ApiTokenListResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(tokens.data.len(), 2);
+    assert!(tokens.data.iter().all(|token| token.last_used_at.is_some()));
+
+    // used again within the minute, the time noted stays as it was
+    assert_eq!(
+        actix_test::call_service(&app, get_tasks(&read_only.token))
+            .await
+            .status(),
+        200
+    );
+    let request = actix_test::TestRequest::get()
+        .uri("/api/v1/users/me/tokens")
+        .insert_header(("x-auth-token", session.clone()))
+        .to_request();
+    let again: ApiTokenListResponse = actix_test::call_and_read_body_json(&app, request).await;
+    let last_used = |list: &ApiTokenListResponse| {
+        list.data
+            .iter()
+            .find(|token| token.id == read_only.api_token.id)
+            .and_then(|token| token.last_used_at)
+    };
+    assert_eq!(last_used(&again), last_used(&tokens));
+
+    let request = actix_test::TestRequest::delete()
+        .uri(&format!(
+            "/api/v1/users/me/tokens/{}",
+            read_only.api_token.id
+        ))
+        .insert_header(("x-auth-token", session.clone()))
+        .to_request();
+    assert_eq!(actix_test::call_service(&app, request).await.status(), 200);
+    assert_eq!(
+        actix_test::call_service(&app, get_tasks(&read_only.token))
+            .await
+            .status(),
+        401
+    );
+    let request = actix_test::TestRequest::delete()
+        .uri(&format!(
+            "/api/v1/users/me/tokens/{}",
+            read_only.api_token.id
+        ))
+        .insert_header(("x-auth-token", session))
+        .to_request();
+    assert_eq!(actix_test::call_service(&app, request).await.status(), 404);
+}
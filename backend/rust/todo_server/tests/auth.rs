@@ -0,0 +1,242 @@
+// The AuthenticatedUser and SessionUser extractors and the require_scope
+// middleware. Only the last test needs Postgres (ignored, run it with
+// `cargo test -p todo_server -- --ignored`), the others settle who is asking
+// with remember_credentials.
+
+use actix_http::Request;
+use actix_web::dev::{Service, ServiceResponse};
+use actix_web::http::header::WWW_AUTHENTICATE;
+use actix_web::{test as actix_test, web, App};
+use serde_json::json;
+use std::time::{SystemTime, UNIX_EPOCH};
+use todo_api::{
+    AuthResponse, CreateApiTokenRequest, CreateTaskRequest, LoginRequest, NewApiTokenResponse,
+    TaskListResponse, TokenScope, UserInfo,
+};
+use todo_server::database::TodoDB;
+use todo_server::events::TaskEvents;
+use todo_server::routes;
+use todo_server::routes::auth::{remember_credentials, Credentials};
+
+const API_TOKEN: &str = "todo_pat_0123456789abcdef";
+
+// every request is from user 1 with an API token of this scope
+fn api_token(scope: TokenScope) -> Credentials {
+    Credentials {
+        user: UserInfo {
+            id: 1,
+            username: "pat".to_string(),
+            token: API_TOKEN.to_string(),
+        },
+        scope,
+        api_token: true,
+    }
+}
+
+// the routes, with every request coming from `credentials`. Without any the
+// token is looked up, in the database when there is one.
+async fn app(
+    credentials: Option<Credentials>,
+) -> impl Service<Request, Response = ServiceResponse, Error = actix_web::Error> {
+    actix_test::init_service(
+        App::new()
+            .app_data(web::Data::new(TodoDB::new()))
+            .app_data(web::Data::new(TaskEvents::new()))
+            .wrap_fn(move |req, srv| {
+                if credentials.is_some() {
+                    remember_credentials(req.request(), credentials.clone());
+                }
+                srv.call(req)
+            })
+            .configure(routes::configure),
+    )
+    .await
+}
+
+// signs up someone new and gives back their session token
+async fn sign_up(
+    app: &impl Service<Request, Response = ServiceResponse, Error = actix_web::Error>,
+    name: &str,
+) -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let request = actix_test::TestRequest::post()
+        .uri("/api/v1/users")
+        .set_json(LoginRequest {
+            username: format!("{}-{}", name, nanos),
+            password: "myfancypass".to_string(),
+        })
+        .to_request();
+    let user: AuthResponse = actix_test::call_and_read_body_json(app, request).await;
+    user.data.token
+}
+
+#[actix_rt::test]
+async fn no_token_gets_a_401_with_a_challenge() {
+    let app = app(None).await;
+    for request in [
+        actix_test::TestRequest::get().uri("/api/v1/tasks"),
+        actix_test::TestRequest::delete().uri("/api/v1/tasks/1"),
+        actix_test::TestRequest::get().uri("/api/v1/users/me/two-factor"),
+        actix_test::TestRequest::get().uri("/api/v1/users/me/tokens"),
+    ] {
+        let response = actix_test::call_service(&app, request.to_request()).await;
+        assert_eq!(response.status(), 401);
+        assert_eq!(
+            response.headers().get(WWW_AUTHENTICATE).unwrap(),
+            r#"Bearer realm="todo_server""#
+        );
+    }
+}
+
+#[actix_rt::test]
+async fn api_tokens_cant_touch_the_account() {
+    let app = app(Some(api_token(TokenScope::ReadWrite))).await;
+    let bearer = ("Authorization", format!("Bearer {}", API_TOKEN));
+    for (request, body) in [
+        (
+            actix_test::TestRequest::post().uri("/api/v1/users/logout"),
+            json!({}),
+        ),
+        (
+            actix_test::TestRequest::patch().uri("/api/v1/users/me"),
+            json!({ "email": "attacker@example.com" }),
+        ),
+        (
+            actix_test::TestRequest::delete().uri("/api/v1/users/me"),
+            json!({}),
+        ),
+        (
+            actix_test::TestRequest::post().uri("/api/v1/users/me/password"),
+            json!({ "current_password": "myfancypass", "new_password": "evenfancierpass" }),
+        ),
+        (
+            actix_test::TestRequest::get().uri("/api/v1/users/me/two-factor"),
+            json!({}),
+        ),
+        (
+            actix_test::TestRequest::post().uri("/api/v1/users/me/two-factor"),
+            json!({}),
+        ),
+        (
+            actix_test::TestRequest::delete().uri("/api/v1/users/me/two-factor"),
+            json!({ "password": "myfancypass" }),
+        ),
+        (
+            actix_test::TestRequest::post().uri("/api/v1/users/me/two-factor/confirm"),
+            json!({ "code": "123456" }),
+        ),
+        (
+            actix_test::TestRequest::get().uri("/api/v1/users/me/tokens"),
+            json!({}),
+        ),
+        (
+            actix_test::TestRequest::post().uri("/api/v1/users/me/tokens"),
+            json!({ "name": "another", "scope": "read_write" }),
+        ),
+        (
+            actix_test::TestRequest::delete().uri("/api/v1/users/me/tokens/1"),
+            json!({}),
+        ),
+    ] {
+        let request = request
+            .insert_header(bearer.clone())
+            .set_json(body)
+            .to_request();
+        let path = request.path().to_string();
+        let response = actix_test::call_service(&app, request).await;
+        assert_eq!(response.status(), 403, "{}", path);
+        assert_eq!(
+            response.headers().get(WWW_AUTHENTICATE).unwrap(),
+            r#"Bearer realm="todo_server", error="insufficient_scope""#
+        );
+    }
+}
+
+#[actix_rt::test]
+async fn read_only_api_tokens_only_read() {
+    let app = app(Some(api_token(TokenScope::ReadOnly))).await;
+    for request in [
+        actix_test::TestRequest::post()
+            .uri("/api/v1/tasks")
+            .set_json(json!({ "title": "not allowed" })),
+        actix_test::TestRequest::patch()
+            .uri("/api/v1/tasks/1")
+            .set_json(json!({ "title": "not allowed" })),
+        actix_test::TestRequest::delete().uri("/api/v1/tasks/1"),
+        actix_test::TestRequest::put().uri("/api/v1/tasks/1/completed"),
+        actix_test::TestRequest::put().uri("/api/v1/tasks/1/uncompleted"),
+    ] {
+        let request = request.to_request();
+        let path = format!("{} {}", request.method(), request.path());
+        let response = actix_test::call_service(&app, request).await;
+        assert_eq!(response.status(), 403, "{}", path);
+        assert_eq!(
+            response.headers().get(WWW_AUTHENTICATE).unwrap(),
+            r#"Bearer realm="todo_server", error="insufficient_scope", scope="read_write""#
+        );
+    }
+}
+
+#[actix_rt::test]
+#[ignore = "needs todo_server's Postgres database"]
+async fn bad_tokens_and_read_only_ones_say_what_went_wrong() {
+    if std::env::var("JWT_SECRET").is_err() {
+        std::env::set_var("JWT_SECRET", "keyboardcat");
+    }
+    let app = app(None).await;
+
+    let request = actix_test::TestRequest::get()
+        .uri("/api/v1/tasks")
+        .insert_header(("x-auth-token", "not a token"))
+        .to_request();
+    let response = actix_test::call_service(&app, request).await;
+    assert_eq!(response.status(), 401);
+    assert_eq!(
+        response.headers().get(WWW_AUTHENTICATE).unwrap(),
+        r#"Bearer realm="todo_server", error="invalid_token""#
+    );
+
+    let token = sign_up(&app, "auth").await;
+    let request = actix_test::TestRequest::post()
+        .uri("/api/v1/users/me/tokens")
+        .insert_header(("x-auth-token", token))
+        .set_json(CreateApiTokenRequest {
+            name: "read only".to_string(),
+            scope: TokenScope::ReadOnly,
+            expires_in_days: None,
+        })
+        .to_request();
+    let read_only: NewApiTokenResponse = actix_test::call_and_read_body_json(&app, request).await;
+    let bearer = ("Authorization", format!("Bearer {}", read_only.data.token));
+
+    let request = actix_test::TestRequest::get()
+        .uri("/api/v1/tasks")
+        .insert_header(bearer.clone())
+        .to_request();
+    let before: TaskListResponse = actix_test::call_and_read_body_json(&app, request).await;
+    let request = actix_test::TestRequest::post()
+        .uri("/api/v1/tasks")
+        .insert_header(bearer.clone())
+        .set_json(CreateTaskRequest {
+            priority: None,
+            title: "not allowed".to_string(),
+            description: None,
+        })
+        .to_request();
+    let response = actix_test::call_service(&app, request).await;
+    assert_eq!(response.status(), 403);
+    assert_eq!(
+        response.headers().get(WWW_AUTHENTICATE).unwrap(),
+        r#"Bearer realm="todo_server", error="insufficient_scope", scope="read_write""#
+    );
+    // turned away before the handler, so nothing was written
+    let request = actix_test::TestRequest::get()
+        .uri("/api/v1/tasks")
+        .insert_header(bearer)
+        .to_request();
+    let after: TaskListResponse = actix_test::call_and_read_body_json(&app, request).await;
+    assert_eq!(after.data.len(), before.data.len());
+}
@@ -0,0 +1,83 @@
+// The Server-Sent Events stream. Events are handed to it like a publish does
+// after storing them, so only the last test needs Postgres (ignored, run it
+// with `cargo test -p todo_server -- --ignored`).
+
+use actix_web::body::to_bytes;
+use actix_web::dev::Service;
+use actix_web::{test as actix_test, web, App};
+use std::time::{SystemTime, UNIX_EPOCH};
+use todo_api::{TaskEvent, TokenScope, UserInfo};
+use todo_server::database::TodoDB;
+use todo_server::events::TaskEvents;
+use todo_server::routes;
+use todo_server::routes::auth::{remember_credentials, Credentials};
+
+#[actix_rt::test]
+async fn events_stored_in_one_order_can_arrive_in_the_other() {
+    let events = web::Data::new(TaskEvents::new());
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(web::Data::new(TodoDB::new()))
+            .app_data(events.clone())
+            // stands in for the database, the request is from user 1
+            .wrap_fn(|req, srv| {
+                let user = UserInfo {
+                    id: 1,
+                    username: "woodroww".to_string(),
+                    token: "some-session-token".to_string(),
+                };
+                remember_credentials(
+                    req.request(),
+                    Some(Credentials {
+                        user,
+                        scope: TokenScope::ReadWrite,
+                        api_token: false,
+                    }),
+                );
+                srv.call(req)
+            })
+            .configure(routes::configure),
+    )
+    .await;
+    let request = actix_test::TestRequest::get()
+        .uri("/api/v1/events")
+        .to_request();
+    let response = actix_test::call_service(&app, request).await;
+    assert_eq!(response.status(), 200);
+
+    // the publish of 42 got to the subscribers first
+    events.send(1, (Some(42), TaskEvent::TaskDeleted { id: 8 }));
+    events.send(1, (Some(41), TaskEvent::TaskDeleted { id: 7 }));
+    // ends the stream so the body can be read to the end
+    events.close();
+    let body = to_bytes(response.into_body()).await.unwrap();
+    let body = String::from_utf8(body.to_vec()).unwrap();
+    assert!(body.contains("id: 42\n"), "{}", body);
+    assert!(body.contains("id: 41\n"), "{}", body);
+}
+
+#[actix_rt::test]
+#[ignore = "needs todo_server's Postgres database"]
+async fn purging_keeps_the_events_within_the_retention() {
+    let db = TodoDB::new();
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let username = format!("events-{}", nanos);
+    let user = db
+        .create_user(&username, "hash", "token", "en")
+        .await
+        .unwrap();
+    let id = db
+        .insert_task_event(user.id, &TaskEvent::TaskDeleted { id: 8 })
+        .await
+        .unwrap();
+
+    db.purge_task_events(60 * 60).await.unwrap();
+    let events = db.get_task_events_after(user.id, id - 1).await.unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].0, id);
+
+    db.delete_user(&username).await.unwrap();
+}
@@ -0,0 +1,180 @@
+// The health and readiness probes. The ignored tests need Postgres, run them
+// with `cargo test -p todo_server -- --ignored`.
+
+use actix_web::{test as actix_test, web, App};
+use todo_server::database::health_queries::{schema_columns, INIT_SQL};
+use todo_server::database::TodoDB;
+use todo_server::health::{healthz, readyz, Health, Status};
+use tokio_postgres::NoTls;
+
+#[test]
+fn the_schema_has_the_columns_of_init_sql() {
+    let columns = schema_columns(INIT_SQL);
+    for (table, column) in [
+        ("users", "email"),
+        ("tasks", "title"),
+        ("api_tokens", "scope"),
+    ] {
+        assert!(
+            columns.contains(&(table.to_string(), column.to_string())),
+            "{}.{}",
+            table,
+            column
+        );
+    }
+    assert!(columns
+        .iter()
+        .all(|(_, column)| column != "CONSTRAINT" && !column.starts_with("fk_")));
+}
+
+#[test]
+fn constraints_and_comments_are_not_columns() {
+    let sql = "CREATE TABLE IF NOT EXISTS things (\n  \
+               thing_id SERIAL PRIMARY KEY,\n  \
+               -- who owns it\n  \
+               owner_id INT NOT NULL,\n  \
+               CONSTRAINT fk_owner FOREIGN KEY(owner_id) REFERENCES users(user_id)\n\
+               );\n\
+               CREATE INDEX things_owner ON things (owner_id);";
+    let columns: Vec<(String, String)> = schema_columns(sql).into_iter().collect();
+    assert_eq!(
+        columns,
+        [
+            ("things".to_string(), "owner_id".to_string()),
+            ("things".to_string(), "thing_id".to_string()),
+        ]
+    );
+}
+
+#[actix_rt::test]
+async fn healthz_needs_no_database() {
+    let app = actix_test::init_service(App::new().route("/healthz", web::get().to(healthz))).await;
+    let request = actix_test::TestRequest::get().uri("/healthz").to_request();
+    let health: Health = actix_test::call_and_read_body_json(&app, request).await;
+    assert_eq!(health.status, Status::Ok);
+    assert!(health.checks.is_empty());
+}
+
+#[actix_rt::test]
+#[ignore = "needs todo_server's Postgres database"]
+async fn readyz_checks_the_database_and_the_schema() {
+    // mounted like main.rs does
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(web::Data::new(TodoDB::new()))
+            .route("/readyz", web::get().to(readyz)),
+    )
+    .await;
+    let request = actix_test::TestRequest::get().uri("/readyz").to_request();
+    let response = actix_test::call_service(&app, request).await;
+    assert_eq!(response.status(), 200);
+    let health: Health = actix_test::read_body_json(response).await;
+    assert_eq!(health.status, Status::Ok);
+    for name in ["database", "schema"] {
+        assert_eq!(health.checks[name].status, Status::Ok, "{}", name);
+    }
+    assert!(health.checks["database"].latency_ms.is_some());
+}
+
+#[actix_rt::test]
+#[ignore = "needs todo_server's Postgres database"]
+async fn init_sql_can_run_again() {
+    let (client, connection) =
+        tokio_postgres::connect("host=localhost user=matt dbname=brooks", NoTls)
+            .await
+            .unwrap();
+    actix_rt::spawn(connection);
+    let seeds = "SELECT (SELECT COUNT(*) FROM users WHERE username = 'deleteduser') \
+                 + (SELECT COUNT(*) FROM tasks WHERE title = 'my deleted task') \
+                 + (SELECT COUNT(*) FROM task_templates)";
+    client.batch_execute(INIT_SQL).await.unwrap();
+    let before: i64 = client.query_one(seeds, &[]).await.unwrap().get(0);
+    client.batch_execute(INIT_SQL).await.unwrap();
+    let after: i64 = client.query_one(seeds, &[]).await.unwrap().get(0);
+    assert_eq!(before, after);
+}
+
+#[actix_rt::test]
+#[ignore = "needs todo_server's Postgres database"]
+async fn init_sql_turns_the_old_default_tasks_into_templates() {
+    let (client, connection) =
+        tokio_postgres::connect("host=localhost user=matt dbname=brooks", NoTls)
+            .await
+            .unwrap();
+    actix_rt::spawn(connection);
+    // a database from before the templates, in a schema of its own so the
+    // templates of the real one are left alone
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let schema = format!("old_defaults_{}", nanos);
+    client
+        .batch_execute(&format!(
+            "CREATE SCHEMA {schema};\n\
+             SET search_path TO {schema};\n\
+             CREATE TABLE users (\n  \
+               id SERIAL PRIMARY KEY,\n  \
+               username VARCHAR(64) NOT NULL UNIQUE,\n  \
+               password VARCHAR(64) NOT NULL,\n  \
+               deleted_at TIMESTAMP DEFAULT NULL,\n  \
+               token TEXT DEFAULT NULL\n\
+             );\n\
+             CREATE TABLE tasks (\n  \
+               id SERIAL PRIMARY KEY,\n  \
+               priority VARCHAR(4) DEFAULT NULL,\n  \
+               title VARCHAR(255) NOT NULL,\n  \
+               completed_at TIMESTAMP DEFAULT NULL,\n  \
+               description TEXT DEFAULT NULL,\n  \
+               deleted_at TIMESTAMP DEFAULT NULL,\n  \
+               user_id INTEGER DEFAULT NULL,\n  \
+               is_default BOOLEAN DEFAULT FALSE,\n  \
+               CONSTRAINT fk_users FOREIGN KEY (user_id) REFERENCES users(id)\n\
+             );\n\
+             INSERT INTO tasks (priority, title, description, is_default) VALUES\n  \
+               ('A', 'Water the plants', 'edited by an admin', true),\n  \
+               ('B', 'Feed the cat', NULL, true),\n  \
+               (NULL, 'Gone', NULL, true);\n\
+             UPDATE tasks SET deleted_at = NOW() WHERE title = 'Gone';"
+        ))
+        .await
+        .unwrap();
+    let english = "SELECT position, priority, title, description \
+                   FROM task_templates WHERE locale = 'en' ORDER BY position";
+    let count = "SELECT (SELECT COUNT(*) FROM task_templates), \
+                 (SELECT COUNT(*) FROM task_templates WHERE locale = 'es'), \
+                 (SELECT COUNT(*) FROM tasks WHERE is_default)";
+    client.batch_execute(INIT_SQL).await.unwrap();
+    let migrated: Vec<(i32, Option<String>, String, Option<String>)> = client
+        .query(english, &[])
+        .await
+        .unwrap()
+        .iter()
+        .map(|row| (row.get(0), row.get(1), row.get(2), row.get(3)))
+        .collect();
+    assert_eq!(
+        migrated,
+        [
+            (
+                0,
+                Some("A".to_string()),
+                "Water the plants".to_string(),
+                Some("edited by an admin".to_string())
+            ),
+            (1, Some("B".to_string()), "Feed the cat".to_string(), None),
+        ]
+    );
+    // the other locales are still seeded and no default task is left
+    let row = client.query_one(count, &[]).await.unwrap();
+    let (templates, spanish, defaults): (i64, i64, i64) = (row.get(0), row.get(1), row.get(2));
+    assert!(spanish > 0);
+    assert_eq!(defaults, 0);
+
+    client.batch_execute(INIT_SQL).await.unwrap();
+    let again: i64 = client.query_one(count, &[]).await.unwrap().get(0);
+    assert_eq!(again, templates);
+    client
+        .batch_execute(&format!("DROP SCHEMA {schema} CASCADE"))
+        .await
+        .unwrap();
+}
@@ -0,0 +1,116 @@
+// Idempotency-Key against the database (ignored, run it with
+// `cargo test -p todo_server -- --ignored`).
+
+use actix_web::{test as actix_test, web, App};
+use std::time::{SystemTime, UNIX_EPOCH};
+use todo_api::{AuthResponse, CreateTaskRequest, LoginRequest, TaskResponse};
+use todo_server::database::idempotency_queries::IdempotencyClaim;
+use todo_server::database::TodoDB;
+use todo_server::events::TaskEvents;
+use todo_server::routes;
+
+#[actix_rt::test]
+#[ignore = "needs todo_server's Postgres database"]
+async fn retries_get_the_first_answer_and_nothing_else_does() {
+    if std::env::var("JWT_SECRET").is_err() {
+        std::env::set_var("JWT_SECRET", "keyboardcat");
+    }
+    let db = web::Data::new(TodoDB::new());
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(db.clone())
+            .app_data(web::Data::new(TaskEvents::new()))
+            .configure(routes::configure),
+    )
+    .await;
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let key = format!("retry-{}", nanos);
+    let username = format!("idempotent-{}", nanos);
+
+    // nobody to scope a key to, so signing up isn't remembered: the retry is a
+    // second sign up, and the name is taken by then
+    let sign_up = || {
+        actix_test::TestRequest::post()
+            .uri("/api/v1/users")
+            .insert_header(("Idempotency-Key", key.clone()))
+            .set_json(LoginRequest {
+                username: username.clone(),
+                password: "myfancypass".to_string(),
+            })
+            .to_request()
+    };
+    let response = actix_test::call_service(&app, sign_up()).await;
+    assert!(response.status().is_success());
+    let user: AuthResponse = actix_test::read_body_json(response).await;
+    let response = actix_test::call_service(&app, sign_up()).await;
+    assert!(response.headers().get("idempotent-replayed").is_none());
+    assert!(!response.status().is_success());
+
+    let create = |title: &str| {
+        actix_test::TestRequest::post()
+            .uri("/api/v1/tasks")
+            .insert_header(("x-auth-token", user.data.token.clone()))
+            .insert_header(("Idempotency-Key", key.clone()))
+            .set_json(CreateTaskRequest {
+                title: title.to_string(),
+                ..CreateTaskRequest::default()
+            })
+            .to_request()
+    };
+    let first: TaskResponse = actix_test::call_and_read_body_json(&app, create("once")).await;
+    let response = actix_test::call_service(&app, create("once")).await;
+    assert_eq!(
+        response.headers().get("idempotent-replayed").unwrap(),
+        "true"
+    );
+    let replayed: TaskResponse = actix_test::read_body_json(response).await;
+    assert_eq!(replayed.data.id, first.data.id);
+    // purging only takes keys past the retention
+    db.purge_idempotency_keys(60 * 60).await.unwrap();
+    let response = actix_test::call_service(&app, create("once")).await;
+    assert_eq!(
+        response.headers().get("idempotent-replayed").unwrap(),
+        "true"
+    );
+    // the same key with another body is a mistake, not a retry
+    let response = actix_test::call_service(&app, create("twice")).await;
+    assert_eq!(response.status(), 422);
+
+    // a claim that never got its answer is given up after the lease
+    let stuck = format!("stuck-{}", nanos);
+    let user_id = user.data.id;
+    let claim = |lease_secs| {
+        let (db, stuck) = (db.clone(), stuck.clone());
+        async move {
+            db.claim_idempotency_key(
+                user_id,
+                &stuck,
+                "POST",
+                "/api/v1/tasks",
+                "fingerprint",
+                60,
+                lease_secs,
+            )
+            .await
+        }
+    };
+    assert!(matches!(claim(60).await, Some(IdempotencyClaim::New)));
+    assert!(matches!(
+        claim(60).await,
+        Some(IdempotencyClaim::InProgress)
+    ));
+    assert!(matches!(claim(0).await, Some(IdempotencyClaim::New)));
+
+    // an answered key past the retention is new again before it's purged
+    let expired = db
+        .claim_idempotency_key(user_id, &key, "POST", "/api/v1/tasks", "fingerprint", 0, 60)
+        .await;
+    assert!(matches!(expired, Some(IdempotencyClaim::New)));
+
+    db.release_idempotency_key(user.data.id, &stuck).await;
+    db.release_idempotency_key(user.data.id, &key).await;
+    db.delete_user(&username).await.unwrap();
+}
@@ -0,0 +1,190 @@
+// The Mailers against a directory and a scripted SMTP server, no database needed.
+
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use todo_server::mailer::{Email, FileMailer, MailError, Mailer, SmtpMailer, SmtpSettings};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+
+fn email() -> Email {
+    Email {
+        to: "matt@example.com".to_string(),
+        subject: "Reset your todo password".to_string(),
+        body: "click here\n.\nthat was a dot".to_string(),
+    }
+}
+
+#[actix_rt::test]
+async fn file_mailer_writes_a_file_per_email() {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let dir = std::env::temp_dir().join(format!("todo-mail-{}", nanos));
+    FileMailer::new(Some(dir.clone()))
+        .send(&email())
+        .await
+        .unwrap();
+
+    let files: Vec<_> = std::fs::read_dir(&dir).unwrap().collect();
+    assert_eq!(files.len(), 1);
+    let written = std::fs::read_to_string(files[0].as_ref().unwrap().path()).unwrap();
+    assert!(written.contains("To: matt@example.com\n"));
+    assert!(written.contains("Subject: Reset your todo password\n"));
+    assert!(written.ends_with("click here\n.\nthat was a dot\n"));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+// answers each command with the next reply and returns what the client sent
+async fn smtp_server(replies: Vec<&'static str>) -> (u16, tokio::task::JoinHandle<Vec<String>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let (reader, mut writer) = stream.into_split();
+        let mut reader = BufReader::new(reader);
+        let mut received = vec![];
+        writer.write_all(b"220 mail.test ESMTP\r\n").await.unwrap();
+        for reply in replies {
+            let mut line = String::new();
+            if reader.read_line(&mut line).await.unwrap() == 0 {
+                break;
+            }
+            // the message itself is read up to the lone dot that ends it
+            if received.last().map(String::as_str) == Some("DATA") {
+                while line != ".\r\n" {
+                    received.push(line.trim_end().to_string());
+                    line.clear();
+                    reader.read_line(&mut line).await.unwrap();
+                }
+            }
+            received.push(line.trim_end().to_string());
+            writer.write_all(reply.as_bytes()).await.unwrap();
+        }
+        received
+    });
+    (port, server)
+}
+
+// plain SMTP to the scripted server, it can't do STARTTLS
+fn plain_smtp(port: u16) -> SmtpSettings {
+    SmtpSettings {
+        host: "127.0.0.1".to_string(),
+        port,
+        from: "todo@example.com".to_string(),
+        starttls: false,
+        timeout: Duration::from_secs(5),
+        ..SmtpSettings::default()
+    }
+}
+
+#[actix_rt::test]
+async fn smtp_mailer_speaks_smtp() {
+    let (port, server) = smtp_server(vec![
+        "250 mail.test\r\n",
+        "250 ok\r\n",
+        "250 ok\r\n",
+        "354 go ahead\r\n",
+        "250 queued\r\n",
+        "221 bye\r\n",
+    ])
+    .await;
+    let mailer = SmtpMailer::new(plain_smtp(port)).unwrap();
+    mailer.send(&email()).await.unwrap();
+
+    let received = server.await.unwrap();
+    assert!(received[0].starts_with("EHLO "));
+    assert_eq!(received[1], "MAIL FROM:<todo@example.com>");
+    assert_eq!(received[2], "RCPT TO:<matt@example.com>");
+    assert_eq!(received[3], "DATA");
+    assert!(received.contains(&"Subject: Reset your todo password".to_string()));
+    assert!(received.contains(&"To: matt@example.com".to_string()));
+    // a line with just a dot would end the message early
+    assert!(received.contains(&"..".to_string()));
+    assert_eq!(received.last().unwrap(), "QUIT");
+}
+
+#[actix_rt::test]
+async fn smtp_mailer_wont_send_without_starttls() {
+    // the server doesn't offer STARTTLS, so nothing goes out
+    let (port, server) = smtp_server(vec!["250 mail.test\r\n", "221 bye\r\n"]).await;
+    let mailer = SmtpMailer::new(SmtpSettings {
+        starttls: true,
+        credentials: Some(("todo".to_string(), "secret".to_string())),
+        ..plain_smtp(port)
+    })
+    .unwrap();
+    assert!(matches!(
+        mailer.send(&email()).await,
+        Err(MailError::Smtp(_))
+    ));
+    let received = server.await.unwrap();
+    assert!(received[0].starts_with("EHLO "));
+    assert!(!received
+        .iter()
+        .any(|line| line.starts_with("AUTH") || line.starts_with("MAIL FROM")));
+
+    // and a password never goes without it
+    let settings = SmtpSettings {
+        credentials: Some(("todo".to_string(), "secret".to_string())),
+        ..plain_smtp(port)
+    };
+    assert!(matches!(
+        SmtpMailer::new(settings),
+        Err(MailError::Settings(_))
+    ));
+}
+
+#[actix_rt::test]
+async fn smtp_mailer_refuses_addresses_with_commands_in_them() {
+    // nothing listens there, the address is turned away before connecting
+    let mailer = SmtpMailer::new(plain_smtp(9)).unwrap();
+    let email = Email {
+        to: "matt@example.com>\r\nRCPT TO:<everyone@example.com".to_string(),
+        ..email()
+    };
+    assert!(matches!(
+        mailer.send(&email).await,
+        Err(MailError::BadAddress(_))
+    ));
+    let settings = SmtpSettings {
+        from: "todo@example.com\r\nBcc: everyone@example.com".to_string(),
+        ..plain_smtp(9)
+    };
+    assert!(matches!(
+        SmtpMailer::new(settings),
+        Err(MailError::BadAddress(_))
+    ));
+}
+
+#[actix_rt::test]
+async fn smtp_mailer_reports_refusals() {
+    let (port, _server) = smtp_server(vec!["250 mail.test\r\n", "550 no such sender\r\n"]).await;
+    let mailer = SmtpMailer::new(plain_smtp(port)).unwrap();
+    match mailer.send(&email()).await {
+        Err(MailError::Smtp(e)) => assert!(e.is_permanent(), "{}", e),
+        other => panic!("{:?}", other),
+    }
+}
+
+#[actix_rt::test]
+async fn smtp_mailer_gives_up_on_a_silent_server() {
+    // connects and then never says a word
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let _server = tokio::spawn(async move {
+        let (_stream, _) = listener.accept().await.unwrap();
+        tokio::time::sleep(Duration::from_secs(60)).await;
+    });
+    let mailer = SmtpMailer::new(SmtpSettings {
+        timeout: Duration::from_millis(200),
+        ..plain_smtp(port)
+    })
+    .unwrap();
+    let started = Instant::now();
+    match mailer.send(&email()).await {
+        Err(MailError::TimedOut(_)) => {}
+        Err(MailError::Smtp(e)) if e.is_timeout() => {}
+        other => panic!("{:?}", other),
+    }
+    assert!(started.elapsed() < Duration::from_secs(2));
+}
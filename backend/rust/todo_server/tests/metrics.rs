@@ -0,0 +1,238 @@
+// The Prometheus metrics. Only the last test needs Postgres (ignored, run it
+// with `cargo test -p todo_server -- --ignored`).
+
+use actix_http::Request;
+use actix_web::dev::{Service, ServiceResponse};
+
+use actix_web::http::header::{AUTHORIZATION, CONTENT_TYPE, WWW_AUTHENTICATE};
+use actix_web::{test as actix_test, web, App};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use todo_api::{AuthResponse, LoginRequest};
+use todo_server::database::TodoDB;
+use todo_server::events::TaskEvents;
+use todo_server::metrics::{serve_metrics, LoginMethod, LoginOutcome, Metrics};
+use todo_server::routes;
+
+const METRICS_TOKEN: &str = "prometheus-scrapes-with-this";
+
+// the value of the sample with exactly these labels
+fn sample(text: &str, name_and_labels: &str) -> Option<f64> {
+    text.lines()
+        .find_map(|line| line.strip_prefix(name_and_labels)?.trim().parse().ok())
+}
+
+#[test]
+fn counters_add_up() {
+    let metrics = Metrics::new();
+    metrics.observe_request("GET", "/api/v1/tasks", 200, Duration::from_millis(3));
+    metrics.observe_request("GET", "/api/v1/tasks", 200, Duration::from_millis(30));
+    metrics.observe_request("GET", "/api/v1/tasks", 401, Duration::from_millis(1));
+    metrics.login(LoginMethod::Password, LoginOutcome::Failure);
+    metrics.login(LoginMethod::Password, LoginOutcome::Failure);
+    metrics.login(LoginMethod::TwoFactor, LoginOutcome::Success);
+
+    let text = metrics.text();
+    let tasks = r#"{method="GET",route="/api/v1/tasks",status="200"}"#;
+    assert_eq!(
+        sample(&text, &format!("todo_http_requests_total{}", tasks)),
+        Some(2.0)
+    );
+    assert_eq!(
+        sample(
+            &text,
+            &format!("todo_http_request_duration_seconds_count{}", tasks)
+        ),
+        Some(2.0)
+    );
+    let sum = sample(
+        &text,
+        &format!("todo_http_request_duration_seconds_sum{}", tasks),
+    )
+    .unwrap();
+    assert!((sum - 0.033).abs() < 1e-9);
+    assert_eq!(
+        sample(
+            &text,
+            r#"todo_http_requests_total{method="GET",route="/api/v1/tasks",status="401"}"#
+        ),
+        Some(1.0)
+    );
+    assert_eq!(
+        sample(
+            &text,
+            r#"todo_logins_total{method="password",outcome="failure"}"#
+        ),
+        Some(2.0)
+    );
+    assert_eq!(
+        sample(
+            &text,
+            r#"todo_logins_total{method="two_factor",outcome="success"}"#
+        ),
+        Some(1.0)
+    );
+}
+
+#[actix_rt::test]
+async fn requests_are_counted_by_route_pattern() {
+    let metrics = web::Data::new(Metrics::new());
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(web::Data::new(TodoDB::new()))
+            .app_data(metrics.clone())
+            .configure(routes::configure),
+    )
+    .await;
+    for id in [1, 2, 3] {
+        let request = actix_test::TestRequest::get()
+            .uri(&format!("/api/v1/tasks/{}", id))
+            .to_request();
+        assert_eq!(actix_test::call_service(&app, request).await.status(), 401);
+    }
+    let request = actix_test::TestRequest::get()
+        .uri("/api/v1/openapi.json")
+        .to_request();
+    assert_eq!(actix_test::call_service(&app, request).await.status(), 200);
+
+    let text = metrics.text();
+    assert_eq!(
+        sample(
+            &text,
+            r#"todo_http_requests_total{method="GET",route="/api/v1/tasks/{id}",status="401"}"#
+        ),
+        Some(3.0)
+    );
+    assert_eq!(
+        sample(
+            &text,
+            r#"todo_http_requests_total{method="GET",route="/api/v1/openapi.json",status="200"}"#
+        ),
+        Some(1.0)
+    );
+    assert!(!text.contains("/api/v1/tasks/1"));
+}
+
+// GET /metrics with this Authorization
+async fn scrape(
+    app: &impl Service<Request, Response = ServiceResponse, Error = actix_web::Error>,
+    authorization: Option<&str>,
+) -> ServiceResponse {
+    let mut request = actix_test::TestRequest::get().uri("/metrics");
+    if let Some(authorization) = authorization {
+        request = request.insert_header((AUTHORIZATION, authorization));
+    }
+    actix_test::call_service(app, request.to_request()).await
+}
+
+#[actix_rt::test]
+async fn scrapes_need_the_token() {
+    let off = actix_test::init_service(
+        App::new()
+            .app_data(web::Data::new(TodoDB::new()))
+            .app_data(web::Data::new(
+                Metrics::new().with_token(Some(String::new())),
+            ))
+            .route("/metrics", web::get().to(serve_metrics)),
+    )
+    .await;
+    let bearer = format!("Bearer {}", METRICS_TOKEN);
+    assert_eq!(scrape(&off, Some(&bearer)).await.status(), 404);
+
+    let metrics = Metrics::new().with_token(Some(METRICS_TOKEN.to_string()));
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(web::Data::new(TodoDB::new()))
+            .app_data(web::Data::new(metrics))
+            .route("/metrics", web::get().to(serve_metrics)),
+    )
+    .await;
+    // turned away before the database is asked anything
+    for authorization in [None, Some("Bearer guess"), Some(METRICS_TOKEN)] {
+        let response = scrape(&app, authorization).await;
+        assert_eq!(response.status(), 401, "{:?}", authorization);
+        assert_eq!(
+            response.headers().get(WWW_AUTHENTICATE).unwrap(),
+            r#"Bearer realm="metrics""#
+        );
+    }
+}
+
+#[actix_rt::test]
+#[ignore = "needs todo_server's Postgres database"]
+async fn the_endpoint_shows_the_pool_logins_and_tasks() {
+    if std::env::var("JWT_SECRET").is_err() {
+        std::env::set_var("JWT_SECRET", "keyboardcat");
+    }
+    // mounted like main.rs does
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(web::Data::new(TodoDB::new()))
+            .app_data(web::Data::new(TaskEvents::new()))
+            .app_data(web::Data::new(
+                Metrics::new().with_token(Some(METRICS_TOKEN.to_string())),
+            ))
+            .route("/metrics", web::get().to(serve_metrics))
+            .configure(routes::configure),
+    )
+    .await;
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let credentials = |password: &str| LoginRequest {
+        username: format!("metrics-{}", nanos),
+        password: password.to_string(),
+    };
+    let request = actix_test::TestRequest::post()
+        .uri("/api/v1/users")
+        .set_json(credentials("myfancypass"))
+        .to_request();
+    let _: AuthResponse = actix_test::call_and_read_body_json(&app, request).await;
+    for password in ["myfancypass", "wrong"] {
+        let request = actix_test::TestRequest::post()
+            .uri("/api/v1/users/login")
+            .set_json(credentials(password))
+            .to_request();
+        actix_test::call_service(&app, request).await;
+    }
+
+    let bearer = format!("Bearer {}", METRICS_TOKEN);
+    let response = scrape(&app, Some(&bearer)).await;
+    assert_eq!(response.status(), 200);
+    assert!(response
+        .headers()
+        .get(CONTENT_TYPE)
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .starts_with("text/plain"));
+    let text = String::from_utf8(actix_test::read_body(response).await.to_vec()).unwrap();
+    for outcome in ["success", "failure"] {
+        let login = format!(
+            r#"todo_logins_total{{method="password",outcome="{}"}}"#,
+            outcome
+        );
+        assert_eq!(sample(&text, &login), Some(1.0), "{}", login);
+    }
+    assert!(sample(&text, "todo_db_pool_max_size").unwrap() >= 1.0);
+    assert!(sample(&text, "todo_db_pool_size").unwrap() >= 1.0);
+    assert!(sample(&text, "todo_db_pool_waiting").is_some());
+    // the new user starts out with the template tasks
+    assert!(sample(&text, r#"todo_tasks{state="open"}"#).unwrap() >= 1.0);
+    assert!(sample(&text, r#"todo_users{state="active"}"#).unwrap() >= 1.0);
+    assert!(sample(&text, "todo_logged_in_users").unwrap() >= 1.0);
+
+    // another user, but the next scrape is too soon to count again
+    let request = actix_test::TestRequest::post()
+        .uri("/api/v1/users")
+        .set_json(LoginRequest {
+            username: format!("metrics-{}-again", nanos),
+            password: "myfancypass".to_string(),
+        })
+        .to_request();
+    let _: AuthResponse = actix_test::call_and_read_body_json(&app, request).await;
+    let response = scrape(&app, Some(&bearer)).await;
+    let again = String::from_utf8(actix_test::read_body(response).await.to_vec()).unwrap();
+    let users = r#"todo_users{state="active"}"#;
+    assert_eq!(sample(&again, users), sample(&text, users));
+}
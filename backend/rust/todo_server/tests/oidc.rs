@@ -0,0 +1,497 @@
+// SSO logins against a mock identity provider run on a random local port:
+// discovery, JWKS and a token endpoint that checks the PKCE verifier and signs
+// ID tokens with tests/oidc_test_key.pem. The login through the routes needs
+// the database and is ignored, run it with `cargo test -p todo_server -- --ignored`.
+
+use actix_web::cookie::{time::Duration as CookieDuration, Cookie};
+use actix_web::dev::ServiceResponse;
+use actix_web::{test as actix_test, web, App, HttpResponse, HttpServer};
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use todo_api::{AuthResponse, LoginRequest, OidcAuthorizationResponse, OidcCallbackRequest};
+use todo_server::database::oidc_queries::NewIdentity;
+use todo_server::database::TodoDB;
+use todo_server::events::TaskEvents;
+use todo_server::oidc::{code_challenge, validate_id_token, Oidc, OidcConfig, OidcError};
+use todo_server::routes;
+
+const KEY: &str = include_str!("oidc_test_key.pem");
+// the modulus of the key above
+const N: &str = "3QN4qBWh4JwUJ8k7q-7HZCFvWRdwYk61dkmt46QLm_m6m-sQKZM4PeQXkt9g1_y-xf62fClk6wfmjcqUxiQATgy1wKoWopDZHesUi_gOLGxLYTpHkDumeRifnWxB4sy_ZQ-OTuswSUU8CE6lBYRAsav2oaZ4LHW1d3JNxXvDSkEwPeQTENWz1qk85fgvoE36lkfsVxecbkbITGdzt3Waq1lPZfp2LnaaLPhTtQMaP49VdIvytgSPbYjV-tf9HBwZ9VURzvFSLyvLAR6MyRe55gBDGj4w6_cQUmZnqIwW0krX6Y4DR0NBgUWZOxlbMZ0Z7iK2RXNIWQgTab3RE199iw";
+const KID: &str = "test-key";
+const CLIENT_ID: &str = "todo";
+
+fn jwks() -> JwkSet {
+    serde_json::from_value(json!({
+        "keys": [{ "kty": "RSA", "kid": KID, "use": "sig", "alg": "RS256", "n": N, "e": "AQAB" }],
+    }))
+    .unwrap()
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+fn sign(claims: &Value) -> String {
+    let mut header = Header::new(Algorithm::RS256);
+    header.kid = Some(KID.to_string());
+    encode(
+        &header,
+        claims,
+        &EncodingKey::from_rsa_pem(KEY.as_bytes()).unwrap(),
+    )
+    .unwrap()
+}
+
+fn config(issuer: &str) -> OidcConfig {
+    OidcConfig {
+        issuer: issuer.to_string(),
+        client_id: CLIENT_ID.to_string(),
+        client_secret: Some("shh".to_string()),
+        redirect_uri: "http://localhost:8080/sso-callback".to_string(),
+    }
+}
+
+fn id_claims(issuer: &str, subject: &str, nonce: &str) -> Value {
+    json!({
+        "iss": issuer,
+        "sub": subject,
+        "aud": CLIENT_ID,
+        "iat": now(),
+        "exp": now() + 300,
+        "nonce": nonce,
+        "email": format!("{}@example.com", subject),
+        "email_verified": true,
+        "preferred_username": subject,
+    })
+}
+
+// whether the token is turned away as invalid
+fn invalid(token: &str, config: &OidcConfig, nonce: &str) -> bool {
+    matches!(
+        validate_id_token(token, &jwks(), config, nonce),
+        Err(OidcError::InvalidToken(_))
+    )
+}
+
+#[test]
+fn code_challenges_are_s256() {
+    // RFC 7636 appendix B
+    assert_eq!(
+        code_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"),
+        "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+    );
+}
+
+#[test]
+fn id_tokens_are_checked() {
+    let issuer = "https://sso.example.com";
+    let config = config(issuer);
+    let nonce = "n-0S6_WzA2Mj";
+    let token = sign(&id_claims(issuer, "alice", nonce));
+    let claims = validate_id_token(&token, &jwks(), &config, nonce).unwrap();
+    assert_eq!(claims.sub, "alice");
+    assert_eq!(claims.email.as_deref(), Some("alice@example.com"));
+    assert_eq!(claims.email_verified, Some(true));
+
+    assert!(invalid(&token, &config, "another login"));
+    let wrong_audience = OidcConfig {
+        client_id: "somebody-else".to_string(),
+        ..config.clone()
+    };
+    assert!(invalid(&token, &wrong_audience, nonce));
+    let wrong_issuer = OidcConfig {
+        issuer: "https://evil.example.com".to_string(),
+        ..config.clone()
+    };
+    assert!(invalid(&token, &wrong_issuer, nonce));
+
+    let mut expired = id_claims(issuer, "alice", nonce);
+    expired["exp"] = json!(now() - 600);
+    assert!(invalid(&sign(&expired), &config, nonce));
+
+    // the key it names isn't the provider's
+    let claims = id_claims(issuer, "alice", nonce);
+    let mut header = Header::new(Algorithm::RS256);
+    header.kid = Some("another-key".to_string());
+    let key = EncodingKey::from_rsa_pem(KEY.as_bytes()).unwrap();
+    assert!(invalid(
+        &encode(&header, &claims, &key).unwrap(),
+        &config,
+        nonce
+    ));
+
+    // a shared secret anybody could sign with
+    let mut header = Header::new(Algorithm::HS256);
+    header.kid = Some(KID.to_string());
+    let key = EncodingKey::from_secret(N.as_bytes());
+    assert!(invalid(
+        &encode(&header, &claims, &key).unwrap(),
+        &config,
+        nonce
+    ));
+}
+
+// what the mock provider remembers about a code it handed out
+struct Grant {
+    code_challenge: String,
+    nonce: String,
+    subject: String,
+}
+
+#[derive(Clone)]
+struct MockIssuer {
+    issuer: String,
+    grants: Arc<Mutex<HashMap<String, Grant>>>,
+}
+
+impl MockIssuer {
+    // what the provider's login page does: the user logs in as `subject` and
+    // the browser goes back with a code
+    fn log_in(&self, authorization_url: &str, subject: &str) -> OidcCallbackRequest {
+        let url = reqwest::Url::parse(authorization_url).unwrap();
+        assert!(url
+            .as_str()
+            .starts_with(&format!("{}/authorize?", self.issuer)));
+        let query: HashMap<String, String> = url.query_pairs().into_owned().collect();
+        assert_eq!(query["response_type"], "code");
+        assert_eq!(query["client_id"], CLIENT_ID);
+        assert_eq!(query["code_challenge_method"], "S256");
+        assert!(query["scope"].split(' ').any(|scope| scope == "openid"));
+        let code = format!("code-{}", query["state"]);
+        self.grants.lock().unwrap().insert(
+            code.clone(),
+            Grant {
+                code_challenge: query["code_challenge"].clone(),
+                nonce: query["nonce"].clone(),
+                subject: subject.to_string(),
+            },
+        );
+        OidcCallbackRequest {
+            code,
+            state: query["state"].clone(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct TokenRequest {
+    grant_type: String,
+    code: String,
+    code_verifier: String,
+}
+
+async fn discovery(mock: web::Data<MockIssuer>) -> HttpResponse {
+    HttpResponse::Ok().json(json!({
+        "issuer": mock.issuer,
+        "authorization_endpoint": format!("{}/authorize", mock.issuer),
+        "token_endpoint": format!("{}/token", mock.issuer),
+        "jwks_uri": format!("{}/jwks", mock.issuer),
+    }))
+}
+
+async fn keys() -> HttpResponse {
+    HttpResponse::Ok().json(jwks())
+}
+
+async fn token(mock: web::Data<MockIssuer>, form: web::Form<TokenRequest>) -> HttpResponse {
+    // codes work once
+    let grant = match mock.grants.lock().unwrap().remove(&form.code) {
+        Some(grant) if form.grant_type == "authorization_code" => grant,
+        _ => return HttpResponse::BadRequest().json(json!({ "error": "invalid_grant" })),
+    };
+    if code_challenge(&form.code_verifier) != grant.code_challenge {
+        return HttpResponse::BadRequest().json(json!({ "error": "invalid_grant" }));
+    }
+    HttpResponse::Ok().json(json!({
+        "access_token": "opaque",
+        "token_type": "Bearer",
+        "id_token": sign(&id_claims(&mock.issuer, &grant.subject, &grant.nonce)),
+    }))
+}
+
+fn start_mock_issuer() -> MockIssuer {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let mock = MockIssuer {
+        issuer: format!("http://{}", listener.local_addr().unwrap()),
+        grants: Arc::new(Mutex::new(HashMap::new())),
+    };
+    let data = web::Data::new(mock.clone());
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(data.clone())
+            .route(
+                "/.well-known/openid-configuration",
+                web::get().to(discovery),
+            )
+            .route("/jwks", web::get().to(keys))
+            .route("/token", web::post().to(token))
+    })
+    .workers(1)
+    .listen(listener)
+    .unwrap()
+    .run();
+    actix_rt::spawn(server);
+    mock
+}
+
+#[actix_rt::test]
+async fn logins_go_through_the_provider() {
+    let mock = start_mock_issuer();
+    let oidc = Oidc::new(config(&mock.issuer));
+    let verifier = todo_server::oidc::new_code_verifier();
+    let url = oidc
+        .authorization_url("some-state", "some-nonce", &verifier)
+        .await
+        .unwrap();
+    let callback = mock.log_in(&url, "alice");
+    assert_eq!(callback.state, "some-state");
+
+    // the wrong verifier is what somebody who only stole the code has
+    let wrong = todo_server::oidc::new_code_verifier();
+    let result = oidc
+        .exchange_code(&callback.code, &wrong, "some-nonce")
+        .await;
+    assert!(matches!(result, Err(OidcError::BadResponse(_))));
+
+    let callback = mock.log_in(&url, "alice");
+    let claims = oidc
+        .exchange_code(&callback.code, &verifier, "some-nonce")
+        .await
+        .unwrap();
+    assert_eq!(claims.iss, mock.issuer);
+    assert_eq!(claims.sub, "alice");
+    assert_eq!(claims.preferred_username.as_deref(), Some("alice"));
+
+    // a replayed code
+    let result = oidc
+        .exchange_code(&callback.code, &verifier, "some-nonce")
+        .await;
+    assert!(matches!(result, Err(OidcError::BadResponse(_))));
+}
+
+// the cookie that ties the login to the browser
+fn sso_cookie(response: &ServiceResponse) -> Cookie<'static> {
+    response
+        .response()
+        .cookies()
+        .find(|cookie| cookie.name() == "todo_sso")
+        .unwrap()
+        .into_owned()
+}
+
+#[actix_rt::test]
+#[ignore = "needs todo_server's Postgres database"]
+async fn sso_logins_find_or_create_the_user() {
+    if std::env::var("JWT_SECRET").is_err() {
+        std::env::set_var("JWT_SECRET", "keyboardcat");
+    }
+    let mock = start_mock_issuer();
+    let db = web::Data::new(TodoDB::new());
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(db.clone())
+            .app_data(web::Data::new(TaskEvents::new()))
+            .app_data(web::Data::new(Oidc::new(config(&mock.issuer))))
+            .configure(routes::configure),
+    )
+    .await;
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let subject = format!("sso-{}", nanos);
+    let authorize = |token: Option<&str>| {
+        let mut request = actix_test::TestRequest::post().uri("/api/v1/auth/oidc/authorize");
+        if let Some(token) = token {
+            request = request.insert_header(("x-auth-token", token.to_string()));
+        }
+        request.to_request()
+    };
+    let callback = |body: &OidcCallbackRequest, cookie: &Cookie<'static>| {
+        actix_test::TestRequest::post()
+            .uri("/api/v1/auth/oidc/callback")
+            .cookie(cookie.clone())
+            .set_json(body)
+            .to_request()
+    };
+
+    // somebody new gets an account named after them
+    let response = actix_test::call_service(&app, authorize(None)).await;
+    let cookie = sso_cookie(&response);
+    let response: OidcAuthorizationResponse = actix_test::read_body_json(response).await;
+    let body = mock.log_in(&response.data.authorization_url, &subject);
+    // only in the browser that started it, a link somebody sent doesn't do
+    let without_cookie = actix_test::TestRequest::post()
+        .uri("/api/v1/auth/oidc/callback")
+        .set_json(&body)
+        .to_request();
+    assert_eq!(
+        actix_test::call_service(&app, without_cookie)
+            .await
+            .status(),
+        400
+    );
+    let someone_elses = actix_test::call_service(&app, authorize(None)).await;
+    let someone_elses = sso_cookie(&someone_elses);
+    assert_eq!(
+        actix_test::call_service(&app, callback(&body, &someone_elses))
+            .await
+            .status(),
+        400
+    );
+    let response = actix_test::call_service(&app, callback(&body, &cookie)).await;
+    assert_eq!(response.status(), 200);
+    // which it then forgets
+    assert_eq!(sso_cookie(&response).max_age(), Some(CookieDuration::ZERO));
+    let user: AuthResponse = actix_test::read_body_json(response).await;
+    assert_eq!(user.data.username, subject);
+
+    // the state only works once
+    assert_eq!(
+        actix_test::call_service(&app, callback(&body, &cookie))
+            .await
+            .status(),
+        400
+    );
+
+    // and the next time it's the same user
+    let response = actix_test::call_service(&app, authorize(None)).await;
+    let cookie = sso_cookie(&response);
+    let response: OidcAuthorizationResponse = actix_test::read_body_json(response).await;
+    let body = mock.log_in(&response.data.authorization_url, &subject);
+    let again: AuthResponse =
+        actix_test::call_and_read_body_json(&app, callback(&body, &cookie)).await;
+    assert_eq!(again.data.id, user.data.id);
+
+    // a user with a password links their SSO account
+    let username = format!("linked-{}", nanos);
+    let request = actix_test::TestRequest::post()
+        .uri("/api/v1/users")
+        .set_json(LoginRequest {
+            username: username.clone(),
+            password: "myfancypass".to_string(),
+        })
+        .to_request();
+    let local: AuthResponse = actix_test::call_and_read_body_json(&app, request).await;
+    let response = actix_test::call_service(&app, authorize(Some(&local.data.token))).await;
+    let cookie = sso_cookie(&response);
+    let response: OidcAuthorizationResponse = actix_test::read_body_json(response).await;
+    let linked_subject = format!("linked-sso-{}", nanos);
+    let body = mock.log_in(&response.data.authorization_url, &linked_subject);
+    let linked: AuthResponse =
+        actix_test::call_and_read_body_json(&app, callback(&body, &cookie)).await;
+    assert_eq!(linked.data.id, local.data.id);
+    assert_eq!(linked.data.username, username);
+
+    // which can't then be linked to somebody else
+    let response = actix_test::call_service(&app, authorize(Some(&again.data.token))).await;
+    let cookie = sso_cookie(&response);
+    let response: OidcAuthorizationResponse = actix_test::read_body_json(response).await;
+    let body = mock.log_in(&response.data.authorization_url, &linked_subject);
+    assert_eq!(
+        actix_test::call_service(&app, callback(&body, &cookie))
+            .await
+            .status(),
+        409
+    );
+
+    // a name somebody has already gets a number
+    let taken = format!("taken-{}", nanos);
+    let request = actix_test::TestRequest::post()
+        .uri("/api/v1/users")
+        .set_json(LoginRequest {
+            username: taken.clone(),
+            password: "myfancypass".to_string(),
+        })
+        .to_request();
+    assert!(actix_test::call_service(&app, request)
+        .await
+        .status()
+        .is_success());
+    let response = actix_test::call_service(&app, authorize(None)).await;
+    let cookie = sso_cookie(&response);
+    let response: OidcAuthorizationResponse = actix_test::read_body_json(response).await;
+    let body = mock.log_in(&response.data.authorization_url, &taken);
+    let numbered: AuthResponse =
+        actix_test::call_and_read_body_json(&app, callback(&body, &cookie)).await;
+    assert_eq!(numbered.data.username, format!("{}-2", taken));
+
+    assert_eq!(
+        actix_test::call_service(&app, authorize(Some("not a token")))
+            .await
+            .status(),
+        401
+    );
+}
+
+#[actix_rt::test]
+#[ignore = "needs todo_server's Postgres database"]
+async fn racing_first_logins_leave_one_user() {
+    let db = TodoDB::new();
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let subject = format!("race-{}", nanos);
+    // somebody else has the email, the account is made without it
+    let email = format!("race-{}@example.com", nanos);
+    let other = db
+        .create_user(&format!("race-other-{}", nanos), "hash", "token", "en")
+        .await
+        .unwrap();
+    assert!(db.change_email(other.id, Some(&email)).await.unwrap());
+    let identity = NewIdentity {
+        issuer: "https://sso.example.com",
+        subject: &subject,
+        email: Some(&email),
+    };
+
+    let first = format!("race-first-{}", nanos);
+    let created = db
+        .create_sso_user(&first, "hash", "token", "en", &identity)
+        .await
+        .unwrap()
+        .unwrap();
+    let linked = db
+        .get_identity_user(identity.issuer, &subject)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(linked.id, created.id);
+
+    // the one that lost the race creates nothing
+    let second = format!("race-second-{}", nanos);
+    let lost = db
+        .create_sso_user(&second, "hash", "token", "en", &identity)
+        .await
+        .unwrap();
+    assert!(lost.is_none());
+    assert!(db.get_by_username(&second).await.unwrap().is_none());
+
+    for username in [first, format!("race-other-{}", nanos)] {
+        db.delete_user(&username).await.unwrap();
+    }
+}
+
+#[actix_rt::test]
+async fn sso_routes_are_missing_without_a_provider() {
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(web::Data::new(TodoDB::new()))
+            .configure(routes::configure),
+    )
+    .await;
+    let request = actix_test::TestRequest::post()
+        .uri("/api/v1/auth/oidc/authorize")
+        .to_request();
+    assert_eq!(actix_test::call_service(&app, request).await.status(), 404);
+}
@@ -0,0 +1,400 @@
+// The OpenAPI document is generated from the handlers' #[utoipa::path] and the
+// todo_api types, these check it against the server: every documented route is
+// served, and the schemas say what the types send. Nothing here needs Postgres.
+
+use actix_web::{test as actix_test, web, App};
+use chrono::NaiveDate;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::time::Duration;
+use todo_api::*;
+use todo_server::database::{PoolSettings, TodoDB};
+use todo_server::events::TaskEvents;
+use todo_server::routes;
+use todo_server::routes::openapi::{spec, API_PREFIX};
+
+const METHODS: [&str; 5] = ["get", "post", "put", "patch", "delete"];
+
+fn documented_routes(spec: &Value) -> BTreeSet<(String, String)> {
+    spec["paths"]
+        .as_object()
+        .unwrap()
+        .iter()
+        .flat_map(|(path, item)| {
+            METHODS
+                .iter()
+                .filter(move |method| item.get(**method).is_some())
+                .map(move |method| (path.clone(), method.to_string()))
+        })
+        .collect()
+}
+
+fn task() -> Task {
+    Task {
+        id: 8,
+        priority: Some("A".to_string()),
+        title: "Curl is fun".to_string(),
+        completed_at: NaiveDate::from_ymd_opt(2022, 5, 11)
+            .unwrap()
+            .and_hms_opt(18, 45, 16),
+        description: Some("typing and stuff in the terminal".to_string()),
+        version: 3,
+    }
+}
+
+fn keys(value: &Value) -> BTreeSet<String> {
+    value.as_object().unwrap().keys().cloned().collect()
+}
+
+// every field the type sends is documented and every documented field is sent
+fn assert_schema_matches<T: Serialize>(spec: &Value, name: &str, value: T) {
+    let schema = &spec["components"]["schemas"][name];
+    assert!(schema.is_object(), "no schema for {}", name);
+    let value = serde_json::to_value(value).unwrap();
+    let properties = &schema["properties"];
+    assert_eq!(keys(properties), keys(&value), "fields of {}", name);
+    for required in schema["required"].as_array().into_iter().flatten() {
+        assert!(
+            properties.get(required.as_str().unwrap()).is_some(),
+            "{} requires {}",
+            name,
+            required
+        );
+    }
+}
+
+fn refs<'a>(value: &'a Value, found: &mut Vec<&'a str>) {
+    match value {
+        Value::Object(object) => {
+            if let Some(Value::String(reference)) = object.get("$ref") {
+                found.push(reference);
+            }
+            object.values().for_each(|value| refs(value, found));
+        }
+        Value::Array(array) => array.iter().for_each(|value| refs(value, found)),
+        _ => {}
+    }
+}
+
+// a route that isn't registered gets actix's empty 404, a registered one is
+// answered by a handler or the middleware, without a token mostly a 401
+#[actix_rt::test]
+async fn every_documented_route_is_served() {
+    let db = TodoDB::with_settings(PoolSettings {
+        max_size: 0,
+        wait: Duration::from_millis(10),
+        ..PoolSettings::default()
+    });
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(web::Data::new(db))
+            .app_data(web::Data::new(TaskEvents::new()))
+            .configure(routes::configure),
+    )
+    .await;
+    let documented = documented_routes(&spec());
+    assert!(documented.len() > 10);
+    for (path, method) in documented {
+        let uri = format!("{}{}", API_PREFIX, path.replace("{id}", "1"));
+        let request = actix_test::TestRequest::default()
+            .method(method.to_uppercase().parse().unwrap())
+            .uri(&uri)
+            .to_request();
+        let (status, body) = match actix_test::try_call_service(&app, request).await {
+            Ok(response) => {
+                let status = response.status();
+                (status, actix_test::read_body(response).await)
+            }
+            // the 503 of a middleware that needed the database
+            Err(error) => (error.as_response_error().status_code(), "error".into()),
+        };
+        assert!(
+            status != 404 || !body.is_empty(),
+            "{} {} isn't registered",
+            method,
+            path
+        );
+    }
+}
+
+#[test]
+fn the_spec_is_served_under_the_prefix() {
+    let spec = spec();
+    assert_eq!(spec["openapi"], "3.1.0");
+    assert_eq!(spec["servers"][0]["url"], API_PREFIX);
+    assert!(spec["paths"]["/openapi.json"]["get"].is_object());
+}
+
+#[test]
+fn schemas_match_the_shared_types() {
+    let spec = spec();
+    assert_schema_matches(&spec, "Task", task());
+    assert_schema_matches(&spec, "DataResponse_Task", TaskResponse { data: task() });
+    assert_schema_matches(
+        &spec,
+        "DataResponse_Vec_Task",
+        TaskListResponse { data: vec![task()] },
+    );
+    assert_schema_matches(
+        &spec,
+        "CreateTaskRequest",
+        CreateTaskRequest {
+            title: "t".to_string(),
+            description: Some("d".to_string()),
+            priority: Some("A".to_string()),
+        },
+    );
+    assert_schema_matches(
+        &spec,
+        "UpdateTaskRequest",
+        UpdateTaskRequest {
+            title: Some("t".to_string()),
+            priority: Some(Some("A".to_string())),
+            description: Some(Some("d".to_string())),
+            completed_at: Some(task().completed_at),
+        },
+    );
+    let template = TaskTemplate {
+        description: Some("d".to_string()),
+        priority: Some("A".to_string()),
+        ..TaskTemplate::default()
+    };
+    assert_schema_matches(&spec, "TaskTemplate", template.clone());
+    assert_schema_matches(
+        &spec,
+        "DataResponse_TaskTemplate",
+        TemplateResponse {
+            data: template.clone(),
+        },
+    );
+    assert_schema_matches(
+        &spec,
+        "DataResponse_Vec_TaskTemplate",
+        TemplateListResponse {
+            data: vec![template],
+        },
+    );
+    assert_schema_matches(
+        &spec,
+        "CreateTemplateRequest",
+        CreateTemplateRequest {
+            title: "t".to_string(),
+            description: Some("d".to_string()),
+            priority: Some("A".to_string()),
+            locale: Some("es".to_string()),
+            position: Some(2),
+        },
+    );
+    assert_schema_matches(
+        &spec,
+        "UpdateTemplateRequest",
+        UpdateTemplateRequest {
+            title: Some("t".to_string()),
+            description: Some("d".to_string()),
+            priority: Some("A".to_string()),
+            locale: Some("es".to_string()),
+            position: Some(2),
+        },
+    );
+    let admin_user = AdminUser {
+        role: Role::Admin,
+        ..AdminUser::default()
+    };
+    assert_schema_matches(&spec, "AdminUser", admin_user.clone());
+    assert_schema_matches(
+        &spec,
+        "DataResponse_AdminUser",
+        AdminUserResponse {
+            data: admin_user.clone(),
+        },
+    );
+    assert_schema_matches(
+        &spec,
+        "DataResponse_Vec_AdminUser",
+        AdminUserListResponse {
+            data: vec![admin_user],
+        },
+    );
+    let roles: Vec<Value> = Role::ALL
+        .iter()
+        .map(|role| serde_json::to_value(role).unwrap())
+        .collect();
+    assert_eq!(
+        spec["components"]["schemas"]["Role"]["enum"],
+        Value::from(roles)
+    );
+    assert_schema_matches(&spec, "UsageStats", UsageStats::default());
+    assert_schema_matches(
+        &spec,
+        "DataResponse_UsageStats",
+        StatsResponse {
+            data: UsageStats::default(),
+        },
+    );
+    assert_schema_matches(&spec, "LoginRequest", LoginRequest::default());
+    assert_schema_matches(
+        &spec,
+        "UpdateUserRequest",
+        UpdateUserRequest {
+            username: Some("u".to_string()),
+            email: Some("u@example.com".to_string()),
+        },
+    );
+    assert_schema_matches(
+        &spec,
+        "ChangePasswordRequest",
+        ChangePasswordRequest::default(),
+    );
+    assert_schema_matches(
+        &spec,
+        "ForgotPasswordRequest",
+        ForgotPasswordRequest::default(),
+    );
+    assert_schema_matches(
+        &spec,
+        "ResetPasswordRequest",
+        ResetPasswordRequest::default(),
+    );
+    let user = UserInfo::default();
+    assert_schema_matches(&spec, "UserInfo", user.clone());
+    assert_schema_matches(&spec, "DataResponse_UserInfo", AuthResponse { data: user });
+    let challenge = TwoFactorChallenge::default();
+    assert_schema_matches(&spec, "TwoFactorChallenge", challenge.clone());
+    assert_schema_matches(
+        &spec,
+        "DataResponse_TwoFactorChallenge",
+        TwoFactorChallengeResponse { data: challenge },
+    );
+    assert_schema_matches(
+        &spec,
+        "TwoFactorLoginRequest",
+        TwoFactorLoginRequest::default(),
+    );
+    let status = TwoFactorStatus::default();
+    assert_schema_matches(&spec, "TwoFactorStatus", status.clone());
+    assert_schema_matches(
+        &spec,
+        "DataResponse_TwoFactorStatus",
+        TwoFactorStatusResponse { data: status },
+    );
+    let setup = TwoFactorSetup::default();
+    assert_schema_matches(&spec, "TwoFactorSetup", setup.clone());
+    assert_schema_matches(
+        &spec,
+        "DataResponse_TwoFactorSetup",
+        TwoFactorSetupResponse { data: setup },
+    );
+    assert_schema_matches(
+        &spec,
+        "TwoFactorCodeRequest",
+        TwoFactorCodeRequest::default(),
+    );
+    let codes = RecoveryCodes::default();
+    assert_schema_matches(&spec, "RecoveryCodes", codes.clone());
+    assert_schema_matches(
+        &spec,
+        "DataResponse_RecoveryCodes",
+        RecoveryCodesResponse { data: codes },
+    );
+    assert_schema_matches(
+        &spec,
+        "DisableTwoFactorRequest",
+        DisableTwoFactorRequest::default(),
+    );
+    let authorization = OidcAuthorization::default();
+    assert_schema_matches(&spec, "OidcAuthorization", authorization.clone());
+    assert_schema_matches(
+        &spec,
+        "DataResponse_OidcAuthorization",
+        OidcAuthorizationResponse {
+            data: authorization,
+        },
+    );
+    assert_schema_matches(&spec, "OidcCallbackRequest", OidcCallbackRequest::default());
+    let api_token = ApiToken::default();
+    assert_schema_matches(&spec, "ApiToken", api_token.clone());
+    assert_schema_matches(
+        &spec,
+        "DataResponse_Vec_ApiToken",
+        ApiTokenListResponse {
+            data: vec![api_token.clone()],
+        },
+    );
+    assert_schema_matches(
+        &spec,
+        "CreateApiTokenRequest",
+        CreateApiTokenRequest::default(),
+    );
+    let new_token = NewApiToken {
+        token: "todo_pat_5f1c".to_string(),
+        api_token,
+    };
+    assert_schema_matches(&spec, "NewApiToken", new_token.clone());
+    assert_schema_matches(
+        &spec,
+        "DataResponse_NewApiToken",
+        NewApiTokenResponse { data: new_token },
+    );
+    let scopes: Vec<Value> = TokenScope::ALL
+        .iter()
+        .map(|scope| serde_json::to_value(scope).unwrap())
+        .collect();
+    assert_eq!(
+        spec["components"]["schemas"]["TokenScope"]["enum"],
+        Value::from(scopes)
+    );
+    assert_schema_matches(
+        &spec,
+        "MessageResponse",
+        MessageResponse {
+            message: "user logged out".to_string(),
+        },
+    );
+}
+
+#[test]
+fn task_events_match_the_shared_type() {
+    let spec = spec();
+    let documented: BTreeSet<String> = spec["components"]["schemas"]["TaskEvent"]["oneOf"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|event| {
+            event["properties"]["type"]["enum"][0]
+                .as_str()
+                .unwrap()
+                .to_string()
+        })
+        .collect();
+    let sent: BTreeSet<String> = [
+        TaskEvent::TaskCreated(task()),
+        TaskEvent::TaskUpdated(task()),
+        TaskEvent::TaskDeleted { id: 8 },
+    ]
+    .iter()
+    .map(|event| {
+        serde_json::to_value(event).unwrap()["type"]
+            .as_str()
+            .unwrap()
+            .to_string()
+    })
+    .collect();
+    assert_eq!(documented, sent);
+}
+
+#[test]
+fn every_ref_resolves() {
+    let spec = spec();
+    let mut found = vec![];
+    refs(&spec, &mut found);
+    assert!(!found.is_empty());
+    for reference in found {
+        let pointer = reference.strip_prefix('#').unwrap();
+        assert!(
+            spec.pointer(pointer).is_some(),
+            "{} points nowhere",
+            reference
+        );
+    }
+}
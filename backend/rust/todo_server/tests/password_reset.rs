@@ -0,0 +1,162 @@
+// Forgot and reset password end to end, reading the link out of the email the
+// FileMailer writes (ignored, run it with `cargo test -p todo_server -- --ignored`).
+
+use actix_web::{test as actix_test, web, App};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use todo_api::{
+    AuthResponse, ForgotPasswordRequest, LoginRequest, ResetPasswordRequest, UpdateUserRequest,
+};
+use todo_server::database::TodoDB;
+use todo_server::events::TaskEvents;
+use todo_server::mailer::{FileMailer, Mailer};
+use todo_server::routes::{self, auth::hash_token};
+
+#[actix_rt::test]
+#[ignore = "needs todo_server's Postgres database"]
+async fn forgotten_passwords_can_be_reset_once() {
+    if std::env::var("JWT_SECRET").is_err() {
+        std::env::set_var("JWT_SECRET", "keyboardcat");
+    }
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let mail_dir = std::env::temp_dir().join(format!("todo-reset-{}", nanos));
+    let mailer: web::Data<dyn Mailer> =
+        web::Data::from(Arc::new(FileMailer::new(Some(mail_dir.clone()))) as Arc<dyn Mailer>);
+    let db = web::Data::new(TodoDB::new());
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(db.clone())
+            .app_data(web::Data::new(TaskEvents::new()))
+            .app_data(mailer)
+            .configure(routes::configure),
+    )
+    .await;
+    let username = format!("reset-{}", nanos);
+    let email = format!("reset-{}@example.com", nanos);
+    let login = |password: &str| {
+        actix_test::TestRequest::post()
+            .uri("/api/v1/users/login")
+            .set_json(LoginRequest {
+                username: username.clone(),
+                password: password.to_string(),
+            })
+            .to_request()
+    };
+    let forgot = |email: &str| {
+        actix_test::TestRequest::post()
+            .uri("/api/v1/users/forgot-password")
+            .set_json(ForgotPasswordRequest {
+                email: email.to_string(),
+            })
+            .to_request()
+    };
+    let reset = |token: &str| {
+        actix_test::TestRequest::post()
+            .uri("/api/v1/users/reset-password")
+            .set_json(ResetPasswordRequest {
+                token: token.to_string(),
+                new_password: "myfancierpass".to_string(),
+            })
+            .to_request()
+    };
+    let sent = || -> Vec<String> {
+        match std::fs::read_dir(&mail_dir) {
+            Ok(files) => files
+                .map(|file| std::fs::read_to_string(file.unwrap().path()).unwrap())
+                .collect(),
+            Err(_) => vec![],
+        }
+    };
+
+    let request = actix_test::TestRequest::post()
+        .uri("/api/v1/users")
+        .set_json(LoginRequest {
+            username: username.clone(),
+            password: "myfancypass".to_string(),
+        })
+        .to_request();
+    let user: AuthResponse = actix_test::call_and_read_body_json(&app, request).await;
+    let request = actix_test::TestRequest::patch()
+        .uri("/api/v1/users/me")
+        .insert_header(("x-auth-token", user.data.token.clone()))
+        .set_json(UpdateUserRequest {
+            email: Some(email.to_uppercase()),
+            ..UpdateUserRequest::default()
+        })
+        .to_request();
+    assert!(actix_test::call_service(&app, request)
+        .await
+        .status()
+        .is_success());
+
+    // nobody finds out whether an email has an account
+    let unknown = actix_test::call_service(&app, forgot("nobody@example.com")).await;
+    assert!(unknown.status().is_success());
+    assert!(sent().is_empty());
+    let known = actix_test::call_service(&app, forgot(&email)).await;
+    assert!(known.status().is_success());
+
+    // it's sent after the answer
+    for _ in 0..100 {
+        if !sent().is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    let emails = sent();
+    assert_eq!(emails.len(), 1);
+    assert!(emails[0].contains(&format!("To: {}", email)));
+    let token: String = emails[0]
+        .split("token=")
+        .nth(1)
+        .unwrap()
+        .chars()
+        .take_while(char::is_ascii_hexdigit)
+        .collect();
+    assert_eq!(token.len(), 64);
+
+    assert_eq!(
+        actix_test::call_service(&app, reset("not-the-token"))
+            .await
+            .status(),
+        400
+    );
+    assert!(actix_test::call_service(&app, reset(&token))
+        .await
+        .status()
+        .is_success());
+    assert!(db.get_by_token(&user.data.token).await.unwrap().is_none());
+    assert_eq!(
+        actix_test::call_service(&app, login("myfancypass"))
+            .await
+            .status(),
+        400
+    );
+    assert!(actix_test::call_service(&app, login("myfancierpass"))
+        .await
+        .status()
+        .is_success());
+    assert_eq!(
+        actix_test::call_service(&app, reset(&token)).await.status(),
+        400
+    );
+
+    // an expired one
+    let expired = format!("expired-{}", nanos);
+    assert!(db
+        .insert_password_reset(&email, &hash_token(&expired), -1)
+        .await
+        .unwrap());
+    assert_eq!(
+        actix_test::call_service(&app, reset(&expired))
+            .await
+            .status(),
+        400
+    );
+
+    db.delete_user(&username).await.unwrap();
+    std::fs::remove_dir_all(mail_dir).unwrap();
+}
@@ -0,0 +1,77 @@
+// The breached-password list and the signup checks that answer before the
+// database is asked anything.
+
+use actix_web::{test as actix_test, web, App};
+use std::time::{SystemTime, UNIX_EPOCH};
+use todo_api::LoginRequest;
+use todo_server::database::TodoDB;
+use todo_server::passwords::{is_breached, sha1_hex};
+use todo_server::routes;
+
+#[test]
+fn sha1_is_uppercase_hex() {
+    assert_eq!(
+        sha1_hex("password"),
+        "5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8"
+    );
+}
+
+#[test]
+fn the_breached_list_is_binary_searched() {
+    let breached = [
+        "password", "123456", "qwerty", "letmein", "dragon", "iloveyou",
+    ];
+    let mut lines: Vec<String> = breached
+        .iter()
+        .enumerate()
+        .map(|(i, password)| match i % 3 {
+            0 => format!("{}:{}", sha1_hex(password), 1000 + i),
+            // some lists are lowercase, or leave the count out
+            1 => sha1_hex(password).to_lowercase(),
+            _ => sha1_hex(password),
+        })
+        .collect();
+    lines.sort_by_key(|line| line.to_uppercase());
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let path = std::env::temp_dir().join(format!("todo-breached-{}.txt", nanos));
+    std::fs::write(&path, lines.join("\r\n") + "\r\n").unwrap();
+
+    for password in breached {
+        assert!(is_breached(&path, password).unwrap(), "{}", password);
+    }
+    for password in ["myfancypass", "", "Password", "zzzzzzzz", "00000000"] {
+        assert!(!is_breached(&path, password).unwrap(), "{}", password);
+    }
+    std::fs::remove_file(&path).unwrap();
+    assert!(is_breached(&path, "password").is_err());
+}
+
+#[actix_rt::test]
+async fn signups_need_a_plain_username_and_a_good_password() {
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(web::Data::new(TodoDB::new()))
+            .configure(routes::configure),
+    )
+    .await;
+    for (username, password) in [
+        ("matt@example.com", "myfancypass"),
+        ("", "myfancypass"),
+        ("woodroww", ""),
+        ("woodroww", "password"),
+        ("woodroww", "woodroww123"),
+    ] {
+        let request = actix_test::TestRequest::post()
+            .uri("/api/v1/users")
+            .set_json(LoginRequest {
+                username: username.to_string(),
+                password: password.to_string(),
+            })
+            .to_request();
+        let response = actix_test::call_service(&app, request).await;
+        assert_eq!(response.status(), 400, "{} {}", username, password);
+    }
+}
@@ -0,0 +1,282 @@
+// The rate limits and the login lockout. The ignored tests need Postgres, run
+// them with `cargo test -p todo_server -- --ignored`.
+
+use actix_web::dev::Service;
+use actix_web::http::header::{CONTENT_TYPE, RETRY_AFTER};
+use actix_web::{test as actix_test, web, App};
+use chrono::Utc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use todo_api::{
+    AuthResponse, LoginRequest, TokenScope, TwoFactorChallengeResponse, TwoFactorLoginRequest,
+    UserInfo,
+};
+use todo_server::database::{request_token, TodoDB};
+use todo_server::events::TaskEvents;
+use todo_server::middleware::rate_limit::{Limit, RateLimiter, RateLimits};
+use todo_server::routes;
+use todo_server::routes::auth::{remember_credentials, Credentials};
+use todo_server::totp;
+
+fn limiter(limits: RateLimits) -> web::Data<RateLimiter> {
+    web::Data::new(RateLimiter::new(limits))
+}
+
+#[test]
+fn buckets_run_out_per_key() {
+    let limiter = RateLimiter::new(RateLimits {
+        api: 2,
+        ..RateLimits::default()
+    });
+    assert_eq!(limiter.check(Limit::Api, "a"), Ok(()));
+    assert_eq!(limiter.check(Limit::Api, "a"), Ok(()));
+    let wait = limiter.check(Limit::Api, "a").unwrap_err();
+    // two a minute, one comes back every 30 seconds
+    assert!(wait > Duration::from_secs(29) && wait <= Duration::from_secs(30));
+    assert_eq!(limiter.check(Limit::Api, "b"), Ok(()));
+}
+
+#[test]
+fn zero_turns_a_limit_off() {
+    let limiter = RateLimiter::new(RateLimits {
+        signup_per_ip: 0,
+        ..RateLimits::default()
+    });
+    for _ in 0..100 {
+        assert_eq!(limiter.check(Limit::SignupPerIp, "a"), Ok(()));
+    }
+}
+
+#[test]
+fn failed_logins_lock_the_username_for_longer_and_longer() {
+    let limiter = RateLimiter::new(RateLimits {
+        login_per_username: 0,
+        lockout_after: 3,
+        lockout: Duration::from_secs(30),
+        ..RateLimits::default()
+    });
+    limiter.login_failed("woodroww");
+    limiter.login_failed("woodroww");
+    assert_eq!(limiter.login_allowed("woodroww"), Ok(()));
+    limiter.login_failed("woodroww");
+    let wait = limiter.login_allowed("woodroww").unwrap_err();
+    assert!(wait > Duration::from_secs(29) && wait <= Duration::from_secs(30));
+    limiter.login_failed("woodroww");
+    let wait = limiter.login_allowed("woodroww").unwrap_err();
+    assert!(wait > Duration::from_secs(59) && wait <= Duration::from_secs(60));
+    assert_eq!(limiter.login_allowed("somebody else"), Ok(()));
+
+    limiter.login_succeeded("woodroww");
+    assert_eq!(limiter.login_allowed("woodroww"), Ok(()));
+}
+
+#[actix_rt::test]
+async fn an_empty_bucket_answers_429_with_retry_after() {
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(web::Data::new(TodoDB::new()))
+            .app_data(limiter(RateLimits {
+                login_per_ip: 2,
+                ..RateLimits::default()
+            }))
+            .configure(routes::configure),
+    )
+    .await;
+    let login = |ip: &str| {
+        actix_test::TestRequest::post()
+            .uri("/api/v1/users/login")
+            .peer_addr(format!("{}:5000", ip).parse().unwrap())
+            .insert_header((CONTENT_TYPE, "application/json"))
+            .set_payload("not json")
+            .to_request()
+    };
+    for _ in 0..2 {
+        let response = actix_test::call_service(&app, login("10.0.0.1")).await;
+        assert_eq!(response.status(), 400);
+    }
+    let response = actix_test::call_service(&app, login("10.0.0.1")).await;
+    assert_eq!(response.status(), 429);
+    assert_eq!(response.headers().get(RETRY_AFTER).unwrap(), "30");
+    // another address has its own bucket
+    let response = actix_test::call_service(&app, login("10.0.0.2")).await;
+    assert_eq!(response.status(), 400);
+    // and so do the other routes
+    let request = actix_test::TestRequest::get()
+        .uri("/api/v1/openapi.json")
+        .peer_addr("10.0.0.1:5000".parse().unwrap())
+        .to_request();
+    assert_eq!(actix_test::call_service(&app, request).await.status(), 200);
+}
+
+#[actix_rt::test]
+#[ignore = "needs todo_server's Postgres database"]
+async fn failed_logins_lock_out_even_the_right_password() {
+    if std::env::var("JWT_SECRET").is_err() {
+        std::env::set_var("JWT_SECRET", "keyboardcat");
+    }
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(web::Data::new(TodoDB::new()))
+            .app_data(web::Data::new(TaskEvents::new()))
+            .app_data(limiter(RateLimits {
+                lockout_after: 3,
+                ..RateLimits::default()
+            }))
+            .configure(routes::configure),
+    )
+    .await;
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let username = format!("rate-limit-{}", nanos);
+    let credentials = |password: &str| LoginRequest {
+        username: username.clone(),
+        password: password.to_string(),
+    };
+    let request = actix_test::TestRequest::post()
+        .uri("/api/v1/users")
+        .set_json(credentials("myfancypass"))
+        .to_request();
+    let _: AuthResponse = actix_test::call_and_read_body_json(&app, request).await;
+
+    let login = |password: &str| {
+        actix_test::TestRequest::post()
+            .uri("/api/v1/users/login")
+            .set_json(credentials(password))
+            .to_request()
+    };
+    // a right password in between starts the count over
+    for password in ["wrong", "wrong", "myfancypass", "wrong", "wrong"] {
+        let response = actix_test::call_service(&app, login(password)).await;
+        assert_ne!(response.status(), 429);
+    }
+    let response = actix_test::call_service(&app, login("wrong")).await;
+    assert_eq!(response.status(), 400);
+    let response = actix_test::call_service(&app, login("myfancypass")).await;
+    assert_eq!(response.status(), 429);
+    assert_eq!(response.headers().get(RETRY_AFTER).unwrap(), "30");
+}
+
+#[actix_rt::test]
+async fn made_up_tokens_share_the_address_bucket() {
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(web::Data::new(TodoDB::new()))
+            .app_data(limiter(RateLimits {
+                api: 2,
+                ..RateLimits::default()
+            }))
+            // stands in for the database, "known" is the one token that works
+            .wrap_fn(|req, srv| {
+                if request_token(req.request()) == Some("known") {
+                    let user = UserInfo {
+                        id: 1,
+                        username: "woodroww".to_string(),
+                        token: "known".to_string(),
+                    };
+                    remember_credentials(
+                        req.request(),
+                        Some(Credentials {
+                            user,
+                            scope: TokenScope::ReadWrite,
+                            api_token: false,
+                        }),
+                    );
+                }
+                srv.call(req)
+            })
+            .configure(routes::configure),
+    )
+    .await;
+    let request = |token: &str| {
+        actix_test::TestRequest::get()
+            .uri("/api/v1/openapi.json")
+            .peer_addr("10.0.0.1:5000".parse().unwrap())
+            .insert_header(("x-auth-token", token.to_string()))
+            .to_request()
+    };
+    let response = actix_test::call_service(&app, request("known")).await;
+    assert_eq!(response.status(), 200);
+    let response = actix_test::call_service(&app, request("made-up-1")).await;
+    assert_eq!(response.status(), 200);
+    let response = actix_test::call_service(&app, request("made-up-2")).await;
+    assert_eq!(response.status(), 429);
+    // the token that worked has a bucket of its own
+    let response = actix_test::call_service(&app, request("known")).await;
+    assert_eq!(response.status(), 200);
+}
+
+#[actix_rt::test]
+#[ignore = "needs todo_server's Postgres database"]
+async fn wrong_two_factor_codes_lock_out_too() {
+    if std::env::var("JWT_SECRET").is_err() {
+        std::env::set_var("JWT_SECRET", "keyboardcat");
+    }
+    let db = web::Data::new(TodoDB::new());
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(db.clone())
+            .app_data(web::Data::new(TaskEvents::new()))
+            .app_data(limiter(RateLimits {
+                lockout_after: 3,
+                ..RateLimits::default()
+            }))
+            .configure(routes::configure),
+    )
+    .await;
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let username = format!("two-factor-lockout-{}", nanos);
+    let credentials = LoginRequest {
+        username: username.clone(),
+        password: "myfancypass".to_string(),
+    };
+    let request = actix_test::TestRequest::post()
+        .uri("/api/v1/users")
+        .set_json(&credentials)
+        .to_request();
+    let user: AuthResponse = actix_test::call_and_read_body_json(&app, request).await;
+    let secret = totp::generate_secret();
+    assert!(db.start_two_factor(user.data.id, &secret).await.unwrap());
+    let step = totp::step_at(Utc::now().timestamp());
+    // an old step, so the current code hasn't been used
+    assert!(db
+        .enable_two_factor(user.data.id, step - 10, &[])
+        .await
+        .unwrap());
+
+    let login = || {
+        actix_test::TestRequest::post()
+            .uri("/api/v1/users/login")
+            .set_json(&credentials)
+            .to_request()
+    };
+    let code = |challenge: &TwoFactorChallengeResponse, code: &str| {
+        actix_test::TestRequest::post()
+            .uri("/api/v1/users/login/two-factor")
+            .set_json(TwoFactorLoginRequest {
+                challenge: challenge.data.challenge.clone(),
+                code: code.to_string(),
+            })
+            .to_request()
+    };
+    let challenge: TwoFactorChallengeResponse =
+        actix_test::call_and_read_body_json(&app, login()).await;
+    for _ in 0..2 {
+        let response = actix_test::call_service(&app, code(&challenge, "000000")).await;
+        assert_eq!(response.status(), 400);
+    }
+    // the right password alone doesn't start the count over
+    let challenge: TwoFactorChallengeResponse =
+        actix_test::call_and_read_body_json(&app, login()).await;
+    let response = actix_test::call_service(&app, code(&challenge, "000000")).await;
+    assert_eq!(response.status(), 400);
+    let right_code = totp::code_at(&secret, step).unwrap();
+    let response = actix_test::call_service(&app, code(&challenge, &right_code)).await;
+    assert_eq!(response.status(), 429);
+    assert_eq!(actix_test::call_service(&app, login()).await.status(), 429);
+
+    db.delete_user(&username).await.unwrap();
+}
@@ -0,0 +1,125 @@
+// Request ids and the request span's log line, none of it needs the database.
+
+use actix_web::{test as actix_test, web, App};
+use serde_json::Value;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+use todo_server::database::TodoDB;
+use todo_server::middleware::request_spans::REQUEST_ID_HEADER;
+use todo_server::routes;
+use todo_server::telemetry::LogFormat;
+use tracing_subscriber::fmt::format::FmtSpan;
+
+#[test]
+fn log_formats() {
+    assert_eq!(LogFormat::parse("json"), Some(LogFormat::Json));
+    assert_eq!(LogFormat::parse(" JSON "), Some(LogFormat::Json));
+    assert_eq!(LogFormat::parse("pretty"), Some(LogFormat::Pretty));
+    assert_eq!(LogFormat::parse("xml"), None);
+}
+
+#[actix_rt::test]
+async fn every_response_has_a_request_id() {
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(web::Data::new(TodoDB::new()))
+            .configure(routes::configure),
+    )
+    .await;
+    let request_id = |request: actix_test::TestRequest| {
+        let app = &app;
+        async move {
+            let response = actix_test::call_service(app, request.to_request()).await;
+            response
+                .headers()
+                .get(REQUEST_ID_HEADER)
+                .map(|id| id.to_str().unwrap().to_string())
+        }
+    };
+
+    let sent = actix_test::TestRequest::get()
+        .uri("/api/v1/openapi.json")
+        .insert_header((REQUEST_ID_HEADER, "from-the-client.42"));
+    assert_eq!(request_id(sent).await.unwrap(), "from-the-client.42");
+
+    let made_up = request_id(actix_test::TestRequest::get().uri("/api/v1/openapi.json"))
+        .await
+        .unwrap();
+    assert_eq!(made_up.len(), 32);
+    assert!(made_up.chars().all(|c| c.is_ascii_hexdigit()));
+    let another = request_id(actix_test::TestRequest::get().uri("/api/v1/openapi.json"))
+        .await
+        .unwrap();
+    assert_ne!(made_up, another);
+
+    // one that would garble the log gets replaced
+    let garbled = actix_test::TestRequest::get()
+        .uri("/api/v1/openapi.json")
+        .insert_header((REQUEST_ID_HEADER, "two words"));
+    assert_ne!(request_id(garbled).await.unwrap(), "two words");
+    let long = actix_test::TestRequest::get()
+        .uri("/api/v1/openapi.json")
+        .insert_header((REQUEST_ID_HEADER, "x".repeat(200)));
+    assert_eq!(request_id(long).await.unwrap().len(), 32);
+
+    // errors and routes that don't exist have one too
+    let no_token = actix_test::TestRequest::get().uri("/api/v1/tasks");
+    assert!(request_id(no_token).await.is_some());
+    let missing = actix_test::TestRequest::get().uri("/api/v1/nothing-here");
+    assert!(request_id(missing).await.is_some());
+}
+
+#[derive(Clone, Default)]
+struct Captured(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Captured {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[actix_rt::test]
+async fn the_request_span_logs_route_status_and_latency() {
+    let captured = Captured::default();
+    let writer = captured.clone();
+    let subscriber = tracing_subscriber::fmt()
+        .json()
+        .flatten_event(true)
+        .with_span_events(FmtSpan::CLOSE)
+        .with_writer(move || writer.clone())
+        .finish();
+    let _logging = tracing::subscriber::set_default(subscriber);
+
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(web::Data::new(TodoDB::new()))
+            .configure(routes::configure),
+    )
+    .await;
+    let request = actix_test::TestRequest::delete()
+        .uri("/api/v1/tasks/7")
+        .insert_header((REQUEST_ID_HEADER, "span-test"))
+        .to_request();
+    assert_eq!(actix_test::call_service(&app, request).await.status(), 401);
+
+    let output = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+    let span = output
+        .lines()
+        .filter_map(|line| serde_json::from_str::<Value>(line).ok())
+        .filter(|line| line["message"] == "close")
+        .map(|line| line["span"].clone())
+        .find(|span| span["name"] == "request")
+        .unwrap();
+    assert_eq!(span["request_id"], "span-test");
+    assert_eq!(span["method"], "DELETE");
+    assert_eq!(span["route"], "/api/v1/tasks/{id}");
+    assert_eq!(span["status"], 401);
+    assert!(span["latency_ms"].is_u64());
+    // nobody was logged in
+    assert!(span.get("user_id").is_none());
+}
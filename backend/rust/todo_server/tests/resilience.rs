@@ -0,0 +1,81 @@
+// Losing the database and shutting down. A pool that never hands out a
+// connection stands in for Postgres being down, so nothing here needs it.
+
+use actix_web::{test as actix_test, web, App};
+use std::time::Duration;
+use todo_api::{LoginRequest, TaskEvent};
+use todo_server::database::{PoolSettings, TodoDB};
+use todo_server::events::TaskEvents;
+use todo_server::health::{readyz, Health};
+use todo_server::metrics::Metrics;
+use todo_server::routes;
+
+fn unreachable_db() -> TodoDB {
+    TodoDB::with_settings(PoolSettings {
+        max_size: 0,
+        wait: Duration::from_millis(10),
+        ..PoolSettings::default()
+    })
+}
+
+#[actix_rt::test]
+async fn requests_get_a_503_without_the_database() {
+    let metrics = web::Data::new(Metrics::new());
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(web::Data::new(unreachable_db()))
+            .app_data(web::Data::new(TaskEvents::new()))
+            .app_data(metrics.clone())
+            .route("/readyz", web::get().to(readyz))
+            .configure(routes::configure),
+    )
+    .await;
+
+    // not a 401, the token may well be good
+    let request = actix_test::TestRequest::get()
+        .uri("/api/v1/tasks")
+        .insert_header(("x-auth-token", "some-session-token"))
+        .to_request();
+    // the middleware's error, the server makes the response out of it
+    let error = actix_test::try_call_service(&app, request)
+        .await
+        .err()
+        .unwrap();
+    assert_eq!(error.as_response_error().status_code(), 503);
+
+    let request = actix_test::TestRequest::post()
+        .uri("/api/v1/users/login")
+        .set_json(LoginRequest {
+            username: "resilience".to_string(),
+            password: "myfancypass".to_string(),
+        })
+        .to_request();
+    assert_eq!(actix_test::call_service(&app, request).await.status(), 503);
+    // and it isn't a failed login
+    assert!(!metrics.text().contains("todo_logins_total{"));
+
+    let request = actix_test::TestRequest::get().uri("/readyz").to_request();
+    let response = actix_test::call_service(&app, request).await;
+    assert_eq!(response.status(), 503);
+    // the pool's error stays in the log
+    let health: Health = actix_test::read_body_json(response).await;
+    assert_eq!(
+        health.checks["database"].error.as_deref(),
+        Some("see the server log")
+    );
+}
+
+#[actix_rt::test]
+async fn closing_the_events_ends_the_subscriptions() {
+    let events = TaskEvents::new();
+    let mut open = events.subscribe(1);
+    events.close();
+    assert!(open.recv().await.is_none());
+    let mut later = events.subscribe(1);
+    assert!(later.recv().await.is_none());
+
+    // publishing has nobody to send to, and doesn't need the database for that
+    events
+        .publish(&unreachable_db(), 1, TaskEvent::TaskDeleted { id: 8 })
+        .await;
+}
@@ -0,0 +1,138 @@
+// The /admin routes against the database (ignored, run it with
+// `cargo test -p todo_server -- --ignored`).
+
+use actix_web::{test as actix_test, web, App};
+use std::time::{SystemTime, UNIX_EPOCH};
+use todo_api::{
+    AdminUserListResponse, AdminUserResponse, AuthResponse, LoginRequest, Role, StatsResponse,
+};
+use todo_server::database::TodoDB;
+use todo_server::events::TaskEvents;
+use todo_server::routes;
+
+#[actix_rt::test]
+#[ignore = "needs todo_server's Postgres database"]
+async fn only_admins_manage_users() {
+    if std::env::var("JWT_SECRET").is_err() {
+        std::env::set_var("JWT_SECRET", "keyboardcat");
+    }
+    let db = web::Data::new(TodoDB::new());
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(db.clone())
+            .app_data(web::Data::new(TaskEvents::new()))
+            .configure(routes::configure),
+    )
+    .await;
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+
+    let mut users = vec![];
+    for name in ["admin", "user", "leaver"] {
+        let request = actix_test::TestRequest::post()
+            .uri("/api/v1/users")
+            .set_json(LoginRequest {
+                username: format!("roles-{}-{}", name, nanos),
+                password: "myfancypass".to_string(),
+            })
+            .to_request();
+        let response: AuthResponse = actix_test::call_and_read_body_json(&app, request).await;
+        users.push(response.data);
+    }
+    let (admin, user, leaver) = (users[0].clone(), users[1].clone(), users[2].clone());
+    assert!(db.set_role(admin.id, Role::Admin).await.unwrap());
+
+    let request = actix_test::TestRequest::get()
+        .uri("/api/v1/admin/users")
+        .to_request();
+    assert_eq!(actix_test::call_service(&app, request).await.status(), 401);
+    let request = actix_test::TestRequest::get()
+        .uri("/api/v1/admin/stats")
+        .insert_header(("x-auth-token", user.token.clone()))
+        .to_request();
+    assert_eq!(actix_test::call_service(&app, request).await.status(), 403);
+
+    let request = actix_test::TestRequest::get()
+        .uri("/api/v1/admin/users")
+        .insert_header(("x-auth-token", admin.token.clone()))
+        .to_request();
+    let listed: AdminUserListResponse = actix_test::call_and_read_body_json(&app, request).await;
+    let listed_admin = listed.data.iter().find(|u| u.id == admin.id).unwrap();
+    assert_eq!(listed_admin.role, Role::Admin);
+    assert!(listed_admin.logged_in);
+    let listed_user = listed.data.iter().find(|u| u.id == user.id).unwrap();
+    assert_eq!(listed_user.role, Role::User);
+
+    let request = actix_test::TestRequest::get()
+        .uri("/api/v1/admin/stats")
+        .insert_header(("x-auth-token", admin.token.clone()))
+        .to_request();
+    let stats: StatsResponse = actix_test::call_and_read_body_json(&app, request).await;
+    assert!(stats.data.admins >= 1);
+    assert!(stats.data.users >= 2);
+
+    let request = actix_test::TestRequest::put()
+        .uri(&format!("/api/v1/admin/users/{}/disabled", admin.id))
+        .insert_header(("x-auth-token", admin.token.clone()))
+        .to_request();
+    assert_eq!(actix_test::call_service(&app, request).await.status(), 400);
+
+    let request = actix_test::TestRequest::put()
+        .uri(&format!("/api/v1/admin/users/{}/disabled", user.id))
+        .insert_header(("x-auth-token", admin.token.clone()))
+        .to_request();
+    let disabled: AdminUserResponse = actix_test::call_and_read_body_json(&app, request).await;
+    assert!(disabled.data.deleted_at.is_some());
+    assert!(disabled.data.disabled);
+    assert!(!disabled.data.logged_in);
+    let request = actix_test::TestRequest::get()
+        .uri("/api/v1/tasks")
+        .insert_header(("x-auth-token", user.token.clone()))
+        .to_request();
+    assert_eq!(actix_test::call_service(&app, request).await.status(), 401);
+
+    let request = actix_test::TestRequest::put()
+        .uri(&format!("/api/v1/admin/users/{}/enabled", user.id))
+        .insert_header(("x-auth-token", admin.token.clone()))
+        .to_request();
+    let enabled: AdminUserResponse = actix_test::call_and_read_body_json(&app, request).await;
+    assert!(enabled.data.deleted_at.is_none());
+    assert!(!enabled.data.disabled);
+    let request = actix_test::TestRequest::post()
+        .uri("/api/v1/users/login")
+        .set_json(LoginRequest {
+            username: user.username.clone(),
+            password: "myfancypass".to_string(),
+        })
+        .to_request();
+    assert_eq!(actix_test::call_service(&app, request).await.status(), 200);
+
+    // an account its user deleted stays deleted, disabling it changes nothing
+    let request = actix_test::TestRequest::delete()
+        .uri("/api/v1/users/me")
+        .insert_header(("x-auth-token", leaver.token.clone()))
+        .to_request();
+    assert_eq!(actix_test::call_service(&app, request).await.status(), 200);
+    for action in ["disabled", "enabled"] {
+        let request = actix_test::TestRequest::put()
+            .uri(&format!("/api/v1/admin/users/{}/{}", leaver.id, action))
+            .insert_header(("x-auth-token", admin.token.clone()))
+            .to_request();
+        let status = actix_test::call_service(&app, request).await.status();
+        assert_eq!(status, if action == "enabled" { 409 } else { 200 });
+    }
+    let gone = db.get_by_username(&leaver.username).await.unwrap().unwrap();
+    assert!(gone.deleted_at.is_some());
+
+    let request = actix_test::TestRequest::put()
+        .uri("/api/v1/admin/users/0/enabled")
+        .insert_header(("x-auth-token", admin.token.clone()))
+        .to_request();
+    assert_eq!(actix_test::call_service(&app, request).await.status(), 404);
+
+    for user in users {
+        db.delete_user(&user.username).await.unwrap();
+    }
+}
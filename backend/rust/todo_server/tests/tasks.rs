@@ -0,0 +1,215 @@
+// Task versions, ETags and If-Match. The ignored tests need Postgres, run them
+// with `cargo test -p todo_server -- --ignored`.
+
+use actix_web::dev::Service;
+use actix_web::http::header::{ETAG, IF_MATCH};
+use actix_web::{test as actix_test, web, App};
+use serde_json::json;
+use std::time::{SystemTime, UNIX_EPOCH};
+use todo_api::{
+    AuthResponse, CreateTaskRequest, LoginRequest, TaskResponse, TokenScope, UpdateTaskRequest,
+    UserInfo,
+};
+use todo_server::database::TodoDB;
+use todo_server::events::TaskEvents;
+use todo_server::routes;
+use todo_server::routes::auth::{remember_credentials, Credentials};
+
+#[actix_rt::test]
+async fn a_weak_if_match_never_matches() {
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(web::Data::new(TodoDB::new()))
+            .app_data(web::Data::new(TaskEvents::new()))
+            // stands in for the database, the request is from user 1
+            .wrap_fn(|req, srv| {
+                let user = UserInfo {
+                    id: 1,
+                    username: "woodroww".to_string(),
+                    token: "some-session-token".to_string(),
+                };
+                remember_credentials(
+                    req.request(),
+                    Some(Credentials {
+                        user,
+                        scope: TokenScope::ReadWrite,
+                        api_token: false,
+                    }),
+                );
+                srv.call(req)
+            })
+            .configure(routes::configure),
+    )
+    .await;
+    // answered before the task is even looked at
+    let request = actix_test::TestRequest::patch()
+        .uri("/api/v1/tasks/8")
+        .insert_header((IF_MATCH, "W/\"3\""))
+        .set_json(UpdateTaskRequest::default())
+        .to_request();
+    assert_eq!(actix_test::call_service(&app, request).await.status(), 412);
+    let request = actix_test::TestRequest::delete()
+        .uri("/api/v1/tasks/8")
+        .insert_header((IF_MATCH, "W/\"3\""))
+        .to_request();
+    assert_eq!(actix_test::call_service(&app, request).await.status(), 412);
+}
+
+#[actix_rt::test]
+#[ignore = "needs todo_server's Postgres database"]
+async fn every_write_answers_with_the_new_etag() {
+    if std::env::var("JWT_SECRET").is_err() {
+        std::env::set_var("JWT_SECRET", "keyboardcat");
+    }
+    let db = web::Data::new(TodoDB::new());
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(db.clone())
+            .app_data(web::Data::new(TaskEvents::new()))
+            .configure(routes::configure),
+    )
+    .await;
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let username = format!("etags-{}", nanos);
+    let request = actix_test::TestRequest::post()
+        .uri("/api/v1/users")
+        .set_json(LoginRequest {
+            username: username.clone(),
+            password: "myfancypass".to_string(),
+        })
+        .to_request();
+    let user: AuthResponse = actix_test::call_and_read_body_json(&app, request).await;
+    let token = user.data.token;
+
+    let request = actix_test::TestRequest::post()
+        .uri("/api/v1/tasks")
+        .insert_header(("x-auth-token", token.clone()))
+        .set_json(CreateTaskRequest {
+            title: "Curl is fun".to_string(),
+            ..CreateTaskRequest::default()
+        })
+        .to_request();
+    let response = actix_test::call_service(&app, request).await;
+    assert_eq!(response.headers().get(ETAG).unwrap(), "\"1\"");
+    let created: TaskResponse = actix_test::read_body_json(response).await;
+    let task = format!("/api/v1/tasks/{}", created.data.id);
+
+    let put = |path: String| {
+        actix_test::TestRequest::put()
+            .uri(&path)
+            .insert_header(("x-auth-token", token.clone()))
+            .to_request()
+    };
+    let response = actix_test::call_service(&app, put(format!("{}/completed", task))).await;
+    assert_eq!(response.headers().get(ETAG).unwrap(), "\"2\"");
+    let response = actix_test::call_service(&app, put(format!("{}/uncompleted", task))).await;
+    assert_eq!(response.headers().get(ETAG).unwrap(), "\"3\"");
+
+    // the strong tag of the version before is too old
+    let request = actix_test::TestRequest::delete()
+        .uri(&task)
+        .insert_header(("x-auth-token", token.clone()))
+        .insert_header((IF_MATCH, "\"2\""))
+        .to_request();
+    let response = actix_test::call_service(&app, request).await;
+    assert_eq!(response.status(), 412);
+    assert_eq!(response.headers().get(ETAG).unwrap(), "\"3\"");
+    let request = actix_test::TestRequest::delete()
+        .uri(&task)
+        .insert_header(("x-auth-token", token.clone()))
+        .insert_header((IF_MATCH, "\"3\""))
+        .to_request();
+    assert_eq!(actix_test::call_service(&app, request).await.status(), 200);
+
+    // a deleted task is as gone as one that never was
+    let request = actix_test::TestRequest::get()
+        .uri(&task)
+        .insert_header(("x-auth-token", token.clone()))
+        .to_request();
+    assert_eq!(actix_test::call_service(&app, request).await.status(), 404);
+    let request = actix_test::TestRequest::patch()
+        .uri(&task)
+        .insert_header(("x-auth-token", token.clone()))
+        .set_json(UpdateTaskRequest::default())
+        .to_request();
+    assert_eq!(actix_test::call_service(&app, request).await.status(), 404);
+    let response = actix_test::call_service(&app, put(format!("{}/completed", task))).await;
+    assert_eq!(response.status(), 404);
+    let request = actix_test::TestRequest::delete()
+        .uri(&task)
+        .insert_header(("x-auth-token", token.clone()))
+        .to_request();
+    assert_eq!(actix_test::call_service(&app, request).await.status(), 404);
+
+    db.delete_user(&username).await.unwrap();
+}
+
+#[actix_rt::test]
+#[ignore = "needs todo_server's Postgres database"]
+async fn a_null_priority_or_description_removes_it() {
+    if std::env::var("JWT_SECRET").is_err() {
+        std::env::set_var("JWT_SECRET", "keyboardcat");
+    }
+    let db = web::Data::new(TodoDB::new());
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(db.clone())
+            .app_data(web::Data::new(TaskEvents::new()))
+            .configure(routes::configure),
+    )
+    .await;
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let username = format!("patch-nulls-{}", nanos);
+    let request = actix_test::TestRequest::post()
+        .uri("/api/v1/users")
+        .set_json(LoginRequest {
+            username: username.clone(),
+            password: "myfancypass".to_string(),
+        })
+        .to_request();
+    let user: AuthResponse = actix_test::call_and_read_body_json(&app, request).await;
+    let token = user.data.token;
+    let request = actix_test::TestRequest::post()
+        .uri("/api/v1/tasks")
+        .insert_header(("x-auth-token", token.clone()))
+        .set_json(CreateTaskRequest {
+            title: "Curl is fun".to_string(),
+            priority: Some("A".to_string()),
+            description: Some("typing and stuff in the terminal".to_string()),
+        })
+        .to_request();
+    let created: TaskResponse = actix_test::call_and_read_body_json(&app, request).await;
+    let patch = |body: serde_json::Value| {
+        actix_test::TestRequest::patch()
+            .uri(&format!("/api/v1/tasks/{}", created.data.id))
+            .insert_header(("x-auth-token", token.clone()))
+            .set_json(body)
+            .to_request()
+    };
+
+    // left out, they stay
+    let kept: TaskResponse =
+        actix_test::call_and_read_body_json(&app, patch(json!({ "title": "Curl is fun!" }))).await;
+    assert_eq!(kept.data.priority.as_deref(), Some("A"));
+    assert_eq!(
+        kept.data.description.as_deref(),
+        Some("typing and stuff in the terminal")
+    );
+
+    let cleared: TaskResponse = actix_test::call_and_read_body_json(
+        &app,
+        patch(json!({ "priority": null, "description": null })),
+    )
+    .await;
+    assert_eq!(cleared.data.title, "Curl is fun!");
+    assert_eq!(cleared.data.priority, None);
+    assert_eq!(cleared.data.description, None);
+
+    db.delete_user(&username).await.unwrap();
+}
@@ -0,0 +1,163 @@
+// Picking the onboarding locale, and the template routes end to end (ignored,
+// run it with `cargo test -p todo_server -- --ignored`).
+
+use actix_web::{test as actix_test, web, App};
+use std::time::{SystemTime, UNIX_EPOCH};
+use todo_api::{
+    AuthResponse, CreateTemplateRequest, LoginRequest, Role, TaskListResponse,
+    TemplateListResponse, TemplateResponse, UpdateTemplateRequest,
+};
+use todo_server::database::TodoDB;
+use todo_server::events::TaskEvents;
+use todo_server::routes::{self, templates::choose_locale};
+
+fn locales(locales: &[&str]) -> Vec<String> {
+    locales.iter().map(|locale| locale.to_string()).collect()
+}
+
+#[test]
+fn picks_the_most_preferred_locale() {
+    let available = locales(&["en", "es", "pt-BR"]);
+    let choose = |accept_language| choose_locale(accept_language, &available);
+    assert_eq!(choose("es"), Some("es".to_string()));
+    assert_eq!(choose("fr, es;q=0.5, en;q=0.8"), Some("en".to_string()));
+    assert_eq!(choose("es-MX,es;q=0.9"), Some("es".to_string()));
+    assert_eq!(choose("PT-br"), Some("pt-BR".to_string()));
+    assert_eq!(choose("en;q=0, es;q=0.1"), Some("es".to_string()));
+    assert_eq!(choose("fr, *;q=0.5"), None);
+    assert_eq!(choose(""), None);
+    assert_eq!(choose("en;q=lots"), None);
+}
+
+#[actix_rt::test]
+#[ignore = "needs todo_server's Postgres database"]
+async fn new_users_get_the_templates_of_their_locale() {
+    if std::env::var("JWT_SECRET").is_err() {
+        std::env::set_var("JWT_SECRET", "keyboardcat");
+    }
+    let db = web::Data::new(TodoDB::new());
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(db.clone())
+            .app_data(web::Data::new(TaskEvents::new()))
+            .configure(routes::configure),
+    )
+    .await;
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    // a locale nobody else asks for
+    let locale = format!("x-{}", nanos % 100_000_000);
+
+    let request = actix_test::TestRequest::get()
+        .uri("/api/v1/templates")
+        .to_request();
+    assert_eq!(actix_test::call_service(&app, request).await.status(), 401);
+
+    let request = actix_test::TestRequest::post()
+        .uri("/api/v1/users")
+        .set_json(LoginRequest {
+            username: format!("templates-admin-{}", nanos),
+            password: "myfancypass".to_string(),
+        })
+        .to_request();
+    let admin: AuthResponse = actix_test::call_and_read_body_json(&app, request).await;
+    let request = actix_test::TestRequest::get()
+        .uri("/api/v1/templates")
+        .insert_header(("x-auth-token", admin.data.token.clone()))
+        .to_request();
+    assert_eq!(actix_test::call_service(&app, request).await.status(), 403);
+    assert!(db.set_role(admin.data.id, Role::Admin).await.unwrap());
+    let admin_token = admin.data.token;
+
+    let mut created = vec![];
+    for (title, priority) in [("first", "C"), ("second", "A")] {
+        let request = actix_test::TestRequest::post()
+            .uri("/api/v1/templates")
+            .insert_header(("x-auth-token", admin_token.clone()))
+            .set_json(CreateTemplateRequest {
+                title: title.to_string(),
+                priority: Some(priority.to_string()),
+                locale: Some(locale.clone()),
+                ..CreateTemplateRequest::default()
+            })
+            .to_request();
+        let response: TemplateResponse = actix_test::call_and_read_body_json(&app, request).await;
+        created.push(response.data);
+    }
+    assert_eq!(created[1].position, created[0].position + 1);
+
+    let request = actix_test::TestRequest::patch()
+        .uri(&format!("/api/v1/templates/{}", created[1].id))
+        .insert_header(("x-auth-token", admin_token.clone()))
+        .set_json(UpdateTemplateRequest {
+            description: Some("changed".to_string()),
+            ..UpdateTemplateRequest::default()
+        })
+        .to_request();
+    let updated: TemplateResponse = actix_test::call_and_read_body_json(&app, request).await;
+    assert_eq!(updated.data.description.as_deref(), Some("changed"));
+    assert_eq!(updated.data.title, "second");
+
+    let request = actix_test::TestRequest::get()
+        .uri(&format!("/api/v1/templates?locale={}", locale))
+        .insert_header(("x-auth-token", admin_token.clone()))
+        .to_request();
+    let listed: TemplateListResponse = actix_test::call_and_read_body_json(&app, request).await;
+    assert_eq!(listed.data.len(), 2);
+
+    let request = actix_test::TestRequest::post()
+        .uri("/api/v1/users")
+        .insert_header(("accept-language", format!("fr, {};q=0.9", locale)))
+        .set_json(LoginRequest {
+            username: format!("templates-{}", nanos),
+            password: "myfancypass".to_string(),
+        })
+        .to_request();
+    let user: AuthResponse = actix_test::call_and_read_body_json(&app, request).await;
+    let request = actix_test::TestRequest::get()
+        .uri("/api/v1/tasks")
+        .insert_header(("x-auth-token", user.data.token))
+        .to_request();
+    let mut tasks: TaskListResponse = actix_test::call_and_read_body_json(&app, request).await;
+    tasks.data.sort_by_key(|task| task.id);
+    let seeded: Vec<(&str, Option<&str>, Option<&str>)> = tasks
+        .data
+        .iter()
+        .map(|task| {
+            (
+                task.title.as_str(),
+                task.priority.as_deref(),
+                task.description.as_deref(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        seeded,
+        vec![
+            ("first", Some("C"), None),
+            ("second", Some("A"), Some("changed"))
+        ]
+    );
+
+    for template in created {
+        let request = actix_test::TestRequest::delete()
+            .uri(&format!("/api/v1/templates/{}", template.id))
+            .insert_header(("x-auth-token", admin_token.clone()))
+            .to_request();
+        assert!(actix_test::call_service(&app, request)
+            .await
+            .status()
+            .is_success());
+    }
+    let request = actix_test::TestRequest::get()
+        .uri(&format!("/api/v1/templates/{}", updated.data.id))
+        .insert_header(("x-auth-token", admin_token.clone()))
+        .to_request();
+    assert_eq!(actix_test::call_service(&app, request).await.status(), 404);
+
+    db.delete_user(&format!("templates-admin-{}", nanos))
+        .await
+        .unwrap();
+}
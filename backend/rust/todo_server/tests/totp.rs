@@ -0,0 +1,89 @@
+// totp.rs against the test vectors of RFC 4648 (base32), RFC 4226 (HOTP) and
+// RFC 6238 (TOTP).
+
+use todo_server::totp::{
+    base32_decode, base32_encode, code_at, generate_secret, hotp, otpauth_uri, step_at, verify,
+};
+
+const RFC_KEY: &[u8] = b"12345678901234567890";
+
+#[test]
+fn base32_matches_rfc_4648() {
+    for (plain, encoded) in [
+        ("", ""),
+        ("f", "MY"),
+        ("fo", "MZXQ"),
+        ("foo", "MZXW6"),
+        ("foob", "MZXW6YQ"),
+        ("fooba", "MZXW6YTB"),
+        ("foobar", "MZXW6YTBOI"),
+    ] {
+        assert_eq!(base32_encode(plain.as_bytes()), encoded);
+        assert_eq!(base32_decode(encoded).unwrap(), plain.as_bytes());
+    }
+    // the way people type secrets in
+    assert_eq!(base32_decode("mzxw 6ytb oi======").unwrap(), b"foobar");
+    assert_eq!(base32_decode("MZXW1"), None);
+}
+
+#[test]
+fn hotp_matches_rfc_4226() {
+    let expected = [
+        "755224", "287082", "359152", "969429", "338314", "254676", "287922", "162583", "399871",
+        "520489",
+    ];
+    for (counter, code) in expected.iter().enumerate() {
+        assert_eq!(hotp(RFC_KEY, counter as u64, 6), *code);
+    }
+}
+
+#[test]
+fn totp_matches_rfc_6238() {
+    for (time, code) in [
+        (59, "94287082"),
+        (1111111109, "07081804"),
+        (1111111111, "14050471"),
+        (1234567890, "89005924"),
+        (2000000000, "69279037"),
+        (20000000000, "65353130"),
+    ] {
+        assert_eq!(hotp(RFC_KEY, step_at(time) as u64, 8), code);
+    }
+}
+
+#[test]
+fn codes_from_the_steps_either_side_are_accepted() {
+    let secret = base32_encode(RFC_KEY);
+    let now = 1234567890;
+    let step = step_at(now);
+    for offset in -1..=1 {
+        let code = code_at(&secret, step + offset).unwrap();
+        assert_eq!(verify(&secret, &code, now), Some(step + offset));
+    }
+    for offset in [-2, 2] {
+        let code = code_at(&secret, step + offset).unwrap();
+        assert_eq!(verify(&secret, &code, now), None);
+    }
+    // spaces are how some apps show it
+    let code = code_at(&secret, step).unwrap();
+    let spaced = format!("{} {}", &code[..3], &code[3..]);
+    assert_eq!(verify(&secret, &spaced, now), Some(step));
+    assert_eq!(verify(&secret, &code[..5], now), None);
+    assert_eq!(verify(&secret, "abcdef", now), None);
+}
+
+#[test]
+fn secrets_are_random_base32() {
+    let secret = generate_secret();
+    assert_eq!(secret.len(), 32);
+    assert_eq!(base32_decode(&secret).unwrap().len(), 20);
+    assert_ne!(secret, generate_secret());
+}
+
+#[test]
+fn otpauth_uris_escape_the_account() {
+    assert_eq!(
+        otpauth_uri("matt w", "MZXW6YTBOI"),
+        "otpauth://totp/Todo:matt%20w?secret=MZXW6YTBOI&issuer=Todo&algorithm=SHA1&digits=6&period=30"
+    );
+}
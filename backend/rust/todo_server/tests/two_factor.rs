@@ -0,0 +1,187 @@
+// Turning two-factor authentication on, logging in with it and turning it off,
+// against the database (ignored, run it with `cargo test -p todo_server -- --ignored`).
+
+use actix_web::{test as actix_test, web, App};
+use chrono::Utc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use todo_api::{
+    AuthResponse, DisableTwoFactorRequest, LoginRequest, RecoveryCodesResponse,
+    TwoFactorChallengeResponse, TwoFactorCodeRequest, TwoFactorLoginRequest,
+    TwoFactorSetupResponse, TwoFactorStatusResponse,
+};
+use todo_server::database::TodoDB;
+use todo_server::events::TaskEvents;
+use todo_server::routes;
+use todo_server::totp::{code_at, step_at};
+
+#[actix_rt::test]
+#[ignore = "needs todo_server's Postgres database"]
+async fn logins_need_a_code_once_two_factor_is_on() {
+    if std::env::var("JWT_SECRET").is_err() {
+        std::env::set_var("JWT_SECRET", "keyboardcat");
+    }
+    let db = web::Data::new(TodoDB::new());
+    let app = actix_test::init_service(
+        App::new()
+            .app_data(db.clone())
+            .app_data(web::Data::new(TaskEvents::new()))
+            .configure(routes::configure),
+    )
+    .await;
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let username = format!("two-factor-{}", nanos);
+    let login = || {
+        actix_test::TestRequest::post()
+            .uri("/api/v1/users/login")
+            .set_json(LoginRequest {
+                username: username.clone(),
+                password: "myfancypass".to_string(),
+            })
+            .to_request()
+    };
+    let second_step = |challenge: &str, code: &str| {
+        actix_test::TestRequest::post()
+            .uri("/api/v1/users/login/two-factor")
+            .set_json(TwoFactorLoginRequest {
+                challenge: challenge.to_string(),
+                code: code.to_string(),
+            })
+            .to_request()
+    };
+    let confirm = |token: &str, code: &str| {
+        actix_test::TestRequest::post()
+            .uri("/api/v1/users/me/two-factor/confirm")
+            .insert_header(("x-auth-token", token.to_string()))
+            .set_json(TwoFactorCodeRequest {
+                code: code.to_string(),
+            })
+            .to_request()
+    };
+
+    let request = actix_test::TestRequest::post()
+        .uri("/api/v1/users")
+        .set_json(LoginRequest {
+            username: username.clone(),
+            password: "myfancypass".to_string(),
+        })
+        .to_request();
+    let user: AuthResponse = actix_test::call_and_read_body_json(&app, request).await;
+    let token = user.data.token;
+
+    // without 2FA login answers the user straight away
+    assert_eq!(actix_test::call_service(&app, login()).await.status(), 200);
+    let user: AuthResponse = actix_test::call_and_read_body_json(&app, login()).await;
+    assert_ne!(user.data.token, token);
+    let token = user.data.token;
+
+    let request = actix_test::TestRequest::post()
+        .uri("/api/v1/users/me/two-factor")
+        .to_request();
+    assert_eq!(actix_test::call_service(&app, request).await.status(), 401);
+    let request = actix_test::TestRequest::post()
+        .uri("/api/v1/users/me/two-factor")
+        .insert_header(("x-auth-token", token.clone()))
+        .to_request();
+    let setup: TwoFactorSetupResponse = actix_test::call_and_read_body_json(&app, request).await;
+    let secret = setup.data.secret;
+    assert!(setup.data.otpauth_uri.contains(&secret));
+    assert!(setup.data.otpauth_uri.contains(&username));
+
+    // not on until it is confirmed
+    assert_eq!(actix_test::call_service(&app, login()).await.status(), 200);
+    let user: AuthResponse = actix_test::call_and_read_body_json(&app, login()).await;
+    let token = user.data.token;
+
+    let step = step_at(Utc::now().timestamp());
+    let wrong = if code_at(&secret, step).unwrap() == "000000" {
+        "111111"
+    } else {
+        "000000"
+    };
+    let response = actix_test::call_service(&app, confirm(&token, wrong)).await;
+    assert_eq!(response.status(), 400);
+    let code = code_at(&secret, step).unwrap();
+    let codes: RecoveryCodesResponse =
+        actix_test::call_and_read_body_json(&app, confirm(&token, &code)).await;
+    let recovery_codes = codes.data.recovery_codes;
+    assert_eq!(recovery_codes.len(), 10);
+    let response = actix_test::call_service(&app, confirm(&token, &code)).await;
+    assert_eq!(response.status(), 409);
+    let request = actix_test::TestRequest::post()
+        .uri("/api/v1/users/me/two-factor")
+        .insert_header(("x-auth-token", token.clone()))
+        .to_request();
+    assert_eq!(actix_test::call_service(&app, request).await.status(), 409);
+
+    // now login only hands out a challenge
+    let response = actix_test::call_service(&app, login()).await;
+    assert_eq!(response.status(), 202);
+    let challenge: TwoFactorChallengeResponse = actix_test::read_body_json(response).await;
+    let challenge = challenge.data.challenge;
+    // the code that confirmed enrolment is used up
+    let response = actix_test::call_service(&app, second_step(&challenge, &code)).await;
+    assert_eq!(response.status(), 400);
+    let next_code = code_at(&secret, step + 1).unwrap();
+    let user: AuthResponse =
+        actix_test::call_and_read_body_json(&app, second_step(&challenge, &next_code)).await;
+    assert_eq!(user.data.username, username);
+    let token = user.data.token;
+    // and so is the challenge
+    let response =
+        actix_test::call_service(&app, second_step(&challenge, &recovery_codes[0])).await;
+    assert_eq!(response.status(), 400);
+
+    // a recovery code works once, however it's typed
+    let challenge: TwoFactorChallengeResponse =
+        actix_test::call_and_read_body_json(&app, login()).await;
+    let typed = recovery_codes[0].replace('-', " ").to_uppercase();
+    let user: AuthResponse =
+        actix_test::call_and_read_body_json(&app, second_step(&challenge.data.challenge, &typed))
+            .await;
+    assert_ne!(user.data.token, token);
+    let token = user.data.token;
+    let challenge: TwoFactorChallengeResponse =
+        actix_test::call_and_read_body_json(&app, login()).await;
+    let challenge = challenge.data.challenge;
+    let response =
+        actix_test::call_service(&app, second_step(&challenge, &recovery_codes[0])).await;
+    assert_eq!(response.status(), 400);
+
+    let request = actix_test::TestRequest::get()
+        .uri("/api/v1/users/me/two-factor")
+        .insert_header(("x-auth-token", token.clone()))
+        .to_request();
+    let status: TwoFactorStatusResponse = actix_test::call_and_read_body_json(&app, request).await;
+    assert!(status.data.enabled);
+    assert_eq!(status.data.recovery_codes_left, 9);
+
+    // a challenge takes 5 tries, the one above was the first
+    for _ in 0..4 {
+        let response = actix_test::call_service(&app, second_step(&challenge, wrong)).await;
+        assert_eq!(response.status(), 400);
+    }
+    let response =
+        actix_test::call_service(&app, second_step(&challenge, &recovery_codes[1])).await;
+    assert_eq!(response.status(), 400);
+
+    let disable = |password: &str| {
+        actix_test::TestRequest::delete()
+            .uri("/api/v1/users/me/two-factor")
+            .insert_header(("x-auth-token", token.clone()))
+            .set_json(DisableTwoFactorRequest {
+                password: password.to_string(),
+            })
+            .to_request()
+    };
+    let response = actix_test::call_service(&app, disable("wrong")).await;
+    assert_eq!(response.status(), 400);
+    let response = actix_test::call_service(&app, disable("myfancypass")).await;
+    assert_eq!(response.status(), 200);
+    assert_eq!(actix_test::call_service(&app, login()).await.status(), 200);
+    let response =
+        actix_test::call_service(&app, second_step(&challenge, &recovery_codes[1])).await;
+    assert_eq!(response.status(), 400);
+}
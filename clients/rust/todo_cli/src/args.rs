@@ -0,0 +1,216 @@
+use todo_cli::view::{Filter, Sort};
+
+pub const USAGE: &str = "\
+usage: todo [--json] [--server URL] <command>
+
+commands:
+  login <username> [--code CODE]           log in and remember the token, asks for the
+                                            password unless $TODO_PASSWORD has it, the
+                                            code is for accounts with two-factor
+                                            authentication
+  logout
+  list [--completed | --uncompleted] [--priority A|B|C] [--sort created|priority|name]
+  add <title> [--description TEXT] [--priority A|B|C]
+  edit <id> [--title TEXT] [--description TEXT] [--priority A|B|C]
+                                            an empty description removes it
+  done <id>                                 mark a task as completed
+  undo <id>                                 mark a task as not completed
+  rm <id>
+  tui                                       browse and edit tasks full screen
+
+options:
+  --json          print JSON instead of a table, for scripts
+  --server URL    the API to talk to, defaults to $TODO_SERVER, then the one you
+                  logged in to, then http://localhost:3010/api/v1
+
+the token is kept in $TODO_CONFIG_DIR, $XDG_CONFIG_HOME/todo or ~/.config/todo
+";
+
+#[derive(Debug, PartialEq)]
+pub struct Args {
+    pub json: bool,
+    pub server: Option<String>,
+    pub command: Command,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Help,
+    Login {
+        username: String,
+        code: Option<String>,
+    },
+    Logout,
+    List {
+        filters: Vec<Filter>,
+        sort: Sort,
+    },
+    Add {
+        title: String,
+        description: Option<String>,
+        priority: Option<String>,
+    },
+    Edit {
+        id: i32,
+        title: Option<String>,
+        description: Option<String>,
+        priority: Option<String>,
+    },
+    Done {
+        id: i32,
+    },
+    Undo {
+        id: i32,
+    },
+    Rm {
+        id: i32,
+    },
+    Tui,
+}
+
+const FLAGS: [&str; 5] = ["--json", "--completed", "--uncompleted", "--help", "-h"];
+// no --password, it would end up in the shell's history and in `ps`
+const OPTIONS: [&str; 6] = [
+    "--server",
+    "--code",
+    "--description",
+    "--priority",
+    "--title",
+    "--sort",
+];
+
+struct Parsed {
+    positional: Vec<String>,
+    flags: Vec<String>,
+    options: Vec<(String, String)>,
+}
+
+impl Parsed {
+    fn flag(&self, name: &str) -> bool {
+        self.flags.iter().any(|flag| flag == name)
+    }
+
+    fn option(&self, name: &str) -> Option<String> {
+        self.options
+            .iter()
+            .rev()
+            .find(|(option, _)| option == name)
+            .map(|(_, value)| value.clone())
+    }
+}
+
+pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Args, String> {
+    let parsed = split(args)?;
+    let json = parsed.flag("--json");
+    let server = parsed.option("--server");
+    if parsed.flag("--help") || parsed.flag("-h") {
+        return Ok(Args {
+            json,
+            server,
+            command: Command::Help,
+        });
+    }
+
+    let mut positional = parsed.positional.iter();
+    let name = positional.next().map(String::as_str);
+    let mut argument = |what: &str| {
+        positional
+            .next()
+            .cloned()
+            .ok_or_else(|| format!("{} needs {}", name.unwrap_or_default(), what))
+    };
+    let priority = parsed.option("--priority").map(|p| p.to_uppercase());
+    if let Some(priority) = &priority {
+        if !["A", "B", "C"].contains(&priority.as_str()) {
+            return Err(format!("priority must be A, B or C, not {}", priority));
+        }
+    }
+
+    let command = match name {
+        None | Some("help") => Command::Help,
+        Some("login") => Command::Login {
+            username: argument("a username")?,
+            code: parsed.option("--code"),
+        },
+        Some("logout") => Command::Logout,
+        Some("list") | Some("ls") => {
+            let mut filters = match (parsed.flag("--completed"), parsed.flag("--uncompleted")) {
+                (true, true) => return Err("pick one of --completed and --uncompleted".to_string()),
+                (true, false) => vec![Filter::Completed],
+                (false, true) => vec![Filter::Uncompleted],
+                (false, false) => vec![],
+            };
+            filters.extend(priority.map(Filter::Priority));
+            let sort = match parsed.option("--sort").as_deref() {
+                None | Some("created") => Sort::Created,
+                Some("priority") => Sort::Priority,
+                Some("name") => Sort::Name,
+                Some(other) => return Err(format!("can't sort by {}", other)),
+            };
+            Command::List { filters, sort }
+        }
+        Some("add") => Command::Add {
+            title: argument("a title")?,
+            description: parsed.option("--description"),
+            priority,
+        },
+        Some("edit") => Command::Edit {
+            id: task_id(&argument("a task id")?)?,
+            title: parsed.option("--title"),
+            description: parsed.option("--description"),
+            priority,
+        },
+        Some("done") => Command::Done {
+            id: task_id(&argument("a task id")?)?,
+        },
+        Some("undo") => Command::Undo {
+            id: task_id(&argument("a task id")?)?,
+        },
+        Some("rm") => Command::Rm {
+            id: task_id(&argument("a task id")?)?,
+        },
+        Some("tui") => Command::Tui,
+        Some(other) => return Err(format!("unknown command {}", other)),
+    };
+    if let Some(extra) = positional.next() {
+        return Err(format!("unexpected argument {}", extra));
+    }
+    Ok(Args {
+        json,
+        server,
+        command,
+    })
+}
+
+fn split<I: IntoIterator<Item = String>>(args: I) -> Result<Parsed, String> {
+    let mut parsed = Parsed {
+        positional: vec![],
+        flags: vec![],
+        options: vec![],
+    };
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if let Some((name, value)) = arg
+            .split_once('=')
+            .filter(|(name, _)| OPTIONS.contains(name))
+        {
+            parsed.options.push((name.to_string(), value.to_string()));
+        } else if OPTIONS.contains(&arg.as_str()) {
+            let value = args
+                .next()
+                .ok_or_else(|| format!("{} needs a value", arg))?;
+            parsed.options.push((arg, value));
+        } else if FLAGS.contains(&arg.as_str()) {
+            parsed.flags.push(arg);
+        } else if arg.starts_with("--") {
+            return Err(format!("unknown option {}", arg));
+        } else {
+            parsed.positional.push(arg);
+        }
+    }
+    Ok(parsed)
+}
+
+fn task_id(arg: &str) -> Result<i32, String> {
+    arg.parse().map_err(|_| format!("{} is not a task id", arg))
+}
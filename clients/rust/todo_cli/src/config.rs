@@ -0,0 +1,70 @@
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+pub const DEFAULT_SERVER: &str = "http://localhost:3010/api/v1";
+const CONFIG_FILE: &str = "config.json";
+
+/// What `todo login` remembers between runs.
+#[derive(Serialize, Deserialize, Default)]
+pub struct Config {
+    pub server: Option<String>,
+    pub username: Option<String>,
+    pub token: Option<String>,
+}
+
+pub fn config_dir() -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os("TODO_CONFIG_DIR") {
+        return Some(PathBuf::from(dir));
+    }
+    if let Some(dir) = std::env::var_os("XDG_CONFIG_HOME") {
+        return Some(PathBuf::from(dir).join("todo"));
+    }
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config").join("todo"))
+}
+
+impl Config {
+    pub fn load() -> io::Result<Config> {
+        let path = match config_dir() {
+            Some(dir) => dir.join(CONFIG_FILE),
+            None => return Ok(Config::default()),
+        };
+        match fs::read_to_string(path) {
+            Ok(text) => serde_json::from_str(&text)
+                .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error)),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(error) => Err(error),
+        }
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let dir = config_dir().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, "no HOME to keep the token in")
+        })?;
+        fs::create_dir_all(&dir)?;
+        let path = dir.join(CONFIG_FILE);
+        // the token is as good as the password, so only we may read it, from
+        // the moment the file exists
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+            options.mode(0o600);
+            // a file from before we created them like this
+            if path.exists() {
+                fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+            }
+        }
+        let mut file = options.open(&path)?;
+        file.write_all(serde_json::to_string_pretty(self)?.as_bytes())
+    }
+
+    /// --server, then $TODO_SERVER, then wherever we logged in.
+    pub fn server(&self, flag: Option<String>) -> String {
+        flag.or_else(|| std::env::var("TODO_SERVER").ok())
+            .or_else(|| self.server.clone())
+            .unwrap_or_else(|| DEFAULT_SERVER.to_string())
+    }
+}
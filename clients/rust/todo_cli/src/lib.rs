@@ -0,0 +1,9 @@
+// What the `todo` binary is made of, as a library so tests can drive the
+// full screen app with keys and a scripted server.
+
+pub mod tui;
+pub mod view;
+
+use std::error::Error;
+
+pub type CliResult = Result<(), Box<dyn Error>>;
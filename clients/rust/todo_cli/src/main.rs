@@ -0,0 +1,198 @@
+mod args;
+mod config;
+mod output;
+
+use args::{Args, Command, USAGE};
+use config::Config;
+use serde_json::json;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::process::{self, Stdio};
+use todo_cli::{tui, view, CliResult};
+use todo_client::{ClientError, CreateTaskRequest, Task, TodoClient, UpdateTaskRequest};
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let args = match args::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(error) => {
+            eprintln!("todo: {}\n\n{}", error, USAGE);
+            process::exit(2);
+        }
+    };
+    if let Err(error) = run(args).await {
+        match error.downcast_ref::<ClientError>() {
+            Some(ClientError::NotAuthenticated) => {
+                eprintln!("todo: not logged in, run `todo login <username>` first")
+            }
+            _ => eprintln!("todo: {}", error),
+        }
+        process::exit(1);
+    }
+}
+
+async fn run(args: Args) -> CliResult {
+    let Args {
+        json,
+        server,
+        command,
+    } = args;
+    let mut config = Config::load()?;
+    let server = config.server(server);
+    let mut client = TodoClient::new(&server);
+    client.set_token(config.token.clone());
+
+    match command {
+        Command::Help => print!("{}", USAGE),
+        Command::Login { username, code } => {
+            let password = match std::env::var("TODO_PASSWORD") {
+                Ok(password) => password,
+                Err(_) => read_password()?,
+            };
+            let user = match client.login(&username, &password).await {
+                Err(ClientError::TwoFactorRequired(challenge)) => {
+                    let code = match code {
+                        Some(code) => code,
+                        None => read_code()?,
+                    };
+                    client.login_two_factor(&challenge, &code).await?
+                }
+                result => result?,
+            };
+            config.server = Some(server);
+            config.username = Some(user.username.clone());
+            config.token = Some(user.token.clone());
+            config.save()?;
+            if json {
+                output::json(&json!({ "id": user.id, "username": user.username }));
+            } else {
+                println!("logged in as {}", user.username);
+            }
+        }
+        Command::Logout => {
+            // forget the token even when the server already has
+            let result = client.logout().await;
+            config.username = None;
+            config.token = None;
+            config.save()?;
+            match result {
+                Ok(()) | Err(ClientError::NotAuthenticated) => {}
+                Err(error) => return Err(error.into()),
+            }
+            if !json {
+                println!("logged out");
+            }
+        }
+        Command::List { filters, sort } => {
+            let tasks = view::view(&client.tasks().await?, &filters, sort);
+            if json {
+                output::json(&tasks);
+            } else {
+                output::table(&tasks);
+            }
+        }
+        Command::Add {
+            title,
+            description,
+            priority,
+        } => {
+            let task = client
+                .create_task(&CreateTaskRequest {
+                    title,
+                    description,
+                    priority,
+                })
+                .await?;
+            print_task(json, "added", &task);
+        }
+        Command::Edit {
+            id,
+            title,
+            description,
+            priority,
+        } => {
+            let patch = UpdateTaskRequest {
+                title,
+                priority: priority.map(Some),
+                // `--description ""` removes it
+                description: description.map(|d| Some(d).filter(|d| !d.is_empty())),
+                completed_at: None,
+            };
+            if patch == UpdateTaskRequest::default() {
+                return Err("nothing to change, pass --title, --description or --priority".into());
+            }
+            let task = client.update_task(id, &patch, None).await?;
+            print_task(json, "edited", &task);
+        }
+        Command::Done { id } => {
+            let version = client.complete_task(id).await?;
+            print_done(json, "completed", id, version);
+        }
+        Command::Undo { id } => {
+            let version = client.uncomplete_task(id).await?;
+            print_done(json, "uncompleted", id, version);
+        }
+        Command::Tui => tui::run(client, config.username.clone().unwrap_or_default()).await?,
+        Command::Rm { id } => {
+            client.delete_task(id, None).await?;
+            if json {
+                output::json(&json!({ "id": id }));
+            } else {
+                println!("deleted task {}", id);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn print_task(json: bool, what: &str, task: &Task) {
+    if json {
+        output::json(task);
+    } else {
+        println!("{} task {}", what, task.id);
+        output::table(std::slice::from_ref(task));
+    }
+}
+
+fn print_done(json: bool, what: &str, id: i32, version: Option<i32>) {
+    if json {
+        output::json(&json!({ "id": id, "version": version }));
+    } else {
+        println!("{} task {}", what, id);
+    }
+}
+
+// prompts on stderr so `--json` output stays clean, without echoing when we can
+fn read_password() -> io::Result<String> {
+    let terminal = io::stdin().is_terminal();
+    if terminal {
+        eprint!("password: ");
+        io::stderr().flush()?;
+        set_echo(false);
+    }
+    let mut password = String::new();
+    let read = io::stdin().lock().read_line(&mut password);
+    if terminal {
+        set_echo(true);
+        eprintln!();
+    }
+    read?;
+    Ok(password.trim_end_matches(['\r', '\n']).to_string())
+}
+
+// a code from the authenticator app or a recovery code
+fn read_code() -> io::Result<String> {
+    if io::stdin().is_terminal() {
+        eprint!("two-factor code: ");
+        io::stderr().flush()?;
+    }
+    let mut code = String::new();
+    io::stdin().lock().read_line(&mut code)?;
+    Ok(code.trim().to_string())
+}
+
+fn set_echo(on: bool) {
+    let _ = process::Command::new("stty")
+        .arg(if on { "echo" } else { "-echo" })
+        .stdin(Stdio::inherit())
+        .status();
+}
@@ -0,0 +1,58 @@
+use serde::Serialize;
+use todo_client::Task;
+
+// the widest a column gets before it is cut short
+const MAX_WIDTH: usize = 40;
+
+pub fn json<T: Serialize>(value: &T) {
+    println!("{}", serde_json::to_string_pretty(value).unwrap());
+}
+
+pub fn table(tasks: &[Task]) {
+    if tasks.is_empty() {
+        println!("no tasks");
+        return;
+    }
+    let header = ["ID", "DONE", "PRI", "TITLE", "DESCRIPTION"];
+    let rows: Vec<[String; 5]> = tasks
+        .iter()
+        .map(|task| {
+            [
+                task.id.to_string(),
+                if task.completed_at.is_some() { "x" } else { "" }.to_string(),
+                task.priority.clone().unwrap_or_default(),
+                cut(&task.title),
+                cut(task.description.as_deref().unwrap_or_default()),
+            ]
+        })
+        .collect();
+    let mut widths = header.map(str::len);
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    print_row(&header.map(str::to_string), &widths);
+    for row in &rows {
+        print_row(row, &widths);
+    }
+}
+
+fn print_row(row: &[String; 5], widths: &[usize; 5]) {
+    let cells: Vec<String> = row
+        .iter()
+        .zip(widths)
+        .map(|(cell, width)| format!("{:width$}", cell, width = width))
+        .collect();
+    println!("{}", cells.join("  ").trim_end());
+}
+
+fn cut(text: &str) -> String {
+    let text = text.replace('\n', " ");
+    if text.chars().count() <= MAX_WIDTH {
+        return text;
+    }
+    let mut cut: String = text.chars().take(MAX_WIDTH - 3).collect();
+    cut.push_str("...");
+    cut
+}
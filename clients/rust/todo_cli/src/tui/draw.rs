@@ -0,0 +1,211 @@
+use super::{App, Field, Form, Mode, PRIORITIES};
+use crate::view::Sort;
+use todo_client::{Task, Transport};
+
+const BOLD: &str = "\x1b[1m";
+const REVERSE: &str = "\x1b[7m";
+const DIM: &str = "\x1b[2m";
+const RED: &str = "\x1b[31m";
+const BLUE: &str = "\x1b[34m";
+const RESET: &str = "\x1b[0m";
+
+const BROWSE_HELP: &str =
+    "↑/↓ move  space done  e edit  a add  d delete  f filter  s sort  r reload  q quit";
+const EDIT_HELP: &str = "tab next field  ←/→ change  enter save  esc cancel";
+
+// the lines above and below the task table
+const HEADER_LINES: usize = 4;
+const FOOTER_LINES: usize = 2;
+
+/// The whole screen for `app`, rows separated by "\r\n" since raw mode stops
+/// the terminal from adding the carriage returns itself.
+pub fn render<T: Transport>(app: &App<T>, rows: usize, columns: usize) -> String {
+    let mut lines = vec![
+        format!(
+            "{}{}{}",
+            BOLD,
+            fit(&format!("Tasks for {}", app.username), columns),
+            RESET
+        ),
+        selector(
+            "Filter",
+            app.filters.iter().map(|filter| filter.label()),
+            app.filter,
+            columns,
+        ),
+        selector(
+            "Sort",
+            Sort::ALL.iter().map(|sort| sort.label().to_string()),
+            app.sort,
+            columns,
+        ),
+        String::new(),
+    ];
+    let body_rows = rows.saturating_sub(HEADER_LINES + FOOTER_LINES);
+    match &app.mode {
+        Mode::Edit(form) => lines.extend(form_lines(form, columns)),
+        _ => lines.extend(table_lines(app, body_rows, columns)),
+    }
+
+    lines.resize(rows.saturating_sub(FOOTER_LINES), String::new());
+    lines.push(String::new());
+    let help = match app.mode {
+        Mode::Edit(_) => EDIT_HELP,
+        _ => BROWSE_HELP,
+    };
+    lines.push(if app.status.is_empty() {
+        format!("{}{}{}", DIM, fit(help, columns), RESET)
+    } else {
+        format!("{}{}{}", BOLD, fit(&app.status, columns), RESET)
+    });
+    lines.join("\r\n")
+}
+
+// every choice on one line with the current one highlighted, like a select box
+fn selector(
+    name: &str,
+    labels: impl Iterator<Item = String>,
+    current: usize,
+    columns: usize,
+) -> String {
+    let mut line = format!("{}: ", name);
+    let mut width = line.chars().count();
+    for (index, label) in labels.enumerate() {
+        let label = format!(" {} ", label);
+        width += label.chars().count() + 1;
+        if width > columns {
+            break;
+        }
+        if index == current {
+            line.push_str(&format!("{}{}{} ", REVERSE, label, RESET));
+        } else {
+            line.push_str(&label);
+            line.push(' ');
+        }
+    }
+    line
+}
+
+fn table_lines<T: Transport>(app: &App<T>, body_rows: usize, columns: usize) -> Vec<String> {
+    let tasks = app.visible_tasks();
+    if tasks.is_empty() {
+        return vec!["no tasks".to_string()];
+    }
+    let title_width = tasks
+        .iter()
+        .map(|task| task.title.chars().count())
+        .max()
+        .unwrap_or(0)
+        .max("TITLE".len());
+    let mut lines = vec![format!(
+        "{}{}{}",
+        BOLD,
+        fit(
+            &row("PRI", "DONE", "TITLE", title_width, "DESCRIPTION"),
+            columns
+        ),
+        RESET
+    )];
+
+    // scroll just far enough to keep the selected task on the screen
+    let visible_rows = body_rows.saturating_sub(1).max(1);
+    let offset = (app.selected + 1).saturating_sub(visible_rows);
+    for (index, task) in tasks.iter().enumerate().skip(offset).take(visible_rows) {
+        let text = fit(&task_row(task, title_width), columns);
+        lines.push(if index == app.selected {
+            format!("{}{}{}", REVERSE, text, RESET)
+        } else {
+            format!(
+                "{}{}{}",
+                priority_color(task.priority.as_deref()),
+                text,
+                RESET
+            )
+        });
+    }
+    lines
+}
+
+fn task_row(task: &Task, title_width: usize) -> String {
+    row(
+        task.priority.as_deref().unwrap_or_default(),
+        if task.completed_at.is_some() {
+            "[x]"
+        } else {
+            "[ ]"
+        },
+        &task.title,
+        title_width,
+        &task
+            .description
+            .clone()
+            .unwrap_or_default()
+            .replace('\n', " "),
+    )
+}
+
+fn row(priority: &str, done: &str, title: &str, title_width: usize, description: &str) -> String {
+    format!(
+        "{:3}  {:4}  {:title_width$}  {}",
+        priority,
+        done,
+        title,
+        description,
+        title_width = title_width
+    )
+}
+
+fn form_lines(form: &Form, columns: usize) -> Vec<String> {
+    let heading = match &form.task {
+        Some(task) => format!("Edit task {}", task.id),
+        None => "Add a task".to_string(),
+    };
+    let priorities: Vec<String> = PRIORITIES
+        .iter()
+        .enumerate()
+        .map(|(index, priority)| {
+            let chosen = if index == form.priority { "(*)" } else { "( )" };
+            format!("{} {}", chosen, priority)
+        })
+        .collect();
+    let completed = if form.completed { "[x]" } else { "[ ]" };
+    let fields = [
+        (Field::Title, "Title", form.title.clone()),
+        (Field::Description, "Description", form.description.clone()),
+        (Field::Priority, "Priority", priorities.join("  ")),
+        (Field::Completed, "Completed", completed.to_string()),
+    ];
+
+    let mut lines = vec![
+        format!("{}{}{}", BOLD, fit(&heading, columns), RESET),
+        String::new(),
+    ];
+    for (field, label, value) in fields {
+        // only the focused text field shows a cursor
+        let value = match field {
+            Field::Title | Field::Description if field == form.field => format!("{}_", value),
+            _ => value,
+        };
+        let line = fit(&format!("{:>12}  {}", label, value), columns);
+        lines.push(if field == form.field {
+            format!("{}{}{}", REVERSE, line, RESET)
+        } else {
+            line
+        });
+    }
+    lines
+}
+
+// the terminal version of choose_priority_color in the yew solution
+fn priority_color(priority: Option<&str>) -> &'static str {
+    match priority {
+        Some("A") => RED,
+        Some("B") => BLUE,
+        _ => "",
+    }
+}
+
+// cut to the width of the screen so a long line never wraps and pushes the rest down
+fn fit(text: &str, columns: usize) -> String {
+    text.chars().take(columns).collect()
+}
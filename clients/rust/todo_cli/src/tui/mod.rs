@@ -0,0 +1,341 @@
+mod draw;
+// raw mode through termios, there's no such thing elsewhere
+#[cfg(unix)]
+mod terminal;
+
+use crate::view::{self, Filter, Sort};
+use crate::CliResult;
+use todo_client::{
+    ClientError, CreateTaskRequest, ReqwestTransport, Task, TodoClient, Transport,
+    UpdateTaskRequest,
+};
+
+const PRIORITIES: [&str; 3] = ["A", "B", "C"];
+
+/*
+A full screen version of the yew Home page: the task table, the filter and sort
+selectors, completing tasks in place and a form to add or edit one. Every change
+goes straight to the server and the list is reloaded after it.
+*/
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Key {
+    Up,
+    Down,
+    Left,
+    Right,
+    Enter,
+    Esc,
+    Tab,
+    BackTab,
+    Backspace,
+    CtrlC,
+    Char(char),
+    Unknown,
+}
+
+pub struct App<T> {
+    client: TodoClient<T>,
+    username: String,
+    tasks: Vec<Task>,
+    filters: Vec<Filter>,
+    filter: usize,
+    sort: usize,
+    selected: usize,
+    status: String,
+    mode: Mode,
+}
+
+pub enum Mode {
+    Browse,
+    Edit(Form),
+    ConfirmDelete(Task),
+}
+
+pub struct Form {
+    // None while adding a task
+    task: Option<Task>,
+    title: String,
+    description: String,
+    priority: usize,
+    completed: bool,
+    field: Field,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Field {
+    Title,
+    Description,
+    Priority,
+    Completed,
+}
+
+const FIELDS: [Field; 4] = [
+    Field::Title,
+    Field::Description,
+    Field::Priority,
+    Field::Completed,
+];
+
+#[cfg(unix)]
+pub async fn run(client: TodoClient<ReqwestTransport>, username: String) -> CliResult {
+    // fail before taking over the screen when we can't even list the tasks
+    let mut app = App::new(client, username).await?;
+
+    let mut terminal = terminal::Terminal::enter()?;
+    loop {
+        let (rows, columns) = terminal.size();
+        terminal.draw(&app.render(rows, columns))?;
+        let key = terminal.read_key()?;
+        match app.handle(key).await {
+            Ok(true) => {}
+            Ok(false) => return Ok(()),
+            Err(ClientError::NotAuthenticated) => {
+                drop(terminal);
+                return Err(ClientError::NotAuthenticated.into());
+            }
+            Err(error) => app.status = error.to_string(),
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub async fn run(_client: TodoClient<ReqwestTransport>, _username: String) -> CliResult {
+    Err("the full screen app only works in a Unix terminal so far".into())
+}
+
+impl<T: Transport> App<T> {
+    /// The app with the user's tasks loaded, browsing them.
+    pub async fn new(client: TodoClient<T>, username: String) -> Result<App<T>, ClientError> {
+        let tasks = client.tasks().await?;
+        Ok(App {
+            client,
+            username,
+            tasks,
+            filters: Filter::all(),
+            filter: 0,
+            sort: 0,
+            selected: 0,
+            status: String::new(),
+            mode: Mode::Browse,
+        })
+    }
+
+    /// The screen for a terminal of that size.
+    pub fn render(&self, rows: usize, columns: usize) -> String {
+        draw::render(self, rows, columns)
+    }
+
+    pub fn visible_tasks(&self) -> Vec<Task> {
+        view::view(
+            &self.tasks,
+            std::slice::from_ref(&self.filters[self.filter]),
+            self.sort(),
+        )
+    }
+
+    pub fn sort(&self) -> Sort {
+        Sort::ALL[self.sort]
+    }
+
+    fn selected_task(&self) -> Option<Task> {
+        self.visible_tasks().get(self.selected).cloned()
+    }
+
+    /// Does what the key does, Ok(false) to quit.
+    pub async fn handle(&mut self, key: Key) -> Result<bool, ClientError> {
+        if key == Key::CtrlC {
+            return Ok(false);
+        }
+        match std::mem::replace(&mut self.mode, Mode::Browse) {
+            Mode::Browse => return self.browse(key).await,
+            Mode::ConfirmDelete(task) => {
+                if key == Key::Char('y') {
+                    self.client.delete_task(task.id, Some(task.version)).await?;
+                    self.refresh(&format!("deleted {}", task.title)).await?;
+                } else {
+                    self.status = String::new();
+                }
+            }
+            Mode::Edit(form) => self.edit(form, key).await?,
+        }
+        Ok(true)
+    }
+
+    async fn browse(&mut self, key: Key) -> Result<bool, ClientError> {
+        self.status = String::new();
+        let count = self.visible_tasks().len();
+        match key {
+            Key::Char('q') | Key::Esc => return Ok(false),
+            Key::Up | Key::Char('k') => self.selected = self.selected.saturating_sub(1),
+            Key::Down | Key::Char('j') => {
+                self.selected = (self.selected + 1).min(count.saturating_sub(1))
+            }
+            Key::Char('f') => {
+                self.filter = (self.filter + 1) % self.filters.len();
+                self.selected = 0;
+            }
+            Key::Char('s') => self.sort = (self.sort + 1) % Sort::ALL.len(),
+            Key::Char('r') => self.refresh("reloaded").await?,
+            Key::Char(' ') | Key::Char('x') => {
+                if let Some(task) = self.selected_task() {
+                    if task.completed_at.is_some() {
+                        self.client.uncomplete_task(task.id).await?;
+                    } else {
+                        self.client.complete_task(task.id).await?;
+                    }
+                    self.refresh("").await?;
+                }
+            }
+            Key::Char('a') => self.mode = Mode::Edit(Form::new(None)),
+            Key::Char('e') | Key::Enter => {
+                if let Some(task) = self.selected_task() {
+                    self.mode = Mode::Edit(Form::new(Some(task)));
+                }
+            }
+            Key::Char('d') => {
+                if let Some(task) = self.selected_task() {
+                    self.status = format!("delete {}? y/n", task.title);
+                    self.mode = Mode::ConfirmDelete(task);
+                }
+            }
+            _ => {}
+        }
+        Ok(true)
+    }
+
+    async fn edit(&mut self, mut form: Form, key: Key) -> Result<(), ClientError> {
+        let field = FIELDS
+            .iter()
+            .position(|field| *field == form.field)
+            .unwrap();
+        match (key, form.field) {
+            (Key::Esc, _) => return Ok(()),
+            (Key::Enter, _) => return self.save(form).await,
+            (Key::Tab | Key::Down, _) => form.field = FIELDS[(field + 1) % FIELDS.len()],
+            (Key::BackTab | Key::Up, _) => {
+                form.field = FIELDS[(field + FIELDS.len() - 1) % FIELDS.len()]
+            }
+            (Key::Left, Field::Priority) => {
+                form.priority = (form.priority + PRIORITIES.len() - 1) % PRIORITIES.len()
+            }
+            (Key::Right | Key::Char(' '), Field::Priority) => {
+                form.priority = (form.priority + 1) % PRIORITIES.len()
+            }
+            (Key::Char(' ') | Key::Left | Key::Right, Field::Completed) => {
+                form.completed = !form.completed
+            }
+            (Key::Backspace, Field::Title) => {
+                form.title.pop();
+            }
+            (Key::Backspace, Field::Description) => {
+                form.description.pop();
+            }
+            (Key::Char(c), Field::Title) => form.title.push(c),
+            (Key::Char(c), Field::Description) => form.description.push(c),
+            _ => {}
+        }
+        self.mode = Mode::Edit(form);
+        Ok(())
+    }
+
+    async fn save(&mut self, form: Form) -> Result<(), ClientError> {
+        if form.title.trim().is_empty() {
+            self.status = "a task needs a title".to_string();
+            self.mode = Mode::Edit(form);
+            return Ok(());
+        }
+        let priority = PRIORITIES[form.priority].to_string();
+        let description = Some(form.description.clone()).filter(|d| !d.is_empty());
+        let (task_id, was_completed) = match &form.task {
+            Some(task) => {
+                let patch = UpdateTaskRequest {
+                    title: Some(form.title.clone()),
+                    priority: Some(Some(priority)),
+                    description: Some(description),
+                    completed_at: None,
+                };
+                match self
+                    .client
+                    .update_task(task.id, &patch, Some(task.version))
+                    .await
+                {
+                    Ok(_) => {}
+                    Err(ClientError::Conflict(_)) => {
+                        // keep the form so nothing typed is lost, on top of the new version
+                        self.refresh(
+                            "the task was changed somewhere else, save again to overwrite it",
+                        )
+                        .await?;
+                        let latest = self
+                            .tasks
+                            .iter()
+                            .find(|latest| latest.id == task.id)
+                            .cloned();
+                        self.mode = Mode::Edit(Form {
+                            task: latest,
+                            ..form
+                        });
+                        return Ok(());
+                    }
+                    Err(error) => return Err(error),
+                }
+                (task.id, task.completed_at.is_some())
+            }
+            None => {
+                let task = self
+                    .client
+                    .create_task(&CreateTaskRequest {
+                        title: form.title.clone(),
+                        description,
+                        priority: Some(priority),
+                    })
+                    .await?;
+                (task.id, false)
+            }
+        };
+        if form.completed && !was_completed {
+            self.client.complete_task(task_id).await?;
+        } else if !form.completed && was_completed {
+            self.client.uncomplete_task(task_id).await?;
+        }
+        self.refresh(&format!("saved {}", form.title)).await
+    }
+
+    async fn refresh(&mut self, status: &str) -> Result<(), ClientError> {
+        let selected_id = self.selected_task().map(|task| task.id);
+        self.tasks = self.client.tasks().await?;
+        let visible = self.visible_tasks();
+        self.selected = selected_id
+            .and_then(|id| visible.iter().position(|task| task.id == id))
+            .unwrap_or_else(|| self.selected.min(visible.len().saturating_sub(1)));
+        self.status = status.to_string();
+        Ok(())
+    }
+}
+
+impl Form {
+    fn new(task: Option<Task>) -> Form {
+        let priority = task
+            .as_ref()
+            .and_then(|task| task.priority.as_deref())
+            .and_then(|priority| PRIORITIES.iter().position(|p| *p == priority))
+            .unwrap_or(0);
+        Form {
+            title: task
+                .as_ref()
+                .map(|task| task.title.clone())
+                .unwrap_or_default(),
+            description: task
+                .as_ref()
+                .and_then(|task| task.description.clone())
+                .unwrap_or_default(),
+            priority,
+            completed: task
+                .as_ref()
+                .is_some_and(|task| task.completed_at.is_some()),
+            field: Field::Title,
+            task,
+        }
+    }
+}
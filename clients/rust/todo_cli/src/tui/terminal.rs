@@ -0,0 +1,145 @@
+use super::Key;
+use std::io::{self, Write};
+use std::mem::MaybeUninit;
+
+// how long to wait for the rest of an escape sequence before taking Esc on its own
+const ESCAPE_TIMEOUT_MS: i32 = 25;
+
+/// The terminal in raw mode on the alternate screen, put back the way it was
+/// when dropped, panics included.
+pub struct Terminal {
+    original: libc::termios,
+    stdout: io::Stdout,
+}
+
+impl Terminal {
+    pub fn enter() -> io::Result<Terminal> {
+        let original = unsafe {
+            let mut termios = MaybeUninit::<libc::termios>::uninit();
+            if libc::tcgetattr(libc::STDIN_FILENO, termios.as_mut_ptr()) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            termios.assume_init()
+        };
+        let mut raw = original;
+        unsafe {
+            libc::cfmakeraw(&mut raw);
+            if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        let mut terminal = Terminal {
+            original,
+            stdout: io::stdout(),
+        };
+        // alternate screen, hidden cursor
+        terminal.stdout.write_all(b"\x1b[?1049h\x1b[?25l")?;
+        terminal.stdout.flush()?;
+        Ok(terminal)
+    }
+
+    /// (rows, columns)
+    pub fn size(&self) -> (usize, usize) {
+        let mut size = MaybeUninit::<libc::winsize>::zeroed();
+        let ok =
+            unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, size.as_mut_ptr()) == 0 };
+        let size = unsafe { size.assume_init() };
+        if ok && size.ws_row > 0 && size.ws_col > 0 {
+            (size.ws_row as usize, size.ws_col as usize)
+        } else {
+            (24, 80)
+        }
+    }
+
+    pub fn draw(&mut self, frame: &str) -> io::Result<()> {
+        self.stdout.write_all(b"\x1b[H\x1b[2J")?;
+        self.stdout.write_all(frame.as_bytes())?;
+        self.stdout.flush()
+    }
+
+    pub fn read_key(&mut self) -> io::Result<Key> {
+        let byte = self.read_byte()?;
+        Ok(match byte {
+            b'\r' | b'\n' => Key::Enter,
+            b'\t' => Key::Tab,
+            0x7f | 0x08 => Key::Backspace,
+            0x03 => Key::CtrlC,
+            0x1b => self.read_escape()?,
+            byte if byte < 0x20 => Key::Unknown,
+            byte => self.read_char(byte)?,
+        })
+    }
+
+    // straight from the file descriptor, io::Stdin's buffer would hide the rest
+    // of an escape sequence from byte_waiting
+    fn read_byte(&mut self) -> io::Result<u8> {
+        let mut byte = 0u8;
+        loop {
+            match unsafe { libc::read(libc::STDIN_FILENO, (&mut byte as *mut u8).cast(), 1) } {
+                1 => return Ok(byte),
+                0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+                _ => {
+                    let error = io::Error::last_os_error();
+                    if error.kind() != io::ErrorKind::Interrupted {
+                        return Err(error);
+                    }
+                }
+            }
+        }
+    }
+
+    fn byte_waiting(&self) -> bool {
+        let mut poll = libc::pollfd {
+            fd: libc::STDIN_FILENO,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        unsafe { libc::poll(&mut poll, 1, ESCAPE_TIMEOUT_MS) > 0 }
+    }
+
+    fn read_escape(&mut self) -> io::Result<Key> {
+        if !self.byte_waiting() {
+            return Ok(Key::Esc);
+        }
+        if self.read_byte()? != b'[' {
+            return Ok(Key::Unknown);
+        }
+        Ok(match self.read_byte()? {
+            b'A' => Key::Up,
+            b'B' => Key::Down,
+            b'C' => Key::Right,
+            b'D' => Key::Left,
+            b'Z' => Key::BackTab,
+            _ => Key::Unknown,
+        })
+    }
+
+    // the rest of a UTF-8 character
+    fn read_char(&mut self, first: u8) -> io::Result<Key> {
+        let length = match first {
+            0xf0..=0xff => 4,
+            0xe0..=0xef => 3,
+            0xc0..=0xdf => 2,
+            _ => 1,
+        };
+        let mut bytes = vec![first];
+        for _ in 1..length {
+            bytes.push(self.read_byte()?);
+        }
+        Ok(std::str::from_utf8(&bytes)
+            .ok()
+            .and_then(|text| text.chars().next())
+            .map(Key::Char)
+            .unwrap_or(Key::Unknown))
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let _ = self.stdout.write_all(b"\x1b[?25h\x1b[?1049l");
+        let _ = self.stdout.flush();
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original);
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use todo_client::Task;
+
+// the same choices as Store.filter_options and Store.sort_options in the yew solution
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Filter {
+    None,
+    Completed,
+    Uncompleted,
+    Priority(String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Sort {
+    Created,
+    Priority,
+    Name,
+}
+
+impl Filter {
+    pub fn all() -> Vec<Filter> {
+        vec![
+            Filter::None,
+            Filter::Completed,
+            Filter::Uncompleted,
+            Filter::Priority("A".to_string()),
+            Filter::Priority("B".to_string()),
+            Filter::Priority("C".to_string()),
+        ]
+    }
+
+    pub fn label(&self) -> String {
+        match self {
+            Filter::None => "None".to_string(),
+            Filter::Completed => "Completed".to_string(),
+            Filter::Uncompleted => "Uncompleted".to_string(),
+            Filter::Priority(priority) => format!("Priority {}", priority),
+        }
+    }
+
+    pub fn matches(&self, task: &Task) -> bool {
+        match self {
+            Filter::None => true,
+            Filter::Completed => task.completed_at.is_some(),
+            Filter::Uncompleted => task.completed_at.is_none(),
+            Filter::Priority(priority) => task.priority.as_ref() == Some(priority),
+        }
+    }
+}
+
+impl Sort {
+    pub const ALL: [Sort; 3] = [Sort::Created, Sort::Priority, Sort::Name];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Sort::Created => "Created Order",
+            Sort::Priority => "Priority",
+            Sort::Name => "Name",
+        }
+    }
+}
+
+/// The tasks every filter lets through, in the chosen order.
+pub fn view(tasks: &[Task], filters: &[Filter], sort: Sort) -> Vec<Task> {
+    let mut tasks: Vec<Task> = tasks
+        .iter()
+        .filter(|task| filters.iter().all(|filter| filter.matches(task)))
+        .cloned()
+        .collect();
+    match sort {
+        Sort::Created => tasks.sort_by_key(|task| task.id),
+        // no priority sorts with the A's, like the yew solution
+        Sort::Priority => tasks.sort_by(|a, b| {
+            let a = a.priority.as_deref().unwrap_or("A");
+            let b = b.priority.as_deref().unwrap_or("A");
+            a.cmp(b)
+        }),
+        Sort::Name => tasks.sort_by(|a, b| a.title.cmp(&b.title)),
+    }
+    tasks
+}
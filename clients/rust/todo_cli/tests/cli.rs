@@ -0,0 +1,206 @@
+// Runs the `todo` binary. The last test needs todo_server's Postgres database,
+// run it with `cargo test -p todo_cli -- --ignored`.
+
+use actix_web::{rt::System, web, App, HttpServer};
+use serde_json::Value;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+use std::sync::mpsc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use todo_client::TodoClient;
+use todo_server::database::TodoDB;
+use todo_server::events::TaskEvents;
+use todo_server::routes;
+
+// a config dir of its own for every test, so they never see a real token
+fn config_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("todo-cli-{}-{}", std::process::id(), name));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+fn todo(config_dir: &PathBuf, server: &str, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_todo"))
+        .args(args)
+        .env("TODO_CONFIG_DIR", config_dir)
+        .env("TODO_SERVER", server)
+        .env_remove("TODO_PASSWORD")
+        .output()
+        .unwrap()
+}
+
+// `todo login`, with the password where a script would put it
+fn login(config_dir: &PathBuf, server: &str, username: &str) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_todo"))
+        .args(["login", username])
+        .env("TODO_CONFIG_DIR", config_dir)
+        .env("TODO_SERVER", server)
+        .env("TODO_PASSWORD", "myfancypass")
+        .output()
+        .unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).to_string()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).to_string()
+}
+
+// nothing listens here
+const NO_SERVER: &str = "http://127.0.0.1:9/api/v1";
+
+#[test]
+fn help_lists_the_commands() {
+    let dir = config_dir("help");
+    let output = todo(&dir, NO_SERVER, &["--help"]);
+    assert!(output.status.success());
+    for command in [
+        "login", "logout", "list", "add", "edit", "done", "undo", "rm", "tui",
+    ] {
+        assert!(
+            stdout(&output).contains(command),
+            "{} missing from help",
+            command
+        );
+    }
+}
+
+#[test]
+fn bad_arguments_exit_with_2() {
+    let dir = config_dir("bad-arguments");
+    for args in [
+        vec!["frobnicate"],
+        vec!["done"],
+        vec!["done", "eight"],
+        vec!["add", "title", "--priority", "Z"],
+        vec!["list", "--completed", "--uncompleted"],
+        vec!["list", "--sort", "colour"],
+        vec!["rm", "8", "9"],
+        vec!["login", "woodroww", "--password", "myfancypass"],
+    ] {
+        let output = todo(&dir, NO_SERVER, &args);
+        assert_eq!(output.status.code(), Some(2), "{:?}", args);
+        assert!(stderr(&output).contains("usage:"), "{:?}", args);
+    }
+}
+
+#[test]
+fn commands_need_a_login() {
+    let dir = config_dir("no-login");
+    let output = todo(&dir, NO_SERVER, &["list"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).contains("todo login"));
+}
+
+#[test]
+fn unreachable_servers_are_reported() {
+    let dir = config_dir("no-server");
+    let output = login(&dir, NO_SERVER, "woodroww");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).contains("could not reach the server"));
+}
+
+fn start_server() -> String {
+    if std::env::var("JWT_SECRET").is_err() {
+        std::env::set_var("JWT_SECRET", "keyboardcat");
+    }
+    let (port_sender, port) = mpsc::channel();
+    std::thread::spawn(move || {
+        System::new().block_on(async move {
+            let db = web::Data::new(TodoDB::new());
+            let task_events = web::Data::new(TaskEvents::new());
+            let server = HttpServer::new(move || {
+                App::new()
+                    .app_data(db.clone())
+                    .app_data(task_events.clone())
+                    .configure(routes::configure)
+            })
+            .workers(1)
+            .bind(("127.0.0.1", 0))
+            .unwrap();
+            port_sender.send(server.addrs()[0].port()).unwrap();
+            server.run().await
+        })
+    });
+    format!("http://127.0.0.1:{}/api/v1", port.recv().unwrap())
+}
+
+#[test]
+#[ignore = "needs todo_server's Postgres database"]
+fn a_whole_session() {
+    let server = start_server();
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let username = format!("cli-{}", nanos);
+    System::new().block_on(async {
+        let mut client = TodoClient::new(&server);
+        client
+            .create_account(&username, "myfancypass")
+            .await
+            .unwrap();
+    });
+
+    let dir = config_dir("session");
+    let todo = |args: &[&str]| {
+        let output = todo(&dir, &server, args);
+        assert!(output.status.success(), "{:?}: {}", args, stderr(&output));
+        output
+    };
+    let json = |args: &[&str]| -> Value {
+        let mut args = args.to_vec();
+        args.insert(0, "--json");
+        serde_json::from_slice(&todo(&args).stdout).unwrap()
+    };
+
+    let output = login(&dir, &server, &username);
+    assert!(output.status.success(), "login: {}", stderr(&output));
+    let config = std::fs::read_to_string(dir.join("config.json")).unwrap();
+    assert!(config.contains(&username));
+    // the token is as good as the password
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(dir.join("config.json"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    let task = json(&[
+        "add",
+        "from the terminal",
+        "--priority",
+        "c",
+        "--description",
+        "typed",
+    ]);
+    let id = task["id"].as_i64().unwrap().to_string();
+    assert_eq!(task["priority"], "C");
+    assert!(stdout(&todo(&["list"])).contains("from the terminal"));
+
+    json(&["done", &id]);
+    let completed = json(&["list", "--completed", "--priority", "C"]);
+    assert_eq!(completed.as_array().unwrap().len(), 1);
+    assert_eq!(completed[0]["id"].as_i64().unwrap().to_string(), id);
+    json(&["undo", &id]);
+    assert!(json(&["list", "--completed"])
+        .as_array()
+        .unwrap()
+        .is_empty());
+
+    let edited = json(&["edit", &id, "--title", "edited in the terminal"]);
+    assert_eq!(edited["title"], "edited in the terminal");
+    assert_eq!(edited["description"], "typed");
+
+    todo(&["rm", &id]);
+    assert!(!stdout(&todo(&["list"])).contains("edited in the terminal"));
+
+    todo(&["logout"]);
+    let output = self::todo(&dir, &server, &["list"]);
+    assert_eq!(output.status.code(), Some(1));
+}
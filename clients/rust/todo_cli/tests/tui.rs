@@ -0,0 +1,160 @@
+// The full screen app driven with keys, against a transport that answers from
+// a script instead of a server, and without a terminal.
+
+use async_trait::async_trait;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use todo_cli::tui::{App, Key};
+use todo_client::transport::{Method, Request, Response};
+use todo_client::{TodoClient, Transport};
+
+#[derive(Clone, Default)]
+struct ScriptedTransport {
+    sent: Arc<Mutex<Vec<Request>>>,
+    answers: Arc<Mutex<VecDeque<Response>>>,
+}
+
+impl ScriptedTransport {
+    fn answer(&self, status: u16, etag: Option<&str>, body: &str) {
+        self.answers.lock().unwrap().push_back(Response {
+            status,
+            etag: etag.map(str::to_string),
+            body: body.to_string(),
+        });
+    }
+
+    fn sent(&self) -> Vec<Request> {
+        self.sent.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl Transport for ScriptedTransport {
+    async fn send(&self, request: Request) -> Result<Response, String> {
+        self.sent.lock().unwrap().push(request);
+        Ok(self.answers.lock().unwrap().pop_front().unwrap())
+    }
+}
+
+const TASKS: &str = r#"{ "data": [
+    { "id": 1, "priority": "A", "title": "Milk", "completed_at": null,
+      "description": null, "version": 4 },
+    { "id": 2, "priority": "B", "title": "Eggs", "completed_at": "2022-05-11T18:45:16",
+      "description": "a dozen", "version": 1 } ] }"#;
+
+async fn app() -> (App<ScriptedTransport>, ScriptedTransport) {
+    let transport = ScriptedTransport::default();
+    transport.answer(200, None, TASKS);
+    let client = TodoClient::with_transport("http://localhost:3010/api/v1", transport.clone())
+        .with_token("abc");
+    let app = App::new(client, "woodroww".to_string()).await.unwrap();
+    (app, transport)
+}
+
+async fn press(app: &mut App<ScriptedTransport>, keys: &[Key]) {
+    for key in keys {
+        assert!(app.handle(*key).await.unwrap(), "{:?} quit", key);
+    }
+}
+
+fn typed(text: &str) -> Vec<Key> {
+    text.chars().map(Key::Char).collect()
+}
+
+#[tokio::test]
+async fn the_filter_key_goes_through_the_filters() {
+    let (mut app, _) = app().await;
+    let screen = app.render(24, 80);
+    assert!(screen.contains("Tasks for woodroww"));
+    assert!(screen.contains("Milk") && screen.contains("Eggs"));
+
+    // None, then Completed
+    press(&mut app, &[Key::Char('f')]).await;
+    let screen = app.render(24, 80);
+    assert!(!screen.contains("Milk") && screen.contains("Eggs"));
+    // then Uncompleted
+    press(&mut app, &[Key::Char('f')]).await;
+    assert_eq!(app.visible_tasks().len(), 1);
+    assert_eq!(app.visible_tasks()[0].title, "Milk");
+}
+
+#[tokio::test]
+async fn space_completes_the_selected_task() {
+    let (mut app, transport) = app().await;
+    transport.answer(200, Some("\"2\""), "OK you un-completed task 2");
+    transport.answer(200, None, TASKS);
+    press(&mut app, &[Key::Down, Key::Char(' ')]).await;
+    let sent = transport.sent();
+    assert_eq!(sent[1].method, Method::Put);
+    assert_eq!(
+        sent[1].url,
+        "http://localhost:3010/api/v1/tasks/2/uncompleted"
+    );
+    // and the list is loaded again
+    assert_eq!(sent[2].method, Method::Get);
+}
+
+#[tokio::test]
+async fn a_task_is_added_with_the_form() {
+    let (mut app, transport) = app().await;
+    press(&mut app, &[Key::Char('a')]).await;
+    press(&mut app, &typed("Bread")).await;
+    press(&mut app, &[Key::Tab]).await;
+    press(&mut app, &typed("rye")).await;
+    // Title, Description, Priority: from A to B
+    press(&mut app, &[Key::Tab, Key::Right]).await;
+    assert!(app.render(24, 80).contains("Bread"));
+
+    transport.answer(
+        200,
+        Some("\"1\""),
+        r#"{ "data": { "id": 3, "priority": "B", "title": "Bread", "completed_at": null,
+            "description": "rye", "version": 1 } }"#,
+    );
+    transport.answer(200, None, TASKS);
+    press(&mut app, &[Key::Enter]).await;
+    let create = transport.sent()[1].clone();
+    assert_eq!(create.method, Method::Post);
+    assert_eq!(
+        serde_json::from_str::<serde_json::Value>(&create.body.unwrap()).unwrap(),
+        serde_json::json!({ "title": "Bread", "description": "rye", "priority": "B" })
+    );
+    assert!(app.render(24, 80).contains("saved Bread"));
+}
+
+#[tokio::test]
+async fn an_empty_title_isnt_sent() {
+    let (mut app, transport) = app().await;
+    press(&mut app, &[Key::Char('a'), Key::Enter]).await;
+    assert!(app.render(24, 80).contains("a task needs a title"));
+    // Esc leaves the form without saving
+    press(&mut app, &[Key::Esc]).await;
+    assert_eq!(transport.sent().len(), 1);
+}
+
+#[tokio::test]
+async fn deleting_asks_first() {
+    let (mut app, transport) = app().await;
+    press(&mut app, &[Key::Char('d')]).await;
+    assert!(app.render(24, 80).contains("delete Milk? y/n"));
+    press(&mut app, &[Key::Char('n')]).await;
+    assert_eq!(transport.sent().len(), 1);
+
+    transport.answer(200, None, "deleted task");
+    transport.answer(200, None, TASKS);
+    press(&mut app, &[Key::Char('d'), Key::Char('y')]).await;
+    let delete = transport.sent()[1].clone();
+    assert_eq!(delete.method, Method::Delete);
+    assert_eq!(delete.url, "http://localhost:3010/api/v1/tasks/1");
+    assert!(delete.headers.contains(&("If-Match", "\"4\"".to_string())));
+}
+
+#[tokio::test]
+async fn q_and_ctrl_c_quit() {
+    let (mut app, _) = app().await;
+    assert!(!app.handle(Key::Char('q')).await.unwrap());
+    // in the form q is part of the title, Ctrl-C still quits
+    press(&mut app, &[Key::Char('a')]).await;
+    assert!(app.handle(Key::Char('q')).await.unwrap());
+    assert!(!app.handle(Key::CtrlC).await.unwrap());
+}
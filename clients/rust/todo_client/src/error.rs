@@ -0,0 +1,20 @@
+use thiserror::Error;
+use todo_api::Task;
+
+#[derive(Debug, Error)]
+pub enum ClientError {
+    #[error("could not reach the server: {0}")]
+    Network(String),
+    #[error("not logged in")]
+    NotAuthenticated,
+    // a PATCH or DELETE with an If-Match the task has moved past, with the task as it is now
+    #[error("the task was changed somewhere else")]
+    Conflict(Option<Box<Task>>),
+    // the password was right, finish with TodoClient::login_two_factor and this challenge
+    #[error("a code from the authenticator app is needed")]
+    TwoFactorRequired(String),
+    #[error("the server answered {status}: {message}")]
+    Server { status: u16, message: String },
+    #[error("could not read the server's answer: {0}")]
+    Decode(String),
+}
@@ -0,0 +1,430 @@
+// An async client for todo_server's /api/v1, for anything that isn't the yew
+// solution's own hand-written calls: scripts, the CLI, tests.
+//
+//     let mut client = TodoClient::new("http://localhost:3010/api/v1");
+//     client.login("woodroww", "myfancypass").await?;
+//     let tasks = client.tasks().await?;
+
+pub mod error;
+pub mod transport;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use todo_api::{
+    ApiTokenListResponse, AuthResponse, ChangePasswordRequest, DisableTwoFactorRequest,
+    ForgotPasswordRequest, LoginRequest, NewApiTokenResponse, OidcAuthorizationResponse,
+    OidcCallbackRequest,
+    RecoveryCodesResponse, ResetPasswordRequest, TaskId, TaskListResponse, TaskResponse,
+    TwoFactorChallengeResponse, TwoFactorCodeRequest, TwoFactorLoginRequest, TwoFactorSetup,
+    TwoFactorSetupResponse, TwoFactorStatus, TwoFactorStatusResponse, UpdateUserRequest,
+    UserInfo,
+};
+use transport::{Method, Request, Response};
+
+pub use error::ClientError;
+pub use todo_api::{
+    ApiToken, ApiTokenId, CreateApiTokenRequest, CreateTaskRequest, NewApiToken, Task, TaskEvent,
+    TokenScope, UpdateTaskRequest,
+};
+#[cfg(feature = "reqwest")]
+pub use transport::ReqwestTransport;
+pub use transport::Transport;
+
+pub type ClientResult<T> = Result<T, ClientError>;
+
+const AUTH_HEADER: &str = "x-auth-token";
+
+pub struct TodoClient<T> {
+    base_url: String,
+    transport: T,
+    token: Option<String>,
+}
+
+#[cfg(feature = "reqwest")]
+impl TodoClient<ReqwestTransport> {
+    /// `base_url` includes the `/api/v1` prefix.
+    pub fn new(base_url: &str) -> Self {
+        Self::with_transport(base_url, ReqwestTransport::new())
+    }
+}
+
+impl<T: Transport> TodoClient<T> {
+    pub fn with_transport(base_url: &str, transport: T) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            transport,
+            token: None,
+        }
+    }
+
+    /// Use a token saved from an earlier login.
+    pub fn with_token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    pub fn token(&self) -> Option<&str> {
+        self.token.as_deref()
+    }
+
+    pub fn set_token(&mut self, token: Option<String>) {
+        self.token = token;
+    }
+
+    /// Creates the user and keeps their token for the calls after it.
+    pub async fn create_account(
+        &mut self,
+        username: &str,
+        password: &str,
+    ) -> ClientResult<UserInfo> {
+        let body = credentials(username, password);
+        let response = self
+            .send(Method::Post, "/users", Some(&body), vec![])
+            .await?;
+        let user = decode::<AuthResponse>(&response)?.data;
+        self.token = Some(user.token.clone());
+        Ok(user)
+    }
+
+    /// Logs in and keeps the token for the calls after it. Users with two-factor
+    /// authentication on get `ClientError::TwoFactorRequired` instead.
+    pub async fn login(&mut self, username: &str, password: &str) -> ClientResult<UserInfo> {
+        let body = credentials(username, password);
+        let response = self
+            .send(Method::Post, "/users/login", Some(&body), vec![])
+            .await?;
+        if response.status == 202 {
+            let challenge = decode::<TwoFactorChallengeResponse>(&response)?.data;
+            return Err(ClientError::TwoFactorRequired(challenge.challenge));
+        }
+        let user = decode::<AuthResponse>(&response)?.data;
+        self.token = Some(user.token.clone());
+        Ok(user)
+    }
+
+    /// The second half of a login, `code` is one from the authenticator app or
+    /// a recovery code.
+    pub async fn login_two_factor(&mut self, challenge: &str, code: &str) -> ClientResult<UserInfo> {
+        let body = TwoFactorLoginRequest {
+            challenge: challenge.to_string(),
+            code: code.to_string(),
+        };
+        let response = self
+            .send(Method::Post, "/users/login/two-factor", Some(&body), vec![])
+            .await?;
+        let user = decode::<AuthResponse>(&response)?.data;
+        self.token = Some(user.token.clone());
+        Ok(user)
+    }
+
+    /// Where to send the browser to log in with SSO. With a token the SSO
+    /// account gets linked to the logged in user instead.
+    pub async fn oidc_authorize(&self) -> ClientResult<String> {
+        let headers = match &self.token {
+            Some(token) => vec![(AUTH_HEADER, token.clone())],
+            None => vec![],
+        };
+        let response = self
+            .send(Method::Post, "/auth/oidc/authorize", None::<&()>, headers)
+            .await?;
+        Ok(decode::<OidcAuthorizationResponse>(&response)?
+            .data
+            .authorization_url)
+    }
+
+    /// Finishes an SSO login with the `code` and `state` the identity provider
+    /// redirected back with, and keeps the token.
+    pub async fn oidc_callback(&mut self, code: &str, state: &str) -> ClientResult<UserInfo> {
+        let body = OidcCallbackRequest {
+            code: code.to_string(),
+            state: state.to_string(),
+        };
+        let response = self
+            .send(Method::Post, "/auth/oidc/callback", Some(&body), vec![])
+            .await?;
+        let user = decode::<AuthResponse>(&response)?.data;
+        self.token = Some(user.token.clone());
+        Ok(user)
+    }
+
+    pub async fn logout(&mut self) -> ClientResult<()> {
+        self.authed(Method::Post, "/users/logout", None::<&()>, vec![])
+            .await?;
+        self.token = None;
+        Ok(())
+    }
+
+    /// A taken username comes back as a 409 `ClientError::Server`.
+    pub async fn change_username(&self, username: &str) -> ClientResult<UserInfo> {
+        let body = UpdateUserRequest {
+            username: Some(username.to_string()),
+            ..UpdateUserRequest::default()
+        };
+        let response = self
+            .authed(Method::Patch, "/users/me", Some(&body), vec![])
+            .await?;
+        Ok(decode::<AuthResponse>(&response)?.data)
+    }
+
+    /// Where password reset links go, "" removes it.
+    pub async fn change_email(&self, email: &str) -> ClientResult<UserInfo> {
+        let body = UpdateUserRequest {
+            email: Some(email.to_string()),
+            ..UpdateUserRequest::default()
+        };
+        let response = self
+            .authed(Method::Patch, "/users/me", Some(&body), vec![])
+            .await?;
+        Ok(decode::<AuthResponse>(&response)?.data)
+    }
+
+    /// Succeeds whether or not the email belongs to an account.
+    pub async fn forgot_password(&self, email: &str) -> ClientResult<()> {
+        let body = ForgotPasswordRequest {
+            email: email.to_string(),
+        };
+        self.send(Method::Post, "/users/forgot-password", Some(&body), vec![])
+            .await?;
+        Ok(())
+    }
+
+    /// `token` comes from the link in the reset email.
+    pub async fn reset_password(&self, token: &str, new_password: &str) -> ClientResult<()> {
+        let body = ResetPasswordRequest {
+            token: token.to_string(),
+            new_password: new_password.to_string(),
+        };
+        self.send(Method::Post, "/users/reset-password", Some(&body), vec![])
+            .await?;
+        Ok(())
+    }
+
+    /// Keeps the new token the server hands out, the old one stops working.
+    pub async fn change_password(
+        &mut self,
+        current_password: &str,
+        new_password: &str,
+    ) -> ClientResult<UserInfo> {
+        let body = ChangePasswordRequest {
+            current_password: current_password.to_string(),
+            new_password: new_password.to_string(),
+        };
+        let response = self
+            .authed(Method::Post, "/users/me/password", Some(&body), vec![])
+            .await?;
+        let user = decode::<AuthResponse>(&response)?.data;
+        self.token = Some(user.token.clone());
+        Ok(user)
+    }
+
+    pub async fn two_factor_status(&self) -> ClientResult<TwoFactorStatus> {
+        let response = self
+            .authed(Method::Get, "/users/me/two-factor", None::<&()>, vec![])
+            .await?;
+        Ok(decode::<TwoFactorStatusResponse>(&response)?.data)
+    }
+
+    /// A new secret for the authenticator app, on once `confirm_two_factor` is.
+    pub async fn start_two_factor(&self) -> ClientResult<TwoFactorSetup> {
+        let response = self
+            .authed(Method::Post, "/users/me/two-factor", None::<&()>, vec![])
+            .await?;
+        Ok(decode::<TwoFactorSetupResponse>(&response)?.data)
+    }
+
+    /// The recovery codes, the server never shows them again.
+    pub async fn confirm_two_factor(&self, code: &str) -> ClientResult<Vec<String>> {
+        let body = TwoFactorCodeRequest {
+            code: code.to_string(),
+        };
+        let response = self
+            .authed(Method::Post, "/users/me/two-factor/confirm", Some(&body), vec![])
+            .await?;
+        Ok(decode::<RecoveryCodesResponse>(&response)?.data.recovery_codes)
+    }
+
+    pub async fn disable_two_factor(&self, password: &str) -> ClientResult<()> {
+        let body = DisableTwoFactorRequest {
+            password: password.to_string(),
+        };
+        self.authed(Method::Delete, "/users/me/two-factor", Some(&body), vec![])
+            .await?;
+        Ok(())
+    }
+
+    pub async fn api_tokens(&self) -> ClientResult<Vec<ApiToken>> {
+        let response = self
+            .authed(Method::Get, "/users/me/tokens", None::<&()>, vec![])
+            .await?;
+        Ok(decode::<ApiTokenListResponse>(&response)?.data)
+    }
+
+    /// Needs the session token. The new token is only in the answer, hand it
+    /// to `with_token` in the script that uses it.
+    pub async fn create_api_token(
+        &self,
+        request: &CreateApiTokenRequest,
+    ) -> ClientResult<NewApiToken> {
+        let response = self
+            .authed(Method::Post, "/users/me/tokens", Some(request), vec![])
+            .await?;
+        Ok(decode::<NewApiTokenResponse>(&response)?.data)
+    }
+
+    pub async fn revoke_api_token(&self, id: ApiTokenId) -> ClientResult<()> {
+        let path = format!("/users/me/tokens/{}", id);
+        self.authed(Method::Delete, &path, None::<&()>, vec![])
+            .await?;
+        Ok(())
+    }
+
+    pub async fn delete_account(&mut self) -> ClientResult<()> {
+        self.authed(Method::Delete, "/users/me", None::<&()>, vec![])
+            .await?;
+        self.token = None;
+        Ok(())
+    }
+
+    pub async fn tasks(&self) -> ClientResult<Vec<Task>> {
+        let response = self
+            .authed(Method::Get, "/tasks", None::<&()>, vec![])
+            .await?;
+        Ok(decode::<TaskListResponse>(&response)?.data)
+    }
+
+    pub async fn task(&self, task_id: TaskId) -> ClientResult<Task> {
+        let path = format!("/tasks/{}", task_id);
+        let response = self.authed(Method::Get, &path, None::<&()>, vec![]).await?;
+        decode_task(&response)
+    }
+
+    pub async fn create_task(&self, task: &CreateTaskRequest) -> ClientResult<Task> {
+        let response = self
+            .authed(Method::Post, "/tasks", Some(task), vec![])
+            .await?;
+        decode_task(&response)
+    }
+
+    /// Sends `If-Match` when `version` is given, a task changed since then comes
+    /// back as `ClientError::Conflict`.
+    pub async fn update_task(
+        &self,
+        task_id: TaskId,
+        patch: &UpdateTaskRequest,
+        version: Option<i32>,
+    ) -> ClientResult<Task> {
+        let path = format!("/tasks/{}", task_id);
+        let response = self
+            .authed(Method::Patch, &path, Some(patch), if_match(version))
+            .await?;
+        decode_task(&response)
+    }
+
+    pub async fn delete_task(&self, task_id: TaskId, version: Option<i32>) -> ClientResult<()> {
+        let path = format!("/tasks/{}", task_id);
+        self.authed(Method::Delete, &path, None::<&()>, if_match(version))
+            .await?;
+        Ok(())
+    }
+
+    /// Returns the task's new version.
+    pub async fn complete_task(&self, task_id: TaskId) -> ClientResult<Option<i32>> {
+        let path = format!("/tasks/{}/completed", task_id);
+        let response = self.authed(Method::Put, &path, None::<&()>, vec![]).await?;
+        Ok(version_from_etag(&response))
+    }
+
+    /// Returns the task's new version.
+    pub async fn uncomplete_task(&self, task_id: TaskId) -> ClientResult<Option<i32>> {
+        let path = format!("/tasks/{}/uncompleted", task_id);
+        let response = self.authed(Method::Put, &path, None::<&()>, vec![]).await?;
+        Ok(version_from_etag(&response))
+    }
+
+    async fn authed<B: Serialize>(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<&B>,
+        mut headers: Vec<(&'static str, String)>,
+    ) -> ClientResult<Response> {
+        let token = self.token.clone().ok_or(ClientError::NotAuthenticated)?;
+        headers.push((AUTH_HEADER, token));
+        self.send(method, path, body, headers).await
+    }
+
+    async fn send<B: Serialize>(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<&B>,
+        mut headers: Vec<(&'static str, String)>,
+    ) -> ClientResult<Response> {
+        let body = match body {
+            Some(body) => {
+                headers.push(("content-type", "application/json".to_string()));
+                Some(
+                    serde_json::to_string(body)
+                        .map_err(|error| ClientError::Decode(error.to_string()))?,
+                )
+            }
+            None => None,
+        };
+        let request = Request {
+            method,
+            url: format!("{}{}", self.base_url, path),
+            headers,
+            body,
+        };
+        let response = self
+            .transport
+            .send(request)
+            .await
+            .map_err(ClientError::Network)?;
+        match response.status {
+            200..=299 => Ok(response),
+            401 => Err(ClientError::NotAuthenticated),
+            412 => Err(ClientError::Conflict(
+                decode_task(&response).ok().map(Box::new),
+            )),
+            status => Err(ClientError::Server {
+                status,
+                message: response.body,
+            }),
+        }
+    }
+}
+
+fn credentials(username: &str, password: &str) -> LoginRequest {
+    LoginRequest {
+        username: username.to_string(),
+        password: password.to_string(),
+    }
+}
+
+fn if_match(version: Option<i32>) -> Vec<(&'static str, String)> {
+    version
+        .map(|version| ("If-Match", format!("\"{}\"", version)))
+        .into_iter()
+        .collect()
+}
+
+fn decode<R: DeserializeOwned>(response: &Response) -> ClientResult<R> {
+    serde_json::from_str(&response.body).map_err(|error| ClientError::Decode(error.to_string()))
+}
+
+// older servers only send the version in the ETag
+fn decode_task(response: &Response) -> ClientResult<Task> {
+    let mut task = decode::<TaskResponse>(response)?.data;
+    if task.version == 0 {
+        task.version = version_from_etag(response).unwrap_or_default();
+    }
+    Ok(task)
+}
+
+fn version_from_etag(response: &Response) -> Option<i32> {
+    response
+        .etag
+        .as_deref()
+        .and_then(|etag| etag.trim_start_matches("W/").trim_matches('"').parse().ok())
+}
@@ -0,0 +1,291 @@
+// TodoClient against a transport that answers from a script, no server needed.
+
+use async_trait::async_trait;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use todo_client::transport::{Method, Request, Response};
+use todo_client::*;
+
+#[derive(Clone, Default)]
+struct ScriptedTransport {
+    sent: Arc<Mutex<Vec<Request>>>,
+    answers: Arc<Mutex<VecDeque<Result<Response, String>>>>,
+}
+
+impl ScriptedTransport {
+    fn answer(&self, status: u16, etag: Option<&str>, body: &str) {
+        self.answers.lock().unwrap().push_back(Ok(Response {
+            status,
+            etag: etag.map(str::to_string),
+            body: body.to_string(),
+        }));
+    }
+
+    fn fail(&self) {
+        self.answers
+            .lock()
+            .unwrap()
+            .push_back(Err("connection refused".to_string()));
+    }
+
+    fn last(&self) -> Request {
+        self.sent.lock().unwrap().last().cloned().unwrap()
+    }
+
+    fn header(&self, name: &str) -> Option<String> {
+        self.last()
+            .headers
+            .into_iter()
+            .find(|(header, _)| *header == name)
+            .map(|(_, value)| value)
+    }
+}
+
+#[async_trait]
+impl Transport for ScriptedTransport {
+    async fn send(&self, request: Request) -> Result<Response, String> {
+        self.sent.lock().unwrap().push(request);
+        self.answers.lock().unwrap().pop_front().unwrap()
+    }
+}
+
+const TASK: &str = r#"{ "data": { "id": 8, "priority": "A", "title": "Curl is fun",
+    "completed_at": null, "description": "typing", "version": 3 } }"#;
+
+fn client() -> (TodoClient<ScriptedTransport>, ScriptedTransport) {
+    let transport = ScriptedTransport::default();
+    let client = TodoClient::with_transport("http://localhost:3010/api/v1/", transport.clone());
+    (client, transport)
+}
+
+#[tokio::test]
+async fn login_keeps_the_token() {
+    let (mut client, transport) = client();
+    transport.answer(
+        200,
+        None,
+        r#"{ "data": { "id": 3, "username": "woodroww", "token": "abc" } }"#,
+    );
+    let user = client.login("woodroww", "myfancypass").await.unwrap();
+    assert_eq!(user.username, "woodroww");
+    assert_eq!(client.token(), Some("abc"));
+
+    let login = transport.last();
+    assert_eq!(login.method, Method::Post);
+    assert_eq!(login.url, "http://localhost:3010/api/v1/users/login");
+    assert_eq!(
+        serde_json::from_str::<serde_json::Value>(&login.body.unwrap()).unwrap(),
+        serde_json::json!({ "username": "woodroww", "password": "myfancypass" })
+    );
+
+    transport.answer(200, None, r#"{ "data": [] }"#);
+    assert!(client.tasks().await.unwrap().is_empty());
+    assert_eq!(transport.header("x-auth-token").as_deref(), Some("abc"));
+
+    transport.answer(200, None, r#"{ "message": "user logged out" }"#);
+    client.logout().await.unwrap();
+    assert_eq!(client.token(), None);
+}
+
+#[tokio::test]
+async fn two_factor_logins_take_a_code() {
+    let (mut client, transport) = client();
+    transport.answer(202, None, r#"{ "data": { "challenge": "c1" } }"#);
+    match client.login("woodroww", "myfancypass").await {
+        Err(ClientError::TwoFactorRequired(challenge)) => assert_eq!(challenge, "c1"),
+        other => panic!("expected a challenge, got {:?}", other),
+    }
+    assert_eq!(client.token(), None);
+
+    transport.answer(
+        200,
+        None,
+        r#"{ "data": { "id": 3, "username": "woodroww", "token": "abc" } }"#,
+    );
+    client.login_two_factor("c1", "123456").await.unwrap();
+    assert_eq!(client.token(), Some("abc"));
+    let second = transport.last();
+    assert_eq!(
+        second.url,
+        "http://localhost:3010/api/v1/users/login/two-factor"
+    );
+    assert_eq!(
+        serde_json::from_str::<serde_json::Value>(&second.body.unwrap()).unwrap(),
+        serde_json::json!({ "challenge": "c1", "code": "123456" })
+    );
+}
+
+#[tokio::test]
+async fn sso_logins_keep_the_token() {
+    let (mut client, transport) = client();
+    transport.answer(
+        200,
+        None,
+        r#"{ "data": { "authorization_url": "https://sso.example.com/authorize?state=s1" } }"#,
+    );
+    let url = client.oidc_authorize().await.unwrap();
+    assert_eq!(url, "https://sso.example.com/authorize?state=s1");
+    assert_eq!(transport.header("x-auth-token"), None);
+
+    transport.answer(
+        200,
+        None,
+        r#"{ "data": { "id": 3, "username": "woodroww", "token": "abc" } }"#,
+    );
+    client.oidc_callback("c1", "s1").await.unwrap();
+    assert_eq!(client.token(), Some("abc"));
+    let callback = transport.last();
+    assert_eq!(callback.url, "http://localhost:3010/api/v1/auth/oidc/callback");
+    assert_eq!(
+        serde_json::from_str::<serde_json::Value>(&callback.body.unwrap()).unwrap(),
+        serde_json::json!({ "code": "c1", "state": "s1" })
+    );
+
+    // logged in it links instead
+    transport.answer(
+        200,
+        None,
+        r#"{ "data": { "authorization_url": "https://sso.example.com/authorize?state=s2" } }"#,
+    );
+    client.oidc_authorize().await.unwrap();
+    assert_eq!(transport.header("x-auth-token").as_deref(), Some("abc"));
+}
+
+#[tokio::test]
+async fn api_tokens_are_shown_once() {
+    let (client, transport) = client();
+    let client = client.with_token("abc");
+    transport.answer(
+        200,
+        None,
+        r#"{ "data": { "token": "todo_pat_x1", "api_token": { "id": 4, "name": "backup",
+            "scope": "read_write", "created_at": "2022-05-11T18:45:16.214145",
+            "expires_at": null, "last_used_at": null } } }"#,
+    );
+    let request = CreateApiTokenRequest {
+        name: "backup".to_string(),
+        scope: TokenScope::ReadWrite,
+        expires_in_days: None,
+    };
+    let created = client.create_api_token(&request).await.unwrap();
+    assert_eq!(created.token, "todo_pat_x1");
+    assert_eq!(created.api_token.scope, TokenScope::ReadWrite);
+    // the session token keeps being the one this client uses
+    assert_eq!(client.token(), Some("abc"));
+    assert_eq!(transport.last().url, "http://localhost:3010/api/v1/users/me/tokens");
+
+    transport.answer(200, None, r#"{ "message": "token revoked" }"#);
+    client.revoke_api_token(4).await.unwrap();
+    let revoke = transport.last();
+    assert_eq!(revoke.method, Method::Delete);
+    assert_eq!(revoke.url, "http://localhost:3010/api/v1/users/me/tokens/4");
+}
+
+#[tokio::test]
+async fn changing_the_password_swaps_the_token() {
+    let (client, transport) = client();
+    let mut client = client.with_token("old");
+    transport.answer(
+        200,
+        None,
+        r#"{ "data": { "id": 3, "username": "woodroww", "token": "new" } }"#,
+    );
+    client
+        .change_password("myfancypass", "myfancierpass")
+        .await
+        .unwrap();
+    assert_eq!(client.token(), Some("new"));
+    let change = transport.last();
+    assert_eq!(change.url, "http://localhost:3010/api/v1/users/me/password");
+    assert_eq!(transport.header("x-auth-token").as_deref(), Some("old"));
+
+    transport.answer(409, None, "woodroww2 is taken");
+    assert!(matches!(
+        client.change_username("woodroww2").await,
+        Err(ClientError::Server { status: 409, .. })
+    ));
+    assert_eq!(transport.last().method, Method::Patch);
+
+    transport.answer(200, None, r#"{ "message": "user deleted" }"#);
+    client.delete_account().await.unwrap();
+    assert_eq!(client.token(), None);
+}
+
+#[tokio::test]
+async fn calls_without_a_token_never_leave() {
+    let (client, transport) = client();
+    assert!(matches!(
+        client.tasks().await,
+        Err(ClientError::NotAuthenticated)
+    ));
+    assert!(transport.sent.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn tasks_come_back_typed() {
+    let (client, transport) = client();
+    let client = client.with_token("abc");
+    transport.answer(200, Some("\"3\""), TASK);
+    let task = client
+        .create_task(&CreateTaskRequest {
+            title: "Curl is fun".to_string(),
+            description: Some("typing".to_string()),
+            priority: Some("A".to_string()),
+        })
+        .await
+        .unwrap();
+    assert_eq!(task.id, 8);
+    assert_eq!(task.version, 3);
+
+    transport.answer(200, Some("\"4\""), "OK you completed task 8");
+    assert_eq!(client.complete_task(8).await.unwrap(), Some(4));
+    assert_eq!(transport.last().method, Method::Put);
+    assert!(transport.last().url.ends_with("/tasks/8/completed"));
+}
+
+#[tokio::test]
+async fn stale_versions_are_conflicts() {
+    let (client, transport) = client();
+    let client = client.with_token("abc");
+    transport.answer(412, Some("\"3\""), TASK);
+    let patch = UpdateTaskRequest {
+        title: Some("new title".to_string()),
+        ..Default::default()
+    };
+    match client.update_task(8, &patch, Some(2)).await {
+        Err(ClientError::Conflict(Some(latest))) => assert_eq!(latest.version, 3),
+        other => panic!("expected a conflict, got {:?}", other),
+    }
+    assert_eq!(transport.header("If-Match").as_deref(), Some("\"2\""));
+    assert_eq!(
+        transport.last().body.as_deref(),
+        Some(r#"{"title":"new title"}"#)
+    );
+}
+
+#[tokio::test]
+async fn failures_are_typed() {
+    let (client, transport) = client();
+    let client = client.with_token("abc");
+
+    transport.fail();
+    assert!(matches!(client.tasks().await, Err(ClientError::Network(_))));
+
+    transport.answer(401, None, "invalid token");
+    assert!(matches!(
+        client.tasks().await,
+        Err(ClientError::NotAuthenticated)
+    ));
+
+    transport.answer(404, None, "no such task");
+    match client.task(9).await {
+        Err(ClientError::Server { status, message }) => {
+            assert_eq!(status, 404);
+            assert_eq!(message, "no such task");
+        }
+        other => panic!("expected a server error, got {:?}", other),
+    }
+
+    transport.answer(200, None, "<html>");
+    assert!(matches!(client.tasks().await, Err(ClientError::Decode(_))));
+}
@@ -0,0 +1,100 @@
+// TodoClient against a todo_server running in this process. It needs the Postgres
+// database todo_server uses (see database/init.sql), so run it with
+// `cargo test -p todo_client -- --ignored`.
+
+use actix_web::{web, App, HttpServer};
+use std::time::{SystemTime, UNIX_EPOCH};
+use todo_client::*;
+use todo_server::database::TodoDB;
+use todo_server::events::TaskEvents;
+use todo_server::routes;
+
+fn start_server() -> String {
+    if std::env::var("JWT_SECRET").is_err() {
+        std::env::set_var("JWT_SECRET", "keyboardcat");
+    }
+    let db = web::Data::new(TodoDB::new());
+    let task_events = web::Data::new(TaskEvents::new());
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(db.clone())
+            .app_data(task_events.clone())
+            .configure(routes::configure)
+    })
+    .workers(1)
+    .bind(("127.0.0.1", 0))
+    .unwrap();
+    let port = server.addrs()[0].port();
+    actix_web::rt::spawn(server.run());
+    format!("http://127.0.0.1:{}/api/v1", port)
+}
+
+fn unique_username() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    format!("client-{}", nanos)
+}
+
+#[actix_web::test]
+#[ignore = "needs todo_server's Postgres database"]
+async fn a_whole_session() {
+    let mut client = TodoClient::new(&start_server());
+    let username = unique_username();
+    let user = client
+        .create_account(&username, "myfancypass")
+        .await
+        .unwrap();
+    assert_eq!(user.username, username);
+
+    client.logout().await.unwrap();
+    assert!(matches!(
+        client.tasks().await,
+        Err(ClientError::NotAuthenticated)
+    ));
+    client.login(&username, "myfancypass").await.unwrap();
+
+    let task = client
+        .create_task(&CreateTaskRequest {
+            title: "from the client".to_string(),
+            description: Some("made by todo_client".to_string()),
+            priority: Some("B".to_string()),
+        })
+        .await
+        .unwrap();
+    assert!(client.tasks().await.unwrap().contains(&task));
+
+    let version = client.complete_task(task.id).await.unwrap().unwrap();
+    assert!(client.task(task.id).await.unwrap().completed_at.is_some());
+    client.uncomplete_task(task.id).await.unwrap();
+
+    let patch = UpdateTaskRequest {
+        title: Some("edited by the client".to_string()),
+        ..Default::default()
+    };
+    // the uncomplete moved the task past `version`
+    match client.update_task(task.id, &patch, Some(version)).await {
+        Err(ClientError::Conflict(Some(latest))) => assert!(latest.version > version),
+        other => panic!("expected a conflict, got {:?}", other),
+    }
+    let latest = client.task(task.id).await.unwrap();
+    let edited = client
+        .update_task(task.id, &patch, Some(latest.version))
+        .await
+        .unwrap();
+    assert_eq!(edited.title, "edited by the client");
+    assert_eq!(edited.priority.as_deref(), Some("B"));
+
+    client
+        .delete_task(task.id, Some(edited.version))
+        .await
+        .unwrap();
+    assert!(!client
+        .tasks()
+        .await
+        .unwrap()
+        .iter()
+        .any(|t| t.id == task.id));
+    client.logout().await.unwrap();
+}
@@ -1,11 +1,42 @@
+-- safe to run again, on an empty database or one made by an older init.sql:
+-- tables and columns are only added when missing, the seeds when there are none
+
 CREATE TABLE IF NOT EXISTS users (
   id          SERIAL PRIMARY KEY,
   username    VARCHAR(64) NOT NULL UNIQUE,
   password    VARCHAR(64) NOT NULL,
   deleted_at  TIMESTAMP DEFAULT NULL,
-  token       TEXT DEFAULT NULL
+  -- deleted_at was set by an admin disabling the user, not by deleting the account
+  disabled    BOOLEAN NOT NULL DEFAULT FALSE,
+  token       TEXT DEFAULT NULL,
+  role        VARCHAR(16) NOT NULL DEFAULT 'user',
+  email       VARCHAR(255) DEFAULT NULL UNIQUE,
+  -- base32, set when enrolment starts, only asked for once totp_enabled_at is
+  totp_secret      VARCHAR(32) DEFAULT NULL,
+  totp_enabled_at  TIMESTAMP DEFAULT NULL,
+  -- the step of the last code used, older and equal ones are turned away
+  totp_last_step   BIGINT DEFAULT NULL
 );
 
+ALTER TABLE users ADD COLUMN IF NOT EXISTS disabled BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE users ADD COLUMN IF NOT EXISTS role VARCHAR(16) NOT NULL DEFAULT 'user';
+ALTER TABLE users ADD COLUMN IF NOT EXISTS email VARCHAR(255) DEFAULT NULL UNIQUE;
+ALTER TABLE users ADD COLUMN IF NOT EXISTS totp_secret VARCHAR(32) DEFAULT NULL;
+ALTER TABLE users ADD COLUMN IF NOT EXISTS totp_enabled_at TIMESTAMP DEFAULT NULL;
+ALTER TABLE users ADD COLUMN IF NOT EXISTS totp_last_step BIGINT DEFAULT NULL;
+
+-- disabling used to have a column of its own, now it sets deleted_at and disabled
+DO $$
+BEGIN
+  IF EXISTS (SELECT 1 FROM information_schema.columns
+             WHERE table_schema = current_schema() AND table_name = 'users' AND column_name = 'disabled_at') THEN
+    UPDATE users SET deleted_at = disabled_at, disabled = TRUE
+      WHERE deleted_at IS NULL AND disabled_at IS NOT NULL;
+    ALTER TABLE users DROP COLUMN disabled_at;
+  END IF;
+END
+$$;
+
 CREATE TABLE IF NOT EXISTS tasks (
   id            SERIAL PRIMARY KEY,
   priority      VARCHAR(4) DEFAULT NULL,
@@ -15,17 +46,160 @@ CREATE TABLE IF NOT EXISTS tasks (
   deleted_at    TIMESTAMP DEFAULT NULL,
   user_id       INTEGER DEFAULT NULL, 
   is_default    BOOLEAN DEFAULT FALSE,
+  version       INTEGER NOT NULL DEFAULT 1,
+  CONSTRAINT fk_users FOREIGN KEY (user_id) REFERENCES users(id)
+);
+
+ALTER TABLE tasks ADD COLUMN IF NOT EXISTS version INTEGER NOT NULL DEFAULT 1;
+
+CREATE TABLE IF NOT EXISTS idempotency_keys (
+  id               SERIAL PRIMARY KEY,
+  idempotency_key  VARCHAR(255) NOT NULL,
+  user_id          INTEGER DEFAULT NULL,
+  request_method   VARCHAR(8) NOT NULL,
+  request_path     TEXT NOT NULL,
+  -- SHA-256 of the method, path and body, a different body is a different request
+  request_hash     VARCHAR(64) DEFAULT NULL,
+  status_code      INTEGER DEFAULT NULL,
+  response_headers TEXT DEFAULT NULL,
+  response_body    TEXT DEFAULT NULL,
+  created_at       TIMESTAMP NOT NULL DEFAULT NOW(),
+  CONSTRAINT fk_users FOREIGN KEY (user_id) REFERENCES users(id)
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idempotency_keys_scope
+  ON idempotency_keys ((COALESCE(user_id, 0)), idempotency_key);
+
+ALTER TABLE idempotency_keys ADD COLUMN IF NOT EXISTS request_hash VARCHAR(64) DEFAULT NULL;
+
+-- for purging the expired keys
+CREATE INDEX IF NOT EXISTS idempotency_keys_created ON idempotency_keys (created_at);
+
+CREATE TABLE IF NOT EXISTS task_events (
+  id          BIGSERIAL PRIMARY KEY,
+  user_id     INTEGER NOT NULL,
+  event       TEXT NOT NULL,
+  created_at  TIMESTAMP NOT NULL DEFAULT NOW(),
   CONSTRAINT fk_users FOREIGN KEY (user_id) REFERENCES users(id)
 );
 
-INSERT INTO users (username, password) VALUES ('deleteduser', '$2b$12$x3hs5oMgjHdcV1GUEElfsO19JtS6.ixJAX9Cj62GyhpdPAIW25sky');
+CREATE INDEX IF NOT EXISTS task_events_user ON task_events (user_id, id);
+-- for purging the old events
+CREATE INDEX IF NOT EXISTS task_events_created ON task_events (created_at);
 
-INSERT INTO tasks (title, deleted_at, user_id) VALUES (
-  'my deleted task',
-  NOW(),
-  (select id from users where username = 'deleteduser')
+-- only the SHA-256 of each reset token is kept, the token itself is in the email
+CREATE TABLE IF NOT EXISTS password_resets (
+  id          SERIAL PRIMARY KEY,
+  user_id     INTEGER NOT NULL,
+  token_hash  VARCHAR(64) NOT NULL UNIQUE,
+  created_at  TIMESTAMP NOT NULL DEFAULT NOW(),
+  expires_at  TIMESTAMP NOT NULL,
+  used_at     TIMESTAMP DEFAULT NULL,
+  CONSTRAINT fk_users FOREIGN KEY (user_id) REFERENCES users(id)
 );
 
-INSERT INTO tasks (priority, title, description, is_default) VALUES 
-  ('A', 'I am a task, you can complete me by checking the box', 'This is my description', true),
-  ('B', 'See my details for by clicking me', 'My description can be changed', true);
\ No newline at end of file
+-- one-time codes for when the authenticator app is gone, hashed like the resets
+CREATE TABLE IF NOT EXISTS recovery_codes (
+  id          SERIAL PRIMARY KEY,
+  user_id     INTEGER NOT NULL,
+  code_hash   VARCHAR(64) NOT NULL,
+  used_at     TIMESTAMP DEFAULT NULL,
+  CONSTRAINT fk_users FOREIGN KEY (user_id) REFERENCES users(id)
+);
+
+CREATE INDEX IF NOT EXISTS recovery_codes_user ON recovery_codes (user_id);
+
+-- a password that was right, waiting for the second factor
+CREATE TABLE IF NOT EXISTS two_factor_challenges (
+  id          SERIAL PRIMARY KEY,
+  user_id     INTEGER NOT NULL,
+  token_hash  VARCHAR(64) NOT NULL UNIQUE,
+  attempts    INTEGER NOT NULL DEFAULT 0,
+  created_at  TIMESTAMP NOT NULL DEFAULT NOW(),
+  expires_at  TIMESTAMP NOT NULL,
+  used_at     TIMESTAMP DEFAULT NULL,
+  CONSTRAINT fk_users FOREIGN KEY (user_id) REFERENCES users(id)
+);
+
+-- an SSO login on its way through the identity provider. The state in the
+-- redirect finds it again, hashed like the reset tokens. user_id is set when a
+-- logged in user is linking their account rather than logging in.
+CREATE TABLE IF NOT EXISTS oidc_logins (
+  id             SERIAL PRIMARY KEY,
+  state_hash     VARCHAR(64) NOT NULL UNIQUE,
+  code_verifier  VARCHAR(128) NOT NULL,
+  nonce          VARCHAR(64) NOT NULL,
+  user_id        INTEGER DEFAULT NULL,
+  created_at     TIMESTAMP NOT NULL DEFAULT NOW(),
+  expires_at     TIMESTAMP NOT NULL,
+  used_at        TIMESTAMP DEFAULT NULL,
+  CONSTRAINT fk_users FOREIGN KEY (user_id) REFERENCES users(id)
+);
+
+-- accounts at an identity provider, each logs in as one user
+CREATE TABLE IF NOT EXISTS user_identities (
+  id          SERIAL PRIMARY KEY,
+  user_id     INTEGER NOT NULL,
+  issuer      VARCHAR(255) NOT NULL,
+  subject     VARCHAR(255) NOT NULL,
+  created_at  TIMESTAMP NOT NULL DEFAULT NOW(),
+  UNIQUE (issuer, subject),
+  CONSTRAINT fk_users FOREIGN KEY (user_id) REFERENCES users(id)
+);
+
+-- personal API tokens for scripts, only the SHA-256 is kept like the resets.
+-- scope is read_only or read_write, see TokenScope in todo_api
+CREATE TABLE IF NOT EXISTS api_tokens (
+  id            SERIAL PRIMARY KEY,
+  user_id       INTEGER NOT NULL,
+  name          VARCHAR(64) NOT NULL,
+  token_hash    VARCHAR(64) NOT NULL UNIQUE,
+  scope         VARCHAR(16) NOT NULL DEFAULT 'read_only',
+  created_at    TIMESTAMP NOT NULL DEFAULT NOW(),
+  expires_at    TIMESTAMP DEFAULT NULL,
+  last_used_at  TIMESTAMP DEFAULT NULL,
+  CONSTRAINT fk_users FOREIGN KEY (user_id) REFERENCES users(id)
+);
+
+CREATE INDEX IF NOT EXISTS api_tokens_user ON api_tokens (user_id);
+
+-- the tasks a new account starts with, in the locale its Accept-Language picks
+CREATE TABLE IF NOT EXISTS task_templates (
+  id           SERIAL PRIMARY KEY,
+  locale       VARCHAR(16) NOT NULL DEFAULT 'en',
+  position     INTEGER NOT NULL DEFAULT 0,
+  title        VARCHAR(255) NOT NULL,
+  description  TEXT DEFAULT NULL,
+  priority     VARCHAR(4) DEFAULT NULL
+);
+
+CREATE INDEX IF NOT EXISTS task_templates_locale ON task_templates (locale, position);
+
+-- the templates used to be tasks without a user marked is_default. Edited ones
+-- become the en templates, unless there are templates already, and they all
+-- stop sitting among the real tasks.
+INSERT INTO task_templates (locale, position, priority, title, description)
+  SELECT 'en', ROW_NUMBER() OVER (ORDER BY id) - 1, priority, title, description
+  FROM tasks
+  WHERE is_default AND user_id IS NULL AND deleted_at IS NULL
+    AND NOT EXISTS (SELECT 1 FROM task_templates);
+
+DELETE FROM tasks WHERE is_default AND user_id IS NULL;
+
+INSERT INTO users (username, password) VALUES ('deleteduser', '$2b$12$x3hs5oMgjHdcV1GUEElfsO19JtS6.ixJAX9Cj62GyhpdPAIW25sky')
+  ON CONFLICT (username) DO NOTHING;
+
+INSERT INTO tasks (title, deleted_at, user_id)
+  SELECT 'my deleted task', NOW(), id FROM users
+  WHERE username = 'deleteduser'
+    AND NOT EXISTS (SELECT 1 FROM tasks WHERE tasks.user_id = users.id);
+
+INSERT INTO task_templates (locale, position, priority, title, description)
+  SELECT * FROM (VALUES
+    ('en', 0, 'A', 'I am a task, you can complete me by checking the box', 'This is my description'),
+    ('en', 1, 'B', 'See my details for by clicking me', 'My description can be changed'),
+    ('es', 0, 'A', 'Soy una tarea, puedes completarme marcando la casilla', 'Esta es mi descripción'),
+    ('es', 1, 'B', 'Haz clic en mí para ver mis detalles', 'Mi descripción se puede cambiar')
+  ) AS seed (locale, position, priority, title, description)
+  -- a locale an admin has templates for already is left alone
+  WHERE NOT EXISTS (SELECT 1 FROM task_templates WHERE task_templates.locale = seed.locale);
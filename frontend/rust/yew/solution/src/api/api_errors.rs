@@ -1,9 +1,46 @@
 use thiserror::Error;
+use todo_client::ClientError;
 
 #[derive(Debug, Error)]
 pub enum ApiError {
     #[error("Expired or missing auth token")]
     NotAuthenticated,
+    #[error("The task was changed somewhere else")]
+    Conflict,
+    #[error("Could not reach the server")]
+    Network,
+    // the server turned the request down and said why, like a taken username
+    #[error("{0}")]
+    Rejected(String),
+    // the password was right, the login page asks for a code with this challenge
+    #[error("Enter the code from your authenticator app")]
+    TwoFactorRequired(String),
     #[error("Unknown Network error")]
     Unknown,
 }
+
+impl ApiError {
+    /// Like `From`, but keeps the reason the server gave for a 4xx so a form
+    /// can show it.
+    pub fn with_reason(error: ClientError) -> Self {
+        match error {
+            ClientError::Server {
+                status: 400..=499,
+                message,
+            } => ApiError::Rejected(message),
+            error => error.into(),
+        }
+    }
+}
+
+impl From<ClientError> for ApiError {
+    fn from(error: ClientError) -> Self {
+        match error {
+            ClientError::NotAuthenticated => ApiError::NotAuthenticated,
+            ClientError::Conflict(_) => ApiError::Conflict,
+            ClientError::Network(_) => ApiError::Network,
+            ClientError::TwoFactorRequired(challenge) => ApiError::TwoFactorRequired(challenge),
+            ClientError::Server { .. } | ClientError::Decode(_) => ApiError::Unknown,
+        }
+    }
+}
@@ -1,111 +1,275 @@
 pub mod api_errors;
 pub mod patch_task;
+pub mod task_events;
+pub mod transport;
 
-use reqwasm::http::Request;
-use serde::{Deserialize, Serialize};
+use gloo::console;
+use gloo::timers::future::TimeoutFuture;
+use reqwasm::http::{Request, Response};
 use serde_json::json;
+use todo_api::{
+    ApiToken, ApiTokenId, CreateApiTokenRequest, CreateTaskRequest, NewApiToken, TaskId,
+    TwoFactorSetup, TwoFactorStatus,
+};
+use todo_client::TodoClient;
 
-use crate::store::Task;
+pub use todo_api::{AuthResponse, TaskListResponse, TaskResponse, UserInfo};
 
-use self::{api_errors::ApiError, patch_task::PatchTask};
+use self::{api_errors::ApiError, patch_task::PatchTask, transport::ReqwasmTransport};
 
 // TODO refactor url to environment variable
 const BASE_URL: &str = include_str!("api_base_uri.txt");
+const RETRIES: u32 = 3;
+const RETRY_DELAY_MS: u32 = 500;
 
-#[derive(Serialize, Deserialize)]
-pub struct AuthResponse {
-    pub data: User,
+/// A 400 is `ApiError::Rejected` with what the server didn't like about the
+/// username or password, like the password being in a breach.
+pub async fn create_account(username: String, password: String) -> Result<AuthResponse, ApiError> {
+    let body = json!({
+      "username": username,
+      "password": password
+    })
+    .to_string();
+    let response = send_idempotent(&idempotency_key(), || {
+        Request::post(&format!("{}/users", BASE_URL))
+            .header("Content-Type", "application/json")
+            .body(body.clone())
+    })
+    .await?;
+    match response.status() {
+        200..=299 => response.json::<AuthResponse>().await.map_err(|_| ApiError::Unknown),
+        400 | 429 => Err(ApiError::Rejected(response.text().await.unwrap_or_default())),
+        status => Err(handle_errors(status)),
+    }
 }
 
-#[derive(Serialize, Deserialize)]
-pub struct User {
-    pub id: u32,
-    pub username: String,
-    pub token: String,
+pub async fn login(username: String, password: String) -> Result<AuthResponse, ApiError> {
+    let mut client = TodoClient::with_transport(BASE_URL, ReqwasmTransport);
+    let user = client.login(&username, &password).await?;
+    Ok(AuthResponse { data: user })
 }
 
-#[derive(Serialize, Deserialize)]
-pub struct TaskResponse {
-    pub data: Vec<Task>,
+/// The second half of a login that came back with `ApiError::TwoFactorRequired`.
+pub async fn login_two_factor(challenge: String, code: String) -> Result<AuthResponse, ApiError> {
+    let mut client = TodoClient::with_transport(BASE_URL, ReqwasmTransport);
+    let user = client
+        .login_two_factor(&challenge, &code)
+        .await
+        .map_err(ApiError::with_reason)?;
+    Ok(AuthResponse { data: user })
 }
 
-#[derive(Serialize, Deserialize)]
-pub struct SingleTaskResponse {
-    pub data: Task,
+/// Where to send the browser for an SSO login. With a token the SSO account
+/// gets linked to that user instead.
+pub async fn oidc_authorize(token: Option<String>) -> Result<String, ApiError> {
+    let mut client = TodoClient::with_transport(BASE_URL, ReqwasmTransport);
+    client.set_token(token);
+    client.oidc_authorize().await.map_err(ApiError::with_reason)
 }
 
-pub async fn create_account(username: String, password: String) -> AuthResponse {
-    Request::post(&format!("{}/users", BASE_URL))
-        .header("Content-Type", "application/json")
-        .body(
-            json!({
-              "username": username,
-              "password": password
-            })
-            .to_string(),
-        )
-        .send()
+/// The `code` and `state` the identity provider sent the browser back with.
+pub async fn oidc_callback(code: String, state: String) -> Result<AuthResponse, ApiError> {
+    let mut client = TodoClient::with_transport(BASE_URL, ReqwasmTransport);
+    let user = client
+        .oidc_callback(&code, &state)
         .await
-        .unwrap()
-        .json::<AuthResponse>()
+        .map_err(ApiError::with_reason)?;
+    Ok(AuthResponse { data: user })
+}
+
+pub async fn two_factor_status(token: &str) -> Result<TwoFactorStatus, ApiError> {
+    let client = TodoClient::with_transport(BASE_URL, ReqwasmTransport).with_token(token);
+    Ok(client.two_factor_status().await?)
+}
+
+pub async fn start_two_factor(token: &str) -> Result<TwoFactorSetup, ApiError> {
+    let client = TodoClient::with_transport(BASE_URL, ReqwasmTransport).with_token(token);
+    client
+        .start_two_factor()
         .await
-        .unwrap()
-}
-
-pub async fn login(username: String, password: String) -> AuthResponse {
-    Request::post(&format!("{}/users/login", BASE_URL))
-        .header("content-type", "application/json")
-        .body(
-            json! ({
-                "username": username,
-                "password": password
-            })
-            .to_string(),
-        )
-        .send()
+        .map_err(ApiError::with_reason)
+}
+
+/// The recovery codes, shown once.
+pub async fn confirm_two_factor(token: &str, code: String) -> Result<Vec<String>, ApiError> {
+    let client = TodoClient::with_transport(BASE_URL, ReqwasmTransport).with_token(token);
+    client
+        .confirm_two_factor(&code)
         .await
-        .unwrap()
-        .json::<AuthResponse>()
+        .map_err(ApiError::with_reason)
+}
+
+pub async fn disable_two_factor(token: &str, password: String) -> Result<(), ApiError> {
+    let client = TodoClient::with_transport(BASE_URL, ReqwasmTransport).with_token(token);
+    client
+        .disable_two_factor(&password)
         .await
-        .unwrap()
+        .map_err(ApiError::with_reason)
 }
 
-pub async fn get_tasks(token: &str) -> Result<TaskResponse, ApiError> {
+pub async fn api_tokens(token: &str) -> Result<Vec<ApiToken>, ApiError> {
+    let client = TodoClient::with_transport(BASE_URL, ReqwasmTransport).with_token(token);
+    Ok(client.api_tokens().await?)
+}
+
+/// The token itself is only in this answer.
+pub async fn create_api_token(
+    token: &str,
+    request: CreateApiTokenRequest,
+) -> Result<NewApiToken, ApiError> {
+    let client = TodoClient::with_transport(BASE_URL, ReqwasmTransport).with_token(token);
+    client
+        .create_api_token(&request)
+        .await
+        .map_err(ApiError::with_reason)
+}
+
+pub async fn revoke_api_token(token: &str, id: ApiTokenId) -> Result<(), ApiError> {
+    let client = TodoClient::with_transport(BASE_URL, ReqwasmTransport).with_token(token);
+    client
+        .revoke_api_token(id)
+        .await
+        .map_err(ApiError::with_reason)
+}
+
+pub async fn change_username(token: &str, username: String) -> Result<AuthResponse, ApiError> {
+    let client = TodoClient::with_transport(BASE_URL, ReqwasmTransport).with_token(token);
+    let user = client
+        .change_username(&username)
+        .await
+        .map_err(ApiError::with_reason)?;
+    Ok(AuthResponse { data: user })
+}
+
+/// Where password reset links go, "" removes it.
+pub async fn change_email(token: &str, email: String) -> Result<AuthResponse, ApiError> {
+    let client = TodoClient::with_transport(BASE_URL, ReqwasmTransport).with_token(token);
+    let user = client
+        .change_email(&email)
+        .await
+        .map_err(ApiError::with_reason)?;
+    Ok(AuthResponse { data: user })
+}
+
+pub async fn forgot_password(email: String) -> Result<(), ApiError> {
+    let client = TodoClient::with_transport(BASE_URL, ReqwasmTransport);
+    client.forgot_password(&email).await?;
+    Ok(())
+}
+
+/// `reset_token` is the one from the link in the reset email.
+pub async fn reset_password(reset_token: String, new_password: String) -> Result<(), ApiError> {
+    let client = TodoClient::with_transport(BASE_URL, ReqwasmTransport);
+    client
+        .reset_password(&reset_token, &new_password)
+        .await
+        .map_err(ApiError::with_reason)?;
+    Ok(())
+}
+
+/// The answer carries a new token, the old one stops working.
+pub async fn change_password(
+    token: &str,
+    current_password: String,
+    new_password: String,
+) -> Result<AuthResponse, ApiError> {
+    let mut client = TodoClient::with_transport(BASE_URL, ReqwasmTransport).with_token(token);
+    let user = client
+        .change_password(&current_password, &new_password)
+        .await
+        .map_err(ApiError::with_reason)?;
+    Ok(AuthResponse { data: user })
+}
+
+pub async fn delete_account(token: &str) -> Result<(), ApiError> {
+    let mut client = TodoClient::with_transport(BASE_URL, ReqwasmTransport).with_token(token);
+    client.delete_account().await?;
+    Ok(())
+}
+
+pub async fn get_tasks(token: &str) -> Result<TaskListResponse, ApiError> {
     let request = Request::get(&format!("{}/tasks", BASE_URL))
         .header("x-auth-token", token)
         .send()
         .await
-        .unwrap();
+        .map_err(|_| ApiError::Network)?;
 
     if request.ok() {
-        Ok(request.json::<TaskResponse>().await.unwrap())
+        request
+            .json::<TaskListResponse>()
+            .await
+            .map_err(|_| ApiError::Unknown)
     } else {
         Err(handle_errors(request.status()))
     }
 }
 
-pub async fn update_task(task_id: u32, token: &str, task: PatchTask) -> Result<(), ApiError> {
-    let request = Request::patch(&format!("{}/tasks/{}", BASE_URL, task_id))
+pub async fn get_task(task_id: TaskId, token: &str) -> Result<TaskResponse, ApiError> {
+    let request = Request::get(&format!("{}/tasks/{}", BASE_URL, task_id))
         .header("x-auth-token", token)
-        .header("content-type", "application/json")
-        .body(serde_json::to_string(&task).unwrap())
         .send()
         .await
-        .unwrap();
+        .map_err(|_| ApiError::Network)?;
 
     if request.ok() {
-        Ok(())
+        let version = version_from_etag(&request);
+        let mut task_response = request
+            .json::<TaskResponse>()
+            .await
+            .map_err(|_| ApiError::Unknown)?;
+        if task_response.data.version == 0 {
+            task_response.data.version = version.unwrap_or_default();
+        }
+        Ok(task_response)
     } else {
         Err(handle_errors(request.status()))
     }
 }
 
-pub async fn delete_task(task_id: u32, token: &str) -> Result<(), ApiError> {
-    let request = Request::delete(&format!("{}/tasks/{}", BASE_URL, task_id))
-        .header("x-auth-token", token)
-        .send()
-        .await
-        .unwrap();
+/// Sends `If-Match` when we know which version we edited, so the server can refuse
+/// to overwrite somebody else's change. Returns the task's new version.
+pub async fn update_task(
+    task_id: TaskId,
+    token: &str,
+    key: &str,
+    task: PatchTask,
+    version: Option<i32>,
+) -> Result<Option<i32>, ApiError> {
+    let body = serde_json::to_string(&task).unwrap();
+    let request = send_idempotent(key, || {
+        let request = Request::patch(&format!("{}/tasks/{}", BASE_URL, task_id))
+            .header("x-auth-token", token)
+            .header("content-type", "application/json")
+            .body(body.clone());
+        match version {
+            Some(version) => request.header("If-Match", &etag(version)),
+            None => request,
+        }
+    })
+    .await?;
+
+    if request.ok() {
+        Ok(version_from_etag(&request))
+    } else {
+        Err(handle_errors(request.status()))
+    }
+}
+
+pub async fn delete_task(
+    task_id: TaskId,
+    token: &str,
+    key: &str,
+    version: Option<i32>,
+) -> Result<(), ApiError> {
+    let request = send_idempotent(key, || {
+        let request = Request::delete(&format!("{}/tasks/{}", BASE_URL, task_id))
+            .header("x-auth-token", token);
+        match version {
+            Some(version) => request.header("If-Match", &etag(version)),
+            None => request,
+        }
+    })
+    .await?;
 
     if request.ok() {
         Ok(())
@@ -116,49 +280,68 @@ pub async fn delete_task(task_id: u32, token: &str) -> Result<(), ApiError> {
 
 pub async fn create_task(
     token: &str,
+    key: &str,
     title: String,
     description: Option<String>,
     priority: String,
-) -> Result<SingleTaskResponse, ApiError> {
-    let new_task = PatchTask::new(Some(title), Some(priority), description, None);
-    let request = Request::post(&format!("{}/tasks", BASE_URL))
-        .header("x-auth-token", token)
-        .header("content-type", "application/json")
-        .body(serde_json::to_string(&new_task).unwrap())
-        .send()
-        .await
-        .unwrap();
+) -> Result<TaskResponse, ApiError> {
+    let new_task = CreateTaskRequest {
+        title,
+        description,
+        priority: Some(priority),
+    };
+    let body = serde_json::to_string(&new_task).unwrap();
+    let request = send_idempotent(key, || {
+        Request::post(&format!("{}/tasks", BASE_URL))
+            .header("x-auth-token", token)
+            .header("content-type", "application/json")
+            .body(body.clone())
+    })
+    .await?;
 
     if request.ok() {
-        Ok(request.json::<SingleTaskResponse>().await.unwrap())
+        request
+            .json::<TaskResponse>()
+            .await
+            .map_err(|_| ApiError::Unknown)
     } else {
         Err(handle_errors(request.status()))
     }
 }
 
-pub async fn complete_task(task_id: u32, token: &str) -> Result<(), ApiError> {
-    let request = Request::put(&format!("{}/tasks/{}/completed", BASE_URL, task_id))
-        .header("x-auth-token", token)
-        .send()
-        .await
-        .unwrap();
+/// Returns the task's new version.
+pub async fn complete_task(
+    task_id: TaskId,
+    token: &str,
+    key: &str,
+) -> Result<Option<i32>, ApiError> {
+    let request = send_idempotent(key, || {
+        Request::put(&format!("{}/tasks/{}/completed", BASE_URL, task_id))
+            .header("x-auth-token", token)
+    })
+    .await?;
 
     if request.ok() {
-        Ok(())
+        Ok(version_from_etag(&request))
     } else {
         Err(handle_errors(request.status()))
     }
 }
 
-pub async fn uncomplete_task(task_id: u32, token: &str) -> Result<(), ApiError> {
-    let request = Request::put(&format!("{}/tasks/{}/uncompleted", BASE_URL, task_id))
-        .header("x-auth-token", token)
-        .send()
-        .await
-        .unwrap();
+/// Returns the task's new version.
+pub async fn uncomplete_task(
+    task_id: TaskId,
+    token: &str,
+    key: &str,
+) -> Result<Option<i32>, ApiError> {
+    let request = send_idempotent(key, || {
+        Request::put(&format!("{}/tasks/{}/uncompleted", BASE_URL, task_id))
+            .header("x-auth-token", token)
+    })
+    .await?;
 
     if request.ok() {
-        Ok(())
+        Ok(version_from_etag(&request))
     } else {
         Err(handle_errors(request.status()))
     }
@@ -169,7 +352,7 @@ pub async fn logout(token: &str) -> Result<(), ApiError> {
         .header("x-auth-token", token)
         .send()
         .await
-        .unwrap();
+        .map_err(|_| ApiError::Network)?;
     if request.ok() {
         Ok(())
     } else {
@@ -177,9 +360,48 @@ pub async fn logout(token: &str) -> Result<(), ApiError> {
     }
 }
 
+/// A random key the server uses to recognise retries of the same request.
+pub fn idempotency_key() -> String {
+    (0..32)
+        .map(|_| std::char::from_digit((js_sys::Math::random() * 16.0) as u32, 16).unwrap())
+        .collect()
+}
+
+// Sends the request built by `build`, retrying with the same Idempotency-Key when the
+// network drops it, so a retry of something that did reach the server isn't applied twice.
+async fn send_idempotent<F>(key: &str, build: F) -> Result<Response, ApiError>
+where
+    F: Fn() -> Request,
+{
+    let mut attempt = 0;
+    loop {
+        match build().header("Idempotency-Key", key).send().await {
+            Ok(response) => return Ok(response),
+            Err(error) if attempt < RETRIES => {
+                console::log!("retrying request", error.to_string());
+                TimeoutFuture::new(RETRY_DELAY_MS * 2u32.pow(attempt)).await;
+                attempt += 1;
+            }
+            Err(_) => return Err(ApiError::Network),
+        }
+    }
+}
+
+fn etag(version: i32) -> String {
+    format!("\"{}\"", version)
+}
+
+fn version_from_etag(response: &Response) -> Option<i32> {
+    response
+        .headers()
+        .get("etag")
+        .and_then(|etag| etag.trim_start_matches("W/").trim_matches('"').parse().ok())
+}
+
 fn handle_errors(status: u16) -> ApiError {
     match status {
         401 => ApiError::NotAuthenticated,
+        412 => ApiError::Conflict,
         _ => ApiError::Unknown,
     }
 }
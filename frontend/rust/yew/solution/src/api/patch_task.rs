@@ -1,43 +1,36 @@
+use chrono::{DateTime, NaiveDateTime};
 use js_sys::Date;
 
-use serde::{Deserialize, Serialize};
+pub use todo_api::UpdateTaskRequest as PatchTask;
 
-#[derive(Serialize, Deserialize, Clone)]
-pub struct PatchTask {
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub title: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub priority: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub description: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub completed_at: Option<Option<String>>,
-}
-
-impl PatchTask {
-    pub fn new(
-        title: Option<String>,
-        priority: Option<String>,
-        description: Option<String>,
-        completed_at: Option<bool>,
-    ) -> Self {
-        let completed_at = match completed_at {
-            Some(completed) => {
-                if completed {
-                    let now = Date::new_0();
-                    Some(now.to_utc_string().to_string().as_string())
-                } else {
-                    Some(None)
-                }
+pub fn new(
+    title: Option<String>,
+    priority: Option<String>,
+    description: Option<String>,
+    completed_at: Option<bool>,
+) -> PatchTask {
+    let completed_at = match completed_at {
+        Some(completed) => {
+            if completed {
+                Some(Some(now()))
+            } else {
+                Some(None)
             }
-            None => None,
-        };
-
-        Self {
-            title,
-            priority,
-            description,
-            completed_at,
         }
+        None => None,
+    };
+
+    PatchTask {
+        title,
+        priority: priority.map(Some),
+        // an emptied description removes it
+        description: description.map(|description| Some(description).filter(|d| !d.is_empty())),
+        completed_at,
     }
 }
+
+pub fn now() -> NaiveDateTime {
+    DateTime::from_timestamp_millis(Date::now() as i64)
+        .unwrap_or_default()
+        .naive_utc()
+}
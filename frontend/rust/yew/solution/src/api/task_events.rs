@@ -0,0 +1,183 @@
+use super::BASE_URL;
+use crate::api::patch_task::PatchTask;
+use crate::store::{self, StoreDispatch};
+use gloo::console;
+use gloo::timers::callback::Timeout;
+use std::cell::RefCell;
+use std::rc::{Rc, Weak};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{EventSource, MessageEvent, WebSocket};
+
+pub use todo_api::TaskEvent;
+
+const RECONNECT_DELAY_MS: u32 = 5000;
+
+/// Keeps a WebSocket to the server open while it is alive and applies the task
+/// changes it pushes to the store. Dropping it closes the socket.
+///
+/// Some proxies kill WebSockets, so when one never manages to open we switch to
+/// Server-Sent Events instead. The browser reconnects those itself and resumes
+/// from the last event id it saw.
+pub struct TaskSocket {
+    state: Rc<RefCell<SocketState>>,
+}
+
+#[derive(Default)]
+struct SocketState {
+    socket: Option<WebSocket>,
+    event_source: Option<EventSource>,
+    opened: bool,
+    closed: bool,
+    // the socket only holds on to these as JS functions, so we keep them alive
+    onmessage: Option<Closure<dyn FnMut(MessageEvent)>>,
+    onopen: Option<Closure<dyn FnMut()>>,
+    onclose: Option<Closure<dyn FnMut()>>,
+    reconnect: Option<Timeout>,
+}
+
+impl TaskSocket {
+    pub fn connect(token: &str, dispatch: StoreDispatch) -> Self {
+        let state = Rc::new(RefCell::new(SocketState::default()));
+        open(Rc::downgrade(&state), token.to_owned(), dispatch);
+        Self { state }
+    }
+}
+
+impl Drop for TaskSocket {
+    fn drop(&mut self) {
+        let mut state = self.state.borrow_mut();
+        state.closed = true;
+        state.reconnect = None;
+        if let Some(socket) = state.socket.take() {
+            socket.set_onclose(None);
+            socket.set_onmessage(None);
+            let _ = socket.close();
+        }
+        if let Some(event_source) = state.event_source.take() {
+            event_source.set_onmessage(None);
+            event_source.close();
+        }
+    }
+}
+
+pub fn apply_task_event(event: TaskEvent, dispatch: StoreDispatch) {
+    match event {
+        TaskEvent::TaskCreated(task) => store::replace_task(dispatch, task),
+        TaskEvent::TaskUpdated(task) => {
+            let task_id = task.id;
+            let version = task.version;
+            let patch_task = PatchTask {
+                title: Some(task.title),
+                priority: Some(task.priority),
+                description: Some(task.description),
+                completed_at: Some(task.completed_at),
+            };
+            store::update_task_by_id(dispatch.clone(), task_id, patch_task);
+            store::set_task_version(dispatch, task_id, version);
+        }
+        TaskEvent::TaskDeleted { id } => store::remove_task_by_id(dispatch, id),
+    }
+}
+
+fn open(weak_state: Weak<RefCell<SocketState>>, token: String, dispatch: StoreDispatch) {
+    let state = match weak_state.upgrade() {
+        Some(state) => state,
+        None => return,
+    };
+    let url = format!("{}/ws?token={}", BASE_URL.replacen("http", "ws", 1), token);
+    let socket = match WebSocket::new(&url) {
+        Ok(socket) => socket,
+        Err(error) => {
+            console::error!("could not open task socket", error);
+            reconnect_later(weak_state, token, dispatch);
+            return;
+        }
+    };
+
+    let onmessage = on_task_message(dispatch.clone());
+    let onopen = {
+        let weak_state = weak_state.clone();
+        Closure::wrap(Box::new(move || {
+            if let Some(state) = weak_state.upgrade() {
+                state.borrow_mut().opened = true;
+            }
+        }) as Box<dyn FnMut()>)
+    };
+    let onclose = {
+        let weak_state = weak_state.clone();
+        Closure::wrap(Box::new(move || {
+            let never_opened = match weak_state.upgrade() {
+                Some(state) => !state.borrow().opened,
+                None => return,
+            };
+            if never_opened {
+                open_event_source(weak_state.clone(), &token, dispatch.clone());
+            } else {
+                reconnect_later(weak_state.clone(), token.clone(), dispatch.clone());
+            }
+        }) as Box<dyn FnMut()>)
+    };
+    socket.set_onmessage(Some(onmessage.as_ref().unchecked_ref()));
+    socket.set_onopen(Some(onopen.as_ref().unchecked_ref()));
+    socket.set_onclose(Some(onclose.as_ref().unchecked_ref()));
+
+    let mut state = state.borrow_mut();
+    state.socket = Some(socket);
+    state.onmessage = Some(onmessage);
+    state.onopen = Some(onopen);
+    state.onclose = Some(onclose);
+}
+
+fn open_event_source(weak_state: Weak<RefCell<SocketState>>, token: &str, dispatch: StoreDispatch) {
+    let state = match weak_state.upgrade() {
+        Some(state) => state,
+        None => return,
+    };
+    if state.borrow().closed {
+        return;
+    }
+    let url = format!("{}/events?token={}", BASE_URL, token);
+    let event_source = match EventSource::new(&url) {
+        Ok(event_source) => event_source,
+        Err(error) => {
+            console::error!("could not open task event stream", error);
+            return;
+        }
+    };
+    let onmessage = on_task_message(dispatch);
+    event_source.set_onmessage(Some(onmessage.as_ref().unchecked_ref()));
+
+    let mut state = state.borrow_mut();
+    state.socket = None;
+    state.event_source = Some(event_source);
+    state.onmessage = Some(onmessage);
+}
+
+fn on_task_message(dispatch: StoreDispatch) -> Closure<dyn FnMut(MessageEvent)> {
+    Closure::wrap(Box::new(move |event: MessageEvent| {
+        if let Some(text) = event.data().as_string() {
+            match serde_json::from_str::<TaskEvent>(&text) {
+                Ok(task_event) => apply_task_event(task_event, dispatch.clone()),
+                Err(error) => console::error!("unknown task event", error.to_string()),
+            }
+        }
+    }) as Box<dyn FnMut(MessageEvent)>)
+}
+
+fn reconnect_later(weak_state: Weak<RefCell<SocketState>>, token: String, dispatch: StoreDispatch) {
+    let state = match weak_state.upgrade() {
+        Some(state) => state,
+        None => return,
+    };
+    if state.borrow().closed {
+        return;
+    }
+    let timeout = {
+        let weak_state = weak_state.clone();
+        Timeout::new(RECONNECT_DELAY_MS, move || {
+            open(weak_state, token, dispatch)
+        })
+    };
+    state.borrow_mut().reconnect = Some(timeout);
+}
@@ -0,0 +1,39 @@
+use async_trait::async_trait;
+use reqwasm::http::{Method as HttpMethod, Request as HttpRequest, RequestCredentials};
+use todo_client::transport::{Method, Request, Response, Transport};
+
+/// Sends todo_client's requests with the browser's fetch.
+pub struct ReqwasmTransport;
+
+#[async_trait(?Send)]
+impl Transport for ReqwasmTransport {
+    async fn send(&self, request: Request) -> Result<Response, String> {
+        let method = match request.method {
+            Method::Get => HttpMethod::GET,
+            Method::Post => HttpMethod::POST,
+            Method::Put => HttpMethod::PUT,
+            Method::Patch => HttpMethod::PATCH,
+            Method::Delete => HttpMethod::DELETE,
+        };
+        let mut http_request = HttpRequest::new(&request.url).method(method);
+        // an SSO login is finished with the cookie its start set, and the API is
+        // on another origin. Only there, a wildcard CORS origin refuses credentials.
+        if request.url.contains("/auth/oidc/") {
+            http_request = http_request.credentials(RequestCredentials::Include);
+        }
+        for (name, value) in &request.headers {
+            http_request = http_request.header(name, value);
+        }
+        if let Some(body) = request.body {
+            http_request = http_request.body(body);
+        }
+        let response = http_request
+            .send()
+            .await
+            .map_err(|error| error.to_string())?;
+        let status = response.status();
+        let etag = response.headers().get("etag");
+        let body = response.text().await.map_err(|error| error.to_string())?;
+        Ok(Response { status, etag, body })
+    }
+}
@@ -2,9 +2,11 @@ use std::ops::Deref;
 
 use crate::components::atoms::{
     bb_button::BBButton,
+    bb_text::{BBText, Color},
     bb_text_input::{BBTextInput, InputType},
 };
 use stylist::yew::styled_component;
+use todo_api::validation::{username_problem, PasswordPolicy};
 use yew::prelude::*;
 
 #[derive(Properties, Clone, PartialEq)]
@@ -35,9 +37,28 @@ pub struct User {
     pub password: String,
 }
 
+impl User {
+    // the server's default rules, it also turns down breached passwords
+    fn problems(&self) -> (Option<String>, Option<String>) {
+        (
+            username_problem(&self.username),
+            PasswordPolicy::default().problem(&self.username, &self.password),
+        )
+    }
+}
+
 #[styled_component(AccountForm)]
 pub fn account_form(props: &Props) -> Html {
     let state = use_state(User::default);
+    // a submit that was held back shows the problems of the empty fields too
+    let tried = use_state(|| false);
+    // only a new account is checked, logging in says what's wrong anyway
+    let validate = props.action == Action::CreateAccount;
+    let (username_problem, password_problem) = if validate {
+        state.problems()
+    } else {
+        (None, None)
+    };
 
     let username_onchange = {
         let state = state.clone();
@@ -59,10 +80,15 @@ pub fn account_form(props: &Props) -> Html {
 
     let onsubmit = {
         let onsubmit_prop = props.onsubmit.clone();
-        let state = state;
+        let state = state.clone();
+        let tried = tried.clone();
         Callback::from(move |event: FocusEvent| {
             event.prevent_default();
             let user = state.deref().clone();
+            if validate && user.problems() != (None, None) {
+                tried.set(true);
+                return;
+            }
             onsubmit_prop.emit(user);
         })
     };
@@ -70,7 +96,13 @@ pub fn account_form(props: &Props) -> Html {
     html! {
       <form {onsubmit}>
         <BBTextInput data_test="username" label="Username" placeholder="What username do you want?" class="input" input_type={InputType::Text} onchange={username_onchange} />
+        if let Some(problem) = username_problem.filter(|_| *tried || !state.username.is_empty()) {
+          <BBText data_test="username-problem" text={problem} color={Color::Danger} />
+        }
         <BBTextInput data_test="password" label="Password" placeholder="What is your password?" class="input" input_type={InputType::Password} onchange={password_onchange} />
+        if let Some(problem) = password_problem.filter(|_| *tried || !state.password.is_empty()) {
+          <BBText data_test="password-problem" text={problem} color={Color::Danger} />
+        }
         <BBButton label={props.action.to_string()} data_test="submit" />
       </form>
     }
@@ -1,3 +1,5 @@
 pub mod account_form;
 pub mod error_message;
+pub mod sso_button;
 pub mod task_edit_buttons;
+pub mod two_factor_form;
@@ -0,0 +1,45 @@
+use crate::api;
+use crate::components::atoms::bb_button::BBButton;
+use crate::store::{set_error_message, StoreType};
+use stylist::yew::styled_component;
+use wasm_bindgen_futures::spawn_local;
+use yew::prelude::*;
+use yewdux_functional::use_store;
+
+#[derive(Properties, Clone, PartialEq)]
+pub struct Props {
+    pub label: String,
+    pub data_test: String,
+    // logged in the SSO account gets linked to the user instead of logging in
+    pub token: Option<String>,
+}
+
+/// Sends the browser to the company's identity provider, which sends it back
+/// to the SsoCallback page.
+#[styled_component(SsoButton)]
+pub fn sso_button(props: &Props) -> Html {
+    let dispatch = use_store::<StoreType>().dispatch().clone();
+
+    let onclick = {
+        let token = props.token.clone();
+        Callback::from(move |event: MouseEvent| {
+            event.prevent_default();
+            let token = token.clone();
+            let dispatch = dispatch.clone();
+            spawn_local(async move {
+                match api::oidc_authorize(token).await {
+                    Ok(url) => {
+                        if let Some(window) = web_sys::window() {
+                            let _ = window.location().set_href(&url);
+                        }
+                    }
+                    Err(error) => set_error_message(dispatch, &error.to_string()),
+                }
+            });
+        })
+    };
+
+    html! {
+      <BBButton label={props.label.clone()} data_test={props.data_test.clone()} {onclick} />
+    }
+}
@@ -1,17 +1,18 @@
-use crate::api;
 use crate::components::atoms::bb_button::BBButton;
 use crate::components::atoms::bb_link::{BBLink, LinkType};
+use crate::outbox;
 use crate::router::Route;
-use crate::store::{remove_task_by_id, StoreType};
+use crate::store::{self, StoreType};
 use serde::{Deserialize, Serialize};
 use stylist::yew::styled_component;
+use todo_api::TaskId;
 use yew::prelude::*;
 use yew_router::prelude::*;
 use yewdux_functional::use_store;
 
 #[derive(Serialize, Deserialize)]
 pub struct NavId {
-    pub id: u32,
+    pub id: TaskId,
 }
 
 #[styled_component(TaskEditButtons)]
@@ -33,22 +34,15 @@ pub fn task_edit_buttons() -> Html {
     };
 
     let delete_onclick = {
-        let token = use_store::<StoreType>()
+        let version = use_store::<StoreType>()
             .state()
-            .map(|state| state.token.clone())
-            .unwrap_or_default();
+            .and_then(|state| task_id.and_then(|id| state.get_task_by_id(id)))
+            .and_then(|task| store::known_version(&task));
         let dispatch = use_store::<StoreType>().dispatch().clone();
         let history = use_history().unwrap();
         Callback::from(move |_| {
-            let token = token.clone();
-            let dispatch = dispatch.clone();
-            let history = history.clone();
-            let task_id = task_id.unwrap();
-            wasm_bindgen_futures::spawn_local(async move {
-                api::delete_task(task_id, &token).await.unwrap();
-                remove_task_by_id(dispatch, task_id);
-                history.push(Route::Home);
-            });
+            outbox::queue_delete(dispatch.clone(), task_id.unwrap(), version);
+            history.push(Route::Home);
         })
     };
 
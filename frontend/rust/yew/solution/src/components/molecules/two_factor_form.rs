@@ -0,0 +1,49 @@
+use std::ops::Deref;
+
+use crate::components::atoms::{
+    bb_button::BBButton,
+    bb_text_input::{BBTextInput, InputType},
+};
+use stylist::yew::styled_component;
+use yew::prelude::*;
+
+#[derive(Properties, Clone, PartialEq)]
+pub struct Props {
+    pub onsubmit: Callback<String>,
+    pub label: String,
+    // the second login step also takes recovery codes, enrolment doesn't
+    pub recovery_codes: Option<bool>,
+}
+
+/// Asks for a code from the authenticator app, for the second login step and
+/// for confirming enrolment.
+#[styled_component(TwoFactorForm)]
+pub fn two_factor_form(props: &Props) -> Html {
+    let code = use_state(String::new);
+
+    let code_onchange = {
+        let code = code.clone();
+        Callback::from(move |new_code: String| code.set(new_code))
+    };
+
+    let onsubmit = {
+        let onsubmit_prop = props.onsubmit.clone();
+        Callback::from(move |event: FocusEvent| {
+            event.prevent_default();
+            onsubmit_prop.emit(code.deref().trim().to_owned());
+        })
+    };
+
+    let placeholder = if props.recovery_codes.unwrap_or_default() {
+        "The 6 digits from your authenticator app, or a recovery code"
+    } else {
+        "The 6 digits from your authenticator app"
+    };
+
+    html! {
+      <form {onsubmit}>
+        <BBTextInput data_test="two-factor-code" label="Code" placeholder={placeholder.to_owned()} class="input" input_type={InputType::Text} onchange={code_onchange} />
+        <BBButton label={props.label.clone()} data_test="submit-code" />
+      </form>
+    }
+}
@@ -77,6 +77,7 @@ pub fn navbar() -> Html {
           } else {
             <div class="nav-right">
               <BBText data_test="welcome" text={format!("Welcome, {}", username)} />
+              <BBLink text={"Account".to_owned()} data_test={"account".to_owned()} route={Route::Account} link_type={LinkType::Button} />
               // <BBLink text={"Logout".to_owned()} data_test={"logout".to_owned()} route={Route::Home} link_type={LinkType::Button} />
               <BBButton
                 data_test="logout"
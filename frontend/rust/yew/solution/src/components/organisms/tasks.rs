@@ -1,10 +1,9 @@
-use crate::api::api_errors::ApiError;
 use crate::components::atoms::bb_checkbox::{BBCheckbox, OnchangeData};
 use crate::components::atoms::bb_link::BBLink;
 use crate::components::atoms::bb_text::{BBText, Color};
+use crate::outbox;
 use crate::router::Route;
 use crate::store::{StoreType, Task};
-use crate::{api, store};
 use stylist::yew::styled_component;
 use yew::prelude::*;
 use yewdux_functional::use_store;
@@ -24,34 +23,14 @@ pub fn tasks(props: &Props) -> Html {
     );
 
     let completed_onchange = {
-        let token = use_store::<StoreType>()
-            .state()
-            .map(|state| state.token.clone())
-            .unwrap_or_default();
-        let dispatch = use_store().dispatch().clone();
+        let dispatch = use_store::<StoreType>().dispatch().clone();
         Callback::from(move |data: OnchangeData| {
-            let token = token.clone();
             let task_id = data.id.parse().unwrap();
-            let dispatch = dispatch.clone();
-            wasm_bindgen_futures::spawn_local(async move {
-                if data.selected {
-                    match api::complete_task(task_id, &token).await {
-                        Ok(_) => store::mark_task_completed(dispatch, task_id),
-                        Err(ApiError::NotAuthenticated) => store::logout(dispatch),
-                        Err(error) => {
-                            gloo::console::error!("error completing task", error.to_string());
-                        }
-                    }
-                } else {
-                    match api::uncomplete_task(task_id, &token).await {
-                        Ok(_) => store::mark_task_uncompleted(dispatch, task_id),
-                        Err(ApiError::NotAuthenticated) => store::logout(dispatch),
-                        Err(error) => {
-                            gloo::console::error!("error completing task", error.to_string());
-                        }
-                    }
-                }
-            });
+            if data.selected {
+                outbox::queue_complete(dispatch.clone(), task_id);
+            } else {
+                outbox::queue_uncomplete(dispatch.clone(), task_id);
+            }
         })
     };
 
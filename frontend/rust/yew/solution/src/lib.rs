@@ -1,27 +1,83 @@
 mod api;
 mod components;
+mod outbox;
 mod pages;
 mod router;
 mod store;
 
-use api::{api_errors::ApiError, get_tasks};
+use api::{api_errors::ApiError, get_tasks, task_events::TaskSocket};
 use components::molecules::error_message::ErrorMessage;
 use components::organisms::navbar::Navbar;
 use gloo::console;
+use gloo::events::EventListener;
+use gloo::timers::callback::Interval;
 use router::{switch, Route};
 use store::{set_tasks, StoreType};
 use yew::prelude::*;
 use yew_router::prelude::*;
 use yewdux_functional::use_store;
 
+// how often to retry sending queued changes, on top of when the browser says we're back online
+const FLUSH_INTERVAL_MS: u32 = 15_000;
+
 #[function_component(App)]
 pub fn app() -> Html {
     let token: String = use_store::<StoreType>()
         .state()
         .map(|store| store.token.clone())
         .unwrap_or_default();
+    let queued = use_store::<StoreType>()
+        .state()
+        .map(|store| store.outbox.clone())
+        .unwrap_or_default();
     let is_loaded = use_state(|| false);
     let dispatch = use_store::<StoreType>().dispatch().clone();
+    let retry_at = use_state(js_sys::Date::now);
+    {
+        let retry_at = retry_at.clone();
+        use_effect_with_deps(
+            move |_| {
+                let online_retry_at = retry_at.clone();
+                let online = EventListener::new(&gloo::utils::window(), "online", move |_| {
+                    online_retry_at.set(js_sys::Date::now());
+                });
+                let interval = Interval::new(FLUSH_INTERVAL_MS, move || {
+                    retry_at.set(js_sys::Date::now());
+                });
+                move || drop((online, interval))
+            },
+            (),
+        );
+    }
+    {
+        let token = token.clone();
+        let dispatch = dispatch.clone();
+        let keys: Vec<String> = queued
+            .iter()
+            .map(|pending| pending.idempotency_key.clone())
+            .collect();
+        use_effect_with_deps(
+            move |_| {
+                wasm_bindgen_futures::spawn_local(outbox::flush(token, queued, dispatch));
+                || {}
+            },
+            (keys, *retry_at),
+        );
+    }
+    {
+        let dispatch = dispatch.clone();
+        use_effect_with_deps(
+            move |token: &String| {
+                let socket = if token.is_empty() {
+                    None
+                } else {
+                    Some(TaskSocket::connect(token, dispatch))
+                };
+                move || drop(socket)
+            },
+            token.clone(),
+        );
+    }
     use_effect(move || {
         if !token.is_empty() && !*is_loaded {
             let dispatch = dispatch.clone();
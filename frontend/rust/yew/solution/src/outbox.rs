@@ -0,0 +1,397 @@
+use std::cell::Cell;
+use std::collections::VecDeque;
+
+use crate::api::{
+    self,
+    api_errors::ApiError,
+    idempotency_key,
+    patch_task::{self, PatchTask},
+};
+use crate::store::{self, StoreDispatch, Task};
+use gloo::console;
+use serde::{Deserialize, Serialize};
+use todo_api::TaskId;
+
+/*
+Every change to a task is applied to the store straight away and queued here,
+then sent to the server in order whenever we're online. The queue is part of the
+persisted store, so changes made offline survive a reload.
+
+Tasks created offline get a temporary id counting down from -1. Server ids are
+Postgres SERIALs and always positive, so the two can't collide. Once the server
+has created the task, the temporary id is swapped for the real one.
+*/
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PendingMutation {
+    // sent with every attempt so the server never applies the same change twice
+    pub idempotency_key: String,
+    pub mutation: Mutation,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub enum Mutation {
+    Create {
+        temp_id: TaskId,
+        title: String,
+        description: Option<String>,
+        priority: String,
+    },
+    Update {
+        task_id: TaskId,
+        patch_task: PatchTask,
+        version: Option<i32>,
+    },
+    Delete {
+        task_id: TaskId,
+        version: Option<i32>,
+    },
+    Complete {
+        task_id: TaskId,
+    },
+    Uncomplete {
+        task_id: TaskId,
+    },
+}
+
+/// An edit the server refused because the task changed somewhere else, kept until
+/// the user picks which version to keep.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TaskConflict {
+    pub latest: Task,
+    pub patch_task: PatchTask,
+}
+
+enum Sent {
+    Created(Task),
+    Version(TaskId, Option<i32>),
+    Deleted,
+}
+
+thread_local! {
+    static FLUSHING: Cell<bool> = Cell::new(false);
+}
+
+impl Mutation {
+    fn task_id_mut(&mut self) -> &mut TaskId {
+        match self {
+            Mutation::Create { temp_id, .. } => temp_id,
+            Mutation::Update { task_id, .. }
+            | Mutation::Delete { task_id, .. }
+            | Mutation::Complete { task_id }
+            | Mutation::Uncomplete { task_id } => task_id,
+        }
+    }
+
+    fn task_id(&self) -> TaskId {
+        match self {
+            Mutation::Create { temp_id, .. } => *temp_id,
+            Mutation::Update { task_id, .. }
+            | Mutation::Delete { task_id, .. }
+            | Mutation::Complete { task_id }
+            | Mutation::Uncomplete { task_id } => *task_id,
+        }
+    }
+
+    fn version_mut(&mut self) -> Option<&mut Option<i32>> {
+        match self {
+            Mutation::Update { version, .. } | Mutation::Delete { version, .. } => Some(version),
+            _ => None,
+        }
+    }
+}
+
+pub fn is_temp_id(task_id: TaskId) -> bool {
+    task_id < 0
+}
+
+pub fn queue_create(
+    dispatch: StoreDispatch,
+    title: String,
+    description: Option<String>,
+    priority: String,
+) {
+    dispatch.reduce(move |store| {
+        let temp_id = -1 - store.temp_ids_issued as TaskId;
+        store.temp_ids_issued += 1;
+        store.tasks.push(Task {
+            completed_at: None,
+            description: description.clone(),
+            id: temp_id,
+            priority: Some(priority.clone()),
+            title: title.clone(),
+            version: 0,
+        });
+        store.outbox.push(PendingMutation::new(Mutation::Create {
+            temp_id,
+            title,
+            description,
+            priority,
+        }));
+    });
+}
+
+pub fn queue_update(
+    dispatch: StoreDispatch,
+    task_id: TaskId,
+    patch_task: PatchTask,
+    version: Option<i32>,
+) {
+    store::update_task_by_id(dispatch.clone(), task_id, patch_task.clone());
+    push(
+        dispatch,
+        Mutation::Update {
+            task_id,
+            patch_task,
+            version,
+        },
+    );
+}
+
+pub fn queue_delete(dispatch: StoreDispatch, task_id: TaskId, version: Option<i32>) {
+    store::remove_task_by_id(dispatch.clone(), task_id);
+    if is_temp_id(task_id) {
+        // the server never heard of it, so forget everything we were going to send
+        dispatch.reduce(move |store| {
+            store
+                .outbox
+                .retain(|pending| pending.mutation.task_id() != task_id);
+        });
+    } else {
+        push(dispatch, Mutation::Delete { task_id, version });
+    }
+}
+
+pub fn queue_complete(dispatch: StoreDispatch, task_id: TaskId) {
+    store::mark_task_completed(dispatch.clone(), task_id);
+    push(dispatch, Mutation::Complete { task_id });
+}
+
+pub fn queue_uncomplete(dispatch: StoreDispatch, task_id: TaskId) {
+    store::mark_task_uncompleted(dispatch.clone(), task_id);
+    push(dispatch, Mutation::Uncomplete { task_id });
+}
+
+/// Applies a change that is still queued to a list of tasks fresh from the server.
+pub fn replay(tasks: &mut Vec<Task>, mutation: &Mutation) {
+    let task_id = mutation.task_id();
+    if let Mutation::Delete { .. } = mutation {
+        tasks.retain(|task| task.id != task_id);
+        return;
+    }
+    // tasks created offline are kept by the caller
+    let task = match tasks.iter_mut().find(|task| task.id == task_id) {
+        Some(task) => task,
+        None => return,
+    };
+    match mutation {
+        Mutation::Update { patch_task, .. } => store::apply_patch(task, patch_task.clone()),
+        Mutation::Complete { .. } => {
+            if task.completed_at.is_none() {
+                task.completed_at = Some(patch_task::now());
+            }
+        }
+        Mutation::Uncomplete { .. } => task.completed_at = None,
+        Mutation::Create { .. } | Mutation::Delete { .. } => {}
+    }
+}
+
+pub fn resolve_conflict(dispatch: StoreDispatch, task_id: TaskId) {
+    dispatch.reduce(move |store| {
+        store
+            .conflicts
+            .retain(|conflict| conflict.latest.id != task_id);
+    });
+}
+
+/// Sends the queued changes in order. Stops at the first one that can't reach the
+/// server and leaves it and everything after it queued for the next try.
+pub async fn flush(token: String, outbox: Vec<PendingMutation>, dispatch: StoreDispatch) {
+    if token.is_empty() || outbox.is_empty() || FLUSHING.with(|flushing| flushing.replace(true)) {
+        return;
+    }
+    let mut outbox: VecDeque<PendingMutation> = outbox.into();
+    while let Some(pending) = outbox.pop_front() {
+        match send(&token, &pending).await {
+            Ok(sent) => {
+                apply_sent(&pending, sent, &mut outbox, dispatch.clone());
+                remove(dispatch.clone(), &pending.idempotency_key);
+            }
+            Err(ApiError::Network) => break,
+            Err(ApiError::NotAuthenticated) => {
+                store::logout(dispatch.clone());
+                break;
+            }
+            Err(ApiError::Conflict) => {
+                handle_conflict(&token, &pending, dispatch.clone()).await;
+                remove(dispatch.clone(), &pending.idempotency_key);
+            }
+            Err(error) => {
+                console::error!("dropping a change the server refused", error.to_string());
+                store::set_error_message(
+                    dispatch.clone(),
+                    "One of your changes could not be saved",
+                );
+                remove(dispatch.clone(), &pending.idempotency_key);
+            }
+        }
+    }
+    FLUSHING.with(|flushing| flushing.set(false));
+}
+
+impl PendingMutation {
+    fn new(mutation: Mutation) -> Self {
+        Self {
+            idempotency_key: idempotency_key(),
+            mutation,
+        }
+    }
+}
+
+fn push(dispatch: StoreDispatch, mutation: Mutation) {
+    dispatch.reduce(move |store| store.outbox.push(PendingMutation::new(mutation)));
+}
+
+fn remove(dispatch: StoreDispatch, key: &str) {
+    let key = key.to_owned();
+    dispatch.reduce(move |store| {
+        store
+            .outbox
+            .retain(|pending| pending.idempotency_key != key);
+    });
+}
+
+async fn send(token: &str, pending: &PendingMutation) -> Result<Sent, ApiError> {
+    let key = &pending.idempotency_key;
+    match pending.mutation.clone() {
+        Mutation::Create {
+            title,
+            description,
+            priority,
+            ..
+        } => {
+            let response = api::create_task(token, key, title, description, priority).await?;
+            Ok(Sent::Created(response.data))
+        }
+        Mutation::Update {
+            task_id,
+            patch_task,
+            version,
+        } => {
+            let version = api::update_task(task_id, token, key, patch_task, version).await?;
+            Ok(Sent::Version(task_id, version))
+        }
+        Mutation::Delete { task_id, version } => {
+            api::delete_task(task_id, token, key, version).await?;
+            Ok(Sent::Deleted)
+        }
+        Mutation::Complete { task_id } => {
+            let version = api::complete_task(task_id, token, key).await?;
+            Ok(Sent::Version(task_id, version))
+        }
+        Mutation::Uncomplete { task_id } => {
+            let version = api::uncomplete_task(task_id, token, key).await?;
+            Ok(Sent::Version(task_id, version))
+        }
+    }
+}
+
+// the rest of the queue was written against what we knew before this change went
+// through, so point it at the real id and the new version
+fn apply_sent(
+    pending: &PendingMutation,
+    sent: Sent,
+    outbox: &mut VecDeque<PendingMutation>,
+    dispatch: StoreDispatch,
+) {
+    match sent {
+        Sent::Created(task) => {
+            let temp_id = pending.mutation.task_id();
+            let (task_id, version) = (task.id, store::known_version(&task));
+            outbox
+                .iter_mut()
+                .for_each(|pending| reconcile(pending, temp_id, task_id, version));
+            dispatch.reduce(move |store| {
+                store.tasks.retain(|stored| stored.id != task_id);
+                if let Some(stored) = store.tasks.iter_mut().find(|stored| stored.id == temp_id) {
+                    stored.id = task_id;
+                    stored.version = version.unwrap_or_default();
+                }
+                store
+                    .outbox
+                    .iter_mut()
+                    .for_each(|pending| reconcile(pending, temp_id, task_id, version));
+            });
+        }
+        Sent::Version(task_id, Some(version)) => {
+            outbox
+                .iter_mut()
+                .for_each(|pending| bump_version(pending, task_id, version));
+            dispatch.reduce(move |store| {
+                if let Some(task) = store.tasks.iter_mut().find(|task| task.id == task_id) {
+                    task.version = version;
+                }
+                store
+                    .outbox
+                    .iter_mut()
+                    .for_each(|pending| bump_version(pending, task_id, version));
+            });
+        }
+        Sent::Version(_, None) | Sent::Deleted => {}
+    }
+}
+
+fn reconcile(
+    pending: &mut PendingMutation,
+    temp_id: TaskId,
+    task_id: TaskId,
+    version: Option<i32>,
+) {
+    if pending.mutation.task_id() == temp_id {
+        *pending.mutation.task_id_mut() = task_id;
+        if let Some(pending_version) = pending.mutation.version_mut() {
+            *pending_version = version;
+        }
+    }
+}
+
+fn bump_version(pending: &mut PendingMutation, task_id: TaskId, version: i32) {
+    if pending.mutation.task_id() != task_id {
+        return;
+    }
+    if let Some(pending_version) = pending.mutation.version_mut() {
+        if pending_version.is_some() {
+            *pending_version = Some(version);
+        }
+    }
+}
+
+async fn handle_conflict(token: &str, pending: &PendingMutation, dispatch: StoreDispatch) {
+    let task_id = pending.mutation.task_id();
+    let latest = match api::get_task(task_id, token).await {
+        Ok(latest) => latest.data,
+        Err(error) => {
+            console::error!("could not fetch the conflicting task", error.to_string());
+            return;
+        }
+    };
+    store::replace_task(dispatch.clone(), latest.clone());
+    match pending.mutation.clone() {
+        Mutation::Update { patch_task, .. } => {
+            dispatch.reduce(move |store| {
+                store
+                    .conflicts
+                    .retain(|conflict| conflict.latest.id != task_id);
+                store.conflicts.push(TaskConflict { latest, patch_task });
+            });
+            store::set_error_message(
+                dispatch,
+                "A task you edited was changed somewhere else, open it to choose which version to keep",
+            );
+        }
+        _ => store::set_error_message(
+            dispatch,
+            "This task was changed somewhere else, check it before deleting",
+        ),
+    }
+}
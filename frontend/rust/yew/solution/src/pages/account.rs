@@ -0,0 +1,191 @@
+use std::ops::Deref;
+
+use crate::api;
+use crate::components::atoms::bb_button::{BBButton, ButtonColor};
+use crate::components::atoms::bb_link::BBLink;
+use crate::components::atoms::bb_text_input::{BBTextInput, InputType};
+use crate::components::molecules::sso_button::SsoButton;
+use crate::router::Route;
+use crate::store::{self, login_reducer, set_error_message, StoreType};
+use stylist::yew::styled_component;
+use wasm_bindgen_futures::spawn_local;
+use yew::prelude::*;
+use yew_router::{history::History, hooks::use_history};
+use yewdux_functional::use_store;
+
+#[styled_component(Account)]
+pub fn account() -> Html {
+    let stylesheet = css!(
+        r#"
+          section {
+            display: flex;
+            justify-content: center;
+          }
+
+          section > div {
+            width: 75vw;
+          }
+
+          form > div, .danger {
+            margin-top: 10px;
+          }
+        "#
+    );
+
+    let store = use_store::<StoreType>();
+    let dispatch = store.dispatch().clone();
+    let (username, token) = store
+        .state()
+        .map(|store| (store.username.clone(), store.token.clone()))
+        .unwrap_or_default();
+    let history = use_history().unwrap();
+
+    let new_username = {
+        let username = username.clone();
+        use_state(move || username)
+    };
+    let email = use_state(String::new);
+    let current_password = use_state(String::new);
+    let new_password = use_state(String::new);
+
+    let username_onchange = {
+        let new_username = new_username.clone();
+        Callback::from(move |username: String| new_username.set(username))
+    };
+    let email_onchange = {
+        let email = email.clone();
+        Callback::from(move |new_email: String| email.set(new_email))
+    };
+    let current_password_onchange = {
+        let current_password = current_password.clone();
+        Callback::from(move |password: String| current_password.set(password))
+    };
+    let new_password_onchange = {
+        let new_password = new_password.clone();
+        Callback::from(move |password: String| new_password.set(password))
+    };
+
+    let username_onsubmit = {
+        let token = token.clone();
+        let dispatch = dispatch.clone();
+        let new_username = new_username;
+        Callback::from(move |event: FocusEvent| {
+            event.prevent_default();
+            let token = token.clone();
+            let dispatch = dispatch.clone();
+            let username = new_username.deref().clone();
+            spawn_local(async move {
+                match api::change_username(&token, username).await {
+                    Ok(result) => login_reducer(result, dispatch),
+                    Err(error) => set_error_message(dispatch, &error.to_string()),
+                }
+            });
+        })
+    };
+
+    let email_onsubmit = {
+        let token = token.clone();
+        let dispatch = dispatch.clone();
+        let email = email;
+        Callback::from(move |event: FocusEvent| {
+            event.prevent_default();
+            let token = token.clone();
+            let dispatch = dispatch.clone();
+            let email = email.deref().clone();
+            spawn_local(async move {
+                if let Err(error) = api::change_email(&token, email).await {
+                    set_error_message(dispatch, &error.to_string());
+                }
+            });
+        })
+    };
+
+    let password_onsubmit = {
+        let token = token.clone();
+        let dispatch = dispatch.clone();
+        let history = history.clone();
+        let current_password = current_password;
+        let new_password = new_password;
+        Callback::from(move |event: FocusEvent| {
+            event.prevent_default();
+            let token = token.clone();
+            let dispatch = dispatch.clone();
+            let history = history.clone();
+            let current_password = current_password.deref().clone();
+            let new_password = new_password.deref().clone();
+            spawn_local(async move {
+                match api::change_password(&token, current_password, new_password).await {
+                    // the new token logs out every other tab and device
+                    Ok(result) => {
+                        login_reducer(result, dispatch);
+                        history.push(Route::Home);
+                    }
+                    Err(error) => set_error_message(dispatch, &error.to_string()),
+                }
+            });
+        })
+    };
+
+    // the SSO button needs it after delete_onclick takes it
+    let sso_token = Some(token.clone());
+
+    let delete_onclick = Callback::from(move |event: MouseEvent| {
+        event.prevent_default();
+        if !gloo::dialogs::confirm("Delete your account and all of your tasks?") {
+            return;
+        }
+        let token = token.clone();
+        let dispatch = dispatch.clone();
+        let history = history.clone();
+        spawn_local(async move {
+            match api::delete_account(&token).await {
+                Ok(()) => {
+                    store::logout(dispatch);
+                    history.push(Route::Home);
+                }
+                Err(error) => set_error_message(dispatch, &error.to_string()),
+            }
+        });
+    });
+
+    html! {
+      <div class={stylesheet}>
+        <h1>{"Account"}</h1>
+        <section>
+          <div>
+            <form onsubmit={username_onsubmit}>
+              <BBTextInput data_test="new-username" label="Username" placeholder="What username do you want?" class="input" input_type={InputType::Text} onchange={username_onchange} value={Some(username)} />
+              <div>
+                <BBButton label="Change Username" data_test="change-username" />
+              </div>
+            </form>
+            <form onsubmit={email_onsubmit}>
+              <BBTextInput data_test="email" label="Email" placeholder="Where should password reset links go?" class="input" input_type={InputType::Text} onchange={email_onchange} />
+              <div>
+                <BBButton label="Change Email" data_test="change-email" />
+              </div>
+            </form>
+            <form onsubmit={password_onsubmit}>
+              <BBTextInput data_test="current-password" label="Current Password" placeholder="What is your password now?" class="input" input_type={InputType::Password} onchange={current_password_onchange} />
+              <BBTextInput data_test="new-password" label="New Password" placeholder="What do you want it to be?" class="input" input_type={InputType::Password} onchange={new_password_onchange} />
+              <div>
+                <BBButton label="Change Password" data_test="change-password" />
+              </div>
+            </form>
+            <div class="danger">
+              <BBLink text={"Two-Factor Authentication".to_owned()} data_test={"two-factor".to_owned()} route={Route::TwoFactor} />
+            </div>
+            <div class="danger">
+              <BBLink text={"API Tokens".to_owned()} data_test={"api-tokens".to_owned()} route={Route::ApiTokens} />
+            </div>
+            <div class="danger">
+              <SsoButton label="Link SSO Account" data_test="sso-link" token={sso_token} />
+            </div>
+            <div class="danger">
+              <BBButton label="Delete Account" data_test="delete-account" color={ButtonColor::Red} onclick={delete_onclick} />
+            </div>
+          </div>
+        </section>
+      </div>
+    }
+}
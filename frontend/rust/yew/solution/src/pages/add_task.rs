@@ -1,13 +1,12 @@
 use std::ops::Deref;
 
-use crate::api;
-use crate::api::api_errors::ApiError;
 use crate::components::atoms::bb_button::{BBButton, ButtonColor};
 use crate::components::atoms::bb_select::{BBSelect, SelectOption};
 use crate::components::atoms::bb_text_input::{BBTextInput, InputType};
 use crate::components::atoms::bb_textarea::BBTextarea;
+use crate::outbox;
 use crate::router::Route;
-use crate::store::{add_task, StoreType};
+use crate::store::StoreType;
 use stylist::css;
 use stylist::yew::styled_component;
 use yew::prelude::*;
@@ -61,35 +60,15 @@ pub fn add_task() -> Html {
         let title = title;
         let description = description;
         let priority = priority;
-        let token = use_store::<StoreType>()
-            .state()
-            .map(|state| state.token.clone())
-            .unwrap_or_default();
         let history = use_history().unwrap();
-        let dispatch = use_store().dispatch().clone();
+        let dispatch = use_store::<StoreType>().dispatch().clone();
         Callback::from(move |event: FocusEvent| {
             event.prevent_default();
-            let token = token.clone();
             let title = title.deref().clone();
             let description = description.deref().clone();
             let priority = priority.deref().clone();
-            let history = history.clone();
-            let dispatch = dispatch.clone();
-            wasm_bindgen_futures::spawn_local(async move {
-                match api::create_task(&token, title, description, priority).await {
-                    Ok(task_response) => {
-                        add_task(dispatch, task_response.data);
-                        history.push(Route::Home);
-                    }
-                    Err(ApiError::NotAuthenticated) => {
-                        history.push(Route::Home);
-                    }
-                    Err(error) => {
-                        gloo::console::error!(error.to_string());
-                        panic!();
-                    }
-                };
-            });
+            outbox::queue_create(dispatch.clone(), title, description, priority);
+            history.push(Route::Home);
         })
     };
 
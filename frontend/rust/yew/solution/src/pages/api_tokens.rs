@@ -0,0 +1,209 @@
+use std::ops::Deref;
+
+use crate::api;
+use crate::components::atoms::bb_button::{BBButton, ButtonColor};
+use crate::components::atoms::bb_select::{BBSelect, SelectOption};
+use crate::components::atoms::bb_text::{BBText, Color};
+use crate::components::atoms::bb_text_input::{BBTextInput, InputType};
+use crate::store::{set_error_message, StoreType};
+use chrono::NaiveDateTime;
+use stylist::yew::styled_component;
+use todo_api::{ApiToken, ApiTokenId, CreateApiTokenRequest, TokenScope};
+use wasm_bindgen_futures::spawn_local;
+use yew::prelude::*;
+use yewdux_functional::use_store;
+
+fn when(time: Option<&NaiveDateTime>, otherwise: &str) -> String {
+    time.map(|time| time.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_else(|| otherwise.to_owned())
+}
+
+#[styled_component(ApiTokens)]
+pub fn api_tokens() -> Html {
+    let stylesheet = css!(
+        r#"
+          section {
+            display: flex;
+            justify-content: center;
+          }
+
+          section > div {
+            width: 75vw;
+          }
+
+          form, .token, .new-token {
+            margin-top: 10px;
+          }
+
+          .new-token {
+            font-family: monospace;
+            font-size: 24px;
+            word-break: break-all;
+          }
+        "#
+    );
+
+    let store = use_store::<StoreType>();
+    let dispatch = store.dispatch().clone();
+    let token = store
+        .state()
+        .map(|store| store.token.clone())
+        .unwrap_or_default();
+
+    let api_tokens = use_state(Vec::<ApiToken>::new);
+    // only there right after creating one, the server never shows it again
+    let new_token = use_state(|| None::<String>);
+    let name = use_state(String::new);
+    let scope = use_state(TokenScope::default);
+    let expires_in_days = use_state(String::new);
+
+    {
+        let api_tokens = api_tokens.clone();
+        let dispatch = dispatch.clone();
+        use_effect_with_deps(
+            move |token: &String| {
+                let token = token.clone();
+                spawn_local(async move {
+                    match api::api_tokens(&token).await {
+                        Ok(tokens) => api_tokens.set(tokens),
+                        Err(error) => set_error_message(dispatch, &error.to_string()),
+                    }
+                });
+                || {}
+            },
+            token.clone(),
+        );
+    }
+
+    let name_onchange = {
+        let name = name.clone();
+        Callback::from(move |new_name: String| name.set(new_name))
+    };
+
+    let scope_onchange = {
+        let scope = scope.clone();
+        Callback::from(move |new_scope: String| {
+            scope.set(TokenScope::parse(&new_scope).unwrap_or_default())
+        })
+    };
+
+    let expires_onchange = {
+        let expires_in_days = expires_in_days.clone();
+        Callback::from(move |days: String| expires_in_days.set(days))
+    };
+
+    let create_onsubmit = {
+        let token = token.clone();
+        let dispatch = dispatch.clone();
+        let api_tokens = api_tokens.clone();
+        let new_token = new_token.clone();
+        let scope = scope.clone();
+        Callback::from(move |event: FocusEvent| {
+            event.prevent_default();
+            let expires_in_days = match expires_in_days.trim() {
+                "" => None,
+                days => match days.parse::<i32>() {
+                    Ok(days) => Some(days),
+                    Err(_) => {
+                        set_error_message(dispatch.clone(), "Expires in days has to be a number");
+                        return;
+                    }
+                },
+            };
+            let request = CreateApiTokenRequest {
+                name: name.deref().clone(),
+                scope: *scope,
+                expires_in_days,
+            };
+            let token = token.clone();
+            let dispatch = dispatch.clone();
+            let api_tokens = api_tokens.clone();
+            let new_token = new_token.clone();
+            spawn_local(async move {
+                match api::create_api_token(&token, request).await {
+                    Ok(created) => {
+                        let mut tokens = api_tokens.deref().clone();
+                        tokens.push(created.api_token);
+                        api_tokens.set(tokens);
+                        new_token.set(Some(created.token));
+                    }
+                    Err(error) => set_error_message(dispatch, &error.to_string()),
+                }
+            });
+        })
+    };
+
+    let revoke = {
+        let api_tokens = api_tokens.clone();
+        Callback::from(move |id: ApiTokenId| {
+            let token = token.clone();
+            let dispatch = dispatch.clone();
+            let api_tokens = api_tokens.clone();
+            spawn_local(async move {
+                match api::revoke_api_token(&token, id).await {
+                    Ok(()) => {
+                        let mut tokens = api_tokens.deref().clone();
+                        tokens.retain(|api_token| api_token.id != id);
+                        api_tokens.set(tokens);
+                    }
+                    Err(error) => set_error_message(dispatch, &error.to_string()),
+                }
+            });
+        })
+    };
+
+    let scope_options = TokenScope::ALL
+        .iter()
+        .map(|option| {
+            let label = match option {
+                TokenScope::ReadOnly => "Read only",
+                TokenScope::ReadWrite => "Read and write",
+            };
+            SelectOption::new(option.as_str(), label, *option == *scope)
+        })
+        .collect::<Vec<SelectOption>>();
+
+    html! {
+      <div class={stylesheet}>
+        <h1>{"API Tokens"}</h1>
+        <section>
+          <div>
+            <BBText data_test="api-tokens-info" text="Scripts log in with a token in an Authorization: Bearer header instead of your password." />
+            if let Some(new_token) = new_token.deref() {
+              <div class="token">
+                <BBText data_test="new-token-info" text="Copy this token now, it won't be shown again." color={Color::Info} />
+              </div>
+              <div class="new-token" data-test="new-token">{new_token}</div>
+            }
+            <form onsubmit={create_onsubmit}>
+              <BBTextInput data_test="token-name" label="Name" placeholder="What will use it?" class="input" input_type={InputType::Text} onchange={name_onchange} />
+              <BBSelect data_test="token-scope" id="token-scope" label="Scope" options={scope_options} onchange={scope_onchange} />
+              <BBTextInput data_test="token-expires" label="Expires In Days" placeholder="Leave empty to keep it until it's revoked" class="input" input_type={InputType::Text} onchange={expires_onchange} />
+              <div>
+                <BBButton label="Create Token" data_test="create-token" />
+              </div>
+            </form>
+            { for api_tokens.iter().map(|api_token| {
+                let id = api_token.id;
+                let onclick = revoke.reform(move |event: MouseEvent| {
+                    event.prevent_default();
+                    id
+                });
+                html! {
+                  <div class="token" data-test="api-token">
+                    <BBText data_test="token-summary" text={format!(
+                      "{} ({}), expires {}, last used {}",
+                      api_token.name,
+                      api_token.scope.as_str(),
+                      when(api_token.expires_at.as_ref(), "never"),
+                      when(api_token.last_used_at.as_ref(), "never"),
+                    )} />
+                    <BBButton label="Revoke" data_test="revoke-token" color={ButtonColor::Red} {onclick} />
+                  </div>
+                }
+            }) }
+          </div>
+        </section>
+      </div>
+    }
+}
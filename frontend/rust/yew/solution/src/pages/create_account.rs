@@ -1,7 +1,7 @@
 use crate::api;
 use crate::components::molecules::account_form::{AccountForm, Action, User};
 use crate::router::Route;
-use crate::store::login_reducer;
+use crate::store::{login_reducer, set_error_message};
 use crate::store::Store;
 use stylist::yew::styled_component;
 use wasm_bindgen_futures::spawn_local;
@@ -36,9 +36,13 @@ pub fn create_account() -> Html {
             let store_dispatch = store_dispatch.clone();
 
             spawn_local(async move {
-                let result = api::create_account(user.username, user.password).await;
-                history.push(Route::Home);
-                login_reducer(result, store_dispatch);
+                match api::create_account(user.username, user.password).await {
+                    Ok(result) => {
+                        history.push(Route::Home);
+                        login_reducer(result, store_dispatch);
+                    }
+                    Err(error) => set_error_message(store_dispatch, &error.to_string()),
+                }
             });
         })
     };
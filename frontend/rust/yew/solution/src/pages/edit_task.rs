@@ -1,25 +1,28 @@
 use std::ops::Deref;
 
-use crate::api::patch_task::PatchTask;
-use crate::api::{self};
+use crate::api::patch_task;
 use crate::components::atoms::bb_button::{BBButton, ButtonColor};
 use crate::components::atoms::bb_checkbox::{BBCheckbox, OnchangeData};
 use crate::components::atoms::bb_select::{BBSelect, SelectOption};
+use crate::components::atoms::bb_text::{BBText, Color};
 use crate::components::atoms::bb_textarea::BBTextarea;
+use crate::outbox::{self, TaskConflict};
 use crate::router::Route;
-use crate::store::update_task_by_id;
+use crate::store::{known_version, replace_task};
 use crate::{
     components::atoms::bb_text_input::{BBTextInput, InputType},
     store::StoreType,
 };
+use chrono::NaiveDateTime;
 use stylist::yew::styled_component;
+use todo_api::TaskId;
 use yew::prelude::*;
 use yew_router::prelude::*;
 use yewdux_functional::use_store;
 
 #[derive(Clone, Properties, PartialEq)]
 pub struct Props {
-    pub id: u32,
+    pub id: TaskId,
 }
 
 #[styled_component(EditTask)]
@@ -33,9 +36,24 @@ pub fn edit_task(props: &Props) -> Html {
       .buttons button {
         margin-right: 10px;
       }
+
+      .conflict {
+        border: 1px solid red;
+        padding: 10px;
+        margin-top: 15px;
+      }
     "#
     );
 
+    // the server's copy of the task and the edit it rejected, while the user decides
+    let conflict = use_store::<StoreType>().state().and_then(|store| {
+        store
+            .conflicts
+            .iter()
+            .find(|conflict| conflict.latest.id == props.id)
+            .cloned()
+    });
+
     let title_state = use_state(|| None);
     let description_state = use_state(|| None);
     let priority_state = use_state(|| None);
@@ -70,33 +88,54 @@ pub fn edit_task(props: &Props) -> Html {
         let description_state = description_state;
         let priority_state = priority_state;
         let completed_state = completed_state.clone();
-        let token = use_store::<StoreType>()
+        let version = use_store::<StoreType>()
             .state()
-            .map(|state| state.token.clone())
-            .unwrap_or_default();
+            .and_then(|state| state.get_task_by_id(props.id))
+            .and_then(|task| known_version(&task));
         let task_id = props.id;
         let history = use_history().unwrap();
-        let dispatch = use_store().dispatch().clone();
+        let dispatch = use_store::<StoreType>().dispatch().clone();
         Callback::from(move |event: FocusEvent| {
             event.prevent_default();
-            let patch_task = PatchTask::new(
+            let patch_task = patch_task::new(
                 title_state.deref().clone(),
                 priority_state.deref().clone(),
                 description_state.deref().clone(),
                 *completed_state.deref(),
             );
-            let token = token.clone();
-            let task_id = task_id;
-            let history = history.clone();
-            let dispatch = dispatch.clone();
+            outbox::queue_update(dispatch.clone(), task_id, patch_task, version);
+            history.push(Route::OneTask { id: task_id });
+        })
+    };
+
+    let keep_mine_onclick = {
+        let conflict = conflict.clone();
+        let task_id = props.id;
+        let history = use_history().unwrap();
+        let dispatch = use_store::<StoreType>().dispatch().clone();
+        Callback::from(move |event: MouseEvent| {
+            event.prevent_default();
+            let TaskConflict { latest, patch_task } = match conflict.clone() {
+                Some(conflict) => conflict,
+                None => return,
+            };
+            // overwrite on top of the version we were just shown
+            let version = known_version(&latest);
+            replace_task(dispatch.clone(), latest);
+            outbox::resolve_conflict(dispatch.clone(), task_id);
+            outbox::queue_update(dispatch.clone(), task_id, patch_task, version);
+            history.push(Route::OneTask { id: task_id });
+        })
+    };
 
-            wasm_bindgen_futures::spawn_local(async move {
-                api::update_task(task_id, &token, patch_task.clone())
-                    .await
-                    .unwrap();
-                history.push(Route::OneTask { id: task_id });
-                update_task_by_id(dispatch, task_id, patch_task)
-            });
+    let use_theirs_onclick = {
+        let task_id = props.id;
+        let history = use_history().unwrap();
+        let dispatch = use_store::<StoreType>().dispatch().clone();
+        Callback::from(move |event: MouseEvent| {
+            event.prevent_default();
+            outbox::resolve_conflict(dispatch.clone(), task_id);
+            history.push(Route::OneTask { id: task_id });
         })
     };
 
@@ -151,6 +190,22 @@ pub fn edit_task(props: &Props) -> Html {
             <BBButton data_test="cancel" label="Cancel" onclick={cancel_onclick} color={ButtonColor::Red} />
           </div>
         </form>
+        if let Some(TaskConflict { latest, .. }) = conflict {
+          <div class="conflict" data-test="conflict">
+            <BBText
+              data_test="conflict-message"
+              text="This task was changed somewhere else while you were editing it. The saved version is:"
+              color={Color::Danger}
+            />
+            <BBText data_test="conflict-title" text={latest.title} />
+            <BBText data_test="conflict-priority" text={latest.priority.unwrap_or_default()} color={Color::Info} />
+            <BBText data_test="conflict-description" text={latest.description.unwrap_or_default()} />
+            <div class="buttons">
+              <BBButton data_test="conflict-keep-mine" label="Save mine anyway" onclick={keep_mine_onclick} />
+              <BBButton data_test="conflict-use-theirs" label="Use saved version" onclick={use_theirs_onclick} color={ButtonColor::Red} />
+            </div>
+          </div>
+        }
       </section>
     }
 }
@@ -166,7 +221,7 @@ fn create_priority_options(task: Option<String>) -> Vec<SelectOption> {
     select_options
 }
 
-fn is_completed(task_completed_at: Option<&String>, state_completed: Option<bool>) -> bool {
+fn is_completed(task_completed_at: Option<&NaiveDateTime>, state_completed: Option<bool>) -> bool {
     if let Some(completed) = state_completed {
         completed
     } else {
@@ -0,0 +1,70 @@
+use std::ops::Deref;
+
+use crate::api;
+use crate::components::atoms::bb_button::BBButton;
+use crate::components::atoms::bb_text::{BBText, Color};
+use crate::components::atoms::bb_text_input::{BBTextInput, InputType};
+use crate::store::{set_error_message, StoreType};
+use stylist::yew::styled_component;
+use wasm_bindgen_futures::spawn_local;
+use yew::prelude::*;
+use yewdux_functional::use_store;
+
+#[styled_component(ForgotPassword)]
+pub fn forgot_password() -> Html {
+    let stylesheet = css!(
+        r#"
+          section {
+            display: flex;
+            justify-content: center;
+          }
+
+          section > div {
+            width: 75vw;
+          }
+        "#
+    );
+
+    let dispatch = use_store::<StoreType>().dispatch().clone();
+    let email = use_state(String::new);
+    let sent = use_state(|| false);
+
+    let email_onchange = {
+        let email = email.clone();
+        Callback::from(move |new_email: String| email.set(new_email))
+    };
+
+    let onsubmit = {
+        let sent = sent.clone();
+        Callback::from(move |event: FocusEvent| {
+            event.prevent_default();
+            let email = email.deref().clone();
+            let dispatch = dispatch.clone();
+            let sent = sent.clone();
+            spawn_local(async move {
+                match api::forgot_password(email).await {
+                    Ok(()) => sent.set(true),
+                    Err(error) => set_error_message(dispatch, &error.to_string()),
+                }
+            });
+        })
+    };
+
+    html! {
+      <div class={stylesheet}>
+        <h1>{"Forgot Password"}</h1>
+        <section>
+          <div>
+            if *sent {
+              <BBText data_test="reset-sent" text="If that email belongs to an account, a reset link is on its way." color={Color::Info} />
+            } else {
+              <form {onsubmit}>
+                <BBTextInput data_test="email" label="Email" placeholder="The email on your account" class="input" input_type={InputType::Text} onchange={email_onchange} />
+                <BBButton label="Send Reset Link" data_test="submit" />
+              </form>
+            }
+          </div>
+        </section>
+      </div>
+    }
+}
@@ -1,8 +1,14 @@
+use std::ops::Deref;
+
 use crate::api;
+use crate::api::api_errors::ApiError;
+use crate::components::atoms::bb_link::BBLink;
 use crate::components::molecules::account_form::{AccountForm, Action, User};
+use crate::components::molecules::sso_button::SsoButton;
+use crate::components::molecules::two_factor_form::TwoFactorForm;
 use crate::router::Route;
-use crate::store::login_reducer;
 use crate::store::Store;
+use crate::store::{login_reducer, set_error_message};
 use stylist::yew::styled_component;
 use wasm_bindgen_futures::spawn_local;
 use yew::prelude::*;
@@ -22,23 +28,66 @@ pub fn login() -> Html {
           section > div {
             width: 75vw;
           }
+
+          .sso {
+            margin-top: 10px;
+          }
         "#
     );
 
     let history = use_history().unwrap();
     let store = use_store::<PersistentStore<Store>>();
     let store_dispatch = store.dispatch();
+    // set once the password was right for an account with two-factor authentication
+    let challenge = use_state(|| None::<String>);
 
     let onsubmit = {
         let store_dispatch = store_dispatch.clone();
+        let history = history.clone();
+        let challenge = challenge.clone();
         Callback::from(move |user: User| {
             let history = history.clone();
             let store_dispatch = store_dispatch.clone();
+            let challenge = challenge.clone();
+
+            spawn_local(async move {
+                match api::login(user.username, user.password).await {
+                    Ok(result) => {
+                        history.push(Route::Home);
+                        login_reducer(result, store_dispatch);
+                    }
+                    Err(ApiError::TwoFactorRequired(new_challenge)) => {
+                        challenge.set(Some(new_challenge))
+                    }
+                    Err(error) => set_error_message(store_dispatch, &error.to_string()),
+                }
+            });
+        })
+    };
+
+    let code_onsubmit = {
+        let store_dispatch = store_dispatch.clone();
+        let challenge = challenge.clone();
+        Callback::from(move |code: String| {
+            let history = history.clone();
+            let store_dispatch = store_dispatch.clone();
+            let challenge = challenge.clone();
 
             spawn_local(async move {
-                let result = api::login(user.username, user.password).await;
-                history.push(Route::Home);
-                login_reducer(result, store_dispatch);
+                let pending = challenge.deref().clone().unwrap_or_default();
+                match api::login_two_factor(pending, code).await {
+                    Ok(result) => {
+                        history.push(Route::Home);
+                        login_reducer(result, store_dispatch);
+                    }
+                    Err(error) => {
+                        // an expired challenge needs the password again
+                        if !matches!(&error, ApiError::Rejected(message) if message == "incorrect code") {
+                            challenge.set(None);
+                        }
+                        set_error_message(store_dispatch, &error.to_string());
+                    }
+                }
             });
         })
     };
@@ -48,7 +97,15 @@ pub fn login() -> Html {
         <h1>{"Login"}</h1>
         <section>
           <div>
-            <AccountForm {onsubmit} action={Action::Login} />
+            if challenge.is_some() {
+              <TwoFactorForm onsubmit={code_onsubmit} label="Login" recovery_codes={true} />
+            } else {
+              <AccountForm {onsubmit} action={Action::Login} />
+              <div class="sso">
+                <SsoButton label="Sign in with SSO" data_test="sso-login" />
+              </div>
+            }
+            <BBLink text={"Forgot your password?".to_owned()} data_test={"forgot-password".to_owned()} route={Route::ForgotPassword} />
           </div>
         </section>
       </div>
@@ -1,6 +1,12 @@
+pub mod account;
 pub mod add_task;
+pub mod api_tokens;
 pub mod create_account;
 pub mod edit_task;
+pub mod forgot_password;
 pub mod home;
 pub mod login;
 pub mod one_task;
+pub mod reset_password;
+pub mod sso_callback;
+pub mod two_factor;
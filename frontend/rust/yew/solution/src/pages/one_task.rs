@@ -5,12 +5,13 @@ use crate::{
     store::StoreType,
 };
 use stylist::yew::styled_component;
+use todo_api::TaskId;
 use yew::prelude::*;
 use yewdux_functional::use_store;
 
 #[derive(Properties, Clone, PartialEq)]
 pub struct Props {
-    pub id: u32,
+    pub id: TaskId,
 }
 
 #[styled_component(OneTask)]
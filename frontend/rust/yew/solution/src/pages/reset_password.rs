@@ -0,0 +1,82 @@
+use std::ops::Deref;
+
+use crate::api;
+use crate::components::atoms::bb_button::BBButton;
+use crate::components::atoms::bb_text::{BBText, Color};
+use crate::components::atoms::bb_text_input::{BBTextInput, InputType};
+use crate::router::Route;
+use crate::store::{set_error_message, StoreType};
+use serde::Deserialize;
+use stylist::yew::styled_component;
+use wasm_bindgen_futures::spawn_local;
+use yew::prelude::*;
+use yew_router::prelude::*;
+use yewdux_functional::use_store;
+
+#[derive(Deserialize)]
+struct ResetQuery {
+    token: String,
+}
+
+#[styled_component(ResetPassword)]
+pub fn reset_password() -> Html {
+    let stylesheet = css!(
+        r#"
+          section {
+            display: flex;
+            justify-content: center;
+          }
+
+          section > div {
+            width: 75vw;
+          }
+        "#
+    );
+
+    let reset_token = use_location()
+        .and_then(|location| location.query::<ResetQuery>().ok())
+        .map(|query| query.token);
+    let dispatch = use_store::<StoreType>().dispatch().clone();
+    let history = use_history().unwrap();
+    let new_password = use_state(String::new);
+
+    let password_onchange = {
+        let new_password = new_password.clone();
+        Callback::from(move |password: String| new_password.set(password))
+    };
+
+    let onsubmit = {
+        let reset_token = reset_token.clone().unwrap_or_default();
+        Callback::from(move |event: FocusEvent| {
+            event.prevent_default();
+            let reset_token = reset_token.clone();
+            let new_password = new_password.deref().clone();
+            let dispatch = dispatch.clone();
+            let history = history.clone();
+            spawn_local(async move {
+                match api::reset_password(reset_token, new_password).await {
+                    Ok(()) => history.push(Route::Login),
+                    Err(error) => set_error_message(dispatch, &error.to_string()),
+                }
+            });
+        })
+    };
+
+    html! {
+      <div class={stylesheet}>
+        <h1>{"Reset Password"}</h1>
+        <section>
+          <div>
+            if reset_token.is_some() {
+              <form {onsubmit}>
+                <BBTextInput data_test="new-password" label="New Password" placeholder="What do you want it to be?" class="input" input_type={InputType::Password} onchange={password_onchange} />
+                <BBButton label="Reset Password" data_test="submit" />
+              </form>
+            } else {
+              <BBText data_test="no-reset-token" text="Open this page from the link in your reset email." color={Color::Danger} />
+            }
+          </div>
+        </section>
+      </div>
+    }
+}
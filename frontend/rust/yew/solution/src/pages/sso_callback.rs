@@ -0,0 +1,95 @@
+use std::ops::Deref;
+
+use crate::api;
+use crate::components::atoms::bb_link::BBLink;
+use crate::components::atoms::bb_text::{BBText, Color};
+use crate::router::Route;
+use crate::store::{login_reducer, StoreType};
+use serde::Deserialize;
+use stylist::yew::styled_component;
+use wasm_bindgen_futures::spawn_local;
+use yew::prelude::*;
+use yew_router::prelude::*;
+use yewdux_functional::use_store;
+
+// what the identity provider sends the browser back with, the error ones when
+// the user cancelled or it turned them away
+#[derive(Deserialize, Clone, Default)]
+struct CallbackQuery {
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+    error_description: Option<String>,
+}
+
+#[styled_component(SsoCallback)]
+pub fn sso_callback() -> Html {
+    let stylesheet = css!(
+        r#"
+          section {
+            display: flex;
+            justify-content: center;
+          }
+
+          section > div {
+            width: 75vw;
+          }
+        "#
+    );
+
+    let query = use_location()
+        .and_then(|location| location.query::<CallbackQuery>().ok())
+        .unwrap_or_default();
+    let store = use_store::<StoreType>();
+    let dispatch = store.dispatch().clone();
+    // linking an account comes back here logged in already
+    let linking = store
+        .state()
+        .is_some_and(|store| !store.token.is_empty());
+    let history = use_history().unwrap();
+    let failure = use_state(|| None::<String>);
+
+    {
+        let failure = failure.clone();
+        let query = query.clone();
+        use_effect_with_deps(
+            move |_| {
+                match (query.code, query.state) {
+                    (Some(code), Some(state)) => spawn_local(async move {
+                        match api::oidc_callback(code, state).await {
+                            Ok(result) => {
+                                login_reducer(result, dispatch);
+                                history.push(if linking { Route::Account } else { Route::Home });
+                            }
+                            Err(error) => failure.set(Some(error.to_string())),
+                        }
+                    }),
+                    _ => failure.set(Some(
+                        query
+                            .error_description
+                            .or(query.error)
+                            .unwrap_or_else(|| "the SSO login didn't finish".to_owned()),
+                    )),
+                }
+                || {}
+            },
+            (),
+        );
+    }
+
+    html! {
+      <div class={stylesheet}>
+        <h1>{"Single Sign-On"}</h1>
+        <section>
+          <div>
+            if let Some(failure) = failure.deref() {
+              <BBText data_test="sso-failed" text={failure.clone()} color={Color::Danger} />
+              <BBLink text={"Back to Login".to_owned()} data_test={"login".to_owned()} route={Route::Login} />
+            } else {
+              <BBText data_test="sso-logging-in" text="Logging you in..." />
+            }
+          </div>
+        </section>
+      </div>
+    }
+}
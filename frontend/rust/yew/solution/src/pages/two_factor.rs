@@ -0,0 +1,197 @@
+use std::ops::Deref;
+
+use crate::api;
+use crate::components::atoms::bb_button::{BBButton, ButtonColor};
+use crate::components::atoms::bb_text::{BBText, Color};
+use crate::components::atoms::bb_text_input::{BBTextInput, InputType};
+use crate::components::molecules::two_factor_form::TwoFactorForm;
+use crate::store::{set_error_message, StoreType};
+use stylist::yew::styled_component;
+use todo_api::{TwoFactorSetup, TwoFactorStatus};
+use wasm_bindgen_futures::spawn_local;
+use yew::prelude::*;
+use yewdux_functional::use_store;
+
+#[styled_component(TwoFactor)]
+pub fn two_factor() -> Html {
+    let stylesheet = css!(
+        r#"
+          section {
+            display: flex;
+            justify-content: center;
+          }
+
+          section > div {
+            width: 75vw;
+          }
+
+          form, .secret, .recovery-codes {
+            margin-top: 10px;
+          }
+
+          .recovery-codes {
+            font-family: monospace;
+            font-size: 24px;
+          }
+        "#
+    );
+
+    let store = use_store::<StoreType>();
+    let dispatch = store.dispatch().clone();
+    let token = store
+        .state()
+        .map(|store| store.token.clone())
+        .unwrap_or_default();
+
+    let status = use_state(|| None::<TwoFactorStatus>);
+    // enrolment that was started but not confirmed yet
+    let setup = use_state(|| None::<TwoFactorSetup>);
+    // only there right after confirming, the server never shows them again
+    let recovery_codes = use_state(Vec::<String>::new);
+    let password = use_state(String::new);
+
+    {
+        let status = status.clone();
+        let dispatch = dispatch.clone();
+        use_effect_with_deps(
+            move |token: &String| {
+                let token = token.clone();
+                spawn_local(async move {
+                    match api::two_factor_status(&token).await {
+                        Ok(new_status) => status.set(Some(new_status)),
+                        Err(error) => set_error_message(dispatch, &error.to_string()),
+                    }
+                });
+                || {}
+            },
+            token.clone(),
+        );
+    }
+
+    let start_onclick = {
+        let token = token.clone();
+        let dispatch = dispatch.clone();
+        let setup = setup.clone();
+        Callback::from(move |event: MouseEvent| {
+            event.prevent_default();
+            let token = token.clone();
+            let dispatch = dispatch.clone();
+            let setup = setup.clone();
+            spawn_local(async move {
+                match api::start_two_factor(&token).await {
+                    Ok(new_setup) => setup.set(Some(new_setup)),
+                    Err(error) => set_error_message(dispatch, &error.to_string()),
+                }
+            });
+        })
+    };
+
+    let confirm_onsubmit = {
+        let token = token.clone();
+        let dispatch = dispatch.clone();
+        let status = status.clone();
+        let setup = setup.clone();
+        let recovery_codes = recovery_codes.clone();
+        Callback::from(move |code: String| {
+            let token = token.clone();
+            let dispatch = dispatch.clone();
+            let status = status.clone();
+            let setup = setup.clone();
+            let recovery_codes = recovery_codes.clone();
+            spawn_local(async move {
+                match api::confirm_two_factor(&token, code).await {
+                    Ok(codes) => {
+                        status.set(Some(TwoFactorStatus {
+                            enabled: true,
+                            recovery_codes_left: codes.len() as i64,
+                        }));
+                        setup.set(None);
+                        recovery_codes.set(codes);
+                    }
+                    Err(error) => set_error_message(dispatch, &error.to_string()),
+                }
+            });
+        })
+    };
+
+    let password_onchange = {
+        let password = password.clone();
+        Callback::from(move |new_password: String| password.set(new_password))
+    };
+
+    let disable_onsubmit = {
+        let status = status.clone();
+        let recovery_codes = recovery_codes.clone();
+        Callback::from(move |event: FocusEvent| {
+            event.prevent_default();
+            let token = token.clone();
+            let dispatch = dispatch.clone();
+            let status = status.clone();
+            let recovery_codes = recovery_codes.clone();
+            let password = password.deref().clone();
+            spawn_local(async move {
+                match api::disable_two_factor(&token, password).await {
+                    Ok(()) => {
+                        status.set(Some(TwoFactorStatus::default()));
+                        recovery_codes.set(vec![]);
+                    }
+                    Err(error) => set_error_message(dispatch, &error.to_string()),
+                }
+            });
+        })
+    };
+
+    let body = match (status.deref(), setup.deref()) {
+        (Some(status), _) if status.enabled => html! {
+          <>
+            <BBText data_test="two-factor-on" text={format!(
+              "Logging in asks for a code from your authenticator app. {} recovery codes left.",
+              status.recovery_codes_left
+            )} />
+            <form onsubmit={disable_onsubmit}>
+              <BBTextInput data_test="password" label="Password" placeholder="What is your password?" class="input" input_type={InputType::Password} onchange={password_onchange} />
+              <BBButton label="Turn Off" data_test="disable-two-factor" color={ButtonColor::Red} />
+            </form>
+          </>
+        },
+        (_, Some(setup)) => html! {
+          <>
+            <BBText data_test="scan" text="Add this account to your authenticator app by opening the link on your phone, or by typing in the key, then enter the code it shows." />
+            <div class="secret">
+              <a href={setup.otpauth_uri.clone()} data-test="otpauth-uri">{"Open in authenticator app"}</a>
+            </div>
+            <div class="secret">
+              <BBText data_test="secret" text={setup.secret.clone()} color={Color::Info} />
+            </div>
+            <TwoFactorForm onsubmit={confirm_onsubmit} label="Turn On" />
+          </>
+        },
+        (Some(_), None) => html! {
+          <>
+            <BBText data_test="two-factor-off" text="Logging in only asks for your password." />
+            <div class="secret">
+              <BBButton label="Set Up" data_test="start-two-factor" onclick={start_onclick} />
+            </div>
+          </>
+        },
+        // still loading
+        (None, None) => html! {},
+    };
+
+    html! {
+      <div class={stylesheet}>
+        <h1>{"Two-Factor Authentication"}</h1>
+        <section>
+          <div>
+            if !recovery_codes.is_empty() {
+              <BBText data_test="recovery-codes-info" text="Keep these recovery codes somewhere safe. Each logs you in once without the app, and they won't be shown again." color={Color::Info} />
+              <div class="recovery-codes" data-test="recovery-codes">
+                { for recovery_codes.iter().map(|code| html! { <div>{code}</div> }) }
+              </div>
+            }
+            {body}
+          </div>
+        </section>
+      </div>
+    }
+}
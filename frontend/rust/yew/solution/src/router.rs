@@ -1,7 +1,14 @@
+use crate::pages::account::Account;
 use crate::pages::add_task::AddTask;
+use crate::pages::api_tokens::ApiTokens;
 use crate::pages::edit_task::EditTask;
+use crate::pages::forgot_password::ForgotPassword;
 use crate::pages::one_task::OneTask;
+use crate::pages::reset_password::ResetPassword;
+use crate::pages::sso_callback::SsoCallback;
+use crate::pages::two_factor::TwoFactor;
 use crate::pages::{create_account::CreateAccount, home::Home, login::Login};
+use todo_api::TaskId;
 use yew::prelude::*;
 use yew_router::prelude::*;
 
@@ -13,10 +20,24 @@ pub enum Route {
     CreateAccount,
     #[at("/login")]
     Login,
+    #[at("/account")]
+    Account,
+    #[at("/account/two-factor")]
+    TwoFactor,
+    #[at("/account/tokens")]
+    ApiTokens,
+    #[at("/forgot-password")]
+    ForgotPassword,
+    // the link in the reset email, with ?token=
+    #[at("/reset-password")]
+    ResetPassword,
+    // where the identity provider sends the browser back to, with ?code=&state=
+    #[at("/sso-callback")]
+    SsoCallback,
     #[at("/tasks/:id")]
-    OneTask { id: u32 },
+    OneTask { id: TaskId },
     #[at("/tasks/:id/edit")]
-    EditTask { id: u32 },
+    EditTask { id: TaskId },
     #[at("/tasks/add")]
     AddTask,
 }
@@ -26,6 +47,12 @@ pub fn switch(route: &Route) -> Html {
         Route::Home => html! { <Home /> },
         Route::CreateAccount => html! { <CreateAccount /> },
         Route::Login => html! { <Login /> },
+        Route::Account => html! { <Account /> },
+        Route::TwoFactor => html! { <TwoFactor /> },
+        Route::ApiTokens => html! { <ApiTokens /> },
+        Route::ForgotPassword => html! { <ForgotPassword /> },
+        Route::ResetPassword => html! { <ResetPassword /> },
+        Route::SsoCallback => html! { <SsoCallback /> },
         Route::OneTask { id } => html! { <OneTask id={*id} /> },
         Route::EditTask { id } => html! { <EditTask id={*id} />},
         Route::AddTask => html! { <AddTask /> },
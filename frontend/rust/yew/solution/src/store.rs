@@ -1,12 +1,18 @@
 use crate::{
-    api::{patch_task::PatchTask, AuthResponse, TaskResponse},
+    api::{
+        patch_task::{self, PatchTask},
+        AuthResponse, TaskListResponse,
+    },
     components::atoms::bb_select::SelectOption,
+    outbox::{self, PendingMutation, TaskConflict},
 };
 use gloo::console;
-use js_sys::Date;
 use serde::{Deserialize, Serialize};
+use todo_api::TaskId;
 use yewdux::prelude::*;
 
+pub use todo_api::Task;
+
 pub type StoreDispatch = Dispatch<StoreType>;
 pub type StoreType = PersistentStore<Store>;
 
@@ -18,10 +24,16 @@ pub struct Store {
     pub filter_options: Vec<SelectOption>,
     pub sort_options: Vec<SelectOption>,
     pub error_message: String,
+    #[serde(default)]
+    pub outbox: Vec<PendingMutation>,
+    #[serde(default)]
+    pub conflicts: Vec<TaskConflict>,
+    #[serde(default)]
+    pub temp_ids_issued: u32,
 }
 
 impl Store {
-    pub fn get_task_by_id(&self, id: u32) -> Option<Task> {
+    pub fn get_task_by_id(&self, id: TaskId) -> Option<Task> {
         self.tasks.iter().find(|task| task.id == id).cloned()
     }
 }
@@ -56,19 +68,13 @@ impl Default for Store {
                 SelectOption::new("name", "Name", false),
             ],
             error_message: Default::default(),
+            outbox: Default::default(),
+            conflicts: Default::default(),
+            temp_ids_issued: Default::default(),
         }
     }
 }
 
-#[derive(Clone, Default, Serialize, Deserialize, PartialEq)]
-pub struct Task {
-    pub completed_at: Option<String>,
-    pub description: Option<String>,
-    pub id: u32,
-    pub priority: Option<String>,
-    pub title: String,
-}
-
 pub fn login_reducer(auth_response: AuthResponse, dispatch: StoreDispatch) {
     dispatch.reduce(move |store| {
         store.username = auth_response.data.username;
@@ -76,9 +82,19 @@ pub fn login_reducer(auth_response: AuthResponse, dispatch: StoreDispatch) {
     });
 }
 
-pub fn set_tasks(tasks: TaskResponse, dispatch: StoreDispatch) {
+pub fn set_tasks(tasks: TaskListResponse, dispatch: StoreDispatch) {
     dispatch.reduce(move |store| {
+        // changes the server hasn't seen yet stay on top of its list
+        let offline_tasks: Vec<Task> = store
+            .tasks
+            .drain(..)
+            .filter(|task| outbox::is_temp_id(task.id))
+            .collect();
         store.tasks = tasks.data;
+        store.tasks.extend(offline_tasks);
+        for pending in &store.outbox {
+            outbox::replay(&mut store.tasks, &pending.mutation);
+        }
     })
 }
 
@@ -87,34 +103,63 @@ pub fn logout(dispatch: StoreDispatch) {
         store.username = String::new();
         store.token = String::new();
         store.tasks = vec![];
+        store.outbox = vec![];
+        store.conflicts = vec![];
     });
 }
 
-pub fn update_task_by_id(dispatch: StoreDispatch, task_id: u32, patch_task: PatchTask) {
+pub fn update_task_by_id(dispatch: StoreDispatch, task_id: TaskId, patch_task: PatchTask) {
     dispatch.reduce(move |store| {
         let task = store.tasks.iter_mut().find(|task| task.id == task_id);
         let task = if let Some(task) = task {
             task
         } else {
+            // pushed updates can arrive for tasks this tab hasn't loaded yet
             console::error!("Could not find task in Yewdux store");
-            panic!();
+            return;
         };
-        if let Some(title) = patch_task.title {
-            task.title = title;
-        }
-        if let Some(completed_at) = patch_task.completed_at {
-            task.completed_at = completed_at;
-        }
-        if patch_task.priority.is_some() {
-            task.priority = patch_task.priority;
-        }
-        if patch_task.description.is_some() {
-            task.description = patch_task.description;
+        apply_patch(task, patch_task);
+    })
+}
+
+pub fn apply_patch(task: &mut Task, patch_task: PatchTask) {
+    if let Some(title) = patch_task.title {
+        task.title = title;
+    }
+    if let Some(completed_at) = patch_task.completed_at {
+        task.completed_at = completed_at;
+    }
+    if let Some(priority) = patch_task.priority {
+        task.priority = priority;
+    }
+    if let Some(description) = patch_task.description {
+        task.description = description;
+    }
+}
+
+/// The version to send in If-Match, if the server has told us one.
+pub fn known_version(task: &Task) -> Option<i32> {
+    Some(task.version).filter(|version| *version > 0)
+}
+
+pub fn set_task_version(dispatch: StoreDispatch, task_id: TaskId, version: i32) {
+    dispatch.reduce(move |store| {
+        if let Some(task) = store.tasks.iter_mut().find(|task| task.id == task_id) {
+            task.version = version;
         }
     })
 }
 
-pub fn remove_task_by_id(dispatch: StoreDispatch, task_id: u32) {
+pub fn replace_task(dispatch: StoreDispatch, task: Task) {
+    dispatch.reduce(
+        move |store| match store.tasks.iter_mut().find(|stored| stored.id == task.id) {
+            Some(stored) => *stored = task,
+            None => store.tasks.push(task),
+        },
+    )
+}
+
+pub fn remove_task_by_id(dispatch: StoreDispatch, task_id: TaskId) {
     dispatch.reduce(move |store| {
         let store_tasks = store.tasks.clone();
         let tasks: Vec<Task> = store_tasks
@@ -125,25 +170,18 @@ pub fn remove_task_by_id(dispatch: StoreDispatch, task_id: u32) {
     })
 }
 
-pub fn add_task(dispatch: StoreDispatch, task: Task) {
-    dispatch.reduce(move |store| {
-        store.tasks.push(task);
-    });
-}
-
-pub fn mark_task_completed(dispatch: StoreDispatch, task_id: u32) {
+pub fn mark_task_completed(dispatch: StoreDispatch, task_id: TaskId) {
     dispatch.reduce(move |store| {
         let task = store.tasks.iter_mut().find(|task| task.id == task_id);
         if task.is_none() {
             gloo::console::error!("Error finding task to complete it");
             panic!();
         }
-        let now = Date::new_0();
-        task.unwrap().completed_at = now.to_utc_string().to_string().as_string();
+        task.unwrap().completed_at = Some(patch_task::now());
     })
 }
 
-pub fn mark_task_uncompleted(dispatch: StoreDispatch, task_id: u32) {
+pub fn mark_task_uncompleted(dispatch: StoreDispatch, task_id: TaskId) {
     dispatch.reduce(move |store| {
         let task = store.tasks.iter_mut().find(|task| task.id == task_id);
         if task.is_none() {
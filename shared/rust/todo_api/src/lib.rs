@@ -0,0 +1,393 @@
+// The request and response bodies of the todo API. todo_server and the yew
+// solution both build against these, so the two can't disagree about the JSON.
+// Everything here has to compile for wasm32-unknown-unknown too.
+
+pub mod validation;
+
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Deserializer, Serialize};
+
+pub type TaskId = i32;
+pub type UserId = i32;
+pub type TemplateId = i32;
+pub type ApiTokenId = i32;
+
+/// Every body the API sends back is wrapped in `{ "data": ... }`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct DataResponse<T> {
+    pub data: T,
+}
+
+pub type TaskResponse = DataResponse<Task>;
+pub type TaskListResponse = DataResponse<Vec<Task>>;
+pub type AuthResponse = DataResponse<UserInfo>;
+pub type TemplateResponse = DataResponse<TaskTemplate>;
+pub type TemplateListResponse = DataResponse<Vec<TaskTemplate>>;
+pub type AdminUserResponse = DataResponse<AdminUser>;
+pub type AdminUserListResponse = DataResponse<Vec<AdminUser>>;
+pub type StatsResponse = DataResponse<UsageStats>;
+pub type TwoFactorChallengeResponse = DataResponse<TwoFactorChallenge>;
+pub type TwoFactorStatusResponse = DataResponse<TwoFactorStatus>;
+pub type TwoFactorSetupResponse = DataResponse<TwoFactorSetup>;
+pub type RecoveryCodesResponse = DataResponse<RecoveryCodes>;
+pub type OidcAuthorizationResponse = DataResponse<OidcAuthorization>;
+pub type ApiTokenListResponse = DataResponse<Vec<ApiToken>>;
+pub type NewApiTokenResponse = DataResponse<NewApiToken>;
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Task {
+    #[cfg_attr(feature = "openapi", schema(value_type = i32))]
+    pub id: TaskId,
+    pub priority: Option<String>,
+    pub title: String,
+    pub completed_at: Option<NaiveDateTime>,
+    pub description: Option<String>,
+    /// Bumped on every write, sent back to the server in If-Match.
+    #[serde(default)]
+    pub version: i32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct CreateTaskRequest {
+    pub title: String,
+    pub description: Option<String>,
+    pub priority: Option<String>,
+}
+
+/// The body of a PATCH, anything left out stays as it is.
+/// `"completed_at": null` marks the task as not completed, a null priority or
+/// description removes it.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct UpdateTaskRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "present"
+    )]
+    pub priority: Option<Option<String>>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "present"
+    )]
+    pub description: Option<Option<String>>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "present"
+    )]
+    pub completed_at: Option<Option<NaiveDateTime>>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct LoginRequest {
+    pub username: String,
+    pub password: String,
+}
+
+/// The body of PATCH /users/me, anything left out stays as it is.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct UpdateUserRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    /// Where password reset links go, an empty one removes it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ChangePasswordRequest {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ForgotPasswordRequest {
+    pub email: String,
+}
+
+/// `token` is the one from the link in the reset email.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ResetPasswordRequest {
+    pub token: String,
+    pub new_password: String,
+}
+
+/// What login answers, with a 202, instead of the user when they have
+/// two-factor authentication on. The challenge goes to /users/login/two-factor
+/// with a code and works for a few minutes.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct TwoFactorChallenge {
+    pub challenge: String,
+}
+
+/// `code` is one from the authenticator app or one of the recovery codes.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct TwoFactorLoginRequest {
+    pub challenge: String,
+    pub code: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct TwoFactorStatus {
+    pub enabled: bool,
+    pub recovery_codes_left: i64,
+}
+
+/// The start of enrolment. `otpauth_uri` is for a QR code, `secret` (base32)
+/// for typing into the app by hand.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct TwoFactorSetup {
+    pub secret: String,
+    pub otpauth_uri: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct TwoFactorCodeRequest {
+    pub code: String,
+}
+
+/// Shown once when two-factor authentication is turned on, each works once
+/// instead of a code from the app.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct RecoveryCodes {
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct DisableTwoFactorRequest {
+    pub password: String,
+}
+
+/// Where to send the browser to log in with SSO. The identity provider sends it
+/// back to the app's /sso-callback page with a `code` and the `state`.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct OidcAuthorization {
+    pub authorization_url: String,
+}
+
+/// The `code` and `state` from the query string of the SSO redirect.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct OidcCallbackRequest {
+    pub code: String,
+    pub state: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct UserInfo {
+    #[cfg_attr(feature = "openapi", schema(value_type = i32))]
+    pub id: UserId,
+    pub username: String,
+    pub token: String,
+}
+
+/// What a user is allowed to do. Admins can also use the /admin routes and
+/// manage the task templates.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    #[default]
+    User,
+    Admin,
+}
+
+impl Role {
+    pub const ALL: [Role; 2] = [Role::User, Role::Admin];
+
+    /// The name stored in `users.role`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::User => "user",
+            Role::Admin => "admin",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Role> {
+        Role::ALL.into_iter().find(|role| role.as_str() == name)
+    }
+}
+
+/// What a personal API token may do. Read-only tokens only work for GET.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum TokenScope {
+    #[default]
+    ReadOnly,
+    ReadWrite,
+}
+
+impl TokenScope {
+    pub const ALL: [TokenScope; 2] = [TokenScope::ReadOnly, TokenScope::ReadWrite];
+
+    /// The name stored in `api_tokens.scope`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TokenScope::ReadOnly => "read_only",
+            TokenScope::ReadWrite => "read_write",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<TokenScope> {
+        TokenScope::ALL.into_iter().find(|scope| scope.as_str() == name)
+    }
+}
+
+/// A personal API token for scripts, sent as `Authorization: Bearer`. Only
+/// what it's called and what it may do, the token itself is shown once.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ApiToken {
+    #[cfg_attr(feature = "openapi", schema(value_type = i32))]
+    pub id: ApiTokenId,
+    pub name: String,
+    pub scope: TokenScope,
+    pub created_at: NaiveDateTime,
+    pub expires_at: Option<NaiveDateTime>,
+    pub last_used_at: Option<NaiveDateTime>,
+}
+
+/// Leaving out `expires_in_days` makes a token that works until it's revoked.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct CreateApiTokenRequest {
+    pub name: String,
+    #[serde(default)]
+    pub scope: TokenScope,
+    pub expires_in_days: Option<i32>,
+}
+
+/// What creating a token answers, the only time `token` is shown.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct NewApiToken {
+    pub token: String,
+    pub api_token: ApiToken,
+}
+
+/// A user as an admin sees them.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct AdminUser {
+    #[cfg_attr(feature = "openapi", schema(value_type = i32))]
+    pub id: UserId,
+    pub username: String,
+    pub role: Role,
+    /// Disabled users have one too.
+    pub deleted_at: Option<NaiveDateTime>,
+    /// An admin disabled them, only these can be enabled again.
+    pub disabled: bool,
+    pub logged_in: bool,
+    /// Tasks that aren't deleted.
+    pub tasks: i64,
+}
+
+/// Counts over the whole system.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct UsageStats {
+    pub users: i64,
+    pub deleted_users: i64,
+    pub logged_in_users: i64,
+    pub admins: i64,
+    pub tasks: i64,
+    pub completed_tasks: i64,
+    pub deleted_tasks: i64,
+    pub templates: i64,
+    pub task_events: i64,
+}
+
+/// One of the tasks a new account starts with. Accounts get the templates of
+/// the locale that best matches the Accept-Language they sign up with.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct TaskTemplate {
+    #[cfg_attr(feature = "openapi", schema(value_type = i32))]
+    pub id: TemplateId,
+    pub locale: String,
+    /// The order the tasks are created in.
+    pub position: i32,
+    pub title: String,
+    pub description: Option<String>,
+    pub priority: Option<String>,
+}
+
+/// Leaving out the locale makes an English template, leaving out the
+/// position puts it after the others of its locale.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct CreateTemplateRequest {
+    pub title: String,
+    pub description: Option<String>,
+    pub priority: Option<String>,
+    #[serde(default)]
+    pub locale: Option<String>,
+    #[serde(default)]
+    pub position: Option<i32>,
+}
+
+/// Like UpdateTaskRequest, anything left out stays as it is.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct UpdateTemplateRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub priority: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub locale: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub position: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct MessageResponse {
+    pub message: String,
+}
+
+/// A change to one of a user's tasks, pushed to every session they have open.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum TaskEvent {
+    TaskCreated(Task),
+    TaskUpdated(Task),
+    TaskDeleted {
+        #[cfg_attr(feature = "openapi", schema(value_type = i32))]
+        id: TaskId,
+    },
+}
+
+// a field that is there, even as null, is Some; only a missing one is None
+fn present<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
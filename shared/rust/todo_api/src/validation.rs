@@ -0,0 +1,157 @@
+// The rules for usernames, emails and passwords, shared so the yew solution can tell
+// the user what's wrong before the server does. The server also checks new
+// passwords against a list of breached ones, which only it has.
+
+pub const MIN_USERNAME_LENGTH: usize = 3;
+/// `users.username` is a VARCHAR(64).
+pub const MAX_USERNAME_LENGTH: usize = 64;
+/// bcrypt ignores everything after the first 72 bytes.
+pub const MAX_PASSWORD_BYTES: usize = 72;
+
+pub fn is_username_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-')
+}
+
+/// What's wrong with a new username, if anything.
+pub fn username_problem(username: &str) -> Option<String> {
+    let length = username.chars().count();
+    if !(MIN_USERNAME_LENGTH..=MAX_USERNAME_LENGTH).contains(&length) {
+        return Some(format!(
+            "the username has to be {} to {} characters",
+            MIN_USERNAME_LENGTH, MAX_USERNAME_LENGTH
+        ));
+    }
+    if !username.chars().all(is_username_char) {
+        return Some("the username can only have letters, digits, '.', '_' and '-'".to_string());
+    }
+    None
+}
+
+/// `users.email` is a VARCHAR(255), and SMTP takes no more than 254.
+pub const MAX_EMAIL_LENGTH: usize = 254;
+
+/// What's wrong with an email address, if anything. Plain `local@domain`
+/// addresses only: no spaces, control characters, quotes, comments or angle
+/// brackets, which are more likely someone sneaking SMTP commands or headers into
+/// an email than a real address.
+pub fn email_problem(email: &str) -> Option<String> {
+    let not_an_email = || Some(format!("{:?} is not an email address", email));
+    if email.len() > MAX_EMAIL_LENGTH {
+        return Some(format!(
+            "the email can't be longer than {} characters",
+            MAX_EMAIL_LENGTH
+        ));
+    }
+    let (local, domain) = match email.split_once('@') {
+        Some(parts) => parts,
+        None => return not_an_email(),
+    };
+    let local_ok = !local.is_empty()
+        && local.len() <= 64
+        && !local.starts_with('.')
+        && !local.ends_with('.')
+        && !local.contains("..")
+        && local.chars().all(|c| {
+            c.is_alphanumeric() || (c.is_ascii_punctuation() && !"\"(),:;<>@[\\]".contains(c))
+        });
+    let domain_ok = domain.contains('.')
+        && domain.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_alphanumeric() || c == '-')
+        });
+    if !local_ok || !domain_ok {
+        return not_an_email();
+    }
+    None
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    /// In bits, as `password_strength` estimates them.
+    pub min_strength: u32,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        PasswordPolicy {
+            min_length: 8,
+            min_strength: 40,
+        }
+    }
+}
+
+impl PasswordPolicy {
+    /// What's wrong with `password` as the password of `username`, if anything.
+    pub fn problem(&self, username: &str, password: &str) -> Option<String> {
+        if password.chars().count() < self.min_length {
+            return Some(format!(
+                "the password has to be at least {} characters",
+                self.min_length
+            ));
+        }
+        if password.len() > MAX_PASSWORD_BYTES {
+            return Some(format!(
+                "the password can't be longer than {} bytes",
+                MAX_PASSWORD_BYTES
+            ));
+        }
+        let username = username.to_lowercase();
+        if username.chars().count() >= MIN_USERNAME_LENGTH
+            && password.to_lowercase().contains(&username)
+        {
+            return Some("the password can't have the username in it".to_string());
+        }
+        if password_strength(password) < self.min_strength {
+            return Some(
+                "the password is too easy to guess, make it longer or mix in capitals, digits \
+                 and symbols"
+                    .to_string(),
+            );
+        }
+        None
+    }
+}
+
+/// A rough guess at how many bits of guessing `password` takes: the length
+/// times the bits of the kinds of characters in it. Characters that repeat the
+/// one before, or go on from it like "abc" or "321", count for a quarter.
+pub fn password_strength(password: &str) -> u32 {
+    let chars: Vec<char> = password.chars().collect();
+    let mut alphabet = 0;
+    if chars.iter().any(char::is_ascii_lowercase) {
+        alphabet += 26;
+    }
+    if chars.iter().any(char::is_ascii_uppercase) {
+        alphabet += 26;
+    }
+    if chars.iter().any(char::is_ascii_digit) {
+        alphabet += 10;
+    }
+    if chars.iter().any(|c| c.is_ascii_punctuation() || *c == ' ') {
+        alphabet += 33;
+    }
+    if chars.iter().any(|c| !c.is_ascii()) {
+        alphabet += 100;
+    }
+    if alphabet == 0 {
+        return 0;
+    }
+    let length: f64 = chars
+        .iter()
+        .enumerate()
+        .map(|(i, c)| {
+            let step = i
+                .checked_sub(1)
+                .map(|before| (*c as i64 - chars[before] as i64).abs());
+            match step {
+                Some(0) | Some(1) => 0.25,
+                _ => 1.0,
+            }
+        })
+        .sum();
+    (length * f64::from(alphabet).log2()) as u32
+}
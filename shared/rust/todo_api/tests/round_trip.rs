@@ -0,0 +1,195 @@
+use chrono::NaiveDate;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::json;
+use std::fmt::Debug;
+use todo_api::*;
+
+fn round_trip<T>(value: &T)
+where
+    T: Serialize + DeserializeOwned + PartialEq + Debug,
+{
+    let text = serde_json::to_string(value).unwrap();
+    let back: T = serde_json::from_str(&text).unwrap();
+    assert_eq!(&back, value, "{}", text);
+}
+
+fn task() -> Task {
+    Task {
+        id: 8,
+        priority: Some("A".to_string()),
+        title: "Curl is fun".to_string(),
+        completed_at: Some(
+            NaiveDate::from_ymd_opt(2022, 5, 11)
+                .unwrap()
+                .and_hms_micro_opt(18, 45, 16, 214145)
+                .unwrap(),
+        ),
+        description: Some("typing and stuff in the terminal".to_string()),
+        version: 3,
+    }
+}
+
+#[test]
+fn task_round_trips() {
+    round_trip(&task());
+    round_trip(&Task::default());
+    round_trip(&TaskResponse { data: task() });
+    round_trip(&TaskListResponse {
+        data: vec![task(), Task::default()],
+    });
+}
+
+#[test]
+fn task_reads_what_the_server_sends() {
+    let body = json!({
+        "data": {
+            "id": 8,
+            "priority": "A",
+            "title": "Curl is fun",
+            "completed_at": "2022-05-11T18:45:16.214145",
+            "description": "typing and stuff in the terminal",
+            "version": 3
+        }
+    });
+    let response: TaskResponse = serde_json::from_value(body).unwrap();
+    assert_eq!(response.data, task());
+}
+
+#[test]
+fn task_allows_missing_description_and_version() {
+    let body = json!({ "id": 1, "priority": null, "title": "t", "completed_at": null });
+    let task: Task = serde_json::from_value(body).unwrap();
+    assert_eq!(task.description, None);
+    assert_eq!(task.version, 0);
+}
+
+#[test]
+fn create_task_request_round_trips() {
+    round_trip(&CreateTaskRequest {
+        title: "title".to_string(),
+        description: Some("description".to_string()),
+        priority: Some("B".to_string()),
+    });
+    let request: CreateTaskRequest = serde_json::from_value(json!({ "title": "t" })).unwrap();
+    assert_eq!(request.description, None);
+    assert_eq!(request.priority, None);
+}
+
+#[test]
+fn update_task_request_tells_null_from_missing() {
+    let missing: UpdateTaskRequest = serde_json::from_value(json!({ "title": "t" })).unwrap();
+    assert_eq!(missing.completed_at, None);
+    assert_eq!(missing.priority, None);
+    assert_eq!(missing.description, None);
+
+    let null: UpdateTaskRequest = serde_json::from_value(
+        json!({ "completed_at": null, "priority": null, "description": null }),
+    )
+    .unwrap();
+    assert_eq!(null.completed_at, Some(None));
+    assert_eq!(null.priority, Some(None));
+    assert_eq!(null.description, Some(None));
+
+    let set: UpdateTaskRequest =
+        serde_json::from_value(json!({ "completed_at": "2022-05-11T18:45:16.214145" })).unwrap();
+    assert_eq!(set.completed_at, Some(task().completed_at));
+}
+
+#[test]
+fn update_task_request_only_sends_what_changed() {
+    let request = UpdateTaskRequest {
+        title: Some("t".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(serde_json::to_value(&request).unwrap(), json!({ "title": "t" }));
+
+    let uncomplete = UpdateTaskRequest {
+        completed_at: Some(None),
+        ..Default::default()
+    };
+    assert_eq!(
+        serde_json::to_value(&uncomplete).unwrap(),
+        json!({ "completed_at": null })
+    );
+    round_trip(&request);
+    round_trip(&uncomplete);
+    round_trip(&UpdateTaskRequest {
+        title: Some("t".to_string()),
+        priority: Some(Some("C".to_string())),
+        description: Some(Some("d".to_string())),
+        completed_at: Some(task().completed_at),
+    });
+    round_trip(&UpdateTaskRequest {
+        priority: Some(None),
+        description: Some(None),
+        ..Default::default()
+    });
+}
+
+#[test]
+fn update_task_request_accepts_a_whole_task() {
+    // older clients PATCH the full task, extra fields are ignored
+    let request: UpdateTaskRequest = serde_json::from_value(serde_json::to_value(task()).unwrap()).unwrap();
+    assert_eq!(request.title.as_deref(), Some("Curl is fun"));
+    assert_eq!(request.completed_at, Some(task().completed_at));
+}
+
+#[test]
+fn users_round_trip() {
+    round_trip(&LoginRequest {
+        username: "woodroww".to_string(),
+        password: "myfancypass".to_string(),
+    });
+    round_trip(&AuthResponse {
+        data: UserInfo {
+            id: 3,
+            username: "woodroww".to_string(),
+            token: "token".to_string(),
+        },
+    });
+    round_trip(&MessageResponse {
+        message: "user logged out".to_string(),
+    });
+}
+
+#[test]
+fn task_events_are_tagged() {
+    let deleted = TaskEvent::TaskDeleted { id: 8 };
+    assert_eq!(
+        serde_json::to_value(&deleted).unwrap(),
+        json!({ "type": "task_deleted", "data": { "id": 8 } })
+    );
+    let updated = serde_json::to_value(TaskEvent::TaskUpdated(task())).unwrap();
+    assert_eq!(updated["type"], "task_updated");
+    assert_eq!(updated["data"]["version"], 3);
+    round_trip(&deleted);
+    round_trip(&TaskEvent::TaskCreated(task()));
+    round_trip(&TaskEvent::TaskUpdated(task()));
+}
+
+#[test]
+fn api_tokens_default_to_read_only() {
+    let request: CreateApiTokenRequest =
+        serde_json::from_value(json!({ "name": "backup script" })).unwrap();
+    assert_eq!(request.scope, TokenScope::ReadOnly);
+    assert_eq!(request.expires_in_days, None);
+    assert_eq!(
+        serde_json::to_value(TokenScope::ReadWrite).unwrap(),
+        json!("read_write")
+    );
+    for scope in TokenScope::ALL {
+        assert_eq!(TokenScope::parse(scope.as_str()), Some(scope));
+    }
+    round_trip(&NewApiTokenResponse {
+        data: NewApiToken {
+            token: "todo_pat_abc".to_string(),
+            api_token: ApiToken {
+                name: "backup script".to_string(),
+                scope: TokenScope::ReadWrite,
+                expires_at: task().completed_at,
+                ..ApiToken::default()
+            },
+        },
+    });
+}
@@ -0,0 +1,75 @@
+use todo_api::validation::*;
+
+#[test]
+fn usernames_are_short_and_plain() {
+    assert_eq!(username_problem("woodroww"), None);
+    assert_eq!(username_problem("matt.w_2-x"), None);
+    assert!(username_problem("ab").is_some());
+    assert!(username_problem(&"a".repeat(65)).is_some());
+    assert_eq!(username_problem(&"a".repeat(64)), None);
+    for username in ["wood roww", "matt@example.com", "wöodroww", "<script>"] {
+        assert!(username_problem(username).is_some(), "{}", username);
+    }
+}
+
+#[test]
+fn emails_are_plain_addresses() {
+    for email in [
+        "matt@example.com",
+        "matt.w+todo@mail.example.co.uk",
+        "mätt@exämple.de",
+    ] {
+        assert_eq!(email_problem(email), None, "{}", email);
+    }
+    for email in [
+        "",
+        "matt",
+        "matt@",
+        "@example.com",
+        "matt@localhost",
+        "matt@@example.com",
+        "matt@example..com",
+        ".matt@example.com",
+        "matt smith@example.com",
+        "matt@example.com\r\nBcc: everyone@example.com",
+        "matt@example.com>\r\nRCPT TO:<everyone@example.com",
+        "\"matt\"@example.com",
+        "matt@-example.com",
+    ] {
+        assert!(email_problem(email).is_some(), "{:?}", email);
+    }
+    let long = format!("{}@example.com", "a".repeat(250));
+    assert!(email_problem(&long).is_some());
+}
+
+#[test]
+fn guessable_passwords_are_weak() {
+    assert!(password_strength("12345678") < password_strength("83729164"));
+    assert!(password_strength("aaaaaaaaaaaa") < password_strength("password"));
+    assert!(password_strength("password") < password_strength("Password1!"));
+    assert_eq!(password_strength(""), 0);
+}
+
+#[test]
+fn the_policy_says_what_is_wrong() {
+    let policy = PasswordPolicy::default();
+    assert_eq!(policy.problem("woodroww", "myfancypass"), None);
+    assert_eq!(
+        policy.problem("woodroww", "correct horse battery staple"),
+        None
+    );
+    for password in ["", "short", "abcdefghijk", "password", "xwoodroww-2022"] {
+        assert!(
+            policy.problem("woodroww", password).is_some(),
+            "{}",
+            password
+        );
+    }
+    // bcrypt would quietly cut it short
+    assert!(policy.problem("woodroww", &"Xy7!".repeat(19)).is_some());
+    let relaxed = PasswordPolicy {
+        min_length: 4,
+        min_strength: 0,
+    };
+    assert_eq!(relaxed.problem("woodroww", "abcd"), None);
+}